@@ -0,0 +1,242 @@
+/* The C interface to mzdata, built with the `capi` feature. */
+
+#ifndef MZDATA_H
+#define MZDATA_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The outcome of a call through the C interface
+ */
+typedef enum MzDataStatus {
+  MZ_DATA_STATUS_OK = 0,
+  /**
+   * A required pointer argument was `NULL`
+   */
+  MZ_DATA_STATUS_NULL_POINTER,
+  /**
+   * A string argument was not valid UTF-8
+   */
+  MZ_DATA_STATUS_INVALID_ARGUMENT,
+  /**
+   * The file could not be opened or read
+   */
+  MZ_DATA_STATUS_IO_ERROR,
+  /**
+   * The file's format was not recognized or is not supported
+   */
+  MZ_DATA_STATUS_UNSUPPORTED_FORMAT,
+  /**
+   * The file's contents could not be parsed
+   */
+  MZ_DATA_STATUS_PARSE_ERROR,
+  /**
+   * The requested spectrum or array does not exist
+   */
+  MZ_DATA_STATUS_NOT_FOUND,
+  /**
+   * There are no more spectra to read
+   */
+  MZ_DATA_STATUS_END_OF_STREAM,
+  /**
+   * A data array could not be decoded
+   */
+  MZ_DATA_STATUS_ARRAY_ERROR,
+  /**
+   * The spectrum has no precursor
+   */
+  MZ_DATA_STATUS_NO_PRECURSOR,
+  /**
+   * An unexpected internal error occurred
+   */
+  MZ_DATA_STATUS_PANIC,
+} MzDataStatus;
+
+/**
+ * An open spectrum source
+ */
+typedef struct MzDataReader MzDataReader;
+
+/**
+ * A spectrum read from an [`MzDataReader`]
+ */
+typedef struct MzDataSpectrum MzDataSpectrum;
+
+/**
+ * The first selected ion of a spectrum's precursor and its isolation window
+ */
+typedef struct MzDataPrecursor {
+  double mz;
+  float intensity;
+  /**
+   * The charge state, or 0 if it is not known
+   */
+  int32_t charge;
+  float isolation_window_target;
+  float isolation_window_lower;
+  float isolation_window_upper;
+} MzDataPrecursor;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message describing the last error on this thread, or `NULL` if there was none. The
+ * string is owned by the library and is valid until the next failing call on this thread.
+ */
+const char *mzdata_last_error_message(void);
+
+/**
+ * Close a reader opened with [`mzdata_reader_open`]. Passing `NULL` does nothing.
+ *
+ * # Safety
+ * `reader` must be `NULL` or a reader which has not already been freed.
+ */
+void mzdata_reader_free(MzDataReader *reader);
+
+/**
+ * Read the spectrum with the native ID `id` into `out_spectrum`.
+ *
+ * # Safety
+ * `reader` and `out_spectrum` must be valid pointers and `id` a NUL-terminated string.
+ */
+MzDataStatus mzdata_reader_get_by_id(MzDataReader *reader,
+                                     const char *id,
+                                     MzDataSpectrum **out_spectrum);
+
+/**
+ * Read the spectrum at `index` into `out_spectrum`.
+ *
+ * # Safety
+ * `reader` and `out_spectrum` must be valid pointers.
+ */
+MzDataStatus mzdata_reader_get_by_index(MzDataReader *reader,
+                                        size_t index,
+                                        MzDataSpectrum **out_spectrum);
+
+/**
+ * Store the number of spectra in the file in `out_len`.
+ *
+ * # Safety
+ * `reader` and `out_len` must be valid pointers.
+ */
+MzDataStatus mzdata_reader_len(const MzDataReader *reader, size_t *out_len);
+
+/**
+ * Read the next spectrum into `out_spectrum`, returning [`MzDataStatus::EndOfStream`] when
+ * there are no more. If the spectrum cannot be read, the status describes why, e.g.
+ * [`MzDataStatus::ParseError`] for a malformed or truncated file.
+ *
+ * # Safety
+ * `reader` and `out_spectrum` must be valid pointers.
+ */
+MzDataStatus mzdata_reader_next(MzDataReader *reader, MzDataSpectrum **out_spectrum);
+
+/**
+ * Open the file at `path`, inferring its format, and store the reader in `out_reader`.
+ *
+ * # Safety
+ * `path` must be a NUL-terminated string and `out_reader` a valid pointer.
+ */
+MzDataStatus mzdata_reader_open(const char *path, MzDataReader **out_reader);
+
+/**
+ * Return the reader to the first spectrum.
+ *
+ * # Safety
+ * `reader` must be a valid pointer.
+ */
+MzDataStatus mzdata_reader_reset(MzDataReader *reader);
+
+/**
+ * Free a spectrum. Passing `NULL` does nothing.
+ *
+ * # Safety
+ * `spectrum` must be `NULL` or a spectrum which has not already been freed.
+ */
+void mzdata_spectrum_free(MzDataSpectrum *spectrum);
+
+/**
+ * The spectrum's native ID, valid until the spectrum is freed.
+ *
+ * # Safety
+ * `spectrum` must be a valid pointer.
+ */
+const char *mzdata_spectrum_id(const MzDataSpectrum *spectrum);
+
+/**
+ * The spectrum's position in its file.
+ *
+ * # Safety
+ * `spectrum` must be a valid pointer.
+ */
+size_t mzdata_spectrum_index(const MzDataSpectrum *spectrum);
+
+/**
+ * Decode the spectrum's intensity array and store a pointer to it and its length in
+ * `out_data` and `out_len`. The array is owned by the spectrum.
+ *
+ * # Safety
+ * `spectrum`, `out_data` and `out_len` must be valid pointers.
+ */
+MzDataStatus mzdata_spectrum_intensities(MzDataSpectrum *spectrum,
+                                         const float **out_data,
+                                         size_t *out_len);
+
+/**
+ * The spectrum's MS level.
+ *
+ * # Safety
+ * `spectrum` must be a valid pointer.
+ */
+uint8_t mzdata_spectrum_ms_level(const MzDataSpectrum *spectrum);
+
+/**
+ * Decode the spectrum's m/z array and store a pointer to it and its length in `out_data`
+ * and `out_len`. The array is owned by the spectrum.
+ *
+ * # Safety
+ * `spectrum`, `out_data` and `out_len` must be valid pointers.
+ */
+MzDataStatus mzdata_spectrum_mzs(MzDataSpectrum *spectrum,
+                                 const double **out_data,
+                                 size_t *out_len);
+
+/**
+ * The spectrum's polarity, 1 for positive, -1 for negative and 0 if it is unknown.
+ *
+ * # Safety
+ * `spectrum` must be a valid pointer.
+ */
+int8_t mzdata_spectrum_polarity(const MzDataSpectrum *spectrum);
+
+/**
+ * Store the first selected ion of the spectrum's first precursor in `out_precursor`,
+ * returning [`MzDataStatus::NoPrecursor`] if there is no precursor or it has no selected ion.
+ *
+ * # Safety
+ * `spectrum` and `out_precursor` must be valid pointers.
+ */
+MzDataStatus mzdata_spectrum_precursor(const MzDataSpectrum *spectrum,
+                                       MzDataPrecursor *out_precursor);
+
+/**
+ * The spectrum's scan start time, in minutes.
+ *
+ * # Safety
+ * `spectrum` must be a valid pointer.
+ */
+double mzdata_spectrum_time(const MzDataSpectrum *spectrum);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MZDATA_H */
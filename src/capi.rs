@@ -0,0 +1,755 @@
+#![cfg(feature = "capi")]
+/*!
+A C interface for reading spectra from other languages.
+
+Files are opened as [`open_file`](crate::io::open_file) would, so any format it recognizes can
+be read. Readers and spectra are handed out as opaque pointers which must be released with
+[`mzdata_reader_free`] and [`mzdata_spectrum_free`]. Every fallible function returns an
+[`MzDataStatus`], and the message of the last error on the calling thread is available from
+[`mzdata_last_error_message`].
+
+The m/z and intensity arrays are decoded in place inside the spectrum, and the pointers handed
+out borrow from it, so they remain valid until the spectrum is freed.
+
+The header `include/mzdata.h` is generated from this module with
+`cbindgen --config cbindgen.toml --output include/mzdata.h`, and the library is built with
+`cargo rustc --lib --release --features capi --crate-type cdylib`.
+
+This module requires the `capi` feature.
+*/
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+#[cfg(feature = "serde")]
+use crate::io::jsonl::JSONLReaderType;
+use crate::io::mgf::MGFReaderType;
+use crate::io::mzml::MzMLReaderType;
+#[cfg(feature = "mzmlb")]
+use crate::io::mzmlb::MzMLbReader;
+use crate::io::traits::SeekRead;
+#[cfg(feature = "serde")]
+use crate::io::JSONLError;
+use crate::io::{
+    infer_format_compression, MGFError, MassSpectrometryFormat, MzMLParserError, ScanSource,
+    SeekableDecompressor, TryScanSource,
+};
+#[cfg(feature = "mzmlb")]
+use crate::io::{MZFileReader, MzMLbError};
+use crate::spectrum::bindata::ArrayRetrievalError;
+use crate::spectrum::{PrecursorSelection, Spectrum, SpectrumLike};
+
+/// The outcome of a call through the C interface
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MzDataStatus {
+    Ok = 0,
+    /// A required pointer argument was `NULL`
+    NullPointer,
+    /// A string argument was not valid UTF-8
+    InvalidArgument,
+    /// The file could not be opened or read
+    IOError,
+    /// The file's format was not recognized or is not supported
+    UnsupportedFormat,
+    /// The file's contents could not be parsed
+    ParseError,
+    /// The requested spectrum or array does not exist
+    NotFound,
+    /// There are no more spectra to read
+    EndOfStream,
+    /// A data array could not be decoded
+    ArrayError,
+    /// The spectrum has no precursor
+    NoPrecursor,
+    /// An unexpected internal error occurred
+    Panic,
+}
+
+impl From<&MzMLParserError> for MzDataStatus {
+    fn from(value: &MzMLParserError) -> Self {
+        match value {
+            MzMLParserError::IOError(_, _) => Self::IOError,
+            MzMLParserError::SectionOver(_) => Self::EndOfStream,
+            MzMLParserError::ArrayDecodingError(_, _) => Self::ArrayError,
+            MzMLParserError::UnknownError(_)
+            | MzMLParserError::IncompleteSpectrum
+            | MzMLParserError::IncompleteElementError(_, _)
+            | MzMLParserError::XMLError(_, _) => Self::ParseError,
+        }
+    }
+}
+
+impl From<&MGFError> for MzDataStatus {
+    fn from(value: &MGFError) -> Self {
+        match value {
+            MGFError::NoError => Self::Ok,
+            MGFError::IOError(_) => Self::IOError,
+            MGFError::MalformedPeakLine
+            | MGFError::MalformedHeaderLine
+            | MGFError::TooManyColumnsForPeakLine
+            | MGFError::IncompleteSpectrum => Self::ParseError,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<&JSONLError> for MzDataStatus {
+    fn from(value: &JSONLError) -> Self {
+        match value {
+            JSONLError::JSONError(_) => Self::ParseError,
+            JSONLError::IOError(err) => err.into(),
+        }
+    }
+}
+
+#[cfg(feature = "mzmlb")]
+impl From<&MzMLbError> for MzDataStatus {
+    fn from(value: &MzMLbError) -> Self {
+        match value {
+            MzMLbError::HDF5Error(_) => Self::IOError,
+            MzMLbError::MzMLError(err) => err.into(),
+            MzMLbError::ArrayRetrievalError(err) => err.into(),
+        }
+    }
+}
+
+impl From<&ArrayRetrievalError> for MzDataStatus {
+    fn from(value: &ArrayRetrievalError) -> Self {
+        match value {
+            ArrayRetrievalError::NotFound(_) => Self::NotFound,
+            _ => Self::ArrayError,
+        }
+    }
+}
+
+impl From<&io::Error> for MzDataStatus {
+    fn from(value: &io::Error) -> Self {
+        if let Some(inner) = value.get_ref() {
+            if let Some(err) = inner.downcast_ref::<MzMLParserError>() {
+                return err.into();
+            }
+            if let Some(err) = inner.downcast_ref::<MGFError>() {
+                return err.into();
+            }
+        }
+        match value.kind() {
+            io::ErrorKind::Unsupported => Self::UnsupportedFormat,
+            io::ErrorKind::InvalidData => Self::ParseError,
+            _ => Self::IOError,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error<E: ToString>(error: E) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn fail<E: ToString>(status: MzDataStatus, error: E) -> MzDataStatus {
+    set_last_error(error);
+    status
+}
+
+/// Run `f`, turning a panic into [`MzDataStatus::Panic`] so it does not unwind into C
+fn guard<F: FnOnce() -> MzDataStatus>(f: F) -> MzDataStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(status) => status,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "An unknown panic occurred".to_string());
+            fail(MzDataStatus::Panic, message)
+        }
+    }
+}
+
+/// The readers the C interface can open. They are kept concrete, rather than behind a
+/// [`ScanSource`] trait object, so that [`TryScanSource::try_next`] can report why reading
+/// stopped.
+enum SourceReader {
+    MzML(Box<MzMLReaderType<Box<dyn SeekRead>>>),
+    Mgf(Box<MGFReaderType<Box<dyn SeekRead>>>),
+    #[cfg(feature = "serde")]
+    Jsonl(Box<JSONLReaderType<Box<dyn SeekRead>>>),
+    #[cfg(feature = "mzmlb")]
+    MzMLb(Box<MzMLbReader>),
+}
+
+impl SourceReader {
+    /// Open the file at `path` the same way as [`open_file`](crate::io::open_file)
+    fn open(path: &str) -> io::Result<Self> {
+        let (format, compression) = infer_format_compression(path)?;
+        #[cfg(feature = "mzmlb")]
+        if format == MassSpectrometryFormat::MzMLb && !compression.is_compressed() {
+            return Ok(Self::MzMLb(Box::new(MzMLbReader::open_path(path)?)));
+        }
+        let handle: Box<dyn SeekRead> = if compression.is_compressed() {
+            Box::new(SeekableDecompressor::open_path(path)?)
+        } else {
+            Box::new(fs::File::open(path)?)
+        };
+        match format {
+            MassSpectrometryFormat::MzML => {
+                Ok(Self::MzML(Box::new(MzMLReaderType::new_indexed(handle))))
+            }
+            MassSpectrometryFormat::MGF => {
+                Ok(Self::Mgf(Box::new(MGFReaderType::new_indexed(handle))))
+            }
+            #[cfg(feature = "serde")]
+            MassSpectrometryFormat::JSONL => {
+                Ok(Self::Jsonl(Box::new(JSONLReaderType::new_indexed(handle))))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "File format not supported",
+            )),
+        }
+    }
+
+    fn source(&self) -> &dyn ScanSource {
+        match self {
+            Self::MzML(reader) => reader.as_ref(),
+            Self::Mgf(reader) => reader.as_ref(),
+            #[cfg(feature = "serde")]
+            Self::Jsonl(reader) => reader.as_ref(),
+            #[cfg(feature = "mzmlb")]
+            Self::MzMLb(reader) => reader.as_ref(),
+        }
+    }
+
+    fn source_mut(&mut self) -> &mut dyn ScanSource {
+        match self {
+            Self::MzML(reader) => reader.as_mut(),
+            Self::Mgf(reader) => reader.as_mut(),
+            #[cfg(feature = "serde")]
+            Self::Jsonl(reader) => reader.as_mut(),
+            #[cfg(feature = "mzmlb")]
+            Self::MzMLb(reader) => reader.as_mut(),
+        }
+    }
+
+    /// Read the next spectrum, recording the error and returning its status if it fails
+    fn try_next(&mut self) -> Result<Option<Spectrum>, MzDataStatus> {
+        match self {
+            Self::MzML(reader) => reader
+                .try_next()
+                .map_err(|err| fail((&err.source).into(), err)),
+            Self::Mgf(reader) => reader
+                .try_next()
+                .map_err(|err| fail((&err.source).into(), err)),
+            #[cfg(feature = "serde")]
+            Self::Jsonl(reader) => reader
+                .try_next()
+                .map_err(|err| fail((&err.source).into(), err)),
+            #[cfg(feature = "mzmlb")]
+            Self::MzMLb(reader) => reader
+                .try_next()
+                .map_err(|err| fail((&err.source).into(), err)),
+        }
+    }
+}
+
+/// An open spectrum source
+pub struct MzDataReader {
+    source: SourceReader,
+}
+
+/// A spectrum read from an [`MzDataReader`]
+pub struct MzDataSpectrum {
+    spectrum: Spectrum,
+    id: CString,
+}
+
+impl MzDataSpectrum {
+    fn new(spectrum: Spectrum) -> Self {
+        let id = CString::new(spectrum.id().replace('\0', " ")).unwrap_or_default();
+        Self { spectrum, id }
+    }
+}
+
+/// The first selected ion of a spectrum's precursor and its isolation window
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MzDataPrecursor {
+    pub mz: f64,
+    pub intensity: f32,
+    /// The charge state, or 0 if it is not known
+    pub charge: i32,
+    pub isolation_window_target: f32,
+    pub isolation_window_lower: f32,
+    pub isolation_window_upper: f32,
+}
+
+unsafe fn read_str<'a>(value: *const c_char) -> Result<&'a str, MzDataStatus> {
+    if value.is_null() {
+        return Err(fail(
+            MzDataStatus::NullPointer,
+            "A string argument was NULL",
+        ));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|err| fail(MzDataStatus::InvalidArgument, err))
+}
+
+unsafe fn emit_spectrum(
+    spectrum: Option<Spectrum>,
+    out: *mut *mut MzDataSpectrum,
+    missing: MzDataStatus,
+) -> MzDataStatus {
+    match spectrum {
+        Some(spectrum) => {
+            *out = Box::into_raw(Box::new(MzDataSpectrum::new(spectrum)));
+            MzDataStatus::Ok
+        }
+        None => {
+            *out = ptr::null_mut();
+            fail(missing, "No spectrum was found")
+        }
+    }
+}
+
+/// The message describing the last error on this thread, or `NULL` if there was none. The
+/// string is owned by the library and is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn mzdata_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match last.borrow().as_ref() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Open the file at `path`, inferring its format, and store the reader in `out_reader`.
+///
+/// # Safety
+/// `path` must be a NUL-terminated string and `out_reader` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_open(
+    path: *const c_char,
+    out_reader: *mut *mut MzDataReader,
+) -> MzDataStatus {
+    if out_reader.is_null() {
+        return fail(MzDataStatus::NullPointer, "out_reader was NULL");
+    }
+    *out_reader = ptr::null_mut();
+    let path = match read_str(path) {
+        Ok(path) => path,
+        Err(status) => return status,
+    };
+    guard(|| match SourceReader::open(path) {
+        Ok(source) => {
+            *out_reader = Box::into_raw(Box::new(MzDataReader { source }));
+            MzDataStatus::Ok
+        }
+        Err(err) => fail((&err).into(), err),
+    })
+}
+
+/// Close a reader opened with [`mzdata_reader_open`]. Passing `NULL` does nothing.
+///
+/// # Safety
+/// `reader` must be `NULL` or a reader which has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_free(reader: *mut MzDataReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Store the number of spectra in the file in `out_len`.
+///
+/// # Safety
+/// `reader` and `out_len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_len(
+    reader: *const MzDataReader,
+    out_len: *mut usize,
+) -> MzDataStatus {
+    if reader.is_null() || out_len.is_null() {
+        return fail(MzDataStatus::NullPointer, "reader or out_len was NULL");
+    }
+    guard(|| {
+        *out_len = (*reader).source.source().len();
+        MzDataStatus::Ok
+    })
+}
+
+/// Return the reader to the first spectrum.
+///
+/// # Safety
+/// `reader` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_reset(reader: *mut MzDataReader) -> MzDataStatus {
+    if reader.is_null() {
+        return fail(MzDataStatus::NullPointer, "reader was NULL");
+    }
+    guard(|| {
+        (*reader).source.source_mut().reset();
+        MzDataStatus::Ok
+    })
+}
+
+/// Read the next spectrum into `out_spectrum`, returning [`MzDataStatus::EndOfStream`] when
+/// there are no more. If the spectrum cannot be read, the status describes why, e.g.
+/// [`MzDataStatus::ParseError`] for a malformed or truncated file.
+///
+/// # Safety
+/// `reader` and `out_spectrum` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_next(
+    reader: *mut MzDataReader,
+    out_spectrum: *mut *mut MzDataSpectrum,
+) -> MzDataStatus {
+    if reader.is_null() || out_spectrum.is_null() {
+        return fail(MzDataStatus::NullPointer, "reader or out_spectrum was NULL");
+    }
+    guard(|| match (*reader).source.try_next() {
+        Ok(spectrum) => emit_spectrum(spectrum, out_spectrum, MzDataStatus::EndOfStream),
+        Err(status) => {
+            *out_spectrum = ptr::null_mut();
+            status
+        }
+    })
+}
+
+/// Read the spectrum at `index` into `out_spectrum`.
+///
+/// # Safety
+/// `reader` and `out_spectrum` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_get_by_index(
+    reader: *mut MzDataReader,
+    index: usize,
+    out_spectrum: *mut *mut MzDataSpectrum,
+) -> MzDataStatus {
+    if reader.is_null() || out_spectrum.is_null() {
+        return fail(MzDataStatus::NullPointer, "reader or out_spectrum was NULL");
+    }
+    guard(|| {
+        let spectrum = (*reader).source.source_mut().get_spectrum_by_index(index);
+        emit_spectrum(spectrum, out_spectrum, MzDataStatus::NotFound)
+    })
+}
+
+/// Read the spectrum with the native ID `id` into `out_spectrum`.
+///
+/// # Safety
+/// `reader` and `out_spectrum` must be valid pointers and `id` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_reader_get_by_id(
+    reader: *mut MzDataReader,
+    id: *const c_char,
+    out_spectrum: *mut *mut MzDataSpectrum,
+) -> MzDataStatus {
+    if reader.is_null() || out_spectrum.is_null() {
+        return fail(MzDataStatus::NullPointer, "reader or out_spectrum was NULL");
+    }
+    let id = match read_str(id) {
+        Ok(id) => id,
+        Err(status) => return status,
+    };
+    guard(|| {
+        // Not every reader tolerates an unknown ID, so check the index first
+        if (*reader).source.source().get_index().get(id).is_none() {
+            *out_spectrum = ptr::null_mut();
+            return fail(MzDataStatus::NotFound, format!("No spectrum with ID {id}"));
+        }
+        let spectrum = (*reader).source.source_mut().get_spectrum_by_id(id);
+        emit_spectrum(spectrum, out_spectrum, MzDataStatus::NotFound)
+    })
+}
+
+/// Free a spectrum. Passing `NULL` does nothing.
+///
+/// # Safety
+/// `spectrum` must be `NULL` or a spectrum which has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_free(spectrum: *mut MzDataSpectrum) {
+    if !spectrum.is_null() {
+        drop(Box::from_raw(spectrum));
+    }
+}
+
+/// The spectrum's native ID, valid until the spectrum is freed.
+///
+/// # Safety
+/// `spectrum` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_id(spectrum: *const MzDataSpectrum) -> *const c_char {
+    (*spectrum).id.as_ptr()
+}
+
+/// The spectrum's position in its file.
+///
+/// # Safety
+/// `spectrum` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_index(spectrum: *const MzDataSpectrum) -> usize {
+    (*spectrum).spectrum.index()
+}
+
+/// The spectrum's scan start time, in minutes.
+///
+/// # Safety
+/// `spectrum` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_time(spectrum: *const MzDataSpectrum) -> f64 {
+    (*spectrum).spectrum.start_time()
+}
+
+/// The spectrum's MS level.
+///
+/// # Safety
+/// `spectrum` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_ms_level(spectrum: *const MzDataSpectrum) -> u8 {
+    (*spectrum).spectrum.ms_level()
+}
+
+/// The spectrum's polarity, 1 for positive, -1 for negative and 0 if it is unknown.
+///
+/// # Safety
+/// `spectrum` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_polarity(spectrum: *const MzDataSpectrum) -> i8 {
+    (*spectrum).spectrum.polarity() as i8
+}
+
+/// Store the first selected ion of the spectrum's first precursor in `out_precursor`,
+/// returning [`MzDataStatus::NoPrecursor`] if there is no precursor or it has no selected ion.
+///
+/// # Safety
+/// `spectrum` and `out_precursor` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_precursor(
+    spectrum: *const MzDataSpectrum,
+    out_precursor: *mut MzDataPrecursor,
+) -> MzDataStatus {
+    if spectrum.is_null() || out_precursor.is_null() {
+        return fail(
+            MzDataStatus::NullPointer,
+            "spectrum or out_precursor was NULL",
+        );
+    }
+    guard(|| {
+        let precursor = match (*spectrum).spectrum.precursor() {
+            Some(precursor) => precursor,
+            None => return fail(MzDataStatus::NoPrecursor, "The spectrum has no precursor"),
+        };
+        let ion = match precursor.first_ion() {
+            Some(ion) => ion,
+            None => {
+                return fail(
+                    MzDataStatus::NoPrecursor,
+                    "The spectrum's precursor has no selected ion",
+                )
+            }
+        };
+        let window = &precursor.isolation_window;
+        *out_precursor = MzDataPrecursor {
+            mz: ion.mz,
+            intensity: ion.intensity,
+            charge: ion.charge.unwrap_or_default(),
+            isolation_window_target: window.target,
+            isolation_window_lower: window.lower_bound,
+            isolation_window_upper: window.upper_bound,
+        };
+        MzDataStatus::Ok
+    })
+}
+
+/// Decode the spectrum's m/z array and store a pointer to it and its length in `out_data`
+/// and `out_len`. The array is owned by the spectrum.
+///
+/// # Safety
+/// `spectrum`, `out_data` and `out_len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_mzs(
+    spectrum: *mut MzDataSpectrum,
+    out_data: *mut *const f64,
+    out_len: *mut usize,
+) -> MzDataStatus {
+    if spectrum.is_null() || out_data.is_null() || out_len.is_null() {
+        return fail(MzDataStatus::NullPointer, "A pointer argument was NULL");
+    }
+    guard(|| match (*spectrum).spectrum.arrays.as_mut() {
+        Some(arrays) => match arrays.mzs_mut() {
+            Ok(mzs) => {
+                *out_data = mzs.as_ptr();
+                *out_len = mzs.len();
+                MzDataStatus::Ok
+            }
+            Err(err) => fail((&err).into(), err),
+        },
+        None => fail(MzDataStatus::NotFound, "The spectrum has no data arrays"),
+    })
+}
+
+/// Decode the spectrum's intensity array and store a pointer to it and its length in
+/// `out_data` and `out_len`. The array is owned by the spectrum.
+///
+/// # Safety
+/// `spectrum`, `out_data` and `out_len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mzdata_spectrum_intensities(
+    spectrum: *mut MzDataSpectrum,
+    out_data: *mut *const f32,
+    out_len: *mut usize,
+) -> MzDataStatus {
+    if spectrum.is_null() || out_data.is_null() || out_len.is_null() {
+        return fail(MzDataStatus::NullPointer, "A pointer argument was NULL");
+    }
+    guard(|| match (*spectrum).spectrum.arrays.as_mut() {
+        Some(arrays) => match arrays.intensities_mut() {
+            Ok(intensities) => {
+                *out_data = intensities.as_ptr();
+                *out_len = intensities.len();
+                MzDataStatus::Ok
+            }
+            Err(err) => fail((&err).into(), err),
+        },
+        None => fail(MzDataStatus::NotFound, "The spectrum has no data arrays"),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_spectra() {
+        let path = CString::new("./test/data/read_index_of.mzML").unwrap();
+        unsafe {
+            let mut reader = ptr::null_mut();
+            assert_eq!(
+                mzdata_reader_open(path.as_ptr(), &mut reader),
+                MzDataStatus::Ok
+            );
+            let mut n = 0;
+            assert_eq!(mzdata_reader_len(reader, &mut n), MzDataStatus::Ok);
+            assert_eq!(n, 48);
+
+            let mut spectrum = ptr::null_mut();
+            assert_eq!(
+                mzdata_reader_get_by_index(reader, 10, &mut spectrum),
+                MzDataStatus::Ok
+            );
+            let id = CStr::from_ptr(mzdata_spectrum_id(spectrum));
+            assert_eq!(
+                id.to_str().unwrap(),
+                "controllerType=0 controllerNumber=1 scan=11"
+            );
+            assert_eq!(mzdata_spectrum_index(spectrum), 10);
+
+            let mut mzs = ptr::null();
+            let mut len = 0;
+            assert_eq!(
+                mzdata_spectrum_mzs(spectrum, &mut mzs, &mut len),
+                MzDataStatus::Ok
+            );
+            assert_eq!(len, 941);
+            let mut intensities = ptr::null();
+            let mut len2 = 0;
+            assert_eq!(
+                mzdata_spectrum_intensities(spectrum, &mut intensities, &mut len2),
+                MzDataStatus::Ok
+            );
+            assert_eq!(len, len2);
+            mzdata_spectrum_free(spectrum);
+
+            let mut precursor = MzDataPrecursor::default();
+            assert_eq!(
+                mzdata_reader_get_by_index(reader, 0, &mut spectrum),
+                MzDataStatus::Ok
+            );
+            assert_eq!(mzdata_spectrum_ms_level(spectrum), 1);
+            assert_eq!(
+                mzdata_spectrum_precursor(spectrum, &mut precursor),
+                MzDataStatus::NoPrecursor
+            );
+            mzdata_spectrum_free(spectrum);
+
+            assert_eq!(
+                mzdata_reader_get_by_index(reader, 2, &mut spectrum),
+                MzDataStatus::Ok
+            );
+            assert_eq!(
+                mzdata_spectrum_precursor(spectrum, &mut precursor),
+                MzDataStatus::Ok
+            );
+            assert!((precursor.mz - 810.79).abs() < 0.01);
+            (*spectrum).spectrum.precursor_mut().unwrap().ions.clear();
+            assert_eq!(
+                mzdata_spectrum_precursor(spectrum, &mut precursor),
+                MzDataStatus::NoPrecursor
+            );
+            mzdata_spectrum_free(spectrum);
+
+            let missing = CString::new("not a spectrum").unwrap();
+            assert_eq!(
+                mzdata_reader_get_by_id(reader, missing.as_ptr(), &mut spectrum),
+                MzDataStatus::NotFound
+            );
+            assert!(spectrum.is_null());
+            assert!(!mzdata_last_error_message().is_null());
+
+            let mut count = 0;
+            while mzdata_reader_next(reader, &mut spectrum) == MzDataStatus::Ok {
+                count += 1;
+                mzdata_spectrum_free(spectrum);
+            }
+            assert_eq!(count, 48);
+            mzdata_reader_free(reader);
+        }
+    }
+
+    #[test]
+    fn test_read_truncated() -> io::Result<()> {
+        // Cut the file off part way through its second spectrum
+        let content = fs::read("./test/data/three_test_scans.mzML")?;
+        let needle = b"<spectrum ";
+        let second = content
+            .windows(needle.len())
+            .enumerate()
+            .filter(|(_, window)| *window == needle)
+            .nth(1)
+            .unwrap()
+            .0;
+        let tmpdir = tempfile::tempdir()?;
+        let truncated = tmpdir.path().join("truncated.mzML");
+        fs::write(&truncated, &content[..second + 500])?;
+
+        let path = CString::new(truncated.to_str().unwrap()).unwrap();
+        unsafe {
+            let mut reader = ptr::null_mut();
+            assert_eq!(
+                mzdata_reader_open(path.as_ptr(), &mut reader),
+                MzDataStatus::Ok
+            );
+            let mut spectrum = ptr::null_mut();
+            assert_eq!(mzdata_reader_next(reader, &mut spectrum), MzDataStatus::Ok);
+            mzdata_spectrum_free(spectrum);
+            assert_eq!(
+                mzdata_reader_next(reader, &mut spectrum),
+                MzDataStatus::ParseError
+            );
+            assert!(spectrum.is_null());
+            let message = CStr::from_ptr(mzdata_last_error_message());
+            assert!(message.to_str().unwrap().contains("at byte"));
+            mzdata_reader_free(reader);
+        }
+        Ok(())
+    }
+}
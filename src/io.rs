@@ -3,29 +3,55 @@
 //! There are many data file formats for recording mass spectrometry data.
 //!
 
+mod filter;
 mod infer_format;
+#[cfg(feature = "serde")]
+pub mod jsonl;
 pub mod mgf;
 pub mod mzml;
 #[cfg(feature = "mzmlb")]
 pub mod mzmlb;
 mod offset_index;
+mod metadata_index;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 pub(crate) mod traits;
 mod utils;
 
 pub(crate) mod compression;
 
+pub use crate::io::filter::SpectrumFilter;
+pub use crate::io::compression::{
+    BgzfWriter, BlockCheckpoint, BlockDecoder, BlockIndex, CompressedWriter, CompressionType,
+    SeekableDecoder, SeekableDecompressor, SeekableGzDecoder, BGZF_BLOCK_SIZE,
+};
+#[cfg(feature = "zstd")]
+pub use crate::io::compression::{
+    SeekableZstdDecoder, ZstdSeekableWriter, ZSTD_SEEKABLE_FRAME_SIZE,
+};
+#[cfg(feature = "xz")]
+pub use crate::io::compression::SeekableXzDecoder;
 pub use crate::io::infer_format::{
-    infer_format, infer_from_path, infer_from_stream, open_file, MassSpectrometryFormat,
+    infer_format, infer_format_compression, infer_from_path, infer_from_path_compression,
+    infer_from_stream, infer_from_stream_compression, open_file, MassSpectrometryFormat,
 };
-pub use crate::io::mgf::{MGFError, MGFReader, MGFWriter};
+#[cfg(feature = "serde")]
+pub use crate::io::jsonl::{JSONLError, JSONLReadError, JSONLReader, JSONLWriter};
+pub use crate::io::mgf::{MGFError, MGFReadError, MGFReader, MGFWriter};
 #[cfg(feature = "async")]
 pub use crate::io::mzml::AsyncMzMLReader;
-pub use crate::io::mzml::{MzMLParserError, MzMLReader, MzMLWriter};
+pub use crate::io::mzml::{MzMLParserError, MzMLReadError, MzMLReader, MzMLWriter};
 #[cfg(feature = "mzmlb")]
-pub use crate::io::mzmlb::{MzMLbError, MzMLbReader};
+pub use crate::io::mzmlb::{MzMLbError, MzMLbReadError, MzMLbReader};
 pub use crate::io::offset_index::OffsetIndex;
+#[cfg(feature = "parquet")]
+pub use crate::io::parquet::{ParquetReader, ParquetTableError, ParquetWriter};
+pub use crate::io::metadata_index::{
+    MetadataQuery, SourceFingerprint, SpectrumMetadataIndex, SpectrumSummary,
+};
 pub use crate::io::traits::{
-    MZFileReader, RandomAccessSpectrumIterator, SpectrumAccessError, ScanSource, ScanWriter,
-    SpectrumGrouping, SpectrumIterator, StreamingSpectrumIterator,
+    MZFileReader, MemoryScanSource, RandomAccessSpectrumIterator, SpectrumAccessError, ScanSource,
+    ScanWriter, SkippedRegion, SpectrumGrouping, SpectrumIterator, SpectrumReadError,
+    StreamingSpectrumIterator, TryIterator, TryScanSource,
 };
 pub use crate::io::utils::{DetailLevel, PreBufferedStream};
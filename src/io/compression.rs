@@ -1,25 +1,149 @@
+use std::fmt::Display;
+use std::fs;
+use std::marker::PhantomData;
 use std::mem::swap;
 use std::{io, path};
 
-use flate2::bufread::MultiGzDecoder;
+use flate2::bufread::{GzDecoder, MultiGzDecoder};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use log::warn;
+use serde::{Deserialize, Serialize};
 use std::io::prelude::*;
 
+use super::utils::FileSource;
+
 pub fn is_gzipped(header: &[u8]) -> bool {
     header.starts_with(b"\x1f\x8b")
 }
 
-pub fn is_gzipped_extension(path: path::PathBuf) -> (bool, path::PathBuf) {
-    if let Some(ext) = path.extension() {
-        if ext.to_ascii_lowercase() == "gz" {
-            (true, path.with_extension(""))
+pub fn is_zstd(header: &[u8]) -> bool {
+    header.starts_with(b"\x28\xb5\x2f\xfd")
+}
+
+pub fn is_xz(header: &[u8]) -> bool {
+    header.starts_with(b"\xfd7zXZ\x00")
+}
+
+/// The whole-file compression schemes that can be detected and read transparently.
+///
+/// Detection works regardless of which features are enabled, but reading or writing
+/// zstd or xz requires the `zstd` or `xz` feature respectively.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressionType {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl CompressionType {
+    /// Detect the compression of a stream from its leading bytes
+    pub fn from_header(header: &[u8]) -> Self {
+        if is_gzipped(header) {
+            Self::Gzip
+        } else if is_zstd(header) {
+            Self::Zstd
+        } else if is_xz(header) {
+            Self::Xz
         } else {
-            (false, path)
+            Self::None
+        }
+    }
+
+    /// Detect the compression of a file from its extension, returning the path with
+    /// the compression extension removed
+    pub fn from_extension(path: path::PathBuf) -> (Self, path::PathBuf) {
+        let compression = match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("gz") => Self::Gzip,
+            Some("zst") | Some("zstd") => Self::Zstd,
+            Some("xz") => Self::Xz,
+            _ => return (Self::None, path),
+        };
+        (compression, path.with_extension(""))
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        !matches!(self, Self::None)
+    }
+
+    /// Whether this build of the library can read and write this compression
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Self::None | Self::Gzip => true,
+            Self::Zstd => cfg!(feature = "zstd"),
+            Self::Xz => cfg!(feature = "xz"),
         }
-    } else {
-        (false, path)
+    }
+
+    fn unsupported_error(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Support for {self} compression is not enabled, recompile with the `{self}` feature"),
+        )
+    }
+}
+
+impl Display for CompressionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::None => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Xz => "xz",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Wrap `reader` in a streaming decoder for `compression`
+pub(crate) fn decompressing_reader<'a, R: Read + 'a>(
+    reader: io::BufReader<R>,
+    compression: CompressionType,
+) -> io::Result<Box<dyn Read + 'a>> {
+    match compression {
+        CompressionType::None => Ok(Box::new(reader)),
+        CompressionType::Gzip => Ok(Box::new(MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        CompressionType::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        #[cfg(feature = "xz")]
+        CompressionType::Xz => Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))),
+        #[allow(unreachable_patterns)]
+        _ => Err(compression.unsupported_error()),
     }
 }
 
+/// Decompress up to `limit` bytes from the start of `stream`, for sniffing the format of
+/// a compressed file. Decoding errors, e.g. from a truncated stream, end the prefix early
+/// rather than failing.
+pub(crate) fn decompress_prefix<R: Read>(
+    stream: R,
+    compression: CompressionType,
+    limit: usize,
+) -> io::Result<Vec<u8>> {
+    // Keep read-ahead small so as to not overrun a partially buffered source
+    let reader = io::BufReader::with_capacity(4096, stream);
+    let mut decoder = decompressing_reader(reader, compression)?;
+    let mut buf = vec![0u8; limit];
+    let mut n = 0;
+    while n < limit {
+        match decoder.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    buf.truncate(n);
+    Ok(buf)
+}
+
 #[allow(unused)]
 pub struct RestartableGzDecoder<R: BufRead + Seek> {
     handle: Option<MultiGzDecoder<R>>,
@@ -96,3 +220,1166 @@ impl<R: BufRead + Seek> Seek for RestartableGzDecoder<R> {
         }
     }
 }
+
+/// The maximum number of uncompressed bytes stored in a single BGZF block, chosen
+/// so that a compressed block always fits in the 16-bit `BSIZE` field.
+pub const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block that terminates a BGZF stream
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+const BGZF_HEADER_SIZE: usize = 18;
+const GZIP_FOOTER_SIZE: usize = 8;
+const BGZF_MAX_BLOCK_SIZE: usize = 0x10000;
+
+/// Test whether `header` starts with a gzip member header carrying the BGZF `BC`
+/// extra subfield. If so, return the total size of the compressed block.
+fn bgzf_block_size(header: &[u8]) -> Option<usize> {
+    if header.len() < BGZF_HEADER_SIZE
+        || !is_gzipped(header)
+        || header[2] != 8
+        || header[3] & 4 == 0
+        || header[10..12] != [6, 0]
+        || header[12..16] != [b'B', b'C', 2, 0]
+    {
+        return None;
+    }
+    let bsize = u16::from_le_bytes([header[16], header[17]]);
+    Some(bsize as usize + 1)
+}
+
+/// A position in a compressed stream where decompression can begin, like the start of a
+/// gzip member or a zstd frame, along with the offset of that position in the decompressed stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockCheckpoint {
+    pub compressed_offset: u64,
+    pub uncompressed_offset: u64,
+}
+
+/**
+An index over the independently decompressible blocks of a compressed stream, mapping offsets
+in the decompressed stream to the block that contains them.
+
+BGZF files and seekable zstd files consist of many small blocks, so any offset can be reached
+by decompressing at most one block. An ordinary gzip, zstd or xz file written as a single block
+only has one checkpoint, at the start of the stream, so seeking in it still requires decompressing
+everything before the target offset.
+
+The index can be saved next to the compressed file, like an [`OffsetIndex`](crate::io::OffsetIndex),
+to avoid rescanning the file each time it is opened.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockIndex {
+    pub checkpoints: Vec<BlockCheckpoint>,
+    /// The total size of the decompressed stream
+    pub uncompressed_size: u64,
+}
+
+impl BlockIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an index with a single checkpoint at the start of the stream by decompressing
+    /// all of `decoder` to find its size.
+    #[cfg(any(feature = "zstd", feature = "xz"))]
+    fn single_block<D: Read>(mut decoder: D) -> io::Result<Self> {
+        let uncompressed_size = io::copy(&mut decoder, &mut io::sink())?;
+        let mut index = Self::new();
+        index.push(BlockCheckpoint::default());
+        index.uncompressed_size = uncompressed_size;
+        Ok(index)
+    }
+
+    /// Scan a gzip stream from the beginning, recording the start of each member.
+    ///
+    /// BGZF blocks record their own size, so they are skipped over without being
+    /// decompressed. Other members are decompressed to find where they end.
+    pub fn build_from_gzip<R: Read + Seek>(stream: &mut R) -> io::Result<Self> {
+        let mut index = Self::new();
+        let start = stream.stream_position()?;
+        stream.seek(io::SeekFrom::Start(0))?;
+        let mut reader = io::BufReader::new(stream);
+        let mut compressed_offset = 0u64;
+        let mut uncompressed_offset = 0u64;
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let member_start = compressed_offset;
+            let mut header = [0u8; BGZF_HEADER_SIZE];
+            let n = buf.len().min(BGZF_HEADER_SIZE);
+            header[..n].copy_from_slice(&buf[..n]);
+
+            let member_size = if let Some(block_size) = bgzf_block_size(&header) {
+                reader.seek(io::SeekFrom::Start(
+                    compressed_offset + (block_size - 4) as u64,
+                ))?;
+                let mut isize_buf = [0u8; 4];
+                reader.read_exact(&mut isize_buf)?;
+                compressed_offset += block_size as u64;
+                u32::from_le_bytes(isize_buf) as u64
+            } else if is_gzipped(&header) {
+                let mut decoder = GzDecoder::new(reader);
+                let member_size = io::copy(&mut decoder, &mut io::sink())?;
+                reader = decoder.into_inner();
+                compressed_offset = reader.stream_position()?;
+                member_size
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Expected a gzip member header",
+                ));
+            };
+            if member_size > 0 {
+                index.push(BlockCheckpoint {
+                    compressed_offset: member_start,
+                    uncompressed_offset,
+                });
+            }
+            uncompressed_offset += member_size;
+        }
+        index.uncompressed_size = uncompressed_offset;
+        let stream = reader.into_inner();
+        stream.seek(io::SeekFrom::Start(start))?;
+        Ok(index)
+    }
+
+    /// Build an index over a zstd stream from its seek table if it was written in the
+    /// seekable format, or with a single checkpoint otherwise.
+    #[cfg(feature = "zstd")]
+    pub fn build_from_zstd<R: Read + Seek>(stream: &mut R) -> io::Result<Self> {
+        if let Some(table) = ZstdSeekTable::read_from(stream)? {
+            return Ok(table.to_index());
+        }
+        let start = stream.stream_position()?;
+        stream.seek(io::SeekFrom::Start(0))?;
+        let index = Self::single_block(zstd::stream::read::Decoder::new(&mut *stream)?);
+        stream.seek(io::SeekFrom::Start(start))?;
+        index
+    }
+
+    /// Build an index over an xz stream. xz blocks are not indexed individually, so
+    /// this always has a single checkpoint.
+    #[cfg(feature = "xz")]
+    pub fn build_from_xz<R: Read + Seek>(stream: &mut R) -> io::Result<Self> {
+        let start = stream.stream_position()?;
+        stream.seek(io::SeekFrom::Start(0))?;
+        let index = Self::single_block(xz2::read::XzDecoder::new_multi_decoder(&mut *stream));
+        stream.seek(io::SeekFrom::Start(start))?;
+        index
+    }
+
+    /// Add a checkpoint to the end of the index
+    pub fn push(&mut self, checkpoint: BlockCheckpoint) {
+        self.checkpoints.push(checkpoint)
+    }
+
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, BlockCheckpoint> {
+        self.checkpoints.iter()
+    }
+
+    /// Whether this index permits seeking without decompressing from the start of the stream
+    pub fn is_random_access(&self) -> bool {
+        self.checkpoints.len() > 1
+    }
+
+    /// Find the last checkpoint at or before `uncompressed_offset`
+    pub fn checkpoint_for(&self, uncompressed_offset: u64) -> Option<&BlockCheckpoint> {
+        let i = self
+            .checkpoints
+            .partition_point(|c| c.uncompressed_offset <= uncompressed_offset);
+        if i == 0 {
+            None
+        } else {
+            self.checkpoints.get(i - 1)
+        }
+    }
+
+    pub fn to_writer<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Read the index saved next to the compressed file at `path` by [`BlockIndex::save_for_path`],
+    /// if there is one
+    pub fn load_for_path<P: Into<path::PathBuf>>(path: P) -> io::Result<Option<Self>> {
+        let source: FileSource<fs::File> = FileSource::from(path.into());
+        match source.block_index_file_name() {
+            Some(index_path) if index_path.exists() => {
+                let stream = io::BufReader::new(fs::File::open(index_path)?);
+                Ok(Some(Self::from_reader(stream)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Save the index next to the compressed file at `path`, where it will be found
+    /// when that file is opened again
+    pub fn save_for_path<P: Into<path::PathBuf>>(&self, path: P) -> io::Result<()> {
+        let source: FileSource<fs::File> = FileSource::from(path.into());
+        let index_path = source.block_index_file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot determine where to save the block index",
+            )
+        })?;
+        let stream = io::BufWriter::new(fs::File::create(index_path)?);
+        self.to_writer(stream)?;
+        Ok(())
+    }
+}
+
+/// A decompressing [`Read`] type which can be restarted at a [`BlockCheckpoint`] by
+/// handing back its underlying reader and being rebuilt over it.
+pub trait BlockDecoder<R: Read + Seek>: Read + Sized {
+    fn from_reader(reader: io::BufReader<R>) -> io::Result<Self>;
+
+    fn into_reader(self) -> io::BufReader<R>;
+
+    /// Scan `stream` to build the [`BlockIndex`] for this compression format
+    fn build_index(stream: &mut R) -> io::Result<BlockIndex>;
+}
+
+impl<R: Read + Seek> BlockDecoder<R> for MultiGzDecoder<io::BufReader<R>> {
+    fn from_reader(reader: io::BufReader<R>) -> io::Result<Self> {
+        Ok(MultiGzDecoder::new(reader))
+    }
+
+    fn into_reader(self) -> io::BufReader<R> {
+        self.into_inner()
+    }
+
+    fn build_index(stream: &mut R) -> io::Result<BlockIndex> {
+        BlockIndex::build_from_gzip(stream)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read + Seek> BlockDecoder<R> for zstd::stream::read::Decoder<'static, io::BufReader<R>> {
+    fn from_reader(reader: io::BufReader<R>) -> io::Result<Self> {
+        zstd::stream::read::Decoder::with_buffer(reader)
+    }
+
+    fn into_reader(self) -> io::BufReader<R> {
+        self.finish()
+    }
+
+    fn build_index(stream: &mut R) -> io::Result<BlockIndex> {
+        BlockIndex::build_from_zstd(stream)
+    }
+}
+
+#[cfg(feature = "xz")]
+impl<R: Read + Seek> BlockDecoder<R> for xz2::bufread::XzDecoder<io::BufReader<R>> {
+    fn from_reader(reader: io::BufReader<R>) -> io::Result<Self> {
+        Ok(xz2::bufread::XzDecoder::new_multi_decoder(reader))
+    }
+
+    fn into_reader(self) -> io::BufReader<R> {
+        self.into_inner()
+    }
+
+    fn build_index(stream: &mut R) -> io::Result<BlockIndex> {
+        BlockIndex::build_from_xz(stream)
+    }
+}
+
+/**
+A decoder which uses a [`BlockIndex`] to support [`Seek`] by decompressing from the nearest
+block before the requested offset instead of from the start of the stream.
+
+Offsets are always positions in the *decompressed* stream, so an [`OffsetIndex`](crate::io::OffsetIndex)
+built over this stream is interchangeable with one built over the uncompressed file.
+*/
+pub struct SeekableDecoder<R: Read + Seek, D: BlockDecoder<R>> {
+    handle: Option<D>,
+    index: BlockIndex,
+    offset: u64,
+    source_type: PhantomData<R>,
+}
+
+/// A gzip decoder supporting [`Seek`], see [`SeekableDecoder`]
+pub type SeekableGzDecoder<R> = SeekableDecoder<R, MultiGzDecoder<io::BufReader<R>>>;
+
+/// A zstd decoder supporting [`Seek`], see [`SeekableDecoder`]. Files written with
+/// [`ZstdSeekableWriter`] or other tools producing the seekable zstd format can be
+/// read at any offset by decompressing a single frame.
+#[cfg(feature = "zstd")]
+pub type SeekableZstdDecoder<R> =
+    SeekableDecoder<R, zstd::stream::read::Decoder<'static, io::BufReader<R>>>;
+
+/// An xz decoder supporting [`Seek`], see [`SeekableDecoder`]
+#[cfg(feature = "xz")]
+pub type SeekableXzDecoder<R> = SeekableDecoder<R, xz2::bufread::XzDecoder<io::BufReader<R>>>;
+
+impl<R: Read + Seek, D: BlockDecoder<R>> SeekableDecoder<R, D> {
+    /// Create a new decoder using a previously built [`BlockIndex`]
+    pub fn with_index(mut handle: R, index: BlockIndex) -> io::Result<Self> {
+        handle.seek(io::SeekFrom::Start(0))?;
+        Ok(Self {
+            handle: Some(D::from_reader(io::BufReader::new(handle))?),
+            index,
+            offset: 0,
+            source_type: PhantomData,
+        })
+    }
+
+    /// Create a new decoder, scanning the stream to build its [`BlockIndex`]
+    pub fn new(mut handle: R) -> io::Result<Self> {
+        let index = D::build_index(&mut handle)?;
+        Self::with_index(handle, index)
+    }
+
+    pub fn index(&self) -> &BlockIndex {
+        &self.index
+    }
+
+    pub fn into_inner(self) -> R {
+        self.handle.unwrap().into_reader().into_inner()
+    }
+
+    fn restart_at(&mut self, checkpoint: BlockCheckpoint) -> io::Result<()> {
+        let mut inner = self.handle.take().unwrap().into_reader();
+        let res = inner.seek(io::SeekFrom::Start(checkpoint.compressed_offset));
+        self.handle = Some(D::from_reader(inner)?);
+        self.offset = checkpoint.uncompressed_offset;
+        res.map(|_| ())
+    }
+
+    fn seek_to(&mut self, target: u64) -> io::Result<u64> {
+        let checkpoint = self
+            .index
+            .checkpoint_for(target)
+            .copied()
+            .unwrap_or_default();
+        // Only restart decompression if the target is behind us, or if there is a
+        // checkpoint between the current position and the target
+        if target < self.offset || checkpoint.uncompressed_offset > self.offset {
+            self.restart_at(checkpoint)?;
+        }
+        let remaining = target - self.offset;
+        io::copy(&mut self.by_ref().take(remaining), &mut io::sink())?;
+        Ok(self.offset)
+    }
+}
+
+impl<D: BlockDecoder<fs::File>> SeekableDecoder<fs::File, D> {
+    /// Open the compressed file at `path`, reading its [`BlockIndex`] from next to the file
+    /// if it was saved with [`BlockIndex::save_for_path`], or building it otherwise.
+    pub fn open_path<P: Into<path::PathBuf> + Clone>(path: P) -> io::Result<Self> {
+        let path: path::PathBuf = path.into();
+        let mut handle = fs::File::open(&path)?;
+        let index = match BlockIndex::load_for_path(path.clone()) {
+            Ok(Some(index)) => index,
+            Ok(None) => D::build_index(&mut handle)?,
+            Err(err) => {
+                warn!("Failed to read block index for {}: {err}", path.display());
+                D::build_index(&mut handle)?
+            }
+        };
+        Self::with_index(handle, index)
+    }
+}
+
+impl<R: Read + Seek, D: BlockDecoder<R>> Read for SeekableDecoder<R, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let b = self.handle.as_mut().unwrap().read(buf)?;
+        self.offset += b as u64;
+        Ok(b)
+    }
+}
+
+impl<R: Read + Seek, D: BlockDecoder<R>> Seek for SeekableDecoder<R, D> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            io::SeekFrom::Start(o) => o as i64,
+            io::SeekFrom::End(o) => self.index.uncompressed_size as i64 + o,
+            io::SeekFrom::Current(o) => self.offset as i64 + o,
+        };
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot seek before the start of the stream",
+            ));
+        }
+        self.seek_to(target as u64)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+/**
+A writer which compresses its input as a series of independent gzip members of at most
+[`BGZF_BLOCK_SIZE`] bytes each, following the BGZF convention used by `bgzip` and `samtools`.
+
+The output is a valid gzip file that any gzip reader can decompress, but because each block
+records its own compressed size, a [`BlockIndex`] can be built over it without
+decompressing it, and any offset can be reached by decompressing a single block. The writer
+also builds the index as it goes, available from [`BgzfWriter::index`].
+*/
+pub struct BgzfWriter<W: Write> {
+    handle: Option<W>,
+    buffer: Vec<u8>,
+    level: Compression,
+    index: BlockIndex,
+    compressed_offset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(handle: W) -> Self {
+        Self::with_level(handle, Compression::default())
+    }
+
+    pub fn with_level(handle: W, level: Compression) -> Self {
+        Self {
+            handle: Some(handle),
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+            level,
+            index: BlockIndex::new(),
+            compressed_offset: 0,
+        }
+    }
+
+    /// The index of the blocks written so far. It does not include data still buffered
+    /// until the next block is full or the writer is finished.
+    pub fn index(&self) -> &BlockIndex {
+        &self.index
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.handle.as_ref().unwrap()
+    }
+
+    fn compress_block(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+        let mut encoder = DeflateEncoder::new(
+            Vec::with_capacity(data.len() + BGZF_HEADER_SIZE + GZIP_FOOTER_SIZE),
+            level,
+        );
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    fn write_block(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut compressed = Self::compress_block(data, self.level)?;
+        if compressed.len() + BGZF_HEADER_SIZE + GZIP_FOOTER_SIZE > BGZF_MAX_BLOCK_SIZE {
+            // Incompressible data may grow slightly, but stored blocks never overflow
+            compressed = Self::compress_block(data, Compression::none())?;
+        }
+        let block_size = compressed.len() + BGZF_HEADER_SIZE + GZIP_FOOTER_SIZE;
+        let mut crc = Crc::new();
+        crc.update(data);
+
+        let handle = self.handle.as_mut().unwrap();
+        handle.write_all(&[
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ])?;
+        handle.write_all(&((block_size - 1) as u16).to_le_bytes())?;
+        handle.write_all(&compressed)?;
+        handle.write_all(&crc.sum().to_le_bytes())?;
+        handle.write_all(&(data.len() as u32).to_le_bytes())?;
+
+        self.index.push(BlockCheckpoint {
+            compressed_offset: self.compressed_offset,
+            uncompressed_offset: self.index.uncompressed_size,
+        });
+        self.index.uncompressed_size += data.len() as u64;
+        self.compressed_offset += block_size as u64;
+        Ok(())
+    }
+
+    fn write_buffered(&mut self) -> io::Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let res = self.write_block(&buffer);
+        self.buffer = buffer;
+        self.buffer.clear();
+        res
+    }
+
+    /// Write any buffered data and the terminating empty block, and return the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.handle.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.write_buffered()?;
+        let handle = self.handle.as_mut().unwrap();
+        handle.write_all(&BGZF_EOF)?;
+        handle.flush()?;
+        self.compressed_offset += BGZF_EOF.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_buffered()?;
+        }
+        Ok(n)
+    }
+
+    /// Flushes the blocks written so far. Data which does not fill a block yet stays
+    /// buffered, so flushing does not produce short blocks.
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+const ZSTD_SKIPPABLE_MAGIC: u32 = 0x184D2A5E;
+#[cfg(feature = "zstd")]
+const ZSTD_SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+#[cfg(feature = "zstd")]
+const ZSTD_SEEK_TABLE_FOOTER_SIZE: u64 = 9;
+
+/// The default number of uncompressed bytes stored in each frame written by [`ZstdSeekableWriter`]
+#[cfg(feature = "zstd")]
+pub const ZSTD_SEEKABLE_FRAME_SIZE: usize = 1 << 18;
+
+#[cfg(feature = "zstd")]
+fn le_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+/// The table of frame sizes stored at the end of a file in the seekable zstd format,
+/// wrapped in a skippable frame so that ordinary zstd decoders ignore it.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ZstdSeekTable {
+    /// The compressed and decompressed size of each frame
+    frames: Vec<(u32, u32)>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdSeekTable {
+    /// Read the seek table from the end of `stream` if it has one, restoring the
+    /// stream position afterwards.
+    fn read_from<R: Read + Seek>(stream: &mut R) -> io::Result<Option<Self>> {
+        let start = stream.stream_position()?;
+        let end = stream.seek(io::SeekFrom::End(0))?;
+        let table = Self::read_before(stream, end);
+        stream.seek(io::SeekFrom::Start(start))?;
+        table
+    }
+
+    fn read_before<R: Read + Seek>(stream: &mut R, end: u64) -> io::Result<Option<Self>> {
+        if end < ZSTD_SEEK_TABLE_FOOTER_SIZE + 8 {
+            return Ok(None);
+        }
+        stream.seek(io::SeekFrom::Start(end - ZSTD_SEEK_TABLE_FOOTER_SIZE))?;
+        let mut footer = [0u8; ZSTD_SEEK_TABLE_FOOTER_SIZE as usize];
+        stream.read_exact(&mut footer)?;
+        if le_u32(&footer[5..]) != ZSTD_SEEKABLE_MAGIC {
+            return Ok(None);
+        }
+
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let n_frames = le_u32(&footer[..4]) as u64;
+        let descriptor = footer[4];
+        if descriptor & 0x7c != 0 {
+            return Err(invalid("Reserved bits set in zstd seek table descriptor"));
+        }
+        // Entries carry a trailing checksum when the high bit is set
+        let entry_size = if descriptor & 0x80 != 0 { 12 } else { 8 };
+        let table_size = n_frames * entry_size + ZSTD_SEEK_TABLE_FOOTER_SIZE;
+        if table_size + 8 > end {
+            return Err(invalid("zstd seek table is larger than the stream"));
+        }
+
+        stream.seek(io::SeekFrom::Start(end - table_size - 8))?;
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header)?;
+        if le_u32(&header[..4]) != ZSTD_SKIPPABLE_MAGIC || le_u32(&header[4..]) as u64 != table_size
+        {
+            return Err(invalid("Malformed zstd seek table frame"));
+        }
+        let mut entries = vec![0u8; (n_frames * entry_size) as usize];
+        stream.read_exact(&mut entries)?;
+        let frames = entries
+            .chunks_exact(entry_size as usize)
+            .map(|entry| (le_u32(&entry[..4]), le_u32(&entry[4..8])))
+            .collect();
+        Ok(Some(Self { frames }))
+    }
+
+    fn to_index(&self) -> BlockIndex {
+        let mut index = BlockIndex::new();
+        let mut compressed_offset = 0u64;
+        for (compressed_size, decompressed_size) in self.frames.iter().copied() {
+            if decompressed_size > 0 {
+                index.push(BlockCheckpoint {
+                    compressed_offset,
+                    uncompressed_offset: index.uncompressed_size,
+                });
+            }
+            compressed_offset += compressed_size as u64;
+            index.uncompressed_size += decompressed_size as u64;
+        }
+        index
+    }
+
+    /// Write the seek table as a skippable frame, without frame checksums
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let table_size = self.frames.len() as u32 * 8 + ZSTD_SEEK_TABLE_FOOTER_SIZE as u32;
+        writer.write_all(&ZSTD_SKIPPABLE_MAGIC.to_le_bytes())?;
+        writer.write_all(&table_size.to_le_bytes())?;
+        for (compressed_size, decompressed_size) in self.frames.iter() {
+            writer.write_all(&compressed_size.to_le_bytes())?;
+            writer.write_all(&decompressed_size.to_le_bytes())?;
+        }
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        writer.write_all(&[0])?;
+        writer.write_all(&ZSTD_SEEKABLE_MAGIC.to_le_bytes())
+    }
+}
+
+/**
+A writer which compresses its input as a series of independent zstd frames followed by a
+seek table, following the seekable zstd format from zstd's `contrib/seekable_format`.
+
+The output can be decompressed by any zstd decoder, but a [`SeekableZstdDecoder`] reads the
+seek table to reach any offset by decompressing a single frame. The writer also builds the
+[`BlockIndex`] as it goes, available from [`ZstdSeekableWriter::index`].
+*/
+#[cfg(feature = "zstd")]
+pub struct ZstdSeekableWriter<W: Write> {
+    handle: Option<W>,
+    buffer: Vec<u8>,
+    frame_size: usize,
+    level: i32,
+    seek_table: ZstdSeekTable,
+    index: BlockIndex,
+    compressed_offset: u64,
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> ZstdSeekableWriter<W> {
+    pub fn new(handle: W) -> Self {
+        Self::with_level(handle, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    pub fn with_level(handle: W, level: i32) -> Self {
+        Self::with_level_and_frame_size(handle, level, ZSTD_SEEKABLE_FRAME_SIZE)
+    }
+
+    /// Create a writer storing `frame_size` uncompressed bytes per frame. Smaller frames
+    /// make seeking cheaper at the cost of compression ratio.
+    ///
+    /// # Panics
+    /// If `frame_size` is zero or larger than the 1 GiB the seekable format permits
+    pub fn with_level_and_frame_size(handle: W, level: i32, frame_size: usize) -> Self {
+        assert!(
+            frame_size > 0 && frame_size <= 1 << 30,
+            "zstd seekable frame size must be between 1 byte and 1 GiB"
+        );
+        Self {
+            handle: Some(handle),
+            buffer: Vec::with_capacity(frame_size),
+            frame_size,
+            level,
+            seek_table: ZstdSeekTable::default(),
+            index: BlockIndex::new(),
+            compressed_offset: 0,
+        }
+    }
+
+    /// The index of the frames written so far. It does not include data still buffered
+    /// until the next frame is full or the writer is finished.
+    pub fn index(&self) -> &BlockIndex {
+        &self.index
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.handle.as_ref().unwrap()
+    }
+
+    fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let compressed = zstd::bulk::compress(data, self.level)?;
+        self.handle.as_mut().unwrap().write_all(&compressed)?;
+
+        self.seek_table
+            .frames
+            .push((compressed.len() as u32, data.len() as u32));
+        self.index.push(BlockCheckpoint {
+            compressed_offset: self.compressed_offset,
+            uncompressed_offset: self.index.uncompressed_size,
+        });
+        self.index.uncompressed_size += data.len() as u64;
+        self.compressed_offset += compressed.len() as u64;
+        Ok(())
+    }
+
+    fn write_buffered(&mut self) -> io::Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let res = self.write_frame(&buffer);
+        self.buffer = buffer;
+        self.buffer.clear();
+        res
+    }
+
+    /// Write any buffered data and the seek table, and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.handle.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.write_buffered()?;
+        let handle = self.handle.as_mut().unwrap();
+        self.seek_table.write_to(handle)?;
+        handle.flush()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Write for ZstdSeekableWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.frame_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == self.frame_size {
+            self.write_buffered()?;
+        }
+        Ok(n)
+    }
+
+    /// Flushes the frames written so far. Data which does not fill a frame yet stays
+    /// buffered, so flushing does not produce short frames.
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.as_mut().unwrap().flush()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Drop for ZstdSeekableWriter<W> {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/**
+A [`Write`] type which compresses its output using a [`CompressionType`] chosen at runtime.
+
+Gzip is written as BGZF and zstd in the seekable format, so those files can be read back with
+cheap random access through a [`SeekableDecompressor`]. The writers in this crate accept it like
+any other [`Write`] type, so this is how to write compressed files directly:
+
+```no_run
+# use std::io;
+use mzdata::prelude::*;
+use mzdata::io::{CompressedWriter, MzMLReader, MzMLWriter};
+
+# fn main() -> io::Result<()> {
+let mut reader = MzMLReader::open_path("./test/data/small.mzML")?;
+// The compression is chosen by the file extension
+let mut writer = MzMLWriter::new(CompressedWriter::create_path("small.mzML.gz")?);
+writer.copy_metadata_from(&reader);
+for spectrum in reader.iter() {
+    writer.write_spectrum(&spectrum)?;
+}
+writer.close()?;
+writer.into_inner()?.finish()?;
+# Ok(())
+# }
+```
+
+Dropping the writer also finishes the compressed stream, but any error is then lost.
+*/
+pub enum CompressedWriter<W: Write> {
+    Plain(W),
+    Gzip(BgzfWriter<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdSeekableWriter<W>),
+    #[cfg(feature = "xz")]
+    Xz(xz2::write::XzEncoder<W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(handle: W, compression: CompressionType) -> io::Result<Self> {
+        match compression {
+            CompressionType::None => Ok(Self::Plain(handle)),
+            CompressionType::Gzip => Ok(Self::Gzip(BgzfWriter::new(handle))),
+            #[cfg(feature = "zstd")]
+            CompressionType::Zstd => Ok(Self::Zstd(ZstdSeekableWriter::new(handle))),
+            #[cfg(feature = "xz")]
+            CompressionType::Xz => Ok(Self::Xz(xz2::write::XzEncoder::new(handle, 6))),
+            #[allow(unreachable_patterns)]
+            _ => Err(compression.unsupported_error()),
+        }
+    }
+
+    pub fn compression(&self) -> CompressionType {
+        match self {
+            Self::Plain(_) => CompressionType::None,
+            Self::Gzip(_) => CompressionType::Gzip,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => CompressionType::Zstd,
+            #[cfg(feature = "xz")]
+            Self::Xz(_) => CompressionType::Xz,
+        }
+    }
+
+    /// Finish the compressed stream and return the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(mut handle) => {
+                handle.flush()?;
+                Ok(handle)
+            }
+            Self::Gzip(handle) => handle.finish(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.finish(),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.finish(),
+        }
+    }
+}
+
+impl CompressedWriter<io::BufWriter<fs::File>> {
+    /// Create the file at `path`, compressing it according to its extension
+    pub fn create_path<P: Into<path::PathBuf>>(path: P) -> io::Result<Self> {
+        let path: path::PathBuf = path.into();
+        let (compression, _) = CompressionType::from_extension(path.clone());
+        if !compression.is_supported() {
+            return Err(compression.unsupported_error());
+        }
+        Self::new(io::BufWriter::new(fs::File::create(path)?), compression)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(handle) => handle.write(buf),
+            Self::Gzip(handle) => handle.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.write(buf),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(handle) => handle.flush(),
+            Self::Gzip(handle) => handle.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.flush(),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.flush(),
+        }
+    }
+}
+
+/// A [`SeekableDecoder`] for whichever of the supported compression formats a stream
+/// uses, detected from its leading bytes.
+pub enum SeekableDecompressor<R: Read + Seek> {
+    Gzip(SeekableGzDecoder<R>),
+    #[cfg(feature = "zstd")]
+    Zstd(SeekableZstdDecoder<R>),
+    #[cfg(feature = "xz")]
+    Xz(SeekableXzDecoder<R>),
+}
+
+fn detect_compression<R: Read + Seek>(handle: &mut R) -> io::Result<CompressionType> {
+    let start = handle.stream_position()?;
+    let mut header = Vec::with_capacity(6);
+    handle.by_ref().take(6).read_to_end(&mut header)?;
+    handle.seek(io::SeekFrom::Start(start))?;
+    match CompressionType::from_header(&header) {
+        CompressionType::None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The stream is not compressed with a recognized format",
+        )),
+        compression if !compression.is_supported() => Err(compression.unsupported_error()),
+        compression => Ok(compression),
+    }
+}
+
+impl<R: Read + Seek> SeekableDecompressor<R> {
+    pub fn new(mut handle: R) -> io::Result<Self> {
+        match detect_compression(&mut handle)? {
+            #[cfg(feature = "zstd")]
+            CompressionType::Zstd => Ok(Self::Zstd(SeekableZstdDecoder::new(handle)?)),
+            #[cfg(feature = "xz")]
+            CompressionType::Xz => Ok(Self::Xz(SeekableXzDecoder::new(handle)?)),
+            _ => Ok(Self::Gzip(SeekableGzDecoder::new(handle)?)),
+        }
+    }
+
+    pub fn compression(&self) -> CompressionType {
+        match self {
+            Self::Gzip(_) => CompressionType::Gzip,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => CompressionType::Zstd,
+            #[cfg(feature = "xz")]
+            Self::Xz(_) => CompressionType::Xz,
+        }
+    }
+
+    pub fn index(&self) -> &BlockIndex {
+        match self {
+            Self::Gzip(handle) => handle.index(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.index(),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.index(),
+        }
+    }
+}
+
+impl SeekableDecompressor<fs::File> {
+    /// Open the compressed file at `path`, using [`SeekableDecoder::open_path`] to load
+    /// or save its [`BlockIndex`]
+    pub fn open_path<P: Into<path::PathBuf> + Clone>(path: P) -> io::Result<Self> {
+        let mut handle = fs::File::open(path.clone().into())?;
+        match detect_compression(&mut handle)? {
+            #[cfg(feature = "zstd")]
+            CompressionType::Zstd => Ok(Self::Zstd(SeekableZstdDecoder::open_path(path)?)),
+            #[cfg(feature = "xz")]
+            CompressionType::Xz => Ok(Self::Xz(SeekableXzDecoder::open_path(path)?)),
+            _ => Ok(Self::Gzip(SeekableGzDecoder::open_path(path)?)),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableDecompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Gzip(handle) => handle.read(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.read(buf),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.read(buf),
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableDecompressor<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Gzip(handle) => handle.seek(pos),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.seek(pos),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.seek(pos),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Gzip(handle) => handle.stream_position(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(handle) => handle.stream_position(),
+            #[cfg(feature = "xz")]
+            Self::Xz(handle) => handle.stream_position(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+
+    fn make_text() -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..50000 {
+            writeln!(text, "<spectrum index=\"{i}\" id=\"scan={i}\">").unwrap();
+        }
+        text
+    }
+
+    fn check_seeks<R: Read + Seek>(decoder: &mut R, text: &[u8]) {
+        for offset in [text.len() - 100, 100, 250000, 70000, 0].iter().copied() {
+            decoder.seek(io::SeekFrom::Start(offset as u64)).unwrap();
+            let mut buf = [0u8; 64];
+            decoder.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf[..], &text[offset..offset + 64]);
+        }
+        decoder.seek(io::SeekFrom::End(-10)).unwrap();
+        let mut buf = Vec::new();
+        decoder.read_to_end(&mut buf).unwrap();
+        assert_eq!(&buf[..], &text[text.len() - 10..]);
+    }
+
+    #[test]
+    fn test_bgzf_roundtrip() -> io::Result<()> {
+        let text = make_text();
+        let mut writer = BgzfWriter::new(io::Cursor::new(Vec::new()));
+        writer.write_all(&text)?;
+        writer.flush()?;
+        let written_index = writer.index().clone();
+        assert_eq!(written_index.len(), text.len() / BGZF_BLOCK_SIZE);
+        let buf = writer.finish()?.into_inner();
+        assert!(bgzf_block_size(&buf).is_some());
+
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(buf.as_slice()).read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, text);
+
+        let mut stream = io::Cursor::new(buf);
+        let index = BlockIndex::build_from_gzip(&mut stream)?;
+        assert!(index.is_random_access());
+        assert_eq!(index.len(), text.len().div_ceil(BGZF_BLOCK_SIZE));
+        assert_eq!(index.uncompressed_size, text.len() as u64);
+        assert_eq!(
+            &index.checkpoints[..written_index.len()],
+            &written_index.checkpoints[..]
+        );
+
+        let mut decoder = SeekableGzDecoder::with_index(stream, index)?;
+        check_seeks(&mut decoder, &text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_member_gzip() -> io::Result<()> {
+        let text = make_text();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&text)?;
+        let buf = encoder.finish()?;
+        assert!(bgzf_block_size(&buf).is_none());
+
+        let mut decoder = SeekableGzDecoder::new(io::Cursor::new(buf))?;
+        assert_eq!(decoder.index().len(), 1);
+        assert!(!decoder.index().is_random_access());
+        check_seeks(&mut decoder, &text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_saved_index() -> io::Result<()> {
+        let text = make_text();
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("text.txt.gz");
+        let mut writer = BgzfWriter::new(fs::File::create(&path)?);
+        writer.write_all(&text)?;
+        writer.finish()?;
+
+        // Opening a file must not write anything next to it
+        let decoder = SeekableGzDecoder::open_path(path.clone())?;
+        assert!(BlockIndex::load_for_path(path.clone())?.is_none());
+
+        decoder.index().save_for_path(path.clone())?;
+        let saved = BlockIndex::load_for_path(path.clone())?;
+        assert_eq!(saved.as_ref(), Some(decoder.index()));
+
+        let mut decoder = SeekableGzDecoder::open_path(path)?;
+        check_seeks(&mut decoder, &text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        let path = path::PathBuf::from("small.mzML.zst");
+        let (compression, path) = CompressionType::from_extension(path);
+        assert_eq!(compression, CompressionType::Zstd);
+        assert_eq!(path, path::PathBuf::from("small.mzML"));
+        let (compression, _) = CompressionType::from_extension(path);
+        assert_eq!(compression, CompressionType::None);
+
+        assert_eq!(
+            CompressionType::from_header(b"\x1f\x8b\x08"),
+            CompressionType::Gzip
+        );
+        assert_eq!(
+            CompressionType::from_header(b"\xfd7zXZ\x00\x00"),
+            CompressionType::Xz
+        );
+        assert_eq!(
+            CompressionType::from_header(b"<?xml"),
+            CompressionType::None
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_seekable_roundtrip() -> io::Result<()> {
+        let text = make_text();
+        let mut writer =
+            ZstdSeekableWriter::with_level_and_frame_size(io::Cursor::new(Vec::new()), 3, 1 << 16);
+        writer.write_all(&text)?;
+        writer.flush()?;
+        let written_index = writer.index().clone();
+        assert_eq!(written_index.len(), text.len() / (1 << 16));
+        let buf = writer.finish()?.into_inner();
+        assert!(is_zstd(&buf));
+
+        // The seek table is a skippable frame, so plain decoders ignore it
+        assert_eq!(zstd::decode_all(buf.as_slice())?, text);
+
+        let mut stream = io::Cursor::new(buf);
+        let index = BlockIndex::build_from_zstd(&mut stream)?;
+        assert!(index.is_random_access());
+        assert_eq!(
+            &index.checkpoints[..written_index.len()],
+            &written_index.checkpoints[..]
+        );
+
+        let mut decoder = SeekableDecompressor::new(stream)?;
+        assert_eq!(decoder.compression(), CompressionType::Zstd);
+        check_seeks(&mut decoder, &text);
+
+        let buf = zstd::encode_all(text.as_slice(), 3)?;
+        let mut decoder = SeekableZstdDecoder::new(io::Cursor::new(buf))?;
+        assert!(!decoder.index().is_random_access());
+        check_seeks(&mut decoder, &text);
+        Ok(())
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn test_xz_roundtrip() -> io::Result<()> {
+        let text = make_text();
+        let mut writer = CompressedWriter::new(Vec::new(), CompressionType::Xz)?;
+        writer.write_all(&text)?;
+        let buf = writer.finish()?;
+        assert_eq!(CompressionType::from_header(&buf), CompressionType::Xz);
+
+        let prefix = decompress_prefix(&buf[..512], CompressionType::Xz, 100)?;
+        assert_eq!(prefix, &text[..100]);
+
+        let mut decoder = SeekableDecompressor::new(io::Cursor::new(buf))?;
+        assert_eq!(decoder.index().uncompressed_size, text.len() as u64);
+        check_seeks(&mut decoder, &text);
+        Ok(())
+    }
+}
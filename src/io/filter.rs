@@ -0,0 +1,222 @@
+//! A predicate over spectrum metadata that readers can evaluate before
+//! reading a spectrum's peak data.
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::spectrum::{ScanPolarity, SpectrumDescription};
+
+type DescriptionPredicate = Arc<dyn Fn(&SpectrumDescription) -> bool + Send + Sync>;
+
+/// A set of conditions on a spectrum's metadata that a reader checks as soon as
+/// the metadata has been parsed.
+///
+/// Spectra which do not satisfy every condition are skipped without reading or
+/// decoding their data arrays. An empty filter accepts every spectrum.
+///
+/// Readers only apply the filter while iterating. A spectrum requested directly, as with
+/// [`ScanSource::get_spectrum_by_id`](crate::io::ScanSource::get_spectrum_by_id), is
+/// returned even if the filter would reject it.
+///
+/// ```rust
+/// use mzdata::io::SpectrumFilter;
+/// use mzdata::spectrum::ScanPolarity;
+///
+/// let filter = SpectrumFilter::new()
+///     .ms_level(2)
+///     .time_range(10.0..=20.0)
+///     .polarity(ScanPolarity::Positive);
+/// assert!(!filter.is_empty());
+/// ```
+#[derive(Clone, Default)]
+pub struct SpectrumFilter {
+    ms_levels: Option<RangeInclusive<u8>>,
+    time_range: Option<RangeInclusive<f64>>,
+    polarity: Option<ScanPolarity>,
+    precursor_mz_range: Option<RangeInclusive<f64>>,
+    compensation_voltage: Option<f32>,
+    predicate: Option<DescriptionPredicate>,
+}
+
+impl Debug for SpectrumFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpectrumFilter")
+            .field("ms_levels", &self.ms_levels)
+            .field("time_range", &self.time_range)
+            .field("polarity", &self.polarity)
+            .field("precursor_mz_range", &self.precursor_mz_range)
+            .field("compensation_voltage", &self.compensation_voltage)
+            .field("predicate", &self.predicate.as_ref().map(|_| "..."))
+            .finish()
+    }
+}
+
+impl SpectrumFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept only spectra with exactly this MS level
+    pub fn ms_level(self, ms_level: u8) -> Self {
+        self.ms_levels(ms_level..=ms_level)
+    }
+
+    /// Accept only spectra whose MS level falls within `levels`
+    pub fn ms_levels(mut self, levels: RangeInclusive<u8>) -> Self {
+        self.ms_levels = Some(levels);
+        self
+    }
+
+    /// Accept only spectra acquired within `range`, in minutes
+    pub fn time_range(mut self, range: RangeInclusive<f64>) -> Self {
+        self.time_range = Some(range);
+        self
+    }
+
+    pub fn polarity(mut self, polarity: ScanPolarity) -> Self {
+        self.polarity = Some(polarity);
+        self
+    }
+
+    /// Accept only spectra with a precursor whose selected ion m/z falls within `range`
+    pub fn precursor_mz_range(mut self, range: RangeInclusive<f64>) -> Self {
+        self.precursor_mz_range = Some(range);
+        self
+    }
+
+    /// Accept only spectra acquired at this FAIMS compensation voltage
+    pub fn compensation_voltage(mut self, compensation_voltage: f32) -> Self {
+        self.compensation_voltage = Some(compensation_voltage);
+        self
+    }
+
+    /// Add an arbitrary condition on the [`SpectrumDescription`]. It is evaluated after
+    /// all of the other conditions pass.
+    ///
+    /// The description will not yet have any data arrays attached when this is called.
+    pub fn predicate<F: Fn(&SpectrumDescription) -> bool + Send + Sync + 'static>(
+        mut self,
+        predicate: F,
+    ) -> Self {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Whether no conditions have been set
+    pub fn is_empty(&self) -> bool {
+        self.ms_levels.is_none()
+            && self.time_range.is_none()
+            && self.polarity.is_none()
+            && self.precursor_mz_range.is_none()
+            && self.compensation_voltage.is_none()
+            && self.predicate.is_none()
+    }
+
+    /// Test whether `description` satisfies all of the conditions of this filter
+    pub fn accepts(&self, description: &SpectrumDescription) -> bool {
+        if let Some(levels) = self.ms_levels.as_ref() {
+            if !levels.contains(&description.ms_level) {
+                return false;
+            }
+        }
+        if let Some(range) = self.time_range.as_ref() {
+            match description.acquisition.first_scan() {
+                Some(scan) if range.contains(&scan.start_time) => {}
+                _ => return false,
+            }
+        }
+        if let Some(polarity) = self.polarity {
+            if description.polarity != polarity {
+                return false;
+            }
+        }
+        if let Some(range) = self.precursor_mz_range.as_ref() {
+            // Multiplexed spectra are accepted if any of their selected ions are in range
+            let in_range = description
+                .precursors
+                .iter()
+                .flat_map(|prec| prec.ions.iter())
+                .any(|ion| range.contains(&ion.mz));
+            if !in_range {
+                return false;
+            }
+        }
+        if let Some(cv) = self.compensation_voltage {
+            let scan_cv = description
+                .acquisition
+                .first_scan()
+                .and_then(|scan| scan.compensation_voltage);
+            if scan_cv != Some(cv) {
+                return false;
+            }
+        }
+        if let Some(predicate) = self.predicate.as_ref() {
+            if !predicate(description) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::{Precursor, ScanEvent, SelectedIon};
+
+    fn make_description(ms_level: u8, time: f64, polarity: ScanPolarity) -> SpectrumDescription {
+        let mut description = SpectrumDescription {
+            ms_level,
+            polarity,
+            ..Default::default()
+        };
+        description.acquisition.scans.push(ScanEvent {
+            start_time: time,
+            ..Default::default()
+        });
+        if ms_level > 1 {
+            let mut prec = Precursor::default();
+            prec.add_ion(SelectedIon {
+                mz: 500.0,
+                ..Default::default()
+            });
+            description.precursors.push(prec);
+        }
+        description
+    }
+
+    #[test]
+    fn test_filter() {
+        let ms1 = make_description(1, 5.0, ScanPolarity::Positive);
+        let ms2 = make_description(2, 12.0, ScanPolarity::Positive);
+        let neg = make_description(2, 12.0, ScanPolarity::Negative);
+
+        let filter = SpectrumFilter::new();
+        assert!(filter.is_empty());
+        assert!(filter.accepts(&ms1));
+
+        let filter = SpectrumFilter::new().ms_level(2);
+        assert!(!filter.accepts(&ms1));
+        assert!(filter.accepts(&ms2));
+
+        let filter = SpectrumFilter::new()
+            .time_range(10.0..=15.0)
+            .polarity(ScanPolarity::Positive);
+        assert!(!filter.accepts(&ms1));
+        assert!(filter.accepts(&ms2));
+        assert!(!filter.accepts(&neg));
+
+        let filter = SpectrumFilter::new().precursor_mz_range(400.0..=450.0);
+        assert!(!filter.accepts(&ms2));
+
+        let mut faims = make_description(1, 5.0, ScanPolarity::Positive);
+        faims.acquisition.scans[0].compensation_voltage = Some(-45.0);
+        let filter = SpectrumFilter::new().compensation_voltage(-45.0);
+        assert!(filter.accepts(&faims));
+        assert!(!filter.accepts(&ms1));
+
+        let filter = SpectrumFilter::new().predicate(|d| d.ms_level == 1);
+        assert!(filter.accepts(&ms1));
+        assert!(!filter.accepts(&ms2));
+    }
+}
@@ -5,18 +5,21 @@ use std::path;
 
 use std::io::prelude::*;
 
-use flate2::bufread::GzDecoder;
-
 use crate::MGFReader;
 use crate::MzMLReader;
 
 #[cfg(feature = "mzmlb")]
 pub use crate::MzMLbReader;
 
+use crate::meta::SourceFile;
+use crate::params::{ControlledVocabulary, Param};
 use crate::io::traits::ScanSource;
 use crate::io::mzml::is_mzml;
 use crate::io::mgf::is_mgf;
-use crate::io::compression::{is_gzipped, is_gzipped_extension};
+#[cfg(feature = "serde")]
+use crate::io::jsonl::{is_jsonl, JSONLReader};
+use crate::io::compression::{decompress_prefix, CompressionType, SeekableDecompressor};
+use crate::io::utils::PREBUFFER_SIZE;
 
 #[cfg(feature = "mzmlb")]
 use super::traits::MZFileReader;
@@ -30,15 +33,48 @@ pub enum MassSpectrometryFormat {
     MzML,
     #[cfg(feature = "mzmlb")]
     MzMLb,
+    #[cfg(feature = "serde")]
+    JSONL,
     Unknown
 }
 
+impl MassSpectrometryFormat {
+    /// The PSI-MS controlled vocabulary term for this file format, if one exists
+    pub fn as_param(&self) -> Option<Param> {
+        let param = match self {
+            MassSpectrometryFormat::MGF => {
+                ControlledVocabulary::MS.const_param_ident("Mascot MGF format", 1001062)
+            }
+            MassSpectrometryFormat::MzML => {
+                ControlledVocabulary::MS.const_param_ident("mzML format", 1000584)
+            }
+            #[cfg(feature = "mzmlb")]
+            MassSpectrometryFormat::MzMLb => {
+                ControlledVocabulary::MS.const_param_ident("mzMLb format", 1002838)
+            }
+            #[cfg(feature = "serde")]
+            MassSpectrometryFormat::JSONL => return None,
+            MassSpectrometryFormat::Unknown => return None,
+        };
+        Some(param.into())
+    }
+}
+
 
 /// Given a path, infer the file format and whether or not the file at that path is
-/// GZIP compressed
+/// compressed.
+///
+/// See [`infer_from_path_compression`] to learn which compression was used.
 pub fn infer_from_path<P: Into<path::PathBuf>,>(path: P) -> (MassSpectrometryFormat, bool) {
+    let (format, compression) = infer_from_path_compression(path);
+    (format, compression.is_compressed())
+}
+
+/// Given a path, infer the file format and the whole-file compression of the file at
+/// that path from its extensions
+pub fn infer_from_path_compression<P: Into<path::PathBuf>,>(path: P) -> (MassSpectrometryFormat, CompressionType) {
     let path: path::PathBuf = path.into();
-    let (is_gzipped, path) = is_gzipped_extension(path);
+    let (compression, path) = CompressionType::from_extension(path);
     if let Some(ext) = path.extension() {
         if let Some(ext) = ext.to_ascii_lowercase().to_str() {
             let form = match ext {
@@ -46,73 +82,161 @@ pub fn infer_from_path<P: Into<path::PathBuf>,>(path: P) -> (MassSpectrometryFor
                 "mgf" => MassSpectrometryFormat::MGF,
                 #[cfg(feature = "mzmlb")]
                 "mzmlb" => MassSpectrometryFormat::MzMLb,
+                #[cfg(feature = "serde")]
+                "jsonl" | "ndjson" => MassSpectrometryFormat::JSONL,
                 _ => MassSpectrometryFormat::Unknown
             };
-            (form, is_gzipped)
+            (form, compression)
         } else {
-            (MassSpectrometryFormat::Unknown, is_gzipped)
+            (MassSpectrometryFormat::Unknown, compression)
         }
     } else {
-        (MassSpectrometryFormat::Unknown, is_gzipped)
+        (MassSpectrometryFormat::Unknown, compression)
     }
 }
 
+/// The number of decompressed bytes examined when sniffing the format of a compressed stream
+const SNIFF_SIZE: usize = 4096;
 
 /// Given a stream of bytes, infer the file format and whether or not the
-/// stream is GZIP compressed. This assumes the stream is seekable.
+/// stream is compressed. This assumes the stream is seekable.
+///
+/// See [`infer_from_stream_compression`] to learn which compression was used.
 pub fn infer_from_stream<R: Read + Seek>(stream: &mut R) -> io::Result<(MassSpectrometryFormat, bool)> {
+    let (format, compression) = infer_from_stream_compression(stream)?;
+    Ok((format, compression.is_compressed()))
+}
+
+/// Given a stream of bytes, infer the file format and the whole-file compression of
+/// the stream. This assumes the stream is seekable.
+///
+/// No more compressed bytes are read than [`PreBufferedStream::new`](crate::io::PreBufferedStream::new)
+/// buffers, so such a stream can still be rewound afterwards.
+///
+/// The format of a zstd or xz compressed stream can only be recognized when the matching
+/// feature is enabled, otherwise it is reported as [`MassSpectrometryFormat::Unknown`].
+pub fn infer_from_stream_compression<R: Read + Seek>(stream: &mut R) -> io::Result<(MassSpectrometryFormat, CompressionType)> {
     let mut buf = Vec::with_capacity(100);
-    buf.resize(100, b'\0');
     let current_pos = stream.stream_position()?;
-    stream.read_exact(buf.as_mut_slice())?;
-    let is_stream_gzipped = is_gzipped(buf.as_slice());
-    if is_stream_gzipped {
-        let mut decoder = GzDecoder::new(buf.as_slice());
-        let mut decompressed_buf = Vec::new();
-        decoder.read_to_end(&mut decompressed_buf)?;
-        buf = decompressed_buf;
+    stream.by_ref().take(100).read_to_end(&mut buf)?;
+    let compression = CompressionType::from_header(&buf);
+    if compression.is_compressed() {
+        stream.seek(io::SeekFrom::Start(current_pos))?;
+        buf = if compression.is_supported() {
+            let compressed = stream.by_ref().take(PREBUFFER_SIZE as u64);
+            decompress_prefix(compressed, compression, SNIFF_SIZE)?
+        } else {
+            Vec::new()
+        };
     }
     stream.seek(io::SeekFrom::Start(current_pos))?;
+    #[cfg(feature = "serde")]
+    {
+        if is_jsonl(&buf) {
+            return Ok((MassSpectrometryFormat::JSONL, compression));
+        }
+    }
     if is_mzml(&buf) {
-        Ok((MassSpectrometryFormat::MzML, is_stream_gzipped))
+        Ok((MassSpectrometryFormat::MzML, compression))
     }
     else if is_mgf(&buf) {
-        Ok((MassSpectrometryFormat::MGF, is_stream_gzipped))
+        Ok((MassSpectrometryFormat::MGF, compression))
     } else {
-        Ok((MassSpectrometryFormat::Unknown, is_stream_gzipped))
+        Ok((MassSpectrometryFormat::Unknown, compression))
     }
 }
 
 
 /// Given a path, infer the file format and whether or not the file at that path is
-/// GZIP compressed, using both the file name and by trying to open and read the file
+/// compressed, using both the file name and by trying to open and read the file
 /// header
+///
+/// See [`infer_format_compression`] to learn which compression was used.
 pub fn infer_format<P: Into<path::PathBuf>>(path: P) -> io::Result<(MassSpectrometryFormat, bool)> {
+    let (format, compression) = infer_format_compression(path)?;
+    Ok((format, compression.is_compressed()))
+}
+
+/// Given a path, infer the file format and the whole-file compression of the file at
+/// that path, using both the file name and by trying to open and read the file header
+pub fn infer_format_compression<P: Into<path::PathBuf>>(path: P) -> io::Result<(MassSpectrometryFormat, CompressionType)> {
     let path: path::PathBuf = path.into();
 
-    let (format, is_gzipped) = infer_from_path(&path);
+    let (format, compression) = infer_from_path_compression(&path);
     match format {
         MassSpectrometryFormat::Unknown => {
             let handle = fs::File::open(path.clone())?;
             let mut stream = BufReader::new(handle);
-            let (format, is_gzipped) = infer_from_stream(&mut stream)?;
-            Ok((format, is_gzipped))
+            let (format, compression) = infer_from_stream_compression(&mut stream)?;
+            Ok((format, compression))
         },
         _ => {
-            Ok((format, is_gzipped))
+            Ok((format, compression))
         }
     }
 }
 
+impl SourceFile {
+    /// Describe the file at `path`, inferring its format from its name and contents.
+    pub fn from_path<P: Into<path::PathBuf>>(path: P) -> io::Result<Self> {
+        let path: path::PathBuf = path.into();
+        let (format, _gzipped) = infer_format(path.clone())?;
+        let path = path.canonicalize()?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let location = path
+            .parent()
+            .map(|p| format!("file://{}", p.display()))
+            .unwrap_or_default();
+        let id = path
+            .file_stem()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(Self {
+            name,
+            location,
+            id,
+            file_format: format.as_param(),
+            ..Default::default()
+        })
+    }
+}
+
 
 /// Given a local file system path, infer the file format, and attempt to open it
 /// for reading.
+///
+/// Compressed mzML and MGF files are read through a [`SeekableDecompressor`], whose
+/// [`BlockIndex`](crate::io::BlockIndex) saved next to the file if there is one. Random access is cheap
+/// when the file was written in blocks, e.g. with [`CompressedWriter`](crate::io::CompressedWriter),
+/// `bgzip` or a seekable zstd writer. Reading zstd and xz files requires the `zstd` and `xz`
+/// features.
 pub fn open_file<P: Into<path::PathBuf>>(path: P) -> io::Result<Box<dyn ScanSource>>{
     let path = path.into();
-    let (format, is_gzipped) = infer_format(path.clone())?;
+    let (format, compression) = infer_format_compression(path.clone())?;
 
-    if is_gzipped {
-        Err(io::Error::new(io::ErrorKind::Unsupported, "Gzipped files are not supported"))
+    if compression.is_compressed() {
+        let handle = SeekableDecompressor::open_path(path)?;
+        match format {
+            MassSpectrometryFormat::MGF => {
+                let reader = MGFReader::new_indexed(handle);
+                Ok(Box::new(reader))
+            },
+            MassSpectrometryFormat::MzML => {
+                let reader = MzMLReader::new_indexed(handle);
+                Ok(Box::new(reader))
+            },
+            #[cfg(feature = "serde")]
+            MassSpectrometryFormat::JSONL => {
+                let reader = JSONLReader::new_indexed(handle);
+                Ok(Box::new(reader))
+            },
+            _ => {
+                Err(io::Error::new(io::ErrorKind::Unsupported, format!("File format not supported for {compression} compressed files")))
+            }
+        }
     } else {
         match format {
             MassSpectrometryFormat::MGF => {
@@ -125,6 +249,12 @@ pub fn open_file<P: Into<path::PathBuf>>(path: P) -> io::Result<Box<dyn ScanSour
                 let reader = MzMLReader::new_indexed(handle);
                 Ok(Box::new(reader))
             },
+            #[cfg(feature = "serde")]
+            MassSpectrometryFormat::JSONL => {
+                let handle = fs::File::open(path)?;
+                let reader = JSONLReader::new_indexed(handle);
+                Ok(Box::new(reader))
+            },
             #[cfg(feature = "mzmlb")]
             MassSpectrometryFormat::MzMLb => {
                 let reader = MzMLbReader::open_path(path);
@@ -187,4 +317,49 @@ mod test {
             panic!("Failed to open file")
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn infer_open_bgzf() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mzML");
+        let tmpdir = tempfile::tempdir()?;
+        let gz_path = tmpdir.path().join("small.mzML.gz");
+
+        let mut writer = crate::io::BgzfWriter::new(fs::File::create(&gz_path)?);
+        io::copy(&mut fs::File::open(path)?, &mut writer)?;
+        writer.finish()?;
+
+        let (fmt, compression) = infer_format_compression(&gz_path)?;
+        assert_eq!(fmt, MassSpectrometryFormat::MzML);
+        assert_eq!(compression, CompressionType::Gzip);
+
+        let mut reader = open_file(&gz_path)?;
+        assert_eq!(reader.len(), 48);
+        let spec: Spectrum = reader.get_spectrum_by_index(10).unwrap();
+        assert_eq!(spec.id(), "controllerType=0 controllerNumber=1 scan=11");
+        assert!(!tmpdir.path().join("small.mzML.blockindex.json").exists());
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn infer_open_zstd() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mzML");
+        let tmpdir = tempfile::tempdir()?;
+        let zst_path = tmpdir.path().join("small.mzML.zst");
+
+        let mut writer = crate::io::CompressedWriter::create_path(&zst_path)?;
+        io::copy(&mut fs::File::open(path)?, &mut writer)?;
+        writer.finish()?;
+
+        let mut stream = BufReader::new(fs::File::open(&zst_path)?);
+        let (fmt, compression) = infer_from_stream_compression(&mut stream)?;
+        assert_eq!(fmt, MassSpectrometryFormat::MzML);
+        assert_eq!(compression, CompressionType::Zstd);
+
+        let mut reader = open_file(&zst_path)?;
+        assert_eq!(reader.len(), 48);
+        let spec: Spectrum = reader.get_spectrum_by_index(10).unwrap();
+        assert_eq!(spec.id(), "controllerType=0 controllerNumber=1 scan=11");
+        Ok(())
+    }
+}
@@ -0,0 +1,657 @@
+#![cfg(feature = "serde")]
+/*!
+Read and write spectra as [JSON Lines](https://jsonlines.org/), one JSON object per line, for
+web front-ends and line-oriented tools like `jq`.
+
+The first line written by [`JSONLWriterType`] is a header carrying the file-level metadata,
+which always starts with `{"format":"mzdata-jsonl"`:
+
+```json
+{"format":"mzdata-jsonl","version":1,"file_description":{},"instrument_configurations":[],"softwares":[],"data_processings":[],"samples":[]}
+```
+
+Every following line is one spectrum, in the `serde` representation of [`MultiLayerSpectrum`]:
+
+- `description`: the [`SpectrumDescription`], with its `id`, `index`, `ms_level`, `polarity`,
+  `signal_continuity`, `params`, `acquisition` and `precursors` list.
+- `arrays`: `null`, or a list of [`DataArray`](crate::spectrum::DataArray) objects whose `data`
+  is a base64 string of the array's bytes, of type `dtype` and encoded with `compression`.
+- `peaks` and `deconvoluted_peaks`: `null`, or a list of centroid or deconvoluted peaks.
+
+A writer emits the data arrays and the most processed layer of peaks a spectrum has, as reported
+by [`SpectrumLike::peaks`]. The header is optional when reading, and blank lines are skipped.
+For example, to list the ID and MS level of every spectrum:
+
+```text
+jq -c 'select(.description) | .description | {id, ms_level}' run.jsonl
+```
+
+This module requires the `serde` feature.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::marker::PhantomData;
+
+use log::warn;
+use mzpeaks::{
+    CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak, MZPeakSetType,
+    MassPeakSetType,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::offset_index::OffsetIndex;
+use super::traits::{
+    MZFileReader, RandomAccessSpectrumIterator, ScanSource, ScanWriter, SeekRead,
+    SpectrumAccessError, SpectrumReadError, TryScanSource,
+};
+use crate::meta::{
+    DataProcessing, FileDescription, InstrumentConfiguration, MSDataFileMetadata, Sample, Software,
+};
+use crate::spectrum::bindata::BinaryArrayMap;
+use crate::spectrum::spectrum::{
+    CentroidPeakAdapting, DeconvolutedPeakAdapting, MultiLayerSpectrum,
+};
+use crate::spectrum::{PeakDataLevel, SpectrumDescription, SpectrumLike};
+
+/// The value of the header's `format` field
+pub const JSONL_FORMAT: &str = "mzdata-jsonl";
+/// The version of the format written by [`JSONLWriterType`]
+pub const JSONL_VERSION: u32 = 1;
+
+const HEADER_PREFIX: &[u8] = b"{\"format\":\"mzdata-jsonl\"";
+const SPECTRUM_PREFIX: &[u8] = b"{\"description\":";
+
+#[derive(Debug, Error)]
+pub enum JSONLError {
+    #[error("Encountered a malformed line: {0}")]
+    JSONError(
+        #[from]
+        #[source]
+        serde_json::Error,
+    ),
+    #[error("Encountered an IO error: {0}")]
+    IOError(
+        #[from]
+        #[source]
+        io::Error,
+    ),
+}
+
+impl From<JSONLError> for io::Error {
+    fn from(value: JSONLError) -> Self {
+        match value {
+            JSONLError::IOError(err) => err,
+            JSONLError::JSONError(err) => err.into(),
+        }
+    }
+}
+
+/// The file-level metadata stored on the first line
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct JSONLHeader {
+    format: String,
+    version: u32,
+    #[serde(default)]
+    file_description: FileDescription,
+    #[serde(default)]
+    instrument_configurations: Vec<InstrumentConfiguration>,
+    #[serde(default)]
+    softwares: Vec<Software>,
+    #[serde(default)]
+    data_processings: Vec<DataProcessing>,
+    #[serde(default)]
+    samples: Vec<Sample>,
+}
+
+/// Just enough of a spectrum line to index it by its ID
+#[derive(Debug, Deserialize)]
+struct SpectrumKey {
+    description: SpectrumKeyDescription,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpectrumKeyDescription {
+    id: String,
+}
+
+/// A borrowed view of a spectrum with the same serialized form as [`MultiLayerSpectrum`]
+#[derive(Serialize)]
+struct SpectrumRecord<'a, C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default> {
+    description: &'a SpectrumDescription,
+    arrays: Option<&'a BinaryArrayMap>,
+    peaks: Option<&'a MZPeakSetType<C>>,
+    deconvoluted_peaks: Option<&'a MassPeakSetType<D>>,
+}
+
+fn skip_whitespace(buf: &[u8]) -> &[u8] {
+    let start = buf
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(buf.len());
+    &buf[start..]
+}
+
+fn is_header_line(buf: &[u8]) -> bool {
+    skip_whitespace(buf).starts_with(HEADER_PREFIX)
+}
+
+pub(crate) fn is_jsonl(buf: &[u8]) -> bool {
+    let buf = skip_whitespace(buf);
+    buf.starts_with(HEADER_PREFIX) || buf.starts_with(SPECTRUM_PREFIX)
+}
+
+/// An error raised while reading a spectrum from a JSON Lines stream with
+/// [`TryScanSource::try_next`]
+pub type JSONLReadError = SpectrumReadError<JSONLError, ()>;
+
+/// A JSON Lines spectrum reader that supports iteration and random access.
+pub struct JSONLReaderType<
+    R: io::Read,
+    C: CentroidPeakAdapting = CentroidPeak,
+    D: DeconvolutedPeakAdapting = DeconvolutedPeak,
+> {
+    pub handle: io::BufReader<R>,
+    pub index: OffsetIndex,
+    /// The last error encountered while reading, which stopped iteration
+    pub error: Option<JSONLError>,
+    buffer: String,
+    file_description: FileDescription,
+    instrument_configurations: HashMap<u32, InstrumentConfiguration>,
+    softwares: Vec<Software>,
+    data_processings: Vec<DataProcessing>,
+    samples: Vec<Sample>,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<
+        R: io::Read,
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > JSONLReaderType<R, C, D>
+{
+    /// Create a new, unindexed JSON Lines reader
+    pub fn new(file: R) -> JSONLReaderType<R, C, D> {
+        let mut reader = JSONLReaderType {
+            handle: io::BufReader::new(file),
+            index: OffsetIndex::new("spectrum".to_owned()),
+            error: None,
+            buffer: String::new(),
+            file_description: FileDescription::default(),
+            instrument_configurations: HashMap::new(),
+            softwares: Vec::new(),
+            data_processings: Vec::new(),
+            samples: Vec::new(),
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        };
+        if let Err(err) = reader.read_file_header() {
+            warn!("Failed to read the JSON Lines file header: {}", err);
+        }
+        reader
+    }
+
+    fn read_file_header(&mut self) -> Result<(), JSONLError> {
+        if !is_header_line(self.handle.fill_buf()?) {
+            return Ok(());
+        }
+        self.buffer.clear();
+        self.handle.read_line(&mut self.buffer)?;
+        let header: JSONLHeader = serde_json::from_str(&self.buffer)?;
+        if header.version > JSONL_VERSION {
+            warn!(
+                "Reading a JSON Lines file of version {}, newer than {}",
+                header.version, JSONL_VERSION
+            );
+        }
+        self.file_description = header.file_description;
+        self.instrument_configurations = header
+            .instrument_configurations
+            .into_iter()
+            .map(|config| (config.id, config))
+            .collect();
+        self.softwares = header.softwares;
+        self.data_processings = header.data_processings;
+        self.samples = header.samples;
+        Ok(())
+    }
+
+    fn read_spectrum(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, JSONLError> {
+        loop {
+            self.buffer.clear();
+            if self.handle.read_line(&mut self.buffer)? == 0 {
+                return Ok(None);
+            }
+            let line = self.buffer.trim();
+            if line.is_empty() || is_header_line(line.as_bytes()) {
+                continue;
+            }
+            return Ok(Some(serde_json::from_str(line)?));
+        }
+    }
+
+    /// Read the next spectrum from the file, if there is one.
+    ///
+    /// If a line cannot be parsed, the error is stored in [`JSONLReaderType::error`] and
+    /// `None` is returned.
+    pub fn read_next(&mut self) -> Option<MultiLayerSpectrum<C, D>> {
+        match self.read_spectrum() {
+            Ok(spectrum) => spectrum,
+            Err(err) => {
+                log::error!("An error was encountered while reading JSON Lines: {}", err);
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+impl<
+        R: io::Read,
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > Iterator for JSONLReaderType<R, C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_next()
+    }
+}
+
+impl<
+        R: SeekRead,
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > JSONLReaderType<R, C, D>
+{
+    /// Construct a new JSONLReaderType and build an offset index
+    /// using [`Self::build_index`]
+    pub fn new_indexed(file: R) -> JSONLReaderType<R, C, D> {
+        let mut reader = Self::new(file);
+        reader.build_index();
+        reader
+    }
+
+    pub fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.handle.seek(pos)
+    }
+
+    /// Builds an offset index to the start of each spectrum line, reading only the
+    /// spectrum's ID from each.
+    pub fn build_index(&mut self) -> u64 {
+        let mut offset: u64 = 0;
+
+        let start = self
+            .handle
+            .stream_position()
+            .expect("Failed to save restore location");
+        self.seek(SeekFrom::Start(0))
+            .expect("Failed to reset stream to beginning");
+
+        let mut buffer: Vec<u8> = Vec::new();
+
+        loop {
+            buffer.clear();
+            let b = match self.handle.read_until(b'\n', &mut buffer) {
+                Ok(b) => b,
+                Err(err) => {
+                    panic!("Error while reading file: {}", err);
+                }
+            };
+            if b == 0 {
+                break;
+            }
+            if !is_header_line(&buffer) && !skip_whitespace(&buffer).is_empty() {
+                match serde_json::from_slice::<SpectrumKey>(&buffer) {
+                    Ok(key) => {
+                        self.index.insert(key.description.id, offset);
+                    }
+                    Err(err) => {
+                        warn!("Failed to read a spectrum ID at offset {}: {}", offset, err)
+                    }
+                }
+            }
+            offset += b as u64;
+        }
+        self.seek(SeekFrom::Start(start))
+            .expect("Failed to restore location");
+        self.index.init = true;
+        if self.index.is_empty() {
+            warn!("An index was built but no entries were found")
+        }
+        offset
+    }
+
+    fn read_at(&mut self, offset: u64) -> Option<MultiLayerSpectrum<C, D>> {
+        let start = self
+            .handle
+            .stream_position()
+            .expect("Failed to save checkpoint");
+        self.seek(SeekFrom::Start(offset)).ok()?;
+        let result = self.read_next();
+        self.seek(SeekFrom::Start(start))
+            .expect("Failed to restore offset");
+        result
+    }
+}
+
+impl<
+        R: SeekRead,
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > ScanSource<C, D, MultiLayerSpectrum<C, D>> for JSONLReaderType<R, C, D>
+{
+    /// Retrieve a spectrum by it's native ID
+    fn get_spectrum_by_id(&mut self, id: &str) -> Option<MultiLayerSpectrum<C, D>> {
+        let offset = self.index.get(id)?;
+        self.read_at(offset)
+    }
+
+    /// Retrieve a spectrum by it's integer index
+    fn get_spectrum_by_index(&mut self, index: usize) -> Option<MultiLayerSpectrum<C, D>> {
+        let (_id, offset) = self.index.get_index(index)?;
+        self.read_at(offset)
+    }
+
+    /// Return the data stream to the beginning
+    fn reset(&mut self) {
+        self.seek(SeekFrom::Start(0))
+            .expect("Failed to reset file stream");
+    }
+
+    fn get_index(&self) -> &OffsetIndex {
+        if !self.index.init {
+            warn!("Attempting to use an uninitialized offset index on JSONLReaderType")
+        }
+        &self.index
+    }
+
+    fn set_index(&mut self, index: OffsetIndex) {
+        self.index = index;
+    }
+}
+
+impl<
+        R: SeekRead,
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > RandomAccessSpectrumIterator<C, D, MultiLayerSpectrum<C, D>> for JSONLReaderType<R, C, D>
+{
+    fn start_from_id(&mut self, id: &str) -> Result<&mut Self, SpectrumAccessError> {
+        match self._offset_of_id(id) {
+            Some(offset) => match self.seek(SeekFrom::Start(offset)) {
+                Ok(_) => Ok(self),
+                Err(err) => Err(SpectrumAccessError::IOError(Some(err))),
+            },
+            None => Err(SpectrumAccessError::SpectrumIdNotFound(id.to_string())),
+        }
+    }
+
+    fn start_from_index(&mut self, index: usize) -> Result<&mut Self, SpectrumAccessError> {
+        match self._offset_of_index(index) {
+            Some(offset) => match self.seek(SeekFrom::Start(offset)) {
+                Ok(_) => Ok(self),
+                Err(err) => Err(SpectrumAccessError::IOError(Some(err))),
+            },
+            None => Err(SpectrumAccessError::SpectrumIndexNotFound(index)),
+        }
+    }
+
+    fn start_from_time(&mut self, time: f64) -> Result<&mut Self, SpectrumAccessError> {
+        match self._offset_of_time(time) {
+            Some(offset) => match self.seek(SeekFrom::Start(offset)) {
+                Ok(_) => Ok(self),
+                Err(err) => Err(SpectrumAccessError::IOError(Some(err))),
+            },
+            None => Err(SpectrumAccessError::SpectrumNotFound),
+        }
+    }
+}
+
+impl<
+        R: SeekRead,
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > TryScanSource<C, D, MultiLayerSpectrum<C, D>> for JSONLReaderType<R, C, D>
+{
+    type Error = JSONLReadError;
+
+    fn try_next(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, Self::Error> {
+        let start = self
+            .handle
+            .stream_position()
+            .map_err(|err| JSONLReadError::new(err.into(), (), 0))?;
+        self.read_spectrum().map_err(|err| {
+            let offset = self.handle.stream_position().unwrap_or(start);
+            JSONLReadError::new(err, (), offset).with_spectrum_from(&self.index, start)
+        })
+    }
+}
+
+impl<
+        C: CentroidPeakAdapting + DeserializeOwned,
+        D: DeconvolutedPeakAdapting + DeserializeOwned,
+    > MZFileReader<C, D, MultiLayerSpectrum<C, D>> for JSONLReaderType<fs::File, C, D>
+{
+    fn open_file(source: fs::File) -> Self {
+        Self::new(source)
+    }
+
+    fn construct_index_from_stream(&mut self) -> u64 {
+        self.build_index()
+    }
+}
+
+impl<R: io::Read, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> MSDataFileMetadata
+    for JSONLReaderType<R, C, D>
+{
+    crate::impl_metadata_trait!();
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        Some(&self.samples)
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        Some(&mut self.samples)
+    }
+
+    fn spectrum_count_hint(&self) -> Option<u64> {
+        if self.index.init {
+            Some(self.index.len() as u64)
+        } else {
+            None
+        }
+    }
+}
+
+pub type JSONLReader<R> = JSONLReaderType<R, CentroidPeak, DeconvolutedPeak>;
+
+/// A JSON Lines spectrum writer. The file-level metadata is written as the first line
+/// when the first spectrum is written or the writer is flushed.
+pub struct JSONLWriterType<
+    W: io::Write,
+    C: CentroidPeakAdapting = CentroidPeak,
+    D: DeconvolutedPeakAdapting = DeconvolutedPeak,
+> {
+    pub handle: io::BufWriter<W>,
+    pub offset: usize,
+    file_description: FileDescription,
+    instrument_configurations: HashMap<u32, InstrumentConfiguration>,
+    softwares: Vec<Software>,
+    data_processings: Vec<DataProcessing>,
+    samples: Vec<Sample>,
+    wrote_file_header: bool,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<
+        W: io::Write,
+        C: CentroidPeakAdapting + Serialize,
+        D: DeconvolutedPeakAdapting + Serialize,
+    > JSONLWriterType<W, C, D>
+{
+    pub fn new(file: W) -> JSONLWriterType<W, C, D> {
+        JSONLWriterType {
+            handle: io::BufWriter::new(file),
+            offset: 0,
+            file_description: FileDescription::default(),
+            instrument_configurations: HashMap::new(),
+            softwares: Vec::new(),
+            data_processings: Vec::new(),
+            samples: Vec::new(),
+            wrote_file_header: false,
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> io::BufWriter<W> {
+        self.handle
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        self.handle.write_all(&line)?;
+        self.offset += line.len();
+        Ok(())
+    }
+
+    /// Write the file-level metadata as the first line. This happens at most once.
+    fn write_file_header(&mut self) -> io::Result<()> {
+        if self.wrote_file_header {
+            return Ok(());
+        }
+        self.wrote_file_header = true;
+        let mut instrument_configurations: Vec<_> =
+            self.instrument_configurations.values().cloned().collect();
+        instrument_configurations.sort_by_key(|config| config.id);
+        let header = JSONLHeader {
+            format: JSONL_FORMAT.to_string(),
+            version: JSONL_VERSION,
+            file_description: self.file_description.clone(),
+            instrument_configurations,
+            softwares: self.softwares.clone(),
+            data_processings: self.data_processings.clone(),
+            samples: self.samples.clone(),
+        };
+        self.write_line(&header)
+    }
+
+    /// Write a spectrum as a single line, returning the number of bytes written so far
+    pub fn write<S: SpectrumLike<C, D> + 'static>(&mut self, spectrum: &S) -> io::Result<usize> {
+        self.write_file_header()?;
+        let (peaks, deconvoluted_peaks) = match spectrum.peaks() {
+            PeakDataLevel::Centroid(peaks) => (Some(peaks), None),
+            PeakDataLevel::Deconvoluted(peaks) => (None, Some(peaks)),
+            PeakDataLevel::RawData(_) | PeakDataLevel::Missing => (None, None),
+        };
+        let record = SpectrumRecord {
+            description: spectrum.description(),
+            arrays: spectrum.raw_arrays(),
+            peaks,
+            deconvoluted_peaks,
+        };
+        self.write_line(&record)?;
+        Ok(self.offset)
+    }
+}
+
+impl<
+        'a,
+        W: io::Write,
+        C: CentroidPeakAdapting + Serialize + 'static,
+        D: DeconvolutedPeakAdapting + Serialize + 'static,
+    > ScanWriter<'a, C, D> for JSONLWriterType<W, C, D>
+{
+    fn write<S: SpectrumLike<C, D> + 'static>(&mut self, spectrum: &S) -> io::Result<usize> {
+        self.write(spectrum)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_file_header()?;
+        self.handle.flush()
+    }
+}
+
+impl<W: io::Write, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> MSDataFileMetadata
+    for JSONLWriterType<W, C, D>
+{
+    crate::impl_metadata_trait!();
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        Some(&self.samples)
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        Some(&mut self.samples)
+    }
+}
+
+/// A convenient alias for [`JSONLWriterType`] with the peak types specified
+pub type JSONLWriter<W> = JSONLWriterType<W, CentroidPeak, DeconvolutedPeak>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{infer_from_stream, MassSpectrometryFormat, MzMLReader};
+
+    #[test]
+    fn test_round_trip() -> io::Result<()> {
+        let mut reader = MzMLReader::new(fs::File::open("./test/data/three_test_scans.mzML")?);
+        let spectra: Vec<_> = reader.by_ref().collect();
+
+        let mut writer = JSONLWriter::new(Vec::new());
+        writer.copy_metadata_from(&reader);
+        for spectrum in spectra.iter() {
+            writer.write(spectrum)?;
+        }
+        ScanWriter::flush(&mut writer)?;
+        let buffer = writer
+            .into_inner()
+            .into_inner()
+            .map_err(|err| err.into_error())?;
+        assert!(buffer.starts_with(HEADER_PREFIX));
+        assert_eq!(
+            buffer.iter().filter(|b| **b == b'\n').count(),
+            spectra.len() + 1
+        );
+
+        let mut stream = io::Cursor::new(buffer);
+        let (format, _) = infer_from_stream(&mut stream)?;
+        assert_eq!(format, MassSpectrometryFormat::JSONL);
+
+        let mut reader2 = JSONLReader::new_indexed(stream);
+        assert_eq!(reader2.len(), spectra.len());
+        assert_eq!(reader.file_description(), reader2.file_description());
+        assert_eq!(reader.softwares(), reader2.softwares());
+        assert_eq!(
+            reader.instrument_configurations(),
+            reader2.instrument_configurations()
+        );
+
+        let spectra2: Vec<_> = reader2.by_ref().collect();
+        assert!(reader2.error.is_none());
+        assert_eq!(spectra.len(), spectra2.len());
+        for (a, b) in spectra.iter().zip(spectra2.iter()) {
+            assert_eq!(a.description, b.description);
+            let arrays = a.arrays.as_ref().unwrap();
+            let arrays2 = b.arrays.as_ref().unwrap();
+            assert_eq!(arrays.mzs()?, arrays2.mzs()?);
+            assert_eq!(arrays.intensities()?, arrays2.intensities()?);
+        }
+
+        let last = spectra.last().unwrap();
+        let found = reader2.get_spectrum_by_id(last.id()).unwrap();
+        assert_eq!(found.index(), last.index());
+        Ok(())
+    }
+}
@@ -0,0 +1,513 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::ops::RangeInclusive;
+use std::path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike};
+
+use crate::spectrum::{PeakDataLevel, PrecursorSelection, ScanPolarity, SpectrumLike};
+
+use super::traits::ScanSource;
+use super::utils::FileSource;
+
+mod polarity_serde {
+    use super::ScanPolarity;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &ScanPolarity, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i8(*value as i8)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ScanPolarity, D::Error> {
+        let value = i8::deserialize(deserializer)?;
+        Ok(match value {
+            1 => ScanPolarity::Positive,
+            -1 => ScanPolarity::Negative,
+            _ => ScanPolarity::Unknown,
+        })
+    }
+}
+
+/// The searchable properties of a single spectrum, recorded without its signal data
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SpectrumSummary {
+    pub index: usize,
+    pub id: String,
+    /// The scan start time, in minutes
+    pub time: f64,
+    pub ms_level: u8,
+    #[serde(with = "polarity_serde")]
+    pub polarity: ScanPolarity,
+    pub precursor_mz: Option<f64>,
+    pub precursor_charge: Option<i32>,
+    pub base_peak_mz: f64,
+    pub base_peak_intensity: f32,
+}
+
+impl SpectrumSummary {
+    pub fn from_spectrum<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    >(
+        spectrum: &S,
+    ) -> Self {
+        let ion = spectrum.precursor().and_then(|p| p.first_ion());
+        let base_peak = match spectrum.peaks() {
+            // Raw data may be missing either array, e.g. when it was not loaded
+            PeakDataLevel::RawData(arrays)
+                if arrays.mzs().is_err() || arrays.intensities().is_err() =>
+            {
+                CentroidPeak::new(0.0, 0.0, 0)
+            }
+            peaks => peaks.base_peak(),
+        };
+        Self {
+            index: spectrum.index(),
+            id: spectrum.id().to_string(),
+            time: spectrum.start_time(),
+            ms_level: spectrum.ms_level(),
+            polarity: spectrum.polarity(),
+            precursor_mz: ion.map(|i| i.mz),
+            precursor_charge: ion.and_then(|i| i.charge),
+            base_peak_mz: base_peak.mz,
+            base_peak_intensity: base_peak.intensity,
+        }
+    }
+}
+
+/// The size and modification time of the file an index was built from, used to tell
+/// when a saved index no longer describes its source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl SourceFingerprint {
+    pub fn from_path<P: AsRef<path::Path>>(path: P) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/**
+An extended spectrum index recording the time, MS level, polarity, precursor and base peak of every
+spectrum in a file, ordered by spectrum index.
+
+Unlike [`OffsetIndex`](super::OffsetIndex), building this index requires reading every spectrum
+once, but afterwards [`SpectrumMetadataIndex::query`] can select spectra by these properties without
+parsing any of them. The index may be saved alongside a file with [`SpectrumMetadataIndex::open_or_build`].
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpectrumMetadataIndex {
+    entries: Vec<SpectrumSummary>,
+    /// The file the index was built from, if it was built from a file
+    #[serde(default)]
+    pub source: Option<SourceFingerprint>,
+    /// Whether the entries are in chronological order as well as by spectrum index
+    #[serde(skip)]
+    time_ordered: bool,
+}
+
+impl SpectrumMetadataIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an index by reading every spectrum from `source`, starting from the beginning.
+    /// The source is reset afterwards.
+    pub fn build_from<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        R: ScanSource<C, D, S>,
+    >(
+        source: &mut R,
+    ) -> Self {
+        source.reset();
+        let entries: Vec<SpectrumSummary> = source
+            .by_ref()
+            .map(|s| SpectrumSummary::from_spectrum(&s))
+            .collect();
+        source.reset();
+        Self::from_entries(entries)
+    }
+
+    /// Create an index from `entries` in any order
+    pub fn from_entries(mut entries: Vec<SpectrumSummary>) -> Self {
+        entries.sort_by_key(|e| e.index);
+        let mut index = Self {
+            entries,
+            ..Default::default()
+        };
+        index.update_time_order();
+        index
+    }
+
+    fn update_time_order(&mut self) {
+        self.time_ordered = self.entries.windows(2).all(|w| w[0].time <= w[1].time);
+    }
+
+    /// Add a new entry to the index, keeping it sorted by spectrum index
+    pub fn push(&mut self, entry: SpectrumSummary) {
+        let i = self.entries.partition_point(|e| e.index < entry.index);
+        let time = entry.time;
+        self.entries.insert(i, entry);
+        let before = i.checked_sub(1).and_then(|j| self.entries.get(j));
+        let after = self.entries.get(i + 1);
+        self.time_ordered = (self.time_ordered || self.entries.len() == 1)
+            && before.map(|e| e.time <= time).unwrap_or(true)
+            && after.map(|e| time <= e.time).unwrap_or(true);
+    }
+
+    /// The entries of the index, ordered by spectrum index
+    pub fn entries(&self) -> &[SpectrumSummary] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SpectrumSummary> {
+        match self.entries.binary_search_by_key(&index, |e| e.index) {
+            Ok(i) => self.entries.get(i),
+            Err(_) => None,
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, SpectrumSummary> {
+        self.entries.iter()
+    }
+
+    /// Find the index of the spectrum whose start time is closest to `time`. This is a binary
+    /// search when spectra are in chronological order, and a linear scan otherwise.
+    pub fn index_nearest_time(&self, time: f64) -> Option<usize> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        if !self.time_ordered {
+            return self
+                .entries
+                .iter()
+                .min_by(|a, b| (a.time - time).abs().total_cmp(&(b.time - time).abs()))
+                .map(|e| e.index);
+        }
+        let i = self.entries.partition_point(|e| e.time < time);
+        let best = if i == 0 {
+            0
+        } else if i == self.entries.len() {
+            i - 1
+        } else if (self.entries[i].time - time).abs() < (time - self.entries[i - 1].time).abs() {
+            i
+        } else {
+            i - 1
+        };
+        Some(self.entries[best].index)
+    }
+
+    /// Start a new query over this index
+    pub fn query(&self) -> MetadataQuery<'_> {
+        MetadataQuery::new(self)
+    }
+
+    pub fn to_writer<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> serde_json::Result<Self> {
+        let mut index: Self = serde_json::from_reader(reader)?;
+        index.entries.sort_by_key(|e| e.index);
+        index.update_time_order();
+        Ok(index)
+    }
+
+    /// The path the metadata index for the file at `path` is stored at, next to its
+    /// offset index
+    pub fn index_file_name<P: Into<path::PathBuf>>(path: P) -> Option<path::PathBuf> {
+        let source: FileSource<fs::File> = FileSource::from(path.into());
+        source.metadata_index_file_name()
+    }
+
+    /// Read the metadata index for the file at `path` if it has been saved and that file
+    /// has not changed since, or otherwise build it from `source`, which must be reading
+    /// that file, and save it.
+    pub fn open_or_build<
+        P: Into<path::PathBuf>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        R: ScanSource<C, D, S>,
+    >(
+        path: P,
+        source: &mut R,
+    ) -> io::Result<Self> {
+        let path: path::PathBuf = path.into();
+        let fingerprint = SourceFingerprint::from_path(&path).ok();
+        let index_path = Self::index_file_name(path);
+        if let Some(index_path) = index_path.as_ref() {
+            if index_path.exists() {
+                let stream = io::BufReader::new(fs::File::open(index_path)?);
+                match Self::from_reader(stream) {
+                    Ok(index) if index.len() == source.len() && index.source == fingerprint => {
+                        return Ok(index)
+                    }
+                    Ok(_) => log::warn!(
+                        "Metadata index {} does not match its source, rebuilding",
+                        index_path.display()
+                    ),
+                    Err(err) => log::warn!(
+                        "Failed to read metadata index {}: {}, rebuilding",
+                        index_path.display(),
+                        err
+                    ),
+                }
+            }
+        }
+        let mut index = Self::build_from(source);
+        index.source = fingerprint;
+        if let Some(index_path) = index_path {
+            let stream = io::BufWriter::new(fs::File::create(index_path)?);
+            index.to_writer(stream)?;
+        }
+        Ok(index)
+    }
+}
+
+/**
+A set of constraints over the entries of a [`SpectrumMetadataIndex`]. Every constraint
+added must be satisfied for an entry to match.
+
+```no_run
+# use mzdata::io::SpectrumMetadataIndex;
+# let index = SpectrumMetadataIndex::new();
+let indices = index
+    .query()
+    .ms_level(2)
+    .time_range(20.0..=30.0)
+    .precursor_mz_range(500.0..=510.0)
+    .indices();
+```
+*/
+#[derive(Debug, Clone)]
+pub struct MetadataQuery<'a> {
+    index: &'a SpectrumMetadataIndex,
+    ms_level: Option<u8>,
+    time_range: Option<RangeInclusive<f64>>,
+    polarity: Option<ScanPolarity>,
+    precursor_mz_range: Option<RangeInclusive<f64>>,
+    precursor_charge: Option<i32>,
+    base_peak_mz_range: Option<RangeInclusive<f64>>,
+    min_base_peak_intensity: Option<f32>,
+}
+
+impl<'a> MetadataQuery<'a> {
+    pub fn new(index: &'a SpectrumMetadataIndex) -> Self {
+        Self {
+            index,
+            ms_level: None,
+            time_range: None,
+            polarity: None,
+            precursor_mz_range: None,
+            precursor_charge: None,
+            base_peak_mz_range: None,
+            min_base_peak_intensity: None,
+        }
+    }
+
+    pub fn ms_level(mut self, ms_level: u8) -> Self {
+        self.ms_level = Some(ms_level);
+        self
+    }
+
+    /// Match spectra acquired within `range`, in minutes
+    pub fn time_range(mut self, range: RangeInclusive<f64>) -> Self {
+        self.time_range = Some(range);
+        self
+    }
+
+    pub fn polarity(mut self, polarity: ScanPolarity) -> Self {
+        self.polarity = Some(polarity);
+        self
+    }
+
+    /// Match spectra with a precursor ion whose m/z is within `range`
+    pub fn precursor_mz_range(mut self, range: RangeInclusive<f64>) -> Self {
+        self.precursor_mz_range = Some(range);
+        self
+    }
+
+    pub fn precursor_charge(mut self, charge: i32) -> Self {
+        self.precursor_charge = Some(charge);
+        self
+    }
+
+    pub fn base_peak_mz_range(mut self, range: RangeInclusive<f64>) -> Self {
+        self.base_peak_mz_range = Some(range);
+        self
+    }
+
+    pub fn min_base_peak_intensity(mut self, intensity: f32) -> Self {
+        self.min_base_peak_intensity = Some(intensity);
+        self
+    }
+
+    /// Test whether `entry` satisfies every constraint of this query
+    pub fn matches(&self, entry: &SpectrumSummary) -> bool {
+        if let Some(level) = self.ms_level {
+            if entry.ms_level != level {
+                return false;
+            }
+        }
+        if let Some(range) = self.time_range.as_ref() {
+            if !range.contains(&entry.time) {
+                return false;
+            }
+        }
+        if let Some(polarity) = self.polarity {
+            if entry.polarity != polarity {
+                return false;
+            }
+        }
+        if let Some(range) = self.precursor_mz_range.as_ref() {
+            match entry.precursor_mz {
+                Some(mz) if range.contains(&mz) => {}
+                _ => return false,
+            }
+        }
+        if let Some(charge) = self.precursor_charge {
+            if entry.precursor_charge != Some(charge) {
+                return false;
+            }
+        }
+        if let Some(range) = self.base_peak_mz_range.as_ref() {
+            if !range.contains(&entry.base_peak_mz) {
+                return false;
+            }
+        }
+        if let Some(intensity) = self.min_base_peak_intensity {
+            if entry.base_peak_intensity < intensity {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Iterate over the matching entries
+    pub fn iter(&self) -> impl Iterator<Item = &'a SpectrumSummary> + '_ {
+        // Entries are usually stored in chronological order, so narrow the search window
+        // by time first when possible.
+        let entries = &self.index.entries;
+        let (start, end) = match self.time_range.as_ref() {
+            Some(range) if self.index.time_ordered => (
+                entries.partition_point(|e| e.time < *range.start()),
+                entries.partition_point(|e| e.time <= *range.end()),
+            ),
+            _ => (0, entries.len()),
+        };
+        entries[start..end].iter().filter(move |e| self.matches(e))
+    }
+
+    /// The spectrum indices of the matching entries
+    pub fn indices(&self) -> Vec<usize> {
+        self.iter().map(|e| e.index).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::{BinaryArrayMap, MultiLayerSpectrum};
+
+    fn make_index() -> SpectrumMetadataIndex {
+        let mut index = SpectrumMetadataIndex::new();
+        for i in 0..40 {
+            let ms_level = if i % 4 == 0 { 1 } else { 2 };
+            index.push(SpectrumSummary {
+                index: i,
+                id: format!("scan={}", i + 1),
+                time: i as f64,
+                ms_level,
+                polarity: ScanPolarity::Positive,
+                precursor_mz: if ms_level == 2 {
+                    Some(490.0 + (i % 4) as f64 * 5.0)
+                } else {
+                    None
+                },
+                precursor_charge: if ms_level == 2 { Some(2) } else { None },
+                base_peak_mz: 300.0,
+                base_peak_intensity: 1000.0,
+            });
+        }
+        index
+    }
+
+    #[test]
+    fn test_query() {
+        let index = make_index();
+        let hits = index
+            .query()
+            .ms_level(2)
+            .time_range(20.0..=30.0)
+            .precursor_mz_range(500.0..=510.0)
+            .indices();
+        assert_eq!(hits, vec![22, 23, 26, 27, 30]);
+        assert_eq!(index.index_nearest_time(12.4), Some(12));
+
+        // An entry out of chronological order must not hide matches
+        let mut index = index;
+        index.push(SpectrumSummary {
+            index: 40,
+            time: 5.0,
+            ms_level: 2,
+            ..Default::default()
+        });
+        let hits = index.query().ms_level(2).time_range(4.5..=5.5).indices();
+        assert_eq!(hits, vec![5, 40]);
+        assert!(index.query().ms_level(3).indices().is_empty());
+        assert_eq!(index.index_nearest_time(5.6), Some(6));
+        assert_eq!(index.index_nearest_time(38.6), Some(39));
+
+        // The nearest spectrum is found even when it is stored out of chronological order
+        index.push(SpectrumSummary {
+            index: 41,
+            time: 12.3,
+            ms_level: 1,
+            ..Default::default()
+        });
+        assert_eq!(index.index_nearest_time(12.4), Some(41));
+    }
+
+    #[test]
+    fn test_summary_without_arrays() {
+        let spectrum: MultiLayerSpectrum = MultiLayerSpectrum {
+            arrays: Some(BinaryArrayMap::new()),
+            ..Default::default()
+        };
+        let summary = SpectrumSummary::from_spectrum(&spectrum);
+        assert_eq!(summary.base_peak_intensity, 0.0);
+    }
+
+    #[test]
+    fn test_round_trip() -> io::Result<()> {
+        let index = make_index();
+        let mut buffer = Vec::new();
+        index.to_writer(&mut buffer)?;
+        let dup = SpectrumMetadataIndex::from_reader(io::Cursor::new(buffer))?;
+        assert_eq!(index, dup);
+        Ok(())
+    }
+}
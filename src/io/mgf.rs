@@ -1,6 +1,11 @@
 /*!
 Read and write [MGF](https://www.matrixscience.com/help/data_file_help.html#GEN) files.
 Supports random access when reading from a source that supports [`io::Seek`].
+
+MGF has no place for file-level metadata, so [`MGFWriterType`] writes the
+[`MSDataFileMetadata`] it carries as a block of `#` comment lines before the first
+spectrum, which other MGF readers ignore and [`MGFReaderType`] recovers when it is
+created.
 */
 
 use std::collections::HashMap;
@@ -24,15 +29,18 @@ use mzpeaks::{
 };
 use regex::Regex;
 
+use super::filter::SpectrumFilter;
 use super::offset_index::OffsetIndex;
 use super::traits::{
-    MZFileReader, RandomAccessSpectrumIterator, SpectrumAccessError, ScanSource, ScanWriter, SeekRead,
+    MZFileReader, RandomAccessSpectrumIterator, ScanSource, ScanWriter, SeekRead, SkippedRegion,
+    SpectrumAccessError, SpectrumReadError, TryScanSource,
 };
-use super::utils::DetailLevel;
+use super::utils::{find_next_of, DetailLevel};
 use crate::meta::{
-    DataProcessing, FileDescription, InstrumentConfiguration, MSDataFileMetadata, Software,
+    Component, ComponentType, DataProcessing, FileDescription, InstrumentConfiguration,
+    MSDataFileMetadata, ProcessingMethod, Software, SourceFile,
 };
-use crate::params::{ControlledVocabulary, Param, ParamDescribed, ParamLike};
+use crate::params::{curie_to_num, ControlledVocabulary, Param, ParamDescribed, ParamLike, Unit};
 use crate::spectrum::bindata::{
     vec_as_bytes, ArrayType, BinaryArrayMap, BinaryDataArrayType, BuildArrayMapFrom,
     BuildFromArrayMap, DataArray,
@@ -43,11 +51,12 @@ use crate::spectrum::spectrum::{
 use crate::spectrum::PeakDataLevel;
 use crate::spectrum::SignalContinuity;
 use crate::spectrum::{
-    Precursor, PrecursorSelection, SelectedIon, SpectrumDescription, SpectrumLike,
+    Activation, ActivationEnergy, ActivationMethod, Precursor, PrecursorSelection, SelectedIon,
+    SpectrumDescription, SpectrumLike,
 };
 use crate::utils::neutral_mass;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum MGFParserState {
     Start,
     FileHeader,
@@ -68,6 +77,8 @@ pub enum MGFError {
     MalformedHeaderLine,
     #[error("Too many columns for peak line encountered")]
     TooManyColumnsForPeakLine,
+    #[error("The stream ended inside of a spectrum")]
+    IncompleteSpectrum,
     #[error("Encountered an IO error: {0}")]
     IOError(
         #[from]
@@ -87,6 +98,10 @@ struct SpectrumBuilderFlex<
     pub charge_array: Vec<i32>,
     pub has_charge: u32,
     pub detail_level: DetailLevel,
+    pub filter: Option<SpectrumFilter>,
+    /// Whether the [`SpectrumFilter`] has already been evaluated against the headers
+    pub filter_applied: bool,
+    pub rejected: bool,
     centroided_type: PhantomData<C>,
     deconvoluted_type: PhantomData<D>,
 }
@@ -122,6 +137,25 @@ impl<
         D: DeconvolutedPeakAdapting + From<DeconvolutedPeak>,
     > SpectrumBuilderFlex<C, D>
 {
+    fn new(detail_level: DetailLevel, filter: Option<SpectrumFilter>) -> Self {
+        Self {
+            detail_level,
+            filter,
+            ..Default::default()
+        }
+    }
+
+    /// Evaluate the [`SpectrumFilter`], if any, once all of the scan headers have been read
+    fn apply_filter(&mut self) {
+        if self.filter_applied {
+            return;
+        }
+        self.filter_applied = true;
+        if let Some(filter) = self.filter.as_ref() {
+            self.rejected = !filter.accepts(&self.description);
+        }
+    }
+
     pub fn into_spectrum(self, spectrum: &mut MultiLayerSpectrum<C, D>) {
         if self.has_charge > 0 {
             spectrum.deconvoluted_peaks = Some(
@@ -205,6 +239,14 @@ pub struct MGFReaderType<
     softwares: Vec<Software>,
     data_processings: Vec<DataProcessing>,
     pub detail_level: DetailLevel,
+    /// A filter on spectrum metadata. Spectra which are rejected are skipped during
+    /// iteration without parsing their peak lists, see [`SpectrumFilter`].
+    pub filter: Option<SpectrumFilter>,
+    /// Whether [`TryScanSource::try_next`] should recover from a spectrum which fails to parse
+    /// by skipping ahead to the next `BEGIN IONS` line instead of returning the error.
+    pub recover: bool,
+    /// The regions skipped while recovering from errors, see [`MGFReaderType::recover`]
+    pub skipped_regions: Vec<SkippedRegion<MGFReadError>>,
     centroid_type: PhantomData<C>,
     deconvoluted_type: PhantomData<D>,
 }
@@ -214,6 +256,80 @@ lazy_static! {
     static ref PEAK_SEPERATOR: Regex = Regex::new(r"\t|\s+").unwrap();
 }
 
+/// Percent-encode the characters which would break a field out of its header line,
+/// so that arbitrary text survives a round trip through the file header block
+fn escape_header_field(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '\n' => escaped.push_str("%0A"),
+            '\r' => escaped.push_str("%0D"),
+            '\t' => escaped.push_str("%09"),
+            '=' => escaped.push_str("%3D"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The inverse of [`escape_header_field`]. Unrecognized escapes are kept as-is.
+fn unescape_header_field(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('%') {
+        unescaped.push_str(&rest[..i]);
+        let c = match rest.get(i + 1..i + 3) {
+            Some("25") => '%',
+            Some("0A") => '\n',
+            Some("0D") => '\r',
+            Some("09") => '\t',
+            Some("3D") => '=',
+            _ => {
+                unescaped.push('%');
+                rest = &rest[i + 1..];
+                continue;
+            }
+        };
+        unescaped.push(c);
+        rest = &rest[i + 3..];
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Encode a [`Param`] as tab-separated accession, name, value and unit accession
+/// fields for the file header block
+fn format_header_param(param: &Param) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        param
+            .controlled_vocabulary
+            .and_then(|_| param.curie())
+            .unwrap_or_default(),
+        escape_header_field(&param.name),
+        escape_header_field(&param.value),
+        param.unit.for_param().0
+    )
+}
+
+/// The inverse of [`format_header_param`]
+fn parse_header_param(value: &str) -> Param {
+    let mut parts = value.splitn(4, '\t');
+    let curie = parts.next().unwrap_or_default();
+    let mut param = Param::new_key_value(
+        unescape_header_field(parts.next().unwrap_or_default()),
+        unescape_header_field(parts.next().unwrap_or_default()),
+    );
+    if !curie.is_empty() {
+        let (controlled_vocabulary, accession) = curie_to_num(curie);
+        param.controlled_vocabulary = controlled_vocabulary;
+        param.accession = accession;
+    }
+    param.unit = Unit::from_accession(parts.next().unwrap_or_default());
+    param
+}
+
 impl<
         R: io::Read,
         C: CentroidPeakAdapting + From<CentroidPeak>,
@@ -228,6 +344,11 @@ impl<
         let mut chars = line.chars();
         let first = chars.next().unwrap();
         if first.is_numeric() {
+            // The first peak line ends the scan headers
+            builder.apply_filter();
+            if builder.rejected {
+                return Some(true);
+            }
             let parts: Vec<&str> = PEAK_SEPERATOR.split(line).collect();
             let nparts = parts.len();
             if !(2..=3).contains(&nparts) {
@@ -236,13 +357,20 @@ impl<
                 return None;
             }
             if !matches!(builder.detail_level, DetailLevel::MetadataOnly) {
-                let mz: f64 = parts[0].parse().unwrap();
-                let intensity: f32 = parts[1].parse().unwrap();
+                let charge = parts.get(2).map(|c| c.parse::<i32>()).transpose();
+                let (mz, intensity, charge) =
+                    match (parts[0].parse::<f64>(), parts[1].parse::<f32>(), charge) {
+                        (Ok(mz), Ok(intensity), Ok(charge)) => (mz, intensity, charge),
+                        _ => {
+                            self.state = MGFParserState::Error;
+                            self.error = Some(MGFError::MalformedPeakLine);
+                            return None;
+                        }
+                    };
                 builder.mz_array.push(mz);
                 builder.intensity_array.push(intensity);
 
-                if nparts == 3 {
-                    let charge = parts[2].parse().unwrap();
+                if let Some(charge) = charge {
                     builder.charge_array.push(charge);
                     builder.has_charge += 1;
                 } else {
@@ -256,6 +384,20 @@ impl<
         }
     }
 
+    fn first_precursor_mut(builder: &mut SpectrumBuilderFlex<C, D>) -> &mut Precursor {
+        let precursors = &mut builder.description.precursors;
+        if precursors.is_empty() {
+            precursors.push(Precursor::default());
+        }
+        precursors.first_mut().unwrap()
+    }
+
+    fn malformed_header(&mut self) -> bool {
+        self.state = MGFParserState::Error;
+        self.error = Some(MGFError::MalformedHeaderLine);
+        false
+    }
+
     fn handle_scan_header_flex(
         &mut self,
         line: &str,
@@ -264,10 +406,13 @@ impl<
         let peak_line = self
             .parse_peak_from_line_flex(line, builder)
             .unwrap_or(false);
-        if peak_line {
+        if self.state == MGFParserState::Error {
+            false
+        } else if peak_line {
             self.state = MGFParserState::Peaks;
             true
         } else if line == "END IONS" {
+            builder.apply_filter();
             self.state = MGFParserState::Between;
             true
         } else if line.contains('=') {
@@ -276,29 +421,68 @@ impl<
             match key {
                 "TITLE" => builder.description.id = value.to_string(),
                 "RTINSECONDS" => {
+                    let time = match value.parse::<f64>() {
+                        Ok(time) => time,
+                        Err(_) => return self.malformed_header(),
+                    };
                     let scan_ev = builder
                         .description
                         .acquisition
                         .first_scan_mut()
                         .expect("Automatically adds scan event");
-                    scan_ev.start_time = value.parse::<f64>().unwrap() / 60.0
+                    scan_ev.start_time = time / 60.0
                 }
                 "PEPMASS" => {
                     let mut parts = value.split_ascii_whitespace();
-                    let mz: f64 = parts.next().unwrap().parse().unwrap();
-                    let intensity: f32 = parts.next().and_then(|v| {
-                        Some(v.parse().unwrap())
-                    }).unwrap_or_default();
-                    let charge: Option<i32> = parts.next().map(|c| c.parse().unwrap());
-                    builder.description.precursor = Some(Precursor {
-                        ion: SelectedIon {
-                            mz,
-                            intensity,
-                            charge,
-                            ..Default::default()
-                        },
+                    let mz = parts.next().map(|v| v.parse::<f64>());
+                    let intensity = parts.next().map(|v| v.parse::<f32>()).transpose();
+                    let charge = parts.next().map(|c| c.parse::<i32>()).transpose();
+                    let (mz, intensity, charge) = match (mz, intensity, charge) {
+                        (Some(Ok(mz)), Ok(intensity), Ok(charge)) => {
+                            (mz, intensity.unwrap_or_default(), charge)
+                        }
+                        _ => return self.malformed_header(),
+                    };
+                    Self::first_precursor_mut(builder).ions = vec![SelectedIon {
+                        mz,
+                        intensity,
+                        charge,
                         ..Default::default()
-                    });
+                    }];
+                }
+                "ACTIVATIONMETHOD" => {
+                    let activation = &mut Self::first_precursor_mut(builder).activation;
+                    for name in value.split(';') {
+                        activation.add_method(ActivationMethod::from_name(name.trim()));
+                    }
+                }
+                "COLLISIONENERGY" | "NCE" | "SUPPLEMENTALCOLLISIONENERGY" => {
+                    let (unit, supplemental) = match key {
+                        "NCE" => (Unit::PercentElectronVolt, false),
+                        "SUPPLEMENTALCOLLISIONENERGY" => (Unit::Electronvolt, true),
+                        _ => (Unit::Electronvolt, false),
+                    };
+                    let energies: Result<Vec<f32>, _> =
+                        value.split(';').map(|energy| energy.trim().parse()).collect();
+                    match energies {
+                        Ok(energies) => {
+                            let activation = &mut Self::first_precursor_mut(builder).activation;
+                            for energy in energies {
+                                activation.add_energy(ActivationEnergy::new(
+                                    energy,
+                                    unit,
+                                    supplemental,
+                                ));
+                            }
+                        }
+                        // Keep values which are not plain numbers, e.g. with units, as-is
+                        Err(_) => {
+                            builder.description.add_param(Param::new_key_value(
+                                key.to_lowercase(),
+                                String::from(value),
+                            ));
+                        }
+                    }
                 }
                 &_ => {
                     builder.description.add_param(Param::new_key_value(
@@ -310,9 +494,7 @@ impl<
 
             true
         } else {
-            self.state = MGFParserState::Error;
-            self.error = Some(MGFError::MalformedHeaderLine);
-            false
+            self.malformed_header()
         }
     }
 
@@ -320,7 +502,9 @@ impl<
         let peak_line = self
             .parse_peak_from_line_flex(line, builder)
             .unwrap_or(false);
-        if peak_line {
+        if self.state == MGFParserState::Error {
+            false
+        } else if peak_line {
             true
         } else if line == "END IONS" {
             self.state = MGFParserState::Between;
@@ -352,28 +536,37 @@ impl<
     }
 
     /// Read the next spectrum from the file, if there is one.
+    ///
+    /// If [`MGFReaderType::filter`] is set, spectra it rejects are skipped.
     pub fn read_next(&mut self) -> Option<MultiLayerSpectrum<C, D>> {
-        let mut builder = SpectrumBuilderFlex::<C, D>::default();
-        match self._parse_into_flex(&mut builder) {
-            Ok(offset) => {
-                if offset > 0 {
-                    Some(builder.into())
-                } else {
-                    None
+        loop {
+            let mut builder =
+                SpectrumBuilderFlex::<C, D>::new(self.detail_level, self.filter.clone());
+            match self._parse_into_flex(&mut builder) {
+                Ok(offset) => {
+                    if offset == 0 {
+                        return None;
+                    }
+                    if builder.rejected {
+                        continue;
+                    }
+                    return Some(builder.into());
+                }
+                Err((err, _state)) => {
+                    eprintln!("An error was encountered: {err:?}");
+                    return None;
                 }
-            }
-            Err(err) => {
-                eprintln!("An error was encountered: {err:?}");
-                None
             }
         }
     }
 
     /// Read the next spectrum's contents directly into the passed struct.
+    ///
+    /// On failure, the error is returned along with the state the parser was in.
     fn _parse_into_flex(
         &mut self,
         builder: &mut SpectrumBuilderFlex<C, D>,
-    ) -> Result<usize, MGFError> {
+    ) -> Result<usize, (MGFError, MGFParserState)> {
         let mut buffer = String::new();
         let mut work = true;
         let mut offset: usize = 0;
@@ -387,12 +580,21 @@ impl<
                     b
                 }
                 Err(err) => {
+                    let state = self.state;
                     self.state = MGFParserState::Error;
-                    return Err(MGFError::IOError(err));
+                    return Err((MGFError::IOError(err), state));
                 }
             };
             offset += b;
             if b == 0 {
+                if matches!(
+                    self.state,
+                    MGFParserState::ScanHeaders | MGFParserState::Peaks
+                ) {
+                    let state = self.state;
+                    self.state = MGFParserState::Error;
+                    return Err((MGFError::IncompleteSpectrum, state));
+                }
                 self.state = MGFParserState::Done;
                 break;
             }
@@ -401,6 +603,7 @@ impl<
             if n == 0 {
                 continue;
             }
+            let state = self.state;
             if self.state == MGFParserState::Start {
                 work = self.handle_start(line);
             } else if self.state == MGFParserState::Between {
@@ -414,7 +617,7 @@ impl<
                 let mut err = None;
                 mem::swap(&mut self.error, &mut err);
                 self.error = None;
-                return Err(err.unwrap());
+                return Err((err.unwrap(), state));
             }
         }
         Ok(offset)
@@ -424,13 +627,182 @@ impl<
         &mut self,
         spectrum: &mut MultiLayerSpectrum<C, D>,
     ) -> Result<usize, MGFError> {
-        let mut accumulator = SpectrumBuilderFlex::default();
-        match self._parse_into_flex(&mut accumulator) {
-            Ok(sz) => {
-                accumulator.into_spectrum(spectrum);
-                Ok(sz)
+        let mut skipped = 0;
+        loop {
+            let mut accumulator = SpectrumBuilderFlex::new(self.detail_level, self.filter.clone());
+            match self._parse_into_flex(&mut accumulator) {
+                Ok(sz) => {
+                    if accumulator.rejected && sz > 0 {
+                        skipped += sz;
+                        continue;
+                    }
+                    accumulator.into_spectrum(spectrum);
+                    return Ok(sz + skipped);
+                }
+                Err((err, _state)) => return Err(err),
+            }
+        }
+    }
+
+    /// Read the block of `#` comment lines preceding the first spectrum, recovering
+    /// any file-level metadata written there by [`MGFWriterType`]
+    fn read_file_header(&mut self) -> io::Result<()> {
+        let mut buffer = String::new();
+        let mut has_contents = false;
+        let mut instrument_id = None;
+        loop {
+            let next = self.handle.fill_buf()?;
+            if next.first() != Some(&b'#') {
+                break;
+            }
+            buffer.clear();
+            self.handle.read_line(&mut buffer)?;
+            let line = buffer.trim_end_matches(['\r', '\n']);
+            if let Some((key, value)) = line[1..].split_once('=') {
+                self.handle_file_header_line(key, value, &mut has_contents, &mut instrument_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_file_header_line(
+        &mut self,
+        key: &str,
+        value: &str,
+        has_contents: &mut bool,
+        instrument_id: &mut Option<u32>,
+    ) {
+        let mut fields = value.split('\t');
+        let mut next_field = || unescape_header_field(fields.next().unwrap_or_default());
+        match key {
+            "FILE_CONTENT" => {
+                // Replace the default content description with the recorded one
+                if !*has_contents {
+                    self.file_description.contents.clear();
+                    *has_contents = true;
+                }
+                self.file_description
+                    .contents
+                    .push(parse_header_param(value));
+            }
+            "SOURCE_FILE" => {
+                let source_file = SourceFile {
+                    id: next_field(),
+                    name: next_field(),
+                    location: next_field(),
+                    ..Default::default()
+                };
+                self.file_description.source_files.push(source_file);
             }
-            Err(err) => Err(err),
+            "SOURCE_FILE_FORMAT" => {
+                if let Some(sf) = self.file_description.source_files.last_mut() {
+                    sf.file_format = Some(parse_header_param(value));
+                }
+            }
+            "SOURCE_FILE_ID_FORMAT" => {
+                if let Some(sf) = self.file_description.source_files.last_mut() {
+                    sf.id_format = Some(parse_header_param(value));
+                }
+            }
+            "SOURCE_FILE_PARAM" => {
+                if let Some(sf) = self.file_description.source_files.last_mut() {
+                    sf.add_param(parse_header_param(value));
+                }
+            }
+            "SOFTWARE" => {
+                let software = Software {
+                    id: next_field(),
+                    version: next_field(),
+                    ..Default::default()
+                };
+                self.softwares.push(software);
+            }
+            "SOFTWARE_PARAM" => {
+                if let Some(sw) = self.softwares.last_mut() {
+                    sw.add_param(parse_header_param(value));
+                }
+            }
+            "INSTRUMENT_CONFIGURATION" => match next_field().parse::<u32>() {
+                Ok(id) => {
+                    let config = InstrumentConfiguration {
+                        id,
+                        software_reference: next_field(),
+                        ..Default::default()
+                    };
+                    self.instrument_configurations.insert(id, config);
+                    *instrument_id = Some(id);
+                }
+                Err(_) => {
+                    warn!(
+                        "Malformed instrument configuration in MGF header: {}",
+                        value
+                    );
+                    *instrument_id = None;
+                }
+            },
+            "INSTRUMENT_CONFIGURATION_PARAM" => {
+                if let Some(config) = instrument_id
+                    .as_ref()
+                    .and_then(|id| self.instrument_configurations.get_mut(id))
+                {
+                    config.add_param(parse_header_param(value));
+                }
+            }
+            "COMPONENT" => {
+                if let Some(config) = instrument_id
+                    .as_ref()
+                    .and_then(|id| self.instrument_configurations.get_mut(id))
+                {
+                    let component_type = match next_field().as_str() {
+                        "Analyzer" => ComponentType::Analyzer,
+                        "IonSource" => ComponentType::IonSource,
+                        "Detector" => ComponentType::Detector,
+                        _ => ComponentType::Unknown,
+                    };
+                    let component = Component {
+                        component_type,
+                        order: next_field().parse().unwrap_or_default(),
+                        ..Default::default()
+                    };
+                    config.components.push(component);
+                }
+            }
+            "COMPONENT_PARAM" => {
+                if let Some(component) = instrument_id
+                    .as_ref()
+                    .and_then(|id| self.instrument_configurations.get_mut(id))
+                    .and_then(|config| config.components.last_mut())
+                {
+                    component.add_param(parse_header_param(value));
+                }
+            }
+            "DATA_PROCESSING" => {
+                let data_processing = DataProcessing {
+                    id: next_field(),
+                    ..Default::default()
+                };
+                self.data_processings.push(data_processing);
+            }
+            "PROCESSING_METHOD" => {
+                if let Some(dp) = self.data_processings.last_mut() {
+                    let method = ProcessingMethod {
+                        order: next_field().parse().unwrap_or_default(),
+                        software_reference: next_field(),
+                        ..Default::default()
+                    };
+                    dp.push(method);
+                }
+            }
+            "PROCESSING_METHOD_PARAM" => {
+                if let Some(method) = self
+                    .data_processings
+                    .last_mut()
+                    .and_then(|dp| dp.methods.last_mut())
+                {
+                    method.add_param(parse_header_param(value));
+                }
+            }
+            _ => {}
         }
     }
 
@@ -447,7 +819,7 @@ impl<
     /// Create a new, unindexed MGF parser
     pub fn new(file: R) -> MGFReaderType<R, C, D> {
         let handle = io::BufReader::with_capacity(500, file);
-        MGFReaderType {
+        let mut reader = MGFReaderType {
             handle,
             state: MGFParserState::Start,
             offset: 0,
@@ -460,7 +832,14 @@ impl<
             softwares: Vec::new(),
             file_description: Self::default_file_description(),
             detail_level: DetailLevel::Full,
+            filter: None,
+            recover: false,
+            skipped_regions: Vec::new(),
+        };
+        if let Err(err) = reader.read_file_header() {
+            warn!("Failed to read the MGF file header: {}", err);
         }
+        reader
     }
 }
 
@@ -496,6 +875,54 @@ impl<
         self.handle.seek(pos)
     }
 
+    /// Find the offset of the first `BEGIN IONS` line starting at or after `offset`, using
+    /// the offset index if it has been built.
+    fn next_spectrum_offset(&mut self, offset: u64) -> io::Result<Option<u64>> {
+        if self.index.init {
+            return Ok(self.index.first_at_or_after(offset).map(|(_, _, o)| o));
+        }
+        let hit = find_next_of(&mut self.handle, offset, &[b"BEGIN IONS"])?;
+        Ok(hit.map(|(o, _)| o))
+    }
+
+    /// Move past the spectrum which failed to parse with `error` after reading from `start`,
+    /// to the next spectrum or the end of the stream, and record the skipped region.
+    fn skip_failed_spectrum(
+        &mut self,
+        start: u64,
+        error: MGFReadError,
+    ) -> Result<(), MGFReadError> {
+        let io_error = |err: io::Error, offset: u64| {
+            MGFReadError::new(MGFError::IOError(err), MGFParserState::Error, offset)
+        };
+        let first = self
+            .next_spectrum_offset(start)
+            .map_err(|e| io_error(e, start))?;
+        // Resume after the spectrum that failed, unless the error came before it started
+        let next = match first {
+            Some(offset) if offset < error.offset || offset <= start => self
+                .next_spectrum_offset(offset + 1)
+                .map_err(|e| io_error(e, offset))?,
+            next => next,
+        };
+        let end = match next {
+            Some(offset) => {
+                self.state = MGFParserState::Between;
+                self.seek(SeekFrom::Start(offset))
+            }
+            None => {
+                self.state = MGFParserState::Done;
+                self.seek(SeekFrom::End(0))
+            }
+        }
+        .map_err(|e| io_error(e, error.offset))?;
+        self.error = None;
+        let region = SkippedRegion { start, end, error };
+        warn!("{region}");
+        self.skipped_regions.push(region);
+        Ok(())
+    }
+
     /// Builds an offset index to each `BEGIN IONS` line
     /// by doing a fast pre-scan of the text file.
     pub fn build_index(&mut self) -> u64 {
@@ -566,7 +993,9 @@ impl<
             .expect("Failed to save checkpoint");
         self.seek(SeekFrom::Start(offset))
             .expect("Failed to move seek to offset");
+        let filter = self.filter.take();
         let result = self.read_next();
+        self.filter = filter;
         self.seek(SeekFrom::Start(start))
             .expect("Failed to restore offset");
         match result {
@@ -587,7 +1016,9 @@ impl<
             .stream_position()
             .expect("Failed to save checkpoint");
         self.seek(SeekFrom::Start(byte_offset)).ok()?;
+        let filter = self.filter.take();
         let result = self.read_next();
+        self.filter = filter;
         self.seek(SeekFrom::Start(start))
             .expect("Failed to restore offset");
         match result {
@@ -654,6 +1085,45 @@ impl<
     }
 }
 
+/// Unlike iteration, reading with [`TryScanSource::try_next`] reports why reading stopped,
+/// including where in the stream a malformed or truncated spectrum was found.
+impl<
+        R: SeekRead,
+        C: CentroidPeakAdapting + From<CentroidPeak>,
+        D: DeconvolutedPeakAdapting + From<DeconvolutedPeak>,
+    > TryScanSource<C, D, MultiLayerSpectrum<C, D>> for MGFReaderType<R, C, D>
+{
+    type Error = MGFReadError;
+
+    fn try_next(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, Self::Error> {
+        loop {
+            let start = self
+                .handle
+                .stream_position()
+                .map_err(|err| MGFReadError::new(MGFError::IOError(err), self.state, 0))?;
+            let mut builder =
+                SpectrumBuilderFlex::<C, D>::new(self.detail_level, self.filter.clone());
+            match self._parse_into_flex(&mut builder) {
+                Ok(_) if self.state == MGFParserState::Done => return Ok(None),
+                Ok(_) if builder.rejected => continue,
+                Ok(_) => return Ok(Some(builder.into())),
+                Err((err, state)) => {
+                    let offset = self.handle.stream_position().unwrap_or(start);
+                    let mut err = MGFReadError::new(err, state, offset)
+                        .with_spectrum_from(&self.index, start);
+                    if err.id.is_none() && !builder.description.id.is_empty() {
+                        err = err.with_id(builder.description.id);
+                    }
+                    if !self.recover {
+                        return Err(err);
+                    }
+                    self.skip_failed_spectrum(start, err)?;
+                }
+            }
+        }
+    }
+}
+
 impl<
         C: CentroidPeakAdapting + From<CentroidPeak>,
         D: DeconvolutedPeakAdapting + From<DeconvolutedPeak>,
@@ -687,6 +1157,9 @@ impl<
 
 pub type MGFReader<R> = MGFReaderType<R, CentroidPeak, DeconvolutedPeak>;
 
+/// An error raised while reading a spectrum from an MGF stream with [`TryScanSource::try_next`]
+pub type MGFReadError = SpectrumReadError<MGFError, MGFParserState>;
+
 pub(crate) fn is_mgf(buf: &[u8]) -> bool {
     let needle = b"BEGIN IONS";
     if let Some(_loc) = buf
@@ -707,6 +1180,11 @@ pub struct MGFWriterType<
 > {
     pub handle: io::BufWriter<W>,
     pub offset: usize,
+    file_description: FileDescription,
+    instrument_configurations: HashMap<u32, InstrumentConfiguration>,
+    softwares: Vec<Software>,
+    data_processings: Vec<DataProcessing>,
+    wrote_file_header: bool,
     centroid_type: PhantomData<C>,
     deconvoluted_type: PhantomData<D>,
 }
@@ -722,6 +1200,11 @@ impl<
         MGFWriterType {
             handle,
             offset: 0,
+            file_description: FileDescription::default(),
+            instrument_configurations: HashMap::new(),
+            softwares: Vec::new(),
+            data_processings: Vec::new(),
+            wrote_file_header: false,
             centroid_type: PhantomData,
             deconvoluted_type: PhantomData,
         }
@@ -731,6 +1214,99 @@ impl<
         self.handle
     }
 
+    /// Write the file-level metadata as a block of `#` comment lines. This happens
+    /// at most once, when the first spectrum is written, so metadata must be set
+    /// before then.
+    fn write_file_header(&mut self) -> io::Result<()> {
+        if self.wrote_file_header {
+            return Ok(());
+        }
+        self.wrote_file_header = true;
+        let handle = &mut self.handle;
+        for param in self.file_description.contents.iter() {
+            writeln!(handle, "#FILE_CONTENT={}", format_header_param(param))?;
+        }
+        for sf in self.file_description.source_files.iter() {
+            writeln!(
+                handle,
+                "#SOURCE_FILE={}\t{}\t{}",
+                escape_header_field(&sf.id),
+                escape_header_field(&sf.name),
+                escape_header_field(&sf.location)
+            )?;
+            if let Some(param) = sf.file_format.as_ref() {
+                writeln!(handle, "#SOURCE_FILE_FORMAT={}", format_header_param(param))?;
+            }
+            if let Some(param) = sf.id_format.as_ref() {
+                writeln!(
+                    handle,
+                    "#SOURCE_FILE_ID_FORMAT={}",
+                    format_header_param(param)
+                )?;
+            }
+            for param in sf.params() {
+                writeln!(handle, "#SOURCE_FILE_PARAM={}", format_header_param(param))?;
+            }
+        }
+        for sw in self.softwares.iter() {
+            writeln!(
+                handle,
+                "#SOFTWARE={}\t{}",
+                escape_header_field(&sw.id),
+                escape_header_field(&sw.version)
+            )?;
+            for param in sw.params() {
+                writeln!(handle, "#SOFTWARE_PARAM={}", format_header_param(param))?;
+            }
+        }
+        let mut configs: Vec<_> = self.instrument_configurations.values().collect();
+        configs.sort_by_key(|config| config.id);
+        for config in configs {
+            writeln!(
+                handle,
+                "#INSTRUMENT_CONFIGURATION={}\t{}",
+                config.id,
+                escape_header_field(&config.software_reference)
+            )?;
+            for param in config.params() {
+                writeln!(
+                    handle,
+                    "#INSTRUMENT_CONFIGURATION_PARAM={}",
+                    format_header_param(param)
+                )?;
+            }
+            for component in config.components.iter() {
+                writeln!(
+                    handle,
+                    "#COMPONENT={}\t{}",
+                    component.component_type, component.order
+                )?;
+                for param in component.params() {
+                    writeln!(handle, "#COMPONENT_PARAM={}", format_header_param(param))?;
+                }
+            }
+        }
+        for dp in self.data_processings.iter() {
+            writeln!(handle, "#DATA_PROCESSING={}", escape_header_field(&dp.id))?;
+            for method in dp.iter() {
+                writeln!(
+                    handle,
+                    "#PROCESSING_METHOD={}\t{}",
+                    method.order,
+                    escape_header_field(&method.software_reference)
+                )?;
+                for param in method.params() {
+                    writeln!(
+                        handle,
+                        "#PROCESSING_METHOD_PARAM={}",
+                        format_header_param(param)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn write_param<P: ParamLike>(&mut self, param: &P) -> io::Result<()> {
         self.handle
             .write_all(param.name().to_uppercase().as_bytes())?;
@@ -739,7 +1315,41 @@ impl<
         Ok(())
     }
 
-    fn write_header<T: SpectrumLike<C, D>>(&mut self, spectrum: &T) -> io::Result<()> {
+    /// Write the activation methods and energies of a precursor, joining stepped or
+    /// combined values with `;`. Only the methods' names are written, so an
+    /// [`ActivationMethod::Other`] method loses its accession when read back.
+    fn write_activation(&mut self, activation: &Activation) -> io::Result<()> {
+        if !activation.methods().is_empty() {
+            let methods: Vec<String> = activation.methods().iter().map(|m| m.name()).collect();
+            writeln!(self.handle, "ACTIVATIONMETHOD={}", methods.join(";"))?;
+        }
+        let keys = [
+            ("COLLISIONENERGY", false, false),
+            ("NCE", true, false),
+            ("SUPPLEMENTALCOLLISIONENERGY", false, true),
+        ];
+        for (key, normalized, supplemental) in keys.iter() {
+            let values: Vec<String> = activation
+                .energies
+                .iter()
+                .filter(|e| e.is_normalized() == *normalized && e.supplemental == *supplemental)
+                .map(|e| e.value.to_string())
+                .collect();
+            if !values.is_empty() {
+                writeln!(self.handle, "{}={}", key, values.join(";"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the header of an MGF entry for `spectrum` under `title`, describing one of
+    /// its selected ions, if it has any
+    fn write_header<T: SpectrumLike<C, D>>(
+        &mut self,
+        spectrum: &T,
+        title: &str,
+        selection: Option<(&Precursor, &SelectedIon)>,
+    ) -> io::Result<()> {
         let desc = spectrum.description();
         if desc.ms_level == 1 {
             log::warn!(
@@ -752,14 +1362,13 @@ impl<
             br#"BEGIN IONS
 TITLE="#,
         )?;
-        self.handle.write_all(desc.id.as_bytes())?;
+        self.handle.write_all(title.as_bytes())?;
         self.handle.write_all(b"\nRTINSECONDS=")?;
         self.handle
             .write_all(spectrum.start_time().to_string().as_bytes())?;
         self.handle.write_all(b"\n")?;
-        match &desc.precursor {
-            Some(precursor) => {
-                let ion = precursor.ion();
+        match selection {
+            Some((precursor, ion)) => {
                 self.handle.write_all(b"PEPMASS=")?;
                 self.handle.write_all(ion.mz.to_string().as_bytes())?;
                 self.handle.write_all(b" ")?;
@@ -771,14 +1380,10 @@ TITLE="#,
                 }
                 self.handle.write_all(b"\n")?;
 
-                for param in precursor
-                    .ion()
-                    .params()
-                    .iter()
-                    .chain(precursor.activation.params())
-                {
+                for param in ion.params().iter().chain(precursor.activation.params()) {
                     self.write_param(param)?;
                 }
+                self.write_activation(&precursor.activation)?;
                 if let Some(pid) = precursor.precursor_id() {
                     self.handle.write_all(b"PRECURSORSCAN=")?;
                     self.handle.write_all(pid.as_bytes())?;
@@ -854,7 +1459,28 @@ TITLE="#,
             );
             return Ok(0);
         }
-        self.write_header(spectrum)?;
+        self.write_file_header()?;
+        let selections: Vec<(&Precursor, &SelectedIon)> = spectrum
+            .precursor_iter()
+            .flat_map(|precursor| precursor.ions.iter().map(move |ion| (precursor, ion)))
+            .collect();
+        if selections.len() > 1 {
+            // MGF entries have a single precursor ion, so a spectrum with several selected
+            // ions is written once per ion, numbering the copies' titles
+            for (i, selection) in selections.into_iter().enumerate() {
+                let title = format!("{}.{}", description.id, i + 1);
+                self.write_header(spectrum, &title, Some(selection))?;
+                self.write_peaks(spectrum)?;
+            }
+        } else {
+            self.write_header(spectrum, &description.id, selections.first().copied())?;
+            self.write_peaks(spectrum)?;
+        }
+        Ok(0)
+    }
+
+    fn write_peaks<S: SpectrumLike<C, D> + 'static>(&mut self, spectrum: &S) -> io::Result<()> {
+        let description = spectrum.description();
         match spectrum.peaks() {
             PeakDataLevel::Missing => {
                 log::warn!(
@@ -871,7 +1497,7 @@ TITLE="#,
             }
         }
         self.handle.write_all(b"END IONS\n")?;
-        Ok(0)
+        Ok(())
     }
 }
 
@@ -907,6 +1533,25 @@ impl<
     }
 }
 
+impl<
+        W: io::Write,
+        C: CentroidPeakAdapting + From<CentroidPeak>,
+        D: DeconvolutedPeakAdapting + From<DeconvolutedPeak>,
+    > MSDataFileMetadata for MGFWriterType<W, C, D>
+{
+    crate::impl_metadata_trait!();
+
+    fn copy_metadata_from<T: MSDataFileMetadata>(&mut self, source: &T) {
+        if self.wrote_file_header {
+            warn!("The MGF file header was already written, metadata copied now will not be written");
+        }
+        *self.data_processings_mut() = source.data_processings().clone();
+        *self.instrument_configurations_mut() = source.instrument_configurations().clone();
+        *self.file_description_mut() = source.file_description().clone();
+        *self.softwares_mut() = source.softwares().clone();
+    }
+}
+
 /// A convenient alias for [`MGFWriterType`] with the peak types specified
 pub type MGFWriter<W> = MGFWriterType<W, CentroidPeak, DeconvolutedPeak>;
 
@@ -966,6 +1611,112 @@ mod test {
         assert_eq!(msn_count, 34);
     }
 
+    #[test]
+    fn test_try_next() {
+        let path = path::Path::new("./test/data/small.mgf");
+        let content = fs::read_to_string(path).expect("Test file doesn't exist");
+
+        let mut begins = 0;
+        let mut peaks = 0;
+        let mut lines = Vec::new();
+        for line in content.lines() {
+            if line == "BEGIN IONS" {
+                begins += 1;
+            } else if begins == 3 && line.starts_with(|c: char| c.is_ascii_digit()) {
+                peaks += 1;
+                if peaks == 2 {
+                    lines.push("233.3398285 not-a-number");
+                    continue;
+                }
+            }
+            lines.push(line);
+        }
+        let corrupted = lines.join("\n");
+
+        let mut reader = MGFReader::new_indexed(io::Cursor::new(corrupted.as_bytes()));
+        let expected_id = reader.index.get_index(2).unwrap().0.clone();
+        let mut results = reader.try_iter();
+        assert!(results.next().unwrap().is_ok());
+        assert!(results.next().unwrap().is_ok());
+        let err = results.next().unwrap().unwrap_err();
+        assert!(results.next().is_none());
+        assert!(matches!(err.source, MGFError::MalformedPeakLine));
+        assert_eq!(err.state, MGFParserState::Peaks);
+        assert_eq!(err.index, Some(2));
+        assert_eq!(err.id, Some(expected_id));
+
+        let truncated = &content[..content.rfind("END IONS").unwrap()];
+        let mut reader = MGFReader::new(io::Cursor::new(truncated.as_bytes()));
+        let err = reader
+            .try_iter()
+            .find_map(|result| result.err())
+            .expect("The truncated spectrum should fail");
+        assert!(matches!(err.source, MGFError::IncompleteSpectrum));
+        assert_eq!(err.offset, truncated.len() as u64);
+        assert!(err.id.is_some());
+    }
+
+    #[test]
+    fn test_recover() {
+        let path = path::Path::new("./test/data/small.mgf");
+        let content = fs::read_to_string(path).expect("Test file doesn't exist");
+        let n = content.matches("BEGIN IONS").count();
+        let third = content.match_indices("BEGIN IONS").nth(2).unwrap().0;
+        let fourth = content.match_indices("BEGIN IONS").nth(3).unwrap().0;
+        let mut corrupted = content.clone();
+        // Corrupt the second peak of the third spectrum
+        let mut peak = third + corrupted[third..].find("PEPMASS").unwrap();
+        for _ in 0..2 {
+            peak += corrupted[peak..].find('\n').unwrap() + 1;
+        }
+        corrupted.replace_range(peak..peak + 1, "x");
+
+        for indexed in [true, false] {
+            let mut reader = if indexed {
+                MGFReader::new_indexed(io::Cursor::new(corrupted.as_bytes()))
+            } else {
+                MGFReader::new(io::Cursor::new(corrupted.as_bytes()))
+            };
+            reader.recover = true;
+            let spectra: Result<Vec<_>, _> = reader.try_iter().collect();
+            assert_eq!(spectra.unwrap().len(), n - 1);
+            assert_eq!(reader.skipped_regions.len(), 1);
+            let region = &reader.skipped_regions[0];
+            assert!(region.start <= third as u64);
+            assert_eq!(region.end, fourth as u64);
+            assert!(matches!(region.error.source, MGFError::MalformedPeakLine));
+        }
+
+        let truncated = &content[..content.rfind("END IONS").unwrap()];
+        let mut reader = MGFReader::new(io::Cursor::new(truncated.as_bytes()));
+        reader.recover = true;
+        let spectra: Result<Vec<_>, _> = reader.try_iter().collect();
+        assert_eq!(spectra.unwrap().len(), n - 1);
+        assert_eq!(reader.skipped_regions.len(), 1);
+        assert_eq!(reader.skipped_regions[0].end, truncated.len() as u64);
+    }
+
+    #[test]
+    fn test_reader_filter() {
+        let path = path::Path::new("./test/data/small.mgf");
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let mzs: Vec<f64> = MGFReaderType::<_>::new(file)
+            .map(|s| s.precursor().unwrap().ion().mz)
+            .collect();
+        let cutoff = mzs[mzs.len() / 2];
+
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let mut reader = MGFReaderType::<_>::new(file);
+        reader.filter = Some(SpectrumFilter::new().precursor_mz_range(0.0..=cutoff));
+        let mut n = 0;
+        for scan in reader {
+            assert!(scan.precursor().unwrap().ion().mz <= cutoff);
+            assert!(!scan.peaks.as_ref().unwrap().is_empty());
+            n += 1;
+        }
+        assert_eq!(n, mzs.iter().filter(|mz| **mz <= cutoff).count());
+    }
+
     #[test]
     fn test_writer() -> io::Result<()> {
         let buff: Vec<u8> = Vec::new();
@@ -988,4 +1739,153 @@ mod test {
         // Not including platform-specific line endings
         Ok(())
     }
+
+    #[test]
+    fn test_writer_metadata() -> io::Result<()> {
+        let source = crate::io::mzml::MzMLReader::open_path("./test/data/small.mzML")?;
+
+        let mut writer = MGFWriter::new(io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&source);
+
+        let path = path::Path::new("./test/data/small.mgf");
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let reader = MGFReader::new(file);
+        assert_eq!(
+            reader.file_description().contents,
+            vec![MGFReader::<fs::File>::default_file_description().contents[0].clone()]
+        );
+        assert!(reader.softwares().is_empty());
+
+        let scans: Vec<_> = reader.collect();
+        for scan in scans.iter() {
+            writer.write(scan)?;
+        }
+        writer.flush()?;
+        let buffer = writer.handle.into_inner()?.into_inner();
+        assert!(buffer.starts_with(b"#FILE_CONTENT="));
+
+        let reader2 = MGFReader::new(io::Cursor::new(buffer));
+        assert_eq!(reader2.file_description(), source.file_description());
+        assert_eq!(reader2.softwares(), source.softwares());
+        assert_eq!(reader2.data_processings(), source.data_processings());
+        assert_eq!(
+            reader2.instrument_configurations(),
+            source.instrument_configurations()
+        );
+        assert_eq!(reader2.count(), scans.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_writer_metadata_escaping() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mgf");
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let scans: Vec<_> = MGFReader::new(file).collect();
+
+        let mut writer = MGFWriter::new(io::Cursor::new(Vec::new()));
+        // Flushing before any spectrum is written does not write the header yet
+        writer.flush()?;
+        let software = Software {
+            id: "tool\nwith=odd%chars".to_string(),
+            version: "1.0\t\r".to_string(),
+            ..Default::default()
+        };
+        writer.softwares_mut().push(software.clone());
+        writer.file_description_mut().contents.push(Param::new_key_value(
+            "note".to_string(),
+            "a=b\nc".to_string(),
+        ));
+        writer.write(&scans[0])?;
+        writer.flush()?;
+        let buffer = writer.handle.into_inner()?.into_inner();
+
+        let reader = MGFReader::new(io::Cursor::new(buffer));
+        assert_eq!(reader.softwares(), &vec![software]);
+        assert_eq!(reader.file_description().contents[0].value, "a=b\nc");
+        assert_eq!(reader.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_writer_multiple_ions() -> io::Result<()> {
+        use crate::spectrum::IonProperties;
+
+        let path = path::Path::new("./test/data/small.mgf");
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let mut scan = MGFReader::new(file).next().unwrap();
+        let mut second = Precursor::default();
+        second.ion_mut().mz = 700.5;
+        scan.description_mut().precursors.push(second);
+        scan.precursor_mut().unwrap().add_ion(SelectedIon {
+            mz: 563.24,
+            charge: Some(3),
+            ..Default::default()
+        });
+
+        let mut writer = MGFWriter::new(io::Cursor::new(Vec::new()));
+        writer.write(&scan)?;
+        writer.flush()?;
+        let buffer = writer.handle.into_inner()?.into_inner();
+
+        let entries: Vec<_> = MGFReader::new(io::Cursor::new(buffer)).collect();
+        assert_eq!(entries.len(), 3);
+        let mzs: Vec<f64> = entries
+            .iter()
+            .map(|s| s.precursor().unwrap().mz())
+            .collect();
+        assert_eq!(&mzs[1..], &[563.24, 700.5]);
+        assert_eq!(entries[0].id(), format!("{}.1", scan.id()));
+        assert_eq!(entries[2].id(), format!("{}.3", scan.id()));
+        assert_eq!(entries[1].precursor().unwrap().charge(), Some(3));
+        assert_eq!(entries[0].peaks().len(), scan.peaks().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_writer_combined_activation() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mgf");
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let mut scan = MGFReader::new(file).next().unwrap();
+        let activation = &mut scan.precursor_mut().unwrap().activation;
+        activation.add_method(ActivationMethod::ElectronTransferDissociation);
+        activation.add_method(ActivationMethod::SupplementalBeamTypeCollisionInducedDissociation);
+        for nce in [25.0, 35.0].iter() {
+            let energy = ActivationEnergy::new(*nce, Unit::PercentElectronVolt, false);
+            activation.add_energy(energy);
+        }
+        activation.add_energy(ActivationEnergy::new(20.0, Unit::Electronvolt, true));
+
+        let mut writer = MGFWriter::new(io::Cursor::new(Vec::new()));
+        writer.write(&scan)?;
+        writer.flush()?;
+        let buffer = writer.handle.into_inner()?.into_inner();
+        let text = String::from_utf8_lossy(&buffer).to_string();
+        assert!(text.contains(
+            "ACTIVATIONMETHOD=electron transfer dissociation;supplemental beam-type collision-induced dissociation\n"
+        ));
+        assert!(text.contains("NCE=25;35\n"));
+
+        let dup = MGFReader::new(io::Cursor::new(buffer)).next().unwrap();
+        let activation = &dup.precursor().unwrap().activation;
+        assert_eq!(activation, &scan.precursor().unwrap().activation);
+        assert!(activation.is_combined());
+        assert_eq!(activation.is_collisional(), Some(true));
+        let mz = scan.precursor().unwrap().ion().mz;
+        assert_eq!(dup.precursor().unwrap().ion().mz, mz);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_unparsed_energy() {
+        let text = b"BEGIN IONS\nTITLE=test\nPEPMASS=500.5\nCOLLISIONENERGY=35 eV\nNCE=\n100.0 20.0\nEND IONS\n";
+        let scans: Vec<_> = MGFReader::new(io::Cursor::new(text.to_vec())).collect();
+        assert_eq!(scans.len(), 1);
+        let scan = &scans[0];
+        assert!(scan.precursor().unwrap().activation.energies.is_empty());
+        assert_eq!(
+            scan.description().get_param_by_name("collisionenergy").unwrap().value,
+            "35 eV"
+        );
+        assert!(scan.description().get_param_by_name("nce").is_some());
+    }
 }
@@ -20,6 +20,8 @@ mod reader;
 mod reading_shared;
 mod writer;
 
+pub mod validate;
+
 #[cfg(feature = "async")]
 mod r#async;
 
@@ -32,7 +34,7 @@ pub use reading_shared::{
 pub(crate) use reading_shared::{IncrementingIdMap, ParserResult};
 
 pub use crate::io::mzml::reader::{
-    MzMLReader, MzMLReaderType, MzMLSpectrumBuilder,
+    MzMLReadError, MzMLReader, MzMLReaderType, MzMLSpectrumBuilder,
     SpectrumBuilding,
 };
 
@@ -22,8 +22,11 @@ use quick_xml::Reader;
 use crate::SpectrumLike;
 
 use crate::io::utils::DetailLevel;
-use crate::meta::{FileDescription, InstrumentConfiguration, DataProcessing, MSDataFileMetadata, Software, MassSpectrometryRun};
-use crate::params::Param;
+use crate::meta::{
+    CVEntry, DataProcessing, FileDescription, InstrumentConfiguration, MSDataFileMetadata,
+    MassSpectrometryRun, Sample, ScanSettings, Software,
+};
+use crate::params::{Param, ParamGroup};
 use crate::spectrum::bindata::BuildFromArrayMap;
 use crate::spectrum::spectrum::{
     CentroidPeakAdapting, DeconvolutedPeakAdapting, MultiLayerSpectrum,
@@ -70,8 +73,14 @@ pub struct MzMLReaderType<
     /// The data processing and signal transformation operations performed on the raw data in previous
     /// source files to produce this file's contents.
     pub(crate) data_processings: Vec<DataProcessing>,
-    /// A cache of repeated paramters
-    pub reference_param_groups: HashMap<String, Vec<Param>>,
+    /// A cache of repeated paramters, in the order they were defined in the document
+    pub param_groups: Vec<ParamGroup>,
+    /// The samples analyzed to produce this file
+    pub(crate) samples: Vec<Sample>,
+    /// The acquisition settings of the instrument, including any target lists
+    pub(crate) scan_settings: Vec<ScanSettings>,
+    /// The controlled vocabularies the file declares
+    pub(crate) cv_list: Vec<CVEntry>,
 
     pub detail_level: DetailLevel,
 
@@ -100,6 +109,17 @@ impl<
         Self::with_buffer_capacity_and_detail_level(file, BUFFER_SIZE, DetailLevel::Full).await
     }
 
+    /// The parameters of each referenceable param group, keyed by group id.
+    ///
+    /// This is built from [`MzMLReaderType::param_groups`] on each call.
+    #[deprecated(note = "Use `param_groups` instead")]
+    pub fn reference_param_group_map(&self) -> HashMap<String, Vec<Param>> {
+        self.param_groups
+            .iter()
+            .map(|group| (group.id.clone(), group.params.clone()))
+            .collect()
+    }
+
     pub async fn with_buffer_capacity_and_detail_level(
         file: R,
         capacity: usize,
@@ -117,7 +137,10 @@ impl<
             instrument_configurations: HashMap::new(),
             softwares: Vec::new(),
             data_processings: Vec::new(),
-            reference_param_groups: HashMap::new(),
+            param_groups: Vec::new(),
+            samples: Vec::new(),
+            scan_settings: Vec::new(),
+            cv_list: Vec::new(),
             detail_level,
 
             centroid_type: PhantomData,
@@ -146,10 +169,12 @@ impl<
                     match accumulator.start_element(e, self.state) {
                         Ok(state) => {
                             self.state = state;
+                            // A run without spectra has nothing more to read here
+                            if e.name().as_ref() == b"chromatogramList" {
+                                break;
+                            }
                             match &self.state {
-                                MzMLParserState::Run
-                                | MzMLParserState::SpectrumList
-                                | MzMLParserState::Spectrum => break,
+                                MzMLParserState::SpectrumList | MzMLParserState::Spectrum => break,
                                 _ => {}
                             }
                         }
@@ -221,12 +246,9 @@ impl<
                 _ => {}
             };
             self.buffer.clear();
-            match self.state {
-                MzMLParserState::Run | MzMLParserState::ParserError => {
-                    break;
-                }
-                _ => {}
-            };
+            if self.state == MzMLParserState::ParserError {
+                break;
+            }
         }
         self.file_description = accumulator.file_description;
         self.instrument_configurations = accumulator
@@ -236,7 +258,10 @@ impl<
             .collect();
         self.softwares = accumulator.softwares;
         self.data_processings = accumulator.data_processings;
-        self.reference_param_groups = accumulator.reference_param_groups;
+        self.param_groups = accumulator.param_groups;
+        self.samples = accumulator.samples;
+        self.scan_settings = accumulator.scan_settings;
+        self.cv_list = accumulator.cv_list;
 
         self.run.id = accumulator.run_id;
         self.run.default_instrument_id = accumulator.default_instrument_config;
@@ -263,6 +288,7 @@ impl<
         let mut reader = Reader::from_reader(&mut self.handle);
         reader.trim_text(true);
         accumulator.instrument_id_map = Some(&mut self.instrument_id_map);
+        accumulator.reference_param_groups = Some(&self.param_groups);
         let mut offset: usize = 0;
         loop {
             let event = reader.read_event_into_async(&mut self.buffer).await;
@@ -430,6 +456,42 @@ impl<
     > MSDataFileMetadata for MzMLReaderType<R, C, D>
 {
     crate::impl_metadata_trait!();
+
+    fn spectrum_count_hint(&self) -> Option<u64> {
+        self.num_spectra
+    }
+
+    fn reference_param_groups(&self) -> Option<&Vec<ParamGroup>> {
+        Some(&self.param_groups)
+    }
+
+    fn reference_param_groups_mut(&mut self) -> Option<&mut Vec<ParamGroup>> {
+        Some(&mut self.param_groups)
+    }
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        Some(&self.samples)
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        Some(&mut self.samples)
+    }
+
+    fn scan_settings(&self) -> Option<&Vec<ScanSettings>> {
+        Some(&self.scan_settings)
+    }
+
+    fn scan_settings_mut(&mut self) -> Option<&mut Vec<ScanSettings>> {
+        Some(&mut self.scan_settings)
+    }
+
+    fn cv_list(&self) -> Option<&Vec<CVEntry>> {
+        Some(&self.cv_list)
+    }
+
+    fn cv_list_mut(&mut self) -> Option<&mut Vec<CVEntry>> {
+        Some(&mut self.cv_list)
+    }
 }
 
 /// A specialization of [`AsyncMzMLReaderType`](crate::io::mzml::AsyncMzMLReaderType) for the default peak types, for common use.
@@ -837,4 +899,22 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_metadata() -> io::Result<()> {
+        let path = path::Path::new("./test/data/read_index_of.mzML");
+        let file = fs::File::open(path).await?;
+        let reader = MzMLReader::new(file).await;
+
+        assert_eq!(reader.softwares().len(), 3);
+        assert_eq!(reader.file_description().source_files[0].id, "RAW1");
+        assert_eq!(reader.spectrum_count_hint(), Some(48));
+
+        let mut writer = crate::io::mzml::MzMLWriter::new(std::io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&reader);
+        assert_eq!(writer.softwares(), reader.softwares());
+        assert_eq!(writer.file_description(), reader.file_description());
+        assert_eq!(writer.data_processings(), reader.data_processings());
+        Ok(())
+    }
 }
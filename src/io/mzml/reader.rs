@@ -12,22 +12,23 @@ use log::warn;
 use mzpeaks::CentroidLike;
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::Error as XMLError;
-use quick_xml::Reader;
+use quick_xml::{Reader, Writer};
 
 use crate::prelude::*;
 
 use super::super::offset_index::OffsetIndex;
 use super::super::traits::{
-    MZFileReader, RandomAccessSpectrumIterator, ScanSource, SeekRead, SpectrumAccessError,
+    MZFileReader, RandomAccessSpectrumIterator, ScanSource, SeekRead, SkippedRegion,
+    SpectrumAccessError, SpectrumReadError, TryScanSource,
 };
 
 use mzpeaks::{CentroidPeak, DeconvolutedPeak};
 
 use crate::meta::{
-    DataProcessing, FileDescription, InstrumentConfiguration, MSDataFileMetadata,
-    MassSpectrometryRun, Software,
+    CVEntry, DataProcessing, FileDescription, InstrumentConfiguration, MSDataFileMetadata,
+    MassSpectrometryRun, Sample, ScanSettings, Software,
 };
-use crate::params::{Param, ParamList, Unit};
+use crate::params::{Param, ParamGroup, ParamList, Unit};
 use crate::prelude::ParamLike;
 use crate::spectrum::bindata::{
     ArrayType, BinaryArrayMap, BinaryCompressionType, BinaryDataArrayType, BuildArrayMapFrom,
@@ -38,8 +39,10 @@ use crate::spectrum::spectrum::{
     CentroidPeakAdapting, CentroidSpectrumType, DeconvolutedPeakAdapting, MultiLayerSpectrum,
     RawSpectrum, Spectrum,
 };
+use crate::spectrum::FilterString;
 
-use crate::io::utils::DetailLevel;
+use crate::io::filter::SpectrumFilter;
+use crate::io::utils::{find_next_of, DetailLevel};
 
 use super::reading_shared::{
     CVParamParse, FileMetadataBuilder, IncrementingIdMap, IndexParserState,
@@ -68,6 +71,12 @@ pub trait SpectrumBuilding<
 
     fn fill_spectrum<P: ParamLike + Into<Param>>(&mut self, param: P);
 
+    /// Whether the spectrum being built was rejected by a [`SpectrumFilter`] and
+    /// should be discarded instead of being returned
+    fn is_rejected(&self) -> bool {
+        false
+    }
+
     fn fill_binary_data_array<P: ParamLike + Into<Param>>(&mut self, param: P) {
         if param.is_ms() {
             match param.accession().unwrap() {
@@ -276,10 +285,50 @@ pub trait SpectrumBuilding<
         self,
         instrument_configurations: &'a mut IncrementingIdMap,
     ) -> Self;
+
+    /// Give the builder the document's `<referenceableParamGroup>` definitions so that
+    /// references to them can be resolved
+    fn borrow_param_groups(self, param_groups: &'a [ParamGroup]) -> Self;
 }
 
 const BUFFER_SIZE: usize = 10000;
 
+/// Re-serializes an element the builder does not recognise, and all of its children,
+/// as it is read
+struct ElementCapture {
+    name: String,
+    depth: usize,
+    writer: Writer<Bytes>,
+}
+
+impl ElementCapture {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            depth: 0,
+            writer: Writer::new(Bytes::new()),
+        }
+    }
+
+    fn write_event(&mut self, event: Event) -> Result<(), XMLError> {
+        match &event {
+            Event::Start(_) => self.depth += 1,
+            Event::End(_) => self.depth -= 1,
+            _ => {}
+        }
+        self.writer.write_event(event)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.depth == 0
+    }
+
+    fn finish(self) -> UnknownElement {
+        let xml = String::from_utf8_lossy(&self.writer.into_inner()).to_string();
+        UnknownElement::new(self.name, xml)
+    }
+}
+
 /// An accumulator for the attributes of a spectrum as it is read from an
 /// mzML document
 #[derive(Default)]
@@ -290,7 +339,7 @@ pub struct MzMLSpectrumBuilder<
 > {
     pub params: ParamList,
     pub acquisition: Acquisition,
-    pub precursor: Precursor,
+    pub precursors: Vec<Precursor>,
 
     pub arrays: BinaryArrayMap,
     pub current_array: DataArray,
@@ -300,9 +349,19 @@ pub struct MzMLSpectrumBuilder<
     pub ms_level: u8,
     pub polarity: ScanPolarity,
     pub signal_continuity: SignalContinuity,
-    pub has_precursor: bool,
+    pub data_processing_id: Option<String>,
     pub detail_level: DetailLevel,
+    pub filter: Option<SpectrumFilter>,
     pub instrument_id_map: Option<&'a mut IncrementingIdMap>,
+    /// The document's param group definitions, used to resolve `<referenceableParamGroupRef>`
+    pub reference_param_groups: Option<&'a [ParamGroup]>,
+    /// Whether to record referenced param groups and unrecognised elements
+    pub preserve_structure: bool,
+    pub param_groups: Vec<ParamGroup>,
+    pub unknown_elements: Vec<UnknownElement>,
+    capture: Option<ElementCapture>,
+    rejected: bool,
+    metadata_complete: bool,
     centroid_type: PhantomData<C>,
     deconvoluted_type: PhantomData<D>,
 }
@@ -326,7 +385,7 @@ impl<
     for MzMLSpectrumBuilder<'inner, C, D>
 {
     fn isolation_window_mut(&mut self) -> &mut IsolationWindow {
-        &mut self.precursor.isolation_window
+        &mut self.current_precursor_mut().isolation_window
     }
 
     fn scan_window_mut(&mut self) -> &mut ScanWindow {
@@ -352,7 +411,11 @@ impl<
     }
 
     fn selected_ion_mut(&mut self) -> &mut SelectedIon {
-        &mut self.precursor.ion
+        let precursor = self.current_precursor_mut();
+        if precursor.ions.is_empty() {
+            precursor.ions.push(SelectedIon::default());
+        }
+        precursor.ions.last_mut().unwrap()
     }
 
     fn current_array_mut(&mut self) -> &mut DataArray {
@@ -370,11 +433,10 @@ impl<
 
         description.params = self.params;
         description.acquisition = self.acquisition;
-        if self.has_precursor {
-            description.precursor = Some(self.precursor);
-        } else {
-            description.precursor = None;
-        }
+        description.precursors = self.precursors;
+        description.data_processing_id = self.data_processing_id;
+        description.param_groups = self.param_groups;
+        description.unknown_elements = self.unknown_elements;
 
         spectrum.arrays = Some(self.arrays);
     }
@@ -402,6 +464,10 @@ impl<
         };
     }
 
+    fn is_rejected(&self) -> bool {
+        self.rejected
+    }
+
     fn borrow_instrument_configuration(
         mut self,
         instrument_configurations: &'inner mut IncrementingIdMap,
@@ -409,6 +475,11 @@ impl<
         self.instrument_id_map = Some(instrument_configurations);
         self
     }
+
+    fn borrow_param_groups(mut self, param_groups: &'inner [ParamGroup]) -> Self {
+        self.reference_param_groups = Some(param_groups);
+        self
+    }
 }
 
 impl<
@@ -429,6 +500,25 @@ impl<
         }
     }
 
+    /// Set the [`SpectrumFilter`] to evaluate once the spectrum's metadata has been read
+    pub fn with_filter(
+        mut self,
+        filter: Option<SpectrumFilter>,
+    ) -> MzMLSpectrumBuilder<'inner, C, D> {
+        self.filter = filter;
+        self
+    }
+
+    /// Record the param groups each spectrum and scan referred to and keep elements the
+    /// builder does not recognise, so that a writer can reproduce them
+    pub fn with_preserve_structure(
+        mut self,
+        preserve_structure: bool,
+    ) -> MzMLSpectrumBuilder<'inner, C, D> {
+        self.preserve_structure = preserve_structure;
+        self
+    }
+
     pub fn _reset(&mut self) {
         self.params.clear();
         self.acquisition = Acquisition::default();
@@ -436,11 +526,16 @@ impl<
         self.current_array.clear();
         self.scan_id.clear();
 
-        self.precursor = Precursor::default();
+        self.precursors.clear();
         self.index = 0;
-        self.has_precursor = false;
         self.signal_continuity = SignalContinuity::Unknown;
         self.polarity = ScanPolarity::Unknown;
+        self.data_processing_id = None;
+        self.param_groups.clear();
+        self.unknown_elements.clear();
+        self.capture = None;
+        self.rejected = false;
+        self.metadata_complete = false;
     }
 
     pub fn _to_spectrum(&self, spectrum: &mut MultiLayerSpectrum<C, D>) {
@@ -454,14 +549,25 @@ impl<
 
         description.params = self.params.clone();
         description.acquisition = self.acquisition.clone();
-        if self.has_precursor {
-            description.precursor = Some(self.precursor.clone());
-        } else {
-            description.precursor = None;
-        }
+        description.precursors = self.precursors.clone();
+        description.data_processing_id = self.data_processing_id.clone();
+        description.param_groups = self.param_groups.clone();
+        description.unknown_elements = self.unknown_elements.clone();
 
         spectrum.arrays = Some(self.arrays.clone());
     }
+}
+
+impl<'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapting>
+    MzMLSpectrumBuilder<'inner, C, D>
+{
+    /// The precursor currently being read, the last one started
+    fn current_precursor_mut(&mut self) -> &mut Precursor {
+        if self.precursors.is_empty() {
+            self.precursors.push(Precursor::default());
+        }
+        self.precursors.last_mut().unwrap()
+    }
 
     pub fn fill_param_into(&mut self, param: Param, state: MzMLParserState) {
         match state {
@@ -505,6 +611,13 @@ impl<
                             .parse()
                             .expect("Expected floating point number for injection time");
                     }
+                    b"FAIMS compensation voltage" => {
+                        event.compensation_voltage = Some(
+                            param
+                                .parse()
+                                .expect("Expected floating point number for compensation voltage"),
+                        );
+                    }
                     _ => event.add_param(param),
                 }
             }
@@ -521,21 +634,7 @@ impl<
                 self.fill_selected_ion(param);
             }
             MzMLParserState::Activation => {
-                if Activation::is_param_activation(&param)
-                    && self.precursor.activation.method().is_none()
-                {
-                    *self.precursor.activation.method_mut() = Some(param.into());
-                } else {
-                    match param.name.as_ref() {
-                        "collision energy" | "activation energy" => {
-                            self.precursor.activation.energy =
-                                param.parse().expect("Failed to parse collision energy");
-                        }
-                        &_ => {
-                            self.precursor.activation.add_param(param);
-                        }
-                    }
-                }
+                self.current_precursor_mut().activation.fill_param(param);
             }
             MzMLParserState::BinaryDataArrayList => {}
             MzMLParserState::BinaryDataArray => {
@@ -547,12 +646,155 @@ impl<
             _ => {}
         };
     }
+
+    /// Evaluate the [`SpectrumFilter`], if any, against the metadata read so far and
+    /// update the rejection flag. The metadata is moved into a temporary [`SpectrumDescription`]
+    /// and back again to avoid copying it.
+    fn apply_filter(&mut self) {
+        let filter = match self.filter.as_ref() {
+            Some(filter) => filter,
+            None => return,
+        };
+        let description = SpectrumDescription {
+            id: mem::take(&mut self.scan_id),
+            index: self.index,
+            ms_level: self.ms_level,
+            polarity: self.polarity,
+            signal_continuity: self.signal_continuity,
+            params: mem::take(&mut self.params),
+            acquisition: mem::take(&mut self.acquisition),
+            precursors: mem::take(&mut self.precursors),
+            ..Default::default()
+        };
+        self.rejected = !filter.accepts(&description);
+        self.scan_id = description.id;
+        self.params = description.params;
+        self.acquisition = description.acquisition;
+        self.precursors = description.precursors;
+    }
+
+    /// Complete the spectrum's metadata from its filter string and evaluate the
+    /// [`SpectrumFilter`] against it. This happens once per spectrum, at the first of
+    /// `<binaryDataArrayList>` or `</spectrum>`.
+    fn finish_metadata(&mut self) {
+        if self.metadata_complete {
+            return;
+        }
+        self.metadata_complete = true;
+        self.fill_from_filter_string();
+        self.apply_filter();
+    }
+
+    /// Fill in the polarity, scan windows and precursor activation from the Thermo filter
+    /// string of the first scan, where the spectrum did not state them explicitly
+    fn fill_from_filter_string(&mut self) {
+        let filter = match self
+            .acquisition
+            .first_scan()
+            .and_then(FilterString::from_scan_event)
+        {
+            Some(Ok(filter)) => filter,
+            Some(Err(e)) => {
+                warn!(
+                    "Failed to parse the filter string of {}: {}",
+                    self.scan_id, e
+                );
+                return;
+            }
+            None => return,
+        };
+        filter.fill_polarity(&mut self.polarity);
+        if let Some(event) = self.acquisition.first_scan_mut() {
+            filter.fill_scan_event(event);
+        }
+        if self.ms_level > 1 {
+            filter.fill_precursors(&mut self.precursors);
+        }
+    }
+
+    /// Inline the params of the group a `<referenceableParamGroupRef>` names into the current
+    /// element, also recording the group on the spectrum or scan when preserving structure
+    fn fill_param_group(&mut self, group_id: &str, state: MzMLParserState) {
+        let groups = self.reference_param_groups.unwrap_or_default();
+        let group = match groups.iter().find(|group| group.id == group_id) {
+            Some(group) => group,
+            None => {
+                warn!("Encountered a referenceableParamGroupRef to an undefined group {group_id}");
+                return;
+            }
+        };
+        for param in group.params.iter() {
+            self.fill_param_into(param.clone(), state);
+        }
+        if self.preserve_structure {
+            match state {
+                MzMLParserState::Spectrum => self.param_groups.push(group.clone()),
+                MzMLParserState::Scan => {
+                    if let Some(event) = self.acquisition.scans.last_mut() {
+                        event.param_groups.push(group.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether an unrecognised element at `state` should be kept, i.e. it is inside a
+    /// `<spectrum>` and structure is being preserved
+    fn should_capture(&self, state: MzMLParserState) -> bool {
+        self.preserve_structure
+            && matches!(
+                state,
+                MzMLParserState::Spectrum
+                    | MzMLParserState::ScanList
+                    | MzMLParserState::Scan
+                    | MzMLParserState::ScanWindowList
+                    | MzMLParserState::ScanWindow
+                    | MzMLParserState::PrecursorList
+                    | MzMLParserState::Precursor
+                    | MzMLParserState::IsolationWindow
+                    | MzMLParserState::SelectedIonList
+                    | MzMLParserState::SelectedIon
+                    | MzMLParserState::Activation
+                    | MzMLParserState::BinaryDataArrayList
+                    | MzMLParserState::BinaryDataArray
+            )
+    }
+
+    /// Pass `event` to the in-progress [`ElementCapture`], if there is one, storing the
+    /// element once it is complete. Returns `None` if nothing is being captured.
+    fn capture_event(&mut self, event: Event, state: MzMLParserState) -> Option<ParserResult> {
+        let capture = self.capture.as_mut()?;
+        if let Err(err) = capture.write_event(event) {
+            return Some(Err(self.handle_xml_error(err, state)));
+        }
+        if capture.is_complete() {
+            let element = self.capture.take().unwrap().finish();
+            self.store_unknown_element(element, state);
+        }
+        Some(Ok(state))
+    }
+
+    fn store_unknown_element(&mut self, element: UnknownElement, state: MzMLParserState) {
+        match (state, self.acquisition.scans.last_mut()) {
+            (
+                MzMLParserState::Scan
+                | MzMLParserState::ScanWindowList
+                | MzMLParserState::ScanWindow,
+                Some(event),
+            ) => event.unknown_elements.push(element),
+            _ => self.unknown_elements.push(element),
+        }
+    }
 }
 
 impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapting> MzMLSAX
     for MzMLSpectrumBuilder<'inner, C, D>
 {
     fn start_element(&mut self, event: &BytesStart, state: MzMLParserState) -> ParserResult {
+        if let Some(result) = self.capture_event(Event::Start(event.borrow()), state) {
+            return result;
+        }
         let elt_name = event.name();
         match elt_name.as_ref() {
             b"spectrum" => {
@@ -570,6 +812,13 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
                                     .parse::<usize>()
                                     .expect("Failed to parse index");
                             }
+                            b"dataProcessingRef" => {
+                                self.data_processing_id = Some(
+                                    attr.unescape_value()
+                                        .expect("Error decoding data processing reference")
+                                        .to_string(),
+                                );
+                            }
                             _ => {}
                         },
                         Err(msg) => {
@@ -589,15 +838,23 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
                 let mut scan_event = ScanEvent::default();
                 for attr_parsed in event.attributes() {
                     match attr_parsed {
-                        Ok(attr) => {
-                            if attr.key.as_ref() == b"instrumentConfigurationRef" {
+                        Ok(attr) => match attr.key.as_ref() {
+                            b"instrumentConfigurationRef" => {
                                 scan_event.instrument_configuration_id = self
                                     .instrument_id_map
                                     .as_mut()
                                     .expect("An instrument ID map was not provided")
                                     .get(&attr.unescape_value().expect("Error decoding id"));
                             }
-                        }
+                            b"sourceFileRef" => {
+                                scan_event.source_file_id = Some(
+                                    attr.unescape_value()
+                                        .expect("Error decoding source file reference")
+                                        .to_string(),
+                                );
+                            }
+                            _ => {}
+                        },
                         Err(msg) => {
                             return Err(self.handle_xml_error(msg.into(), state));
                         }
@@ -623,47 +880,86 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
                 return Ok(MzMLParserState::PrecursorList);
             }
             b"precursor" => {
-                self.has_precursor = true;
+                let mut precursor = Precursor::default();
                 for attr_parsed in event.attributes() {
                     match attr_parsed {
-                        Ok(attr) => {
-                            if attr.key.as_ref() == b"spectrumRef" {
-                                self.precursor.precursor_id = Some(
+                        Ok(attr) => match attr.key.as_ref() {
+                            b"spectrumRef" => {
+                                precursor.precursor_id = Some(
                                     attr.unescape_value()
                                         .expect("Error decoding id")
                                         .to_string(),
                                 );
                             }
-                        }
+                            b"sourceFileRef" => {
+                                precursor.source_file_id = Some(
+                                    attr.unescape_value()
+                                        .expect("Error decoding source file reference")
+                                        .to_string(),
+                                );
+                            }
+                            _ => {}
+                        },
                         Err(msg) => {
                             return Err(self.handle_xml_error(msg.into(), state));
                         }
                     }
                 }
+                self.precursors.push(precursor);
                 return Ok(MzMLParserState::Precursor);
             }
             b"isolationWindow" => {
                 return Ok(MzMLParserState::IsolationWindow);
             }
             b"selectedIonList" => {
+                self.current_precursor_mut().ions.clear();
                 return Ok(MzMLParserState::SelectedIonList);
             }
             b"selectedIon" => {
+                self.current_precursor_mut()
+                    .ions
+                    .push(SelectedIon::default());
                 return Ok(MzMLParserState::SelectedIon);
             }
             b"activation" => {
                 return Ok(MzMLParserState::Activation);
             }
             b"binaryDataArrayList" => {
+                // All of the spectrum's metadata precedes its data arrays
+                self.finish_metadata();
                 return Ok(MzMLParserState::BinaryDataArrayList);
             }
             b"binaryDataArray" => {
+                for attr_parsed in event.attributes() {
+                    match attr_parsed {
+                        Ok(attr) => {
+                            if attr.key.as_ref() == b"dataProcessingRef" {
+                                self.current_array.data_processing_id = Some(
+                                    attr.unescape_value()
+                                        .expect("Error decoding data processing reference")
+                                        .to_string(),
+                                );
+                            }
+                        }
+                        Err(msg) => {
+                            return Err(self.handle_xml_error(msg.into(), state));
+                        }
+                    }
+                }
                 return Ok(MzMLParserState::BinaryDataArray);
             }
             b"binary" => {
                 return Ok(MzMLParserState::Binary);
             }
-            _ => {}
+            name => {
+                if self.should_capture(state) {
+                    let name = String::from_utf8_lossy(name).to_string();
+                    self.capture = Some(ElementCapture::new(name));
+                    return self
+                        .capture_event(Event::Start(event.borrow()), state)
+                        .unwrap();
+                }
+            }
         };
         Ok(state)
     }
@@ -674,6 +970,9 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
         state: MzMLParserState,
         reader_position: usize,
     ) -> ParserResult {
+        if let Some(result) = self.capture_event(Event::Empty(event.borrow()), state) {
+            return result;
+        }
         let elt_name = event.name();
         match elt_name.as_ref() {
             // b"cvParam" | b"userParam" => match Self::handle_param(event, reader_position, state) {
@@ -724,6 +1023,11 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
                                         "Expected floating point number for injection time",
                                     );
                                 }
+                                b"FAIMS compensation voltage" => {
+                                    event.compensation_voltage = Some(param.parse().expect(
+                                        "Expected floating point number for compensation voltage",
+                                    ));
+                                }
                                 _ => event.add_param(param.into()),
                             }
                         }
@@ -743,22 +1047,9 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
                             self.fill_selected_ion(param.into());
                         }
                         MzMLParserState::Activation => {
-                            if Activation::is_param_activation(&param)
-                                && self.precursor.activation.method().is_none()
-                            {
-                                *self.precursor.activation.method_mut() = Some(param.into());
-                            } else {
-                                match param.name.as_ref() {
-                                    "collision energy" | "activation energy" => {
-                                        self.precursor.activation.energy = param
-                                            .parse()
-                                            .expect("Failed to parse collision energy");
-                                    }
-                                    &_ => {
-                                        self.precursor.activation.add_param(param.into());
-                                    }
-                                }
-                            }
+                            self.current_precursor_mut()
+                                .activation
+                                .fill_param(param.into());
                         }
                         MzMLParserState::BinaryDataArrayList => {}
                         MzMLParserState::BinaryDataArray => {
@@ -772,15 +1063,48 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
                     Err(err) => return Err(err),
                 }
             }
-            &_ => {}
+            b"referenceableParamGroupRef" => {
+                for attr_parsed in event.attributes() {
+                    match attr_parsed {
+                        Ok(attr) => {
+                            if attr.key.as_ref() == b"ref" {
+                                let group_id = attr
+                                    .unescape_value()
+                                    .expect("Error decoding reference group");
+                                self.fill_param_group(&group_id, state);
+                            }
+                        }
+                        Err(msg) => {
+                            return Err(self.handle_xml_error(msg.into(), state));
+                        }
+                    }
+                }
+            }
+            // An empty array, or one stored externally as in mzMLb
+            b"binary" => {}
+            name => {
+                if self.should_capture(state) {
+                    let name = String::from_utf8_lossy(name).to_string();
+                    self.capture = Some(ElementCapture::new(name));
+                    return self
+                        .capture_event(Event::Empty(event.borrow()), state)
+                        .unwrap();
+                }
+            }
         }
         Ok(state)
     }
 
     fn end_element(&mut self, event: &BytesEnd, state: MzMLParserState) -> ParserResult {
+        if let Some(result) = self.capture_event(Event::End(event.borrow()), state) {
+            return result;
+        }
         let elt_name = event.name();
         match elt_name.as_ref() {
-            b"spectrum" => return Ok(MzMLParserState::SpectrumDone),
+            b"spectrum" => {
+                self.finish_metadata();
+                return Ok(MzMLParserState::SpectrumDone);
+            }
             b"scanList" => return Ok(MzMLParserState::Spectrum),
             b"scan" => return Ok(MzMLParserState::ScanList),
             b"scanWindow" => return Ok(MzMLParserState::ScanWindowList),
@@ -796,10 +1120,13 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
             }
             b"binaryDataArray" => {
                 let mut array = mem::take(&mut self.current_array);
+                if self.rejected {
+                    return Ok(MzMLParserState::BinaryDataArrayList);
+                }
                 if self.detail_level == DetailLevel::Full {
-                    array
-                        .decode_and_store()
-                        .expect("Error during decoding and storing of array data");
+                    if let Err(err) = array.decode_and_store() {
+                        return Err(MzMLParserError::ArrayDecodingError(state, err));
+                    }
                 }
                 self.arrays.add(array);
                 return Ok(MzMLParserState::BinaryDataArrayList);
@@ -812,7 +1139,13 @@ impl<'inner, 'outer: 'inner, C: CentroidLike + Default, D: DeconvolutedPeakAdapt
     }
 
     fn text(&mut self, event: &BytesText, state: MzMLParserState) -> ParserResult {
-        if state == MzMLParserState::Binary && self.detail_level != DetailLevel::MetadataOnly {
+        if let Some(result) = self.capture_event(Event::Text(event.borrow()), state) {
+            return result;
+        }
+        if state == MzMLParserState::Binary
+            && self.detail_level != DetailLevel::MetadataOnly
+            && !self.rejected
+        {
             let bin = event
                 .unescape()
                 .expect("Failed to unescape binary data array content");
@@ -853,6 +1186,19 @@ impl<'a> From<MzMLSpectrumBuilder<'a>> for RawSpectrum {
     }
 }
 
+/// An error raised while reading a spectrum from an mzML stream with [`TryScanSource::try_next`]
+pub type MzMLReadError = SpectrumReadError<MzMLParserError, MzMLParserState>;
+
+/// The start of a `<spectrum>` element, or the end of the `<spectrumList>` as the last entry
+const SPECTRUM_BOUNDARIES: &[&[u8]] = &[
+    b"<spectrum ",
+    b"<spectrum\t",
+    b"<spectrum\n",
+    b"<spectrum\r",
+    b"<spectrum>",
+    b"</spectrumList",
+];
+
 /**
 An mzML parser that supports iteration and random access. The parser produces
 [`Spectrum`] instances, which may be converted to [`RawSpectrum`](crate::spectrum::spectrum::RawSpectrum)
@@ -886,10 +1232,29 @@ pub struct MzMLReaderType<
     /// The data processing and signal transformation operations performed on the raw data in previous
     /// source files to produce this file's contents.
     pub(crate) data_processings: Vec<DataProcessing>,
-    /// A cache of repeated paramters
-    pub reference_param_groups: HashMap<String, Vec<Param>>,
+    /// A cache of repeated paramters, in the order they were defined in the document
+    pub param_groups: Vec<ParamGroup>,
+    /// The samples analyzed to produce this file
+    pub(crate) samples: Vec<Sample>,
+    /// The acquisition settings of the instrument, including any target lists
+    pub(crate) scan_settings: Vec<ScanSettings>,
+    /// The controlled vocabularies the file declares
+    pub(crate) cv_list: Vec<CVEntry>,
     pub detail_level: DetailLevel,
-
+    /// A filter on spectrum metadata. Spectra which are rejected are skipped during
+    /// iteration without reading their data arrays, see [`SpectrumFilter`].
+    pub filter: Option<SpectrumFilter>,
+    /// Whether to record which [`ParamGroup`]s each spectrum and scan referred to, and keep
+    /// elements of spectra the parser does not recognise, in
+    /// [`SpectrumDescription::param_groups`] and [`SpectrumDescription::unknown_elements`].
+    /// This lets [`MzMLWriterType`](crate::io::mzml::MzMLWriterType) reproduce them.
+    pub preserve_structure: bool,
+    /// Whether [`TryScanSource::try_next`] should recover from a spectrum which fails to parse
+    /// by skipping ahead to the next `<spectrum>` element, found with [`MzMLReaderType::index`]
+    /// when it has been built, instead of returning the error.
+    pub recover: bool,
+    /// The regions skipped while recovering from errors, see [`MzMLReaderType::recover`]
+    pub skipped_regions: Vec<SkippedRegion<MzMLReadError>>,
 
     // SpectrumList attributes
     pub run: MassSpectrometryRun,
@@ -918,6 +1283,17 @@ impl<
         Self::with_buffer_capacity_and_detail_level(file, BUFFER_SIZE, DetailLevel::Full)
     }
 
+    /// The parameters of each referenceable param group, keyed by group id.
+    ///
+    /// This is built from [`MzMLReaderType::param_groups`] on each call.
+    #[deprecated(note = "Use `param_groups` instead")]
+    pub fn reference_param_group_map(&self) -> HashMap<String, Vec<Param>> {
+        self.param_groups
+            .iter()
+            .map(|group| (group.id.clone(), group.params.clone()))
+            .collect()
+    }
+
     pub fn with_buffer_capacity_and_detail_level(
         file: R,
         capacity: usize,
@@ -935,8 +1311,15 @@ impl<
             instrument_configurations: HashMap::new(),
             softwares: Vec::new(),
             data_processings: Vec::new(),
-            reference_param_groups: HashMap::new(),
+            param_groups: Vec::new(),
+            samples: Vec::new(),
+            scan_settings: Vec::new(),
+            cv_list: Vec::new(),
             detail_level,
+            filter: None,
+            preserve_structure: false,
+            recover: false,
+            skipped_regions: Vec::new(),
 
             centroid_type: PhantomData,
             deconvoluted_type: PhantomData,
@@ -966,6 +1349,10 @@ impl<
                     match accumulator.start_element(e, self.state) {
                         Ok(state) => {
                             self.state = state;
+                            // A run without spectra has nothing more to read here
+                            if e.name().as_ref() == b"chromatogramList" {
+                                break;
+                            }
                             match &self.state {
                                 MzMLParserState::SpectrumList | MzMLParserState::Spectrum => break,
                                 MzMLParserState::ParserError => {
@@ -1045,12 +1432,9 @@ impl<
                 _ => {}
             };
             self.buffer.clear();
-            match self.state {
-                MzMLParserState::Run | MzMLParserState::ParserError => {
-                    break;
-                }
-                _ => {}
-            };
+            if self.state == MzMLParserState::ParserError {
+                break;
+            }
         }
         self.file_description = accumulator.file_description;
         self.instrument_configurations = accumulator
@@ -1060,7 +1444,10 @@ impl<
             .collect();
         self.softwares = accumulator.softwares;
         self.data_processings = accumulator.data_processings;
-        self.reference_param_groups = accumulator.reference_param_groups;
+        self.param_groups = accumulator.param_groups;
+        self.samples = accumulator.samples;
+        self.scan_settings = accumulator.scan_settings;
+        self.cv_list = accumulator.cv_list;
 
         self.run.id = accumulator.run_id;
         self.run.default_instrument_id = accumulator.default_instrument_config;
@@ -1092,7 +1479,9 @@ impl<
 
         let mut reader = Reader::from_reader(&mut self.handle);
         reader.trim_text(true);
-        accumulator = accumulator.borrow_instrument_configuration(&mut self.instrument_id_map);
+        accumulator = accumulator
+            .borrow_instrument_configuration(&mut self.instrument_id_map)
+            .borrow_param_groups(&self.param_groups);
         let mut offset: usize = 0;
         loop {
             match reader.read_event_into(&mut self.buffer) {
@@ -1113,6 +1502,31 @@ impl<
                                         String::from_utf8_lossy(&self.buffer)
                                     );
                                 }
+                                MzMLParserState::BinaryDataArrayList
+                                    if accumulator.is_rejected() =>
+                                {
+                                    // The spectrum was rejected by the filter, so jump past its
+                                    // data arrays without handing them to the accumulator.
+                                    let mut skip_buffer = Bytes::new();
+                                    match reader.read_to_end_into(e.name(), &mut skip_buffer) {
+                                        Ok(span) => {
+                                            // The span covers the element's content but not
+                                            // its `</binaryDataArrayList>` end tag
+                                            offset += span.end - span.start
+                                                + e.name().as_ref().len()
+                                                + 3;
+                                            self.state = MzMLParserState::Spectrum;
+                                        }
+                                        Err(err) => {
+                                            self.error =
+                                                Some(MzMLParserError::IncompleteElementError(
+                                                    err.to_string(),
+                                                    self.state,
+                                                ));
+                                            self.state = MzMLParserState::ParserError;
+                                        }
+                                    }
+                                }
                                 _ => {}
                             }
                         }
@@ -1163,7 +1577,15 @@ impl<
                 }
                 Ok(Event::Eof) => {
                     log::trace!("Reached EOF");
-                    self.state = MzMLParserState::EOF;
+                    if self.state.is_within_spectrum() {
+                        self.error = Some(MzMLParserError::IncompleteElementError(
+                            "the stream ended inside of a spectrum".to_string(),
+                            self.state,
+                        ));
+                        self.state = MzMLParserState::ParserError;
+                    } else {
+                        self.state = MzMLParserState::EOF;
+                    }
                     break;
                 }
                 Err(err) => match &err {
@@ -1222,32 +1644,44 @@ impl<
     /// Populate a new [`Spectrum`] in-place on the next available spectrum data.
     /// This allocates memory to build the spectrum's attributes but then moves it
     /// into `spectrum` rather than copying it.
+    ///
+    /// If [`MzMLReaderType::filter`] is set, spectra it rejects are skipped and the
+    /// next accepted spectrum is read instead.
     pub fn read_into(
         &mut self,
         spectrum: &mut MultiLayerSpectrum<C, D>,
     ) -> Result<usize, MzMLParserError> {
-        let accumulator = MzMLSpectrumBuilder::<C, D>::with_detail_level(self.detail_level);
-        match self.state {
-            MzMLParserState::SpectrumDone => {
-                self.state = MzMLParserState::Resume;
-            }
-            MzMLParserState::ParserError => {
-                eprintln!("Starting parsing from error: {:?}", self.error);
-            }
-            state if state > MzMLParserState::SpectrumDone => {
-                eprintln!(
-                    "Attempting to start parsing a spectrum in state {}",
-                    self.state
-                );
+        let mut skipped = 0;
+        loop {
+            let accumulator = MzMLSpectrumBuilder::<C, D>::with_detail_level(self.detail_level)
+                .with_filter(self.filter.clone())
+                .with_preserve_structure(self.preserve_structure);
+            match self.state {
+                MzMLParserState::SpectrumDone => {
+                    self.state = MzMLParserState::Resume;
+                }
+                MzMLParserState::ParserError => {
+                    eprintln!("Starting parsing from error: {:?}", self.error);
+                }
+                state if state > MzMLParserState::SpectrumDone => {
+                    eprintln!(
+                        "Attempting to start parsing a spectrum in state {}",
+                        self.state
+                    );
+                }
+                _ => {}
             }
-            _ => {}
-        }
-        match self._parse_into(accumulator) {
-            Ok((accumulator, sz)) => {
-                accumulator.into_spectrum(spectrum);
-                Ok(sz)
+            match self._parse_into(accumulator) {
+                Ok((accumulator, sz)) => {
+                    if accumulator.is_rejected() {
+                        skipped += sz;
+                        continue;
+                    }
+                    accumulator.into_spectrum(spectrum);
+                    return Ok(sz + skipped);
+                }
+                Err(err) => return Err(err),
             }
-            Err(err) => Err(err),
         }
     }
 
@@ -1376,7 +1810,9 @@ impl<
             "The next XML tag was not `spectrum`"
         );
         self.state = MzMLParserState::Resume;
+        let filter = self.filter.take();
         let result = self.read_next();
+        self.filter = filter;
         self.seek(SeekFrom::Start(start))
             .expect("Failed to restore offset");
         result
@@ -1396,7 +1832,9 @@ impl<
             "The next XML tag was not `spectrum`"
         );
         self.state = MzMLParserState::Resume;
+        let filter = self.filter.take();
         let result = self.read_next();
+        self.filter = filter;
         self.seek(SeekFrom::Start(start))
             .expect("Failed to restore offset");
         result
@@ -1522,6 +1960,56 @@ impl<
         self.handle.stream_position()
     }
 
+    /// Find the offset of the first `<spectrum>` element starting at or after `offset`, using
+    /// the offset index if it has been built, or `None` if the spectrum list ends first.
+    fn next_spectrum_offset(&mut self, offset: u64) -> io::Result<Option<u64>> {
+        if self.index.init {
+            return Ok(self.index.first_at_or_after(offset).map(|(_, _, o)| o));
+        }
+        let hit = find_next_of(&mut self.handle, offset, SPECTRUM_BOUNDARIES)?;
+        Ok(hit
+            .filter(|(_, k)| k + 1 < SPECTRUM_BOUNDARIES.len())
+            .map(|(o, _)| o))
+    }
+
+    /// Move past the spectrum which failed to parse with `error` after reading from `start`,
+    /// to the next spectrum or the end of the stream, and record the skipped region.
+    fn skip_failed_spectrum(
+        &mut self,
+        start: u64,
+        error: MzMLReadError,
+    ) -> Result<(), MzMLReadError> {
+        let io_error = |err: io::Error, offset: u64| {
+            let state = MzMLParserState::ParserError;
+            MzMLReadError::new(MzMLParserError::IOError(state, err), state, offset)
+        };
+        let first = self
+            .next_spectrum_offset(start)
+            .map_err(|e| io_error(e, start))?;
+        // Resume after the spectrum that failed, unless the error came before it started
+        let next = match first {
+            Some(offset) if offset < error.offset || offset <= start => self
+                .next_spectrum_offset(offset + 1)
+                .map_err(|e| io_error(e, offset))?,
+            next => next,
+        };
+        let end = match next {
+            Some(offset) => {
+                self.state = MzMLParserState::Resume;
+                self.seek(SeekFrom::Start(offset))
+            }
+            None => {
+                self.state = MzMLParserState::EOF;
+                self.seek(SeekFrom::End(0))
+            }
+        }
+        .map_err(|e| io_error(e, error.offset))?;
+        let region = SkippedRegion { start, end, error };
+        warn!("{region}");
+        self.skipped_regions.push(region);
+        Ok(())
+    }
+
     /// Read the offset index at the end of an `<indexedmzML>` document,
     /// though this index may be malformed in some older files.
     pub fn read_index_from_end(&mut self) -> Result<u64, MzMLIndexingError> {
@@ -1659,6 +2147,45 @@ impl<
     }
 }
 
+/// Unlike iteration, reading with [`TryScanSource::try_next`] reports why reading stopped,
+/// including where in the stream a malformed or truncated spectrum was found.
+impl<
+        R: SeekRead,
+        C: CentroidPeakAdapting + BuildFromArrayMap,
+        D: DeconvolutedPeakAdapting + BuildFromArrayMap,
+    > TryScanSource<C, D, MultiLayerSpectrum<C, D>> for MzMLReaderType<R, C, D>
+{
+    type Error = MzMLReadError;
+
+    fn try_next(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, Self::Error> {
+        loop {
+            if self.state == MzMLParserState::EOF {
+                return Ok(None);
+            }
+            let start = self.stream_position().map_err(|err| {
+                MzMLReadError::new(MzMLParserError::IOError(self.state, err), self.state, 0)
+            })?;
+            let mut spectrum = MultiLayerSpectrum::<C, D>::default();
+            let error = match self.read_into(&mut spectrum) {
+                Ok(_) => return Ok(Some(spectrum)),
+                Err(MzMLParserError::SectionOver(_)) => return Ok(None),
+                Err(MzMLParserError::IncompleteSpectrum) if self.state == MzMLParserState::EOF => {
+                    return Ok(None)
+                }
+                Err(err) => {
+                    let state = err.state().unwrap_or(self.state);
+                    let offset = self.stream_position().unwrap_or(start);
+                    MzMLReadError::new(err, state, offset).with_spectrum_from(&self.index, start)
+                }
+            };
+            if !self.recover {
+                return Err(error);
+            }
+            self.skip_failed_spectrum(start, error)?;
+        }
+    }
+}
+
 impl<
         C: CentroidPeakAdapting + BuildFromArrayMap,
         D: DeconvolutedPeakAdapting + BuildFromArrayMap,
@@ -1685,6 +2212,38 @@ impl<R: Read, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> MSDataFileMe
     fn spectrum_count_hint(&self) -> Option<u64> {
         self.num_spectra
     }
+
+    fn reference_param_groups(&self) -> Option<&Vec<ParamGroup>> {
+        Some(&self.param_groups)
+    }
+
+    fn reference_param_groups_mut(&mut self) -> Option<&mut Vec<ParamGroup>> {
+        Some(&mut self.param_groups)
+    }
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        Some(&self.samples)
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        Some(&mut self.samples)
+    }
+
+    fn scan_settings(&self) -> Option<&Vec<ScanSettings>> {
+        Some(&self.scan_settings)
+    }
+
+    fn scan_settings_mut(&mut self) -> Option<&mut Vec<ScanSettings>> {
+        Some(&mut self.scan_settings)
+    }
+
+    fn cv_list(&self) -> Option<&Vec<CVEntry>> {
+        Some(&self.cv_list)
+    }
+
+    fn cv_list_mut(&mut self) -> Option<&mut Vec<CVEntry>> {
+        Some(&mut self.cv_list)
+    }
 }
 
 /// A specialization of [`MzMLReaderType`] for the default peak types, for common use.
@@ -1872,6 +2431,16 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_spectrum_count_hint() -> io::Result<()> {
+        let path = path::Path::new("./test/data/read_index_of.mzML");
+        let file = fs::File::open(path)?;
+        let reader = MzMLReader::new(file);
+        assert_eq!(reader.spectrum_count_hint(), Some(48));
+        assert_eq!(reader.count(), 48);
+        Ok(())
+    }
+
     #[test_log::test]
     fn read_index() -> io::Result<()> {
         let path = path::Path::new("./test/data/read_index_of.mzML");
@@ -2023,6 +2592,179 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_with_filter() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mzML");
+        let times: Vec<f64> = MzMLReader::open_path(path)?
+            .map(|s| s.start_time())
+            .collect();
+        let cutoff = times[times.len() / 2];
+
+        let mut reader = MzMLReader::open_path(path)?;
+        reader.filter = Some(SpectrumFilter::new().ms_level(2));
+        let mut n = 0;
+        for scan in reader.by_ref() {
+            assert_eq!(scan.ms_level(), 2);
+            assert!(!scan.arrays.as_ref().unwrap().mzs().unwrap().is_empty());
+            n += 1;
+        }
+        assert_eq!(n, 34);
+
+        // Random access ignores the filter
+        let scan = reader.get_spectrum_by_index(0).unwrap();
+        assert_eq!(scan.ms_level(), 1);
+
+        let mut reader = MzMLReader::open_path(path)?;
+        reader.filter = Some(SpectrumFilter::new().time_range(0.0..=cutoff));
+        let n = reader.by_ref().count();
+        assert_eq!(n, times.iter().filter(|t| **t <= cutoff).count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_next() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mzML");
+        let mut reader = MzMLReader::open_path(path)?;
+        let spectra: Result<Vec<_>, _> = reader.try_iter().collect();
+        assert_eq!(spectra.unwrap().len(), 48);
+        assert!(reader.try_next().unwrap().is_none());
+
+        let index = reader.get_index().clone();
+        let (_, offset) = index.get_index(10).unwrap();
+        let mut content = fs::read(path)?;
+        content.truncate(offset as usize + 500);
+
+        let mut reader = MzMLReader::new(io::Cursor::new(content));
+        reader.set_index(index);
+        let mut results = reader.try_iter();
+        for _ in 0..10 {
+            assert!(results.next().unwrap().is_ok());
+        }
+        let err = results.next().unwrap().unwrap_err();
+        assert!(results.next().is_none());
+        assert_eq!(err.index, Some(10));
+        assert_eq!(
+            err.id.as_deref(),
+            Some("controllerType=0 controllerNumber=1 scan=11")
+        );
+        assert!(err.state.is_within_spectrum());
+        assert!(err.offset > offset);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover() -> io::Result<()> {
+        let path = path::Path::new("./test/data/read_index_of.mzML");
+        let index = MzMLReader::open_path(path)?.get_index().clone();
+        let (_, offset) = index.get_index(10).unwrap();
+        let (_, next_offset) = index.get_index(11).unwrap();
+
+        // Corrupt the first data array of the 11th spectrum without moving anything else
+        let mut content = fs::read(path)?;
+        let binary = offset as usize
+            + content[offset as usize..]
+                .windows(8)
+                .position(|w| w == b"<binary>")
+                .unwrap()
+            + 8;
+        content[binary..binary + 8].copy_from_slice(b"!!!!!!!!");
+
+        for use_index in [true, false] {
+            let mut reader = MzMLReader::new(io::Cursor::new(content.clone()));
+            if use_index {
+                reader.set_index(index.clone());
+            }
+            reader.recover = true;
+            let spectra: Result<Vec<_>, _> = reader.try_iter().collect();
+            let spectra = spectra.unwrap();
+            assert_eq!(spectra.len(), 47);
+            assert_eq!(spectra[10].id(), index.get_index(11).unwrap().0.as_str());
+
+            assert_eq!(reader.skipped_regions.len(), 1);
+            let region = &reader.skipped_regions[0];
+            assert!(region.start <= offset);
+            assert_eq!(region.end, next_offset);
+            assert!(matches!(
+                region.error.source,
+                MzMLParserError::ArrayDecodingError(..)
+            ));
+            if use_index {
+                assert_eq!(region.error.index, Some(10));
+            }
+        }
+
+        content.truncate(offset as usize + 500);
+        let content_len = content.len() as u64;
+        let mut reader = MzMLReader::new(io::Cursor::new(content));
+        reader.recover = true;
+        let spectra: Result<Vec<_>, _> = reader.try_iter().collect();
+        assert_eq!(spectra.unwrap().len(), 10);
+        assert_eq!(reader.skipped_regions.len(), 1);
+        assert_eq!(reader.skipped_regions[0].end, content_len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_instrument_components() -> io::Result<()> {
+        use crate::meta::{DetectorType, InstrumentVendor, IonizationType, MassAnalyzer};
+
+        let reader = MzMLReader::new(fs::File::open("./test/data/three_test_scans.mzML")?);
+        let config = reader.instrument_configurations().values().next().unwrap();
+        let analyzers: Vec<_> = config.analyzers().collect();
+        assert_eq!(
+            analyzers,
+            vec![MassAnalyzer::Quadrupole, MassAnalyzer::Orbitrap]
+        );
+        assert!(config.has_analyzer(MassAnalyzer::Orbitrap));
+        assert_eq!(
+            config.ionization_types().collect::<Vec<_>>(),
+            vec![IonizationType::Nanoelectrospray]
+        );
+        assert_eq!(
+            config.detectors().collect::<Vec<_>>(),
+            vec![DetectorType::InductiveDetector]
+        );
+        assert_eq!(config.model().unwrap().name, "Q Exactive");
+        assert_eq!(config.vendor(), Some(InstrumentVendor::ThermoFisher));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fill_from_filter_string() -> io::Result<()> {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let content = content
+            .replace(
+                "<cvParam cvRef=\"PSI-MS\" accession=\"MS:1000130\" name=\"positive scan\" value=\"\"/>",
+                "",
+            )
+            .replace(
+                "<cvParam cvRef=\"PSI-MS\" accession=\"MS:1000422\" name=\"beam-type collision-induced dissociation\" value=\"\"/>",
+                "",
+            )
+            .replace(
+                "<cvParam cvRef=\"PSI-MS\" accession=\"MS:1000045\" name=\"collision energy\" value=\"27.0\" unitCvRef=\"PSI-MS\" unitAccession=\"UO:0000266\" unitName=\"electronvolt\"/>",
+                "",
+            );
+        let mut reader = MzMLReader::new(io::Cursor::new(content.clone().into_bytes()));
+        reader.filter = Some(SpectrumFilter::new().polarity(ScanPolarity::Positive));
+        assert_eq!(reader.count(), 3);
+
+        let reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        for scan in reader {
+            assert_eq!(scan.polarity(), ScanPolarity::Positive);
+            if let Some(precursor) = scan.precursor() {
+                let activation = precursor.activation();
+                assert_eq!(
+                    activation.method(),
+                    Some(&ActivationMethod::BeamTypeCollisionInducedDissociation)
+                );
+                assert_eq!(activation.energy(), 27.0);
+                assert!(activation.energies[0].is_normalized());
+            }
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_interleaved_groups() -> io::Result<()> {
         let path = path::Path::new("./test/data/batching_test.mzML");
@@ -2063,6 +2805,20 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_target_outside_scan_settings() -> io::Result<()> {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let content = content.replacen(
+            "    </fileDescription>\n",
+            "    </fileDescription>\n    <targetList count=\"1\">\n      <target>\n      </target>\n    </targetList>\n",
+            1,
+        );
+        let reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        assert_eq!(reader.state, MzMLParserState::ParserError);
+        assert!(reader.scan_settings().unwrap().is_empty());
+        Ok(())
+    }
+
     #[cfg(feature = "mzsignal")]
     #[test]
     fn test_averaging() -> io::Result<()> {
@@ -13,10 +13,12 @@ use crate::prelude::*;
 use crate::io::traits::SeekRead;
 use crate::io::OffsetIndex;
 use crate::meta::{
-    Component, ComponentType, DataProcessing, FileDescription, InstrumentConfiguration,
-    ProcessingMethod, Software, SourceFile,
+    CVEntry, Component, ComponentType, DataProcessing, FileDescription, InstrumentConfiguration,
+    ProcessingMethod, Sample, ScanSettings, ScanTarget, Software, SourceFile,
 };
-use crate::params::{curie_to_num, ControlledVocabulary, Param, ParamCow, Unit};
+use crate::params::{curie_to_num, ControlledVocabulary, Param, ParamCow, ParamGroup, ParamList, Unit};
+
+use crate::spectrum::bindata::ArrayRetrievalError;
 
 use super::reader::Bytes;
 
@@ -41,9 +43,17 @@ pub enum MzMLParserState {
     ReferenceParamGroupList,
     ReferenceParamGroup,
 
+    SampleList,
+    Sample,
+
     SoftwareList,
     Software,
 
+    ScanSettingsList,
+    ScanSettings,
+    TargetList,
+    Target,
+
     InstrumentConfigurationList,
     InstrumentConfiguration,
     ComponentList,
@@ -88,6 +98,16 @@ pub enum MzMLParserState {
     EOF
 }
 
+impl MzMLParserState {
+    /// Whether this state is inside of a `<spectrum>` element, so that reaching the end of
+    /// the stream would truncate a spectrum
+    pub fn is_within_spectrum(&self) -> bool {
+        *self >= MzMLParserState::Spectrum
+            && *self < MzMLParserState::SpectrumDone
+            && *self != MzMLParserState::SpectrumList
+    }
+}
+
 impl Display for MzMLParserState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("{:?}", self))
@@ -109,10 +129,26 @@ pub enum MzMLParserError {
     XMLError(MzMLParserState, #[source] XMLError),
     #[error("An IO error {1} was encountered in {0:?}")]
     IOError(MzMLParserState, #[source] io::Error),
+    #[error("A binary data array could not be decoded in {0:?}: {1}")]
+    ArrayDecodingError(MzMLParserState, #[source] ArrayRetrievalError),
     #[error("The {0} section is over")]
     SectionOver(&'static str)
 }
 
+impl MzMLParserError {
+    /// The parser state recorded with this error, if any
+    pub fn state(&self) -> Option<MzMLParserState> {
+        match self {
+            MzMLParserError::UnknownError(state)
+            | MzMLParserError::IncompleteElementError(_, state)
+            | MzMLParserError::XMLError(state, _)
+            | MzMLParserError::IOError(state, _)
+            | MzMLParserError::ArrayDecodingError(state, _) => Some(*state),
+            MzMLParserError::IncompleteSpectrum | MzMLParserError::SectionOver(_) => None,
+        }
+    }
+}
+
 impl From<MzMLParserError> for io::Error {
     fn from(value: MzMLParserError) -> Self {
         match value {
@@ -543,8 +579,11 @@ pub struct FileMetadataBuilder<'a> {
     pub instrument_configurations: Vec<InstrumentConfiguration>,
     pub softwares: Vec<Software>,
     pub data_processings: Vec<DataProcessing>,
-    pub reference_param_groups: HashMap<String, Vec<Param>>,
-    pub last_group: String,
+    /// The referenceable param groups in the order they were defined
+    pub param_groups: Vec<ParamGroup>,
+    pub samples: Vec<Sample>,
+    pub scan_settings: Vec<ScanSettings>,
+    pub cv_list: Vec<CVEntry>,
     pub(crate) instrument_id_map: Option<&'a mut IncrementingIdMap>,
 
     // Run attributes
@@ -565,6 +604,7 @@ impl<'a> FileMetadataBuilder<'a> {
     pub fn start_element(&mut self, event: &BytesStart, state: MzMLParserState) -> ParserResult {
         let elt_name = event.name();
         match elt_name.as_ref() {
+            b"cvList" => return Ok(MzMLParserState::CVList),
             b"fileDescription" => return Ok(MzMLParserState::FileDescription),
             b"fileContent" => return Ok(MzMLParserState::FileContents),
             b"sourceFileList" => return Ok(MzMLParserState::SourceFileList),
@@ -598,6 +638,67 @@ impl<'a> FileMetadataBuilder<'a> {
                 self.file_description.source_files.push(source_file);
                 return Ok(MzMLParserState::SourceFile);
             }
+            b"sampleList" => return Ok(MzMLParserState::SampleList),
+            b"sample" => {
+                let mut sample = Sample::default();
+                for attr_parsed in event.attributes() {
+                    match attr_parsed {
+                        Ok(attr) => {
+                            if attr.key.as_ref() == b"id" {
+                                sample.id = attr
+                                    .unescape_value()
+                                    .expect("Error decoding id")
+                                    .to_string();
+                            } else if attr.key.as_ref() == b"name" {
+                                sample.name = Some(
+                                    attr.unescape_value()
+                                        .expect("Error decoding name")
+                                        .to_string(),
+                                );
+                            }
+                        }
+                        Err(msg) => {
+                            return Err(self.handle_xml_error(msg.into(), state));
+                        }
+                    }
+                }
+                self.samples.push(sample);
+                return Ok(MzMLParserState::Sample);
+            }
+            b"scanSettingsList" => return Ok(MzMLParserState::ScanSettingsList),
+            b"scanSettings" => {
+                let mut scan_settings = ScanSettings::default();
+                for attr_parsed in event.attributes() {
+                    match attr_parsed {
+                        Ok(attr) => {
+                            if attr.key.as_ref() == b"id" {
+                                scan_settings.id = attr
+                                    .unescape_value()
+                                    .expect("Error decoding id")
+                                    .to_string();
+                            }
+                        }
+                        Err(msg) => {
+                            return Err(self.handle_xml_error(msg.into(), state));
+                        }
+                    }
+                }
+                self.scan_settings.push(scan_settings);
+                return Ok(MzMLParserState::ScanSettings);
+            }
+            b"targetList" => return Ok(MzMLParserState::TargetList),
+            b"target" => {
+                match self.scan_settings.last_mut() {
+                    Some(scan_settings) => scan_settings.targets.push(ScanTarget::default()),
+                    None => {
+                        return Err(MzMLParserError::IncompleteElementError(
+                            "target outside of scanSettings".to_string(),
+                            state,
+                        ))
+                    }
+                }
+                return Ok(MzMLParserState::Target);
+            }
             b"softwareList" => return Ok(MzMLParserState::SoftwareList),
             b"software" => {
                 let mut software = Software::default();
@@ -636,8 +737,8 @@ impl<'a> FileMetadataBuilder<'a> {
                                     .unescape_value()
                                     .expect("Error decoding id")
                                     .to_string();
-                                self.reference_param_groups.entry(key.clone()).or_default();
-                                self.last_group = key;
+                                self.param_groups
+                                    .push(ParamGroup::new(key, ParamList::new()));
                             }
                         }
                         Err(msg) => {
@@ -912,11 +1013,20 @@ impl<'a> FileMetadataBuilder<'a> {
                 .unwrap()
                 .add_param(param),
             MzMLParserState::ReferenceParamGroup => {
-                self.reference_param_groups
-                    .get_mut(&self.last_group)
-                    .unwrap()
-                    .push(param);
+                self.param_groups.last_mut().unwrap().add_param(param);
             }
+            MzMLParserState::Sample => self.samples.last_mut().unwrap().add_param(param),
+            MzMLParserState::ScanSettings => {
+                self.scan_settings.last_mut().unwrap().add_param(param)
+            }
+            MzMLParserState::Target => self
+                .scan_settings
+                .last_mut()
+                .unwrap()
+                .targets
+                .last_mut()
+                .unwrap()
+                .add_param(param),
             _ => {}
         }
     }
@@ -956,6 +1066,51 @@ impl<'a> FileMetadataBuilder<'a> {
                     }
                 }
             }
+            b"cv" => {
+                let mut cv = CVEntry::default();
+                for attr_parsed in event.attributes() {
+                    match attr_parsed {
+                        Ok(attr) => {
+                            let value = attr
+                                .unescape_value()
+                                .expect("Error decoding cv attribute")
+                                .to_string();
+                            match attr.key.as_ref() {
+                                b"id" => cv.id = value,
+                                b"fullName" => cv.full_name = value,
+                                b"URI" => cv.uri = value,
+                                b"version" => cv.version = Some(value),
+                                _ => {}
+                            }
+                        }
+                        Err(msg) => {
+                            return Err(self.handle_xml_error(msg.into(), state));
+                        }
+                    }
+                }
+                self.cv_list.push(cv);
+            }
+            b"sourceFileRef" => {
+                if state == MzMLParserState::ScanSettings {
+                    let scan_settings = self.scan_settings.last_mut().unwrap();
+                    for attr_parsed in event.attributes() {
+                        match attr_parsed {
+                            Ok(attr) => {
+                                if attr.key.as_ref() == b"ref" {
+                                    scan_settings.source_file_refs.push(
+                                        attr.unescape_value()
+                                            .expect("Error decoding source file reference")
+                                            .to_string(),
+                                    );
+                                }
+                            }
+                            Err(msg) => {
+                                return Err(self.handle_xml_error(msg.into(), state));
+                            }
+                        }
+                    }
+                }
+            }
             b"referenceableParamGroupRef" => {
                 for attr_parsed in event.attributes() {
                     match attr_parsed {
@@ -966,8 +1121,12 @@ impl<'a> FileMetadataBuilder<'a> {
                                     .expect("Error decoding reference group")
                                     .to_string();
 
-                                let param_group = match self.reference_param_groups.get(&group_id) {
-                                    Some(params) => params.clone(),
+                                let param_group = match self
+                                    .param_groups
+                                    .iter()
+                                    .find(|group| group.id == group_id)
+                                {
+                                    Some(group) => group.params.clone(),
                                     None => {
                                         panic!("Encountered a referenceableParamGroupRef without a group definition")
                                     }
@@ -992,11 +1151,18 @@ impl<'a> FileMetadataBuilder<'a> {
     pub fn end_element(&mut self, event: &BytesEnd, state: MzMLParserState) -> ParserResult {
         let elt_name = event.name();
         match elt_name.as_ref() {
+            b"cvList" => return Ok(MzMLParserState::CVList),
             b"fileDescription" => return Ok(MzMLParserState::FileDescription),
             b"fileContent" => return Ok(MzMLParserState::FileDescription),
             b"sourceFile" => return Ok(MzMLParserState::SourceFileList),
             b"softwareList" => return Ok(MzMLParserState::SoftwareList),
             b"software" => return Ok(MzMLParserState::SoftwareList),
+            b"sampleList" => return Ok(MzMLParserState::SampleList),
+            b"sample" => return Ok(MzMLParserState::SampleList),
+            b"scanSettingsList" => return Ok(MzMLParserState::ScanSettingsList),
+            b"scanSettings" => return Ok(MzMLParserState::ScanSettingsList),
+            b"targetList" => return Ok(MzMLParserState::ScanSettings),
+            b"target" => return Ok(MzMLParserState::TargetList),
             b"referenceableParamGroupList" => {
                 return Ok(MzMLParserState::ReferenceParamGroupList);
             }
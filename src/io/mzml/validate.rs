@@ -0,0 +1,1117 @@
+/*!
+Semantic validation of mzML documents.
+
+XML schema validation only checks that a document is well-formed. The checks here
+look at what the document *says*: whether elements carry the controlled vocabulary
+terms the PSI mapping rules require of them, whether data arrays hold as many values
+as they claim, whether the IDs elements refer to are actually defined, whether scan
+times move forward, and whether the trailing `indexedmzML` index points at the right
+places.
+
+```no_run
+use mzdata::io::mzml::validate::validate_path;
+
+let report = validate_path("./test/data/three_test_scans.mzML").unwrap();
+for issue in report.iter() {
+    println!("{issue}");
+}
+assert!(report.is_valid());
+```
+*/
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path;
+
+use flate2::read::ZlibDecoder;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Reader;
+
+use crate::io::compression::{decompressing_reader, CompressionType};
+use crate::params::{ControlledVocabulary, Param, Unit};
+use crate::spectrum::{Activation, BinaryDataArrayType};
+
+use super::reader::Bytes;
+use super::reading_shared::{
+    CVParamParse, MzMLParserError, MzMLParserState, MzMLSAX, ParserResult, XMLParseBase,
+};
+
+/// How serious a [`ValidationIssue`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The document is usable, but other tools may disagree about what it means
+    Warning,
+    /// The document violates the mzML specification
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// The kinds of identifiers that elements may refer to by ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    DataProcessing,
+    InstrumentConfiguration,
+    ReferenceableParamGroup,
+    SourceFile,
+    Software,
+}
+
+impl ReferenceKind {
+    /// The element which defines identifiers of this kind
+    pub const fn element_name(&self) -> &'static str {
+        match self {
+            ReferenceKind::DataProcessing => "dataProcessing",
+            ReferenceKind::InstrumentConfiguration => "instrumentConfiguration",
+            ReferenceKind::ReferenceableParamGroup => "referenceableParamGroup",
+            ReferenceKind::SourceFile => "sourceFile",
+            ReferenceKind::Software => "software",
+        }
+    }
+
+    fn defined_by(element: &[u8]) -> Option<Self> {
+        match element {
+            b"dataProcessing" => Some(Self::DataProcessing),
+            b"instrumentConfiguration" => Some(Self::InstrumentConfiguration),
+            b"referenceableParamGroup" => Some(Self::ReferenceableParamGroup),
+            b"sourceFile" => Some(Self::SourceFile),
+            b"software" => Some(Self::Software),
+            _ => None,
+        }
+    }
+
+    /// The attributes of `element` which refer to other elements by ID
+    fn referenced_by(element: &[u8]) -> &'static [(&'static str, Self)] {
+        match element {
+            b"spectrum" => &[
+                ("dataProcessingRef", Self::DataProcessing),
+                ("sourceFileRef", Self::SourceFile),
+            ],
+            b"chromatogram" | b"binaryDataArray" => &[("dataProcessingRef", Self::DataProcessing)],
+            b"spectrumList" | b"chromatogramList" => {
+                &[("defaultDataProcessingRef", Self::DataProcessing)]
+            }
+            b"scan" => &[
+                ("instrumentConfigurationRef", Self::InstrumentConfiguration),
+                ("sourceFileRef", Self::SourceFile),
+            ],
+            b"precursor" => &[("sourceFileRef", Self::SourceFile)],
+            b"run" => &[
+                (
+                    "defaultInstrumentConfigurationRef",
+                    Self::InstrumentConfiguration,
+                ),
+                ("defaultSourceFileRef", Self::SourceFile),
+            ],
+            b"referenceableParamGroupRef" => &[("ref", Self::ReferenceableParamGroup)],
+            b"sourceFileRef" => &[("ref", Self::SourceFile)],
+            b"softwareRef" => &[("ref", Self::Software)],
+            b"processingMethod" => &[("softwareRef", Self::Software)],
+            _ => &[],
+        }
+    }
+}
+
+/// The specific problem a [`ValidationIssue`] describes
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssueKind {
+    /// An element lacks a controlled vocabulary term that the mapping rules require
+    MissingRequiredTerm {
+        element: String,
+        requirement: &'static str,
+    },
+    /// A data array decoded to a different number of values than its `arrayLength`
+    /// or the enclosing `defaultArrayLength` declares
+    ArrayLengthMismatch { expected: usize, found: usize },
+    /// The `encodedLength` of a data array does not match its base64 text
+    EncodedLengthMismatch { expected: usize, found: usize },
+    /// The content of a `<binary>` element could not be decoded
+    InvalidBinaryData(String),
+    /// An attribute refers to an ID that no element defines
+    DanglingReference {
+        attribute: &'static str,
+        kind: ReferenceKind,
+        reference: String,
+    },
+    /// The same spectrum or chromatogram ID was used more than once
+    DuplicateId(String),
+    /// A spectrum's scan start time is earlier than the preceding spectrum's, in minutes
+    NonMonotonicScanTime { previous: f64, current: f64 },
+    /// An index entry's offset does not point at the element with that ID, or
+    /// that ID was not found in the document
+    IndexOffsetMismatch {
+        id: String,
+        offset: u64,
+        actual: Option<u64>,
+    },
+    /// The `indexListOffset` does not point at the `<indexList>` element
+    IndexListOffsetMismatch { offset: u64, actual: Option<u64> },
+    /// A spectrum or chromatogram is missing from the document's index
+    MissingIndexEntry(String),
+    /// The XML could not be parsed
+    MalformedDocument(String),
+}
+
+impl Display for ValidationIssueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingRequiredTerm {
+                element,
+                requirement,
+            } => write!(f, "<{element}> is missing a {requirement} term"),
+            Self::ArrayLengthMismatch { expected, found } => write!(
+                f,
+                "Data array holds {found} values but its declared length is {expected}"
+            ),
+            Self::EncodedLengthMismatch { expected, found } => write!(
+                f,
+                "Data array has {found} bytes of base64 text but its encodedLength is {expected}"
+            ),
+            Self::InvalidBinaryData(msg) => write!(f, "Data array could not be decoded: {msg}"),
+            Self::DanglingReference {
+                attribute,
+                kind,
+                reference,
+            } => write!(
+                f,
+                "{attribute}=\"{reference}\" does not match any <{}> id",
+                kind.element_name()
+            ),
+            Self::DuplicateId(id) => write!(f, "The id \"{id}\" is used more than once"),
+            Self::NonMonotonicScanTime { previous, current } => write!(
+                f,
+                "Scan start time {current} is earlier than the previous spectrum's {previous}"
+            ),
+            Self::IndexOffsetMismatch {
+                id,
+                offset,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "The index places \"{id}\" at byte {offset}, but it starts at byte {actual}"
+            ),
+            Self::IndexOffsetMismatch {
+                id,
+                offset,
+                actual: None,
+            } => write!(
+                f,
+                "The index places \"{id}\" at byte {offset}, but there is no such element"
+            ),
+            Self::IndexListOffsetMismatch {
+                offset,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "indexListOffset is {offset}, but <indexList> starts at byte {actual}"
+            ),
+            Self::IndexListOffsetMismatch {
+                offset,
+                actual: None,
+            } => write!(
+                f,
+                "indexListOffset is {offset}, but there is no <indexList> element"
+            ),
+            Self::MissingIndexEntry(id) => write!(f, "\"{id}\" is missing from the index"),
+            Self::MalformedDocument(msg) => write!(f, "Malformed document: {msg}"),
+        }
+    }
+}
+
+/// A single problem found while validating a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub kind: ValidationIssueKind,
+    /// The byte offset of the element the issue was found in
+    pub offset: u64,
+    /// The ID of the spectrum or chromatogram the issue was found in, if any
+    pub context: Option<String>,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.severity, self.offset)?;
+        if let Some(context) = self.context.as_ref() {
+            write!(f, " in \"{context}\"")?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// The issues found in a document, in document order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+    pub spectrum_count: usize,
+    pub chromatogram_count: usize,
+}
+
+impl ValidationReport {
+    /// Whether the document had no [`Severity::Error`] issues
+    pub fn is_valid(&self) -> bool {
+        !self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ValidationIssue> {
+        self.issues.iter()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    pub fn len(&self) -> usize {
+        self.issues.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+fn ms_accession(param: &Param) -> Option<u32> {
+    match param.controlled_vocabulary {
+        Some(ControlledVocabulary::MS) => param.accession,
+        _ => None,
+    }
+}
+
+const SPECTRUM_TYPES: &[u32] = &[
+    1000322, 1000325, 1000326, 1000328, 1000341, 1000579, 1000580, 1000581, 1000582, 1000583,
+    1000620, 1000789, 1000790, 1000804, 1000805, 1000806,
+];
+const BINARY_DATA_TYPES: &[u32] = &[1000519, 1000521, 1000522, 1000523, 1001479];
+const BINARY_COMPRESSIONS: &[u32] = &[
+    1000574, 1000576, 1002312, 1002313, 1002314, 1002746, 1002747, 1002748, 1003089, 1003090,
+];
+const ARRAY_TYPES: &[u32] = &[
+    1000514, 1000515, 1000516, 1000517, 1000595, 1000617, 1000786, 1000820, 1000821, 1000822,
+    1002477, 1002816, 1003006, 1003007, 1003008, 1003153, 1003154, 1003155, 1003156,
+];
+const CHROMATOGRAM_TYPES: &[u32] = &[
+    1000235, 1000627, 1000628, 1000810, 1000811, 1000812, 1000813, 1001472, 1001473, 1001474,
+];
+
+fn is_spectrum_type(param: &Param) -> bool {
+    match ms_accession(param) {
+        Some(1000127) | Some(1000128) => false,
+        Some(acc) => SPECTRUM_TYPES.contains(&acc) || param.name.ends_with(" spectrum"),
+        None => false,
+    }
+}
+
+fn is_array_type(param: &Param) -> bool {
+    match ms_accession(param) {
+        Some(acc) => ARRAY_TYPES.contains(&acc) || param.name.ends_with(" array"),
+        None => false,
+    }
+}
+
+fn is_chromatogram_type(param: &Param) -> bool {
+    match ms_accession(param) {
+        Some(acc) => CHROMATOGRAM_TYPES.contains(&acc) || param.name.ends_with(" chromatogram"),
+        None => false,
+    }
+}
+
+/// A term that an element must carry, either directly or through a `referenceableParamGroupRef`
+struct TermRequirement {
+    element: &'static str,
+    description: &'static str,
+    satisfied_by: fn(&Param) -> bool,
+}
+
+/// A subset of the PSI-MS mapping rules for mzML covering the elements readers depend on.
+/// Term sets are matched by accession, falling back on the naming conventions of the CV
+/// for terms added since these lists were written.
+const REQUIRED_TERMS: &[TermRequirement] = &[
+    TermRequirement {
+        element: "fileContent",
+        description: "data file content",
+        satisfied_by: |p| p.is_controlled(),
+    },
+    TermRequirement {
+        element: "source",
+        description: "ionization type",
+        satisfied_by: |p| p.is_controlled(),
+    },
+    TermRequirement {
+        element: "analyzer",
+        description: "mass analyzer type",
+        satisfied_by: |p| p.is_controlled(),
+    },
+    TermRequirement {
+        element: "detector",
+        description: "detector type",
+        satisfied_by: |p| p.is_controlled(),
+    },
+    TermRequirement {
+        element: "spectrum",
+        description: "ms level",
+        satisfied_by: |p| ms_accession(p) == Some(1000511),
+    },
+    TermRequirement {
+        element: "spectrum",
+        description: "spectrum type",
+        satisfied_by: is_spectrum_type,
+    },
+    TermRequirement {
+        element: "spectrum",
+        description: "spectrum representation",
+        satisfied_by: |p| matches!(ms_accession(p), Some(1000127) | Some(1000128)),
+    },
+    TermRequirement {
+        element: "chromatogram",
+        description: "chromatogram type",
+        satisfied_by: is_chromatogram_type,
+    },
+    TermRequirement {
+        element: "binaryDataArray",
+        description: "binary data array",
+        satisfied_by: is_array_type,
+    },
+    TermRequirement {
+        element: "binaryDataArray",
+        description: "binary data type",
+        satisfied_by: |p| matches!(ms_accession(p), Some(acc) if BINARY_DATA_TYPES.contains(&acc)),
+    },
+    TermRequirement {
+        element: "binaryDataArray",
+        description: "binary data compression type",
+        satisfied_by: |p| matches!(ms_accession(p), Some(acc) if BINARY_COMPRESSIONS.contains(&acc)),
+    },
+    TermRequirement {
+        element: "selectedIon",
+        description: "selected ion m/z",
+        satisfied_by: |p| ms_accession(p) == Some(1000744),
+    },
+    TermRequirement {
+        element: "activation",
+        description: "dissociation method",
+        satisfied_by: |p| Activation::is_param_activation(p),
+    },
+];
+
+/// The state of an element which has been opened but not yet closed
+#[derive(Debug, Default)]
+struct ElementFrame {
+    name: String,
+    id: Option<String>,
+    offset: u64,
+    params: Vec<Param>,
+    array_length: Option<usize>,
+    encoded_length: Option<usize>,
+}
+
+#[derive(Debug)]
+struct PendingReference {
+    attribute: &'static str,
+    kind: ReferenceKind,
+    reference: String,
+    offset: u64,
+    context: Option<String>,
+}
+
+#[derive(Debug)]
+struct IndexEntry {
+    index_name: String,
+    id: String,
+    offset: u64,
+    entry_offset: u64,
+}
+
+/// The whitespace preceding an element and the start of the element itself. An offset
+/// anywhere in this span leads a reader to the element.
+type ElementSpan = (u64, u64);
+
+fn span_contains(span: &ElementSpan, offset: u64) -> bool {
+    span.0 <= offset && offset <= span.1
+}
+
+/**
+A SAX-style [`MzMLSAX`] handler which checks an mzML document as it is read and
+accumulates a [`ValidationReport`].
+
+Most callers should use [`validate_reader`] or [`validate_path`]. The handler can
+also be driven by hand, in which case [`MzMLValidator::set_element_span`] must be
+called before each start or empty element event for offsets to be checked.
+*/
+#[derive(Debug, Default)]
+pub struct MzMLValidator {
+    issues: Vec<ValidationIssue>,
+    stack: Vec<ElementFrame>,
+    element_span: ElementSpan,
+
+    definitions: HashMap<ReferenceKind, HashSet<String>>,
+    references: Vec<PendingReference>,
+    param_groups: HashMap<String, Vec<Param>>,
+
+    context: Option<String>,
+    default_array_length: Option<usize>,
+    binary: Option<String>,
+    scan_time: Option<f64>,
+    previous_scan_time: Option<f64>,
+
+    spectrum_offsets: HashMap<String, ElementSpan>,
+    spectrum_ids: Vec<String>,
+    chromatogram_offsets: HashMap<String, ElementSpan>,
+    chromatogram_ids: Vec<String>,
+
+    index_name: Option<String>,
+    index_ref: Option<String>,
+    index_entries: Vec<IndexEntry>,
+    index_list_span: Option<ElementSpan>,
+    index_list_offset: Option<u64>,
+}
+
+impl XMLParseBase for MzMLValidator {}
+impl CVParamParse for MzMLValidator {}
+
+fn attribute(event: &BytesStart, key: &str) -> Result<Option<String>, MzMLParserError> {
+    match event.try_get_attribute(key) {
+        Ok(Some(attr)) => match attr.unescape_value() {
+            Ok(value) => Ok(Some(value.to_string())),
+            Err(err) => Err(MzMLParserError::IncompleteElementError(
+                err.to_string(),
+                MzMLParserState::ParserError,
+            )),
+        },
+        Ok(None) => Ok(None),
+        Err(err) => Err(MzMLParserError::XMLError(MzMLParserState::ParserError, err)),
+    }
+}
+
+fn element_state(name: &[u8]) -> Option<MzMLParserState> {
+    let state = match name {
+        b"cvList" => MzMLParserState::CVList,
+        b"fileDescription" => MzMLParserState::FileDescription,
+        b"fileContent" => MzMLParserState::FileContents,
+        b"sourceFileList" => MzMLParserState::SourceFileList,
+        b"sourceFile" => MzMLParserState::SourceFile,
+        b"referenceableParamGroupList" => MzMLParserState::ReferenceParamGroupList,
+        b"referenceableParamGroup" => MzMLParserState::ReferenceParamGroup,
+        b"sampleList" => MzMLParserState::SampleList,
+        b"sample" => MzMLParserState::Sample,
+        b"softwareList" => MzMLParserState::SoftwareList,
+        b"software" => MzMLParserState::Software,
+        b"scanSettingsList" => MzMLParserState::ScanSettingsList,
+        b"scanSettings" => MzMLParserState::ScanSettings,
+        b"targetList" => MzMLParserState::TargetList,
+        b"target" => MzMLParserState::Target,
+        b"instrumentConfigurationList" => MzMLParserState::InstrumentConfigurationList,
+        b"instrumentConfiguration" => MzMLParserState::InstrumentConfiguration,
+        b"componentList" => MzMLParserState::ComponentList,
+        b"source" => MzMLParserState::Source,
+        b"analyzer" => MzMLParserState::Analyzer,
+        b"detector" => MzMLParserState::Detector,
+        b"dataProcessingList" => MzMLParserState::DataProcessingList,
+        b"dataProcessing" => MzMLParserState::DataProcessing,
+        b"processingMethod" => MzMLParserState::ProcessingMethod,
+        b"run" => MzMLParserState::Run,
+        b"spectrumList" => MzMLParserState::SpectrumList,
+        b"spectrum" => MzMLParserState::Spectrum,
+        b"binaryDataArrayList" => MzMLParserState::BinaryDataArrayList,
+        b"binaryDataArray" => MzMLParserState::BinaryDataArray,
+        b"binary" => MzMLParserState::Binary,
+        b"scanList" => MzMLParserState::ScanList,
+        b"scan" => MzMLParserState::Scan,
+        b"scanWindowList" => MzMLParserState::ScanWindowList,
+        b"scanWindow" => MzMLParserState::ScanWindow,
+        b"precursorList" => MzMLParserState::PrecursorList,
+        b"precursor" => MzMLParserState::Precursor,
+        b"isolationWindow" => MzMLParserState::IsolationWindow,
+        b"selectedIonList" => MzMLParserState::SelectedIonList,
+        b"selectedIon" => MzMLParserState::SelectedIon,
+        b"activation" => MzMLParserState::Activation,
+        b"chromatogram" => MzMLParserState::Chromatogram,
+        _ => return None,
+    };
+    Some(state)
+}
+
+impl MzMLValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record where the next element starts, as a span from the end of the preceding
+    /// event to the element's opening `<`
+    pub fn set_element_span(&mut self, preceding_end: u64, start: u64) {
+        self.element_span = (preceding_end, start);
+    }
+
+    fn push_issue(&mut self, severity: Severity, kind: ValidationIssueKind, offset: u64) {
+        self.issues.push(ValidationIssue {
+            severity,
+            kind,
+            offset,
+            context: self.context.clone(),
+        });
+    }
+
+    fn open(&mut self, event: &BytesStart, state: MzMLParserState) -> ParserResult {
+        let name = event.name();
+        let name = name.as_ref();
+        let offset = self.element_span.1;
+
+        if name == b"cvParam" {
+            let param = Self::handle_param(event, offset as usize, state)?;
+            if let Some(frame) = self.stack.last_mut() {
+                if frame.name == "scan" && ms_accession(&param) == Some(1000016) {
+                    if let (None, Ok(time)) = (self.scan_time, param.value.parse::<f64>()) {
+                        self.scan_time = Some(match param.unit {
+                            Unit::Second => time / 60.0,
+                            _ => time,
+                        });
+                    }
+                }
+                frame.params.push(param);
+            }
+        }
+
+        let mut frame = ElementFrame {
+            name: String::from_utf8_lossy(name).to_string(),
+            offset,
+            ..Default::default()
+        };
+
+        match name {
+            b"spectrum" | b"chromatogram" => {
+                let id = attribute(event, "id")?.unwrap_or_default();
+                self.context = Some(id.clone());
+                let (offsets, ids) = if name == b"spectrum" {
+                    (&mut self.spectrum_offsets, &mut self.spectrum_ids)
+                } else {
+                    (&mut self.chromatogram_offsets, &mut self.chromatogram_ids)
+                };
+                if offsets.insert(id.clone(), self.element_span).is_some() {
+                    self.push_issue(
+                        Severity::Error,
+                        ValidationIssueKind::DuplicateId(id),
+                        offset,
+                    );
+                } else {
+                    ids.push(id);
+                }
+                self.default_array_length =
+                    attribute(event, "defaultArrayLength")?.and_then(|v| v.parse().ok());
+                self.scan_time = None;
+            }
+            b"binaryDataArray" => {
+                frame.array_length = attribute(event, "arrayLength")?
+                    .and_then(|v| v.parse().ok())
+                    .or(self.default_array_length);
+                frame.encoded_length =
+                    attribute(event, "encodedLength")?.and_then(|v| v.parse().ok());
+            }
+            b"binary" => {
+                self.binary = Some(String::new());
+            }
+            b"referenceableParamGroupRef" => {
+                if let Some(group) = attribute(event, "ref")? {
+                    if let (Some(params), Some(parent)) =
+                        (self.param_groups.get(&group), self.stack.last_mut())
+                    {
+                        parent.params.extend(params.iter().cloned());
+                    }
+                }
+            }
+            b"indexList" => {
+                self.index_list_span = Some(self.element_span);
+            }
+            b"index" => {
+                self.index_name = attribute(event, "name")?;
+            }
+            b"offset" => {
+                self.index_ref = attribute(event, "idRef")?;
+            }
+            _ => {}
+        }
+
+        if let Some(kind) = ReferenceKind::defined_by(name) {
+            if let Some(id) = attribute(event, "id")? {
+                self.definitions.entry(kind).or_default().insert(id.clone());
+                frame.id = Some(id);
+            }
+        }
+        for (attr, kind) in ReferenceKind::referenced_by(name).iter().copied() {
+            if let Some(reference) = attribute(event, attr)? {
+                self.references.push(PendingReference {
+                    attribute: attr,
+                    kind,
+                    reference,
+                    offset,
+                    context: self.context.clone(),
+                });
+            }
+        }
+
+        self.stack.push(frame);
+        Ok(element_state(name).unwrap_or(state))
+    }
+
+    fn close(&mut self, state: MzMLParserState) -> ParserResult {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return Ok(state),
+        };
+
+        for requirement in REQUIRED_TERMS.iter().filter(|r| r.element == frame.name) {
+            if !frame.params.iter().any(requirement.satisfied_by) {
+                self.push_issue(
+                    Severity::Error,
+                    ValidationIssueKind::MissingRequiredTerm {
+                        element: frame.name.clone(),
+                        requirement: requirement.description,
+                    },
+                    frame.offset,
+                );
+            }
+        }
+
+        match frame.name.as_str() {
+            "binaryDataArray" => self.check_data_array(&frame),
+            "referenceableParamGroup" => {
+                if let Some(id) = frame.id {
+                    self.param_groups.insert(id, frame.params);
+                }
+            }
+            "spectrum" => {
+                if let Some(current) = self.scan_time {
+                    if let Some(previous) = self.previous_scan_time {
+                        if current < previous {
+                            self.push_issue(
+                                Severity::Warning,
+                                ValidationIssueKind::NonMonotonicScanTime { previous, current },
+                                frame.offset,
+                            );
+                        }
+                    }
+                    self.previous_scan_time = Some(current);
+                }
+                self.context = None;
+                self.default_array_length = None;
+            }
+            "chromatogram" => {
+                self.context = None;
+                self.default_array_length = None;
+            }
+            _ => {}
+        }
+
+        Ok(self
+            .stack
+            .last()
+            .and_then(|f| element_state(f.name.as_bytes()))
+            .unwrap_or(state))
+    }
+
+    fn check_data_array(&mut self, frame: &ElementFrame) {
+        let text = match self.binary.take() {
+            Some(text) => text,
+            None => return,
+        };
+        if let Some(expected) = frame.encoded_length {
+            if expected != text.len() {
+                self.push_issue(
+                    Severity::Warning,
+                    ValidationIssueKind::EncodedLengthMismatch {
+                        expected,
+                        found: text.len(),
+                    },
+                    frame.offset,
+                );
+            }
+        }
+
+        let mut dtype = None;
+        let mut zlib = false;
+        let mut other_compression = false;
+        for acc in frame.params.iter().filter_map(ms_accession) {
+            match acc {
+                1000519 => dtype = Some(BinaryDataArrayType::Int32),
+                1000521 => dtype = Some(BinaryDataArrayType::Float32),
+                1000522 => dtype = Some(BinaryDataArrayType::Int64),
+                1000523 => dtype = Some(BinaryDataArrayType::Float64),
+                1001479 => dtype = Some(BinaryDataArrayType::ASCII),
+                1000574 => zlib = true,
+                1000576 => {}
+                acc if BINARY_COMPRESSIONS.contains(&acc) => other_compression = true,
+                _ => {}
+            }
+        }
+        // Numpress and other transforms do not preserve the byte width of the values,
+        // so their length cannot be checked without a full decoder
+        let dtype = match dtype {
+            Some(dtype) if !other_compression => dtype,
+            _ => return,
+        };
+
+        let bytes = match base64_simd::STANDARD.decode_type::<Bytes>(text.as_bytes()) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.push_issue(
+                    Severity::Error,
+                    ValidationIssueKind::InvalidBinaryData(err.to_string()),
+                    frame.offset,
+                );
+                return;
+            }
+        };
+        let bytes = if zlib {
+            let mut buffer = Bytes::new();
+            if let Err(err) = ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut buffer) {
+                self.push_issue(
+                    Severity::Error,
+                    ValidationIssueKind::InvalidBinaryData(err.to_string()),
+                    frame.offset,
+                );
+                return;
+            }
+            buffer
+        } else {
+            bytes
+        };
+
+        if bytes.len() % dtype.size_of() != 0 {
+            self.push_issue(
+                Severity::Error,
+                ValidationIssueKind::InvalidBinaryData(format!(
+                    "{} bytes is not a whole number of {dtype} values",
+                    bytes.len()
+                )),
+                frame.offset,
+            );
+            return;
+        }
+        let found = bytes.len() / dtype.size_of();
+        if let Some(expected) = frame.array_length {
+            if expected != found {
+                self.push_issue(
+                    Severity::Error,
+                    ValidationIssueKind::ArrayLengthMismatch { expected, found },
+                    frame.offset,
+                );
+            }
+        }
+    }
+
+    /// Check everything that can only be resolved once the whole document has been seen,
+    /// and produce the final report
+    pub fn finish(mut self) -> ValidationReport {
+        if let Some(frame) = self.stack.last() {
+            let msg = format!("The document ended inside <{}>", frame.name);
+            let offset = frame.offset;
+            self.push_issue(
+                Severity::Error,
+                ValidationIssueKind::MalformedDocument(msg),
+                offset,
+            );
+        }
+        self.context = None;
+
+        let references = std::mem::take(&mut self.references);
+        for reference in references {
+            let defined = self
+                .definitions
+                .get(&reference.kind)
+                .map(|ids| ids.contains(&reference.reference))
+                .unwrap_or_default();
+            if !defined {
+                self.issues.push(ValidationIssue {
+                    severity: Severity::Error,
+                    kind: ValidationIssueKind::DanglingReference {
+                        attribute: reference.attribute,
+                        kind: reference.kind,
+                        reference: reference.reference,
+                    },
+                    offset: reference.offset,
+                    context: reference.context,
+                });
+            }
+        }
+
+        self.check_index();
+
+        self.issues.sort_by_key(|issue| issue.offset);
+        ValidationReport {
+            issues: self.issues,
+            spectrum_count: self.spectrum_ids.len(),
+            chromatogram_count: self.chromatogram_ids.len(),
+        }
+    }
+
+    fn check_index(&mut self) {
+        if let Some(offset) = self.index_list_offset {
+            let span = self.index_list_span;
+            if !span.map(|s| span_contains(&s, offset)).unwrap_or_default() {
+                self.push_issue(
+                    Severity::Error,
+                    ValidationIssueKind::IndexListOffsetMismatch {
+                        offset,
+                        actual: span.map(|s| s.1),
+                    },
+                    offset,
+                );
+            }
+        }
+        if self.index_entries.is_empty() {
+            return;
+        }
+
+        let mut indexed: HashSet<(bool, &str)> = HashSet::new();
+        let mut issues = Vec::new();
+        for entry in self.index_entries.iter() {
+            let is_spectrum = entry.index_name == "spectrum";
+            let offsets = match entry.index_name.as_str() {
+                "spectrum" => &self.spectrum_offsets,
+                "chromatogram" => &self.chromatogram_offsets,
+                _ => continue,
+            };
+            indexed.insert((is_spectrum, entry.id.as_str()));
+            let span = offsets.get(&entry.id);
+            if !span
+                .map(|s| span_contains(s, entry.offset))
+                .unwrap_or_default()
+            {
+                issues.push(ValidationIssue {
+                    severity: Severity::Error,
+                    kind: ValidationIssueKind::IndexOffsetMismatch {
+                        id: entry.id.clone(),
+                        offset: entry.offset,
+                        actual: span.map(|s| s.1),
+                    },
+                    offset: entry.entry_offset,
+                    context: None,
+                });
+            }
+        }
+
+        let unindexed = self
+            .spectrum_ids
+            .iter()
+            .filter(|id| !indexed.contains(&(true, id.as_str())))
+            .map(|id| (id, self.spectrum_offsets[id].1))
+            .chain(
+                self.chromatogram_ids
+                    .iter()
+                    .filter(|id| !indexed.contains(&(false, id.as_str())))
+                    .map(|id| (id, self.chromatogram_offsets[id].1)),
+            );
+        for (id, offset) in unindexed {
+            issues.push(ValidationIssue {
+                severity: Severity::Warning,
+                kind: ValidationIssueKind::MissingIndexEntry(id.clone()),
+                offset,
+                context: Some(id.clone()),
+            });
+        }
+        self.issues.extend(issues);
+    }
+
+    /// Read the mzML document from `stream` to the end, returning the report
+    pub fn validate<R: BufRead>(mut self, stream: R) -> ValidationReport {
+        let mut reader = Reader::from_reader(stream);
+        reader.trim_text(true);
+        let mut buffer = Bytes::new();
+        let mut state = MzMLParserState::Start;
+        loop {
+            let preceding_end = reader.buffer_position() as u64;
+            let event = reader.read_event_into(&mut buffer);
+            let position = reader.buffer_position();
+            // A tag's event holds its content without the enclosing `<` and `>`, nor
+            // the `/` of an empty element
+            let result = match event {
+                Ok(Event::Start(ref e)) => {
+                    let start = position.saturating_sub(e.len() + 2) as u64;
+                    self.set_element_span(preceding_end, start);
+                    self.start_element(e, state)
+                }
+                Ok(Event::Empty(ref e)) => {
+                    let start = position.saturating_sub(e.len() + 3);
+                    self.set_element_span(preceding_end, start as u64);
+                    self.empty_element(e, state, start)
+                }
+                Ok(Event::End(ref e)) => self.end_element(e, state),
+                Ok(Event::Text(ref e)) => self.text(e, state),
+                Ok(Event::Eof) => break,
+                Err(err) => Err(self.handle_xml_error(err, state)),
+                _ => Ok(state),
+            };
+            match result {
+                Ok(next) => state = next,
+                Err(err) => {
+                    self.push_issue(
+                        Severity::Error,
+                        ValidationIssueKind::MalformedDocument(err.to_string()),
+                        reader.buffer_position() as u64,
+                    );
+                    break;
+                }
+            }
+            buffer.clear();
+        }
+        self.finish()
+    }
+}
+
+impl MzMLSAX for MzMLValidator {
+    fn start_element(&mut self, event: &BytesStart, state: MzMLParserState) -> ParserResult {
+        self.open(event, state)
+    }
+
+    fn empty_element(
+        &mut self,
+        event: &BytesStart,
+        state: MzMLParserState,
+        _reader_position: usize,
+    ) -> ParserResult {
+        let state = self.open(event, state)?;
+        self.close(state)
+    }
+
+    fn end_element(&mut self, _event: &BytesEnd, state: MzMLParserState) -> ParserResult {
+        self.close(state)
+    }
+
+    fn text(&mut self, event: &BytesText, state: MzMLParserState) -> ParserResult {
+        let element = match self.stack.last() {
+            Some(frame) => frame.name.as_str(),
+            None => return Ok(state),
+        };
+        match element {
+            "binary" => {
+                if let Some(binary) = self.binary.as_mut() {
+                    binary.push_str(&String::from_utf8_lossy(event));
+                }
+            }
+            "offset" => {
+                let entry_offset = self.stack.last().unwrap().offset;
+                let offset = String::from_utf8_lossy(event).trim().parse::<u64>();
+                if let (Ok(offset), Some(index_name), Some(id)) =
+                    (offset, self.index_name.clone(), self.index_ref.take())
+                {
+                    self.index_entries.push(IndexEntry {
+                        index_name,
+                        id,
+                        offset,
+                        entry_offset,
+                    });
+                }
+            }
+            "indexListOffset" => {
+                self.index_list_offset = String::from_utf8_lossy(event).trim().parse().ok();
+            }
+            _ => {}
+        }
+        Ok(state)
+    }
+}
+
+/// Validate the mzML document read from `stream`, which may be compressed with any
+/// [`CompressionType`] this build supports
+pub fn validate_reader<R: Read>(stream: R) -> io::Result<ValidationReport> {
+    let mut stream = io::BufReader::new(stream);
+    let compression = CompressionType::from_header(stream.fill_buf()?);
+    let stream = io::BufReader::new(decompressing_reader(stream, compression)?);
+    Ok(MzMLValidator::new().validate(stream))
+}
+
+/// Validate the mzML file at `path`
+pub fn validate_path<P: AsRef<path::Path>>(path: P) -> io::Result<ValidationReport> {
+    validate_reader(fs::File::open(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn load() -> io::Result<String> {
+        fs::read_to_string("./test/data/three_test_scans.mzML")
+    }
+
+    #[test]
+    fn test_valid_document() -> io::Result<()> {
+        let report = validate_path("./test/data/three_test_scans.mzML")?;
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert!(report.is_empty(), "{:?}", report.issues);
+        assert_eq!(report.spectrum_count, 3);
+        assert_eq!(report.chromatogram_count, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_document() -> io::Result<()> {
+        let content = load()?
+            .replacen("defaultArrayLength=\"27826\"", "defaultArrayLength=\"27825\"", 1)
+            .replacen(
+                "<scan instrumentConfigurationRef=\"IC1\">",
+                "<scan instrumentConfigurationRef=\"IC2\">",
+                1,
+            )
+            .replacen(
+                "<cvParam cvRef=\"PSI-MS\" accession=\"MS:1000511\" name=\"ms level\" value=\"1\"/>",
+                "",
+                1,
+            );
+        let report = validate_reader(content.as_bytes())?;
+        assert!(!report.is_valid());
+
+        let kinds: Vec<_> = report.iter().map(|issue| &issue.kind).collect();
+        assert!(kinds.contains(&&ValidationIssueKind::MissingRequiredTerm {
+            element: "spectrum".into(),
+            requirement: "ms level"
+        }));
+        assert!(kinds.contains(&&ValidationIssueKind::ArrayLengthMismatch {
+            expected: 27825,
+            found: 27826
+        }));
+        assert!(kinds.contains(&&ValidationIssueKind::DanglingReference {
+            attribute: "instrumentConfigurationRef",
+            kind: ReferenceKind::InstrumentConfiguration,
+            reference: "IC2".into()
+        }));
+        // Removing the cvParam shifts everything after it, but not the first spectrum
+        let shifted: Vec<_> = report
+            .iter()
+            .filter_map(|issue| match &issue.kind {
+                ValidationIssueKind::IndexOffsetMismatch { id, .. } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shifted.len(), 4);
+        assert!(!shifted.contains(&"controllerType=0 controllerNumber=1 scan=10014"));
+        assert!(kinds
+            .iter()
+            .any(|k| matches!(k, ValidationIssueKind::IndexListOffsetMismatch { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_time_order() -> io::Result<()> {
+        let content = load()?.replacen("value=\"22.134031\"", "value=\"22.000000\"", 1);
+        let report = validate_reader(content.as_bytes())?;
+        assert!(report.is_valid());
+        assert_eq!(report.warnings().count(), 1);
+        assert!(matches!(
+            report.issues[0].kind,
+            ValidationIssueKind::NonMonotonicScanTime { .. }
+        ));
+        Ok(())
+    }
+}
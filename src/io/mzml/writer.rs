@@ -11,7 +11,7 @@ use mzpeaks::{CentroidLike, DeconvolutedCentroidLike, PeakCollection};
 use quick_xml::events::BytesDecl;
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::Error as XMLError;
-use quick_xml::Writer;
+use quick_xml::{Reader, Writer};
 
 use super::super::offset_index::OffsetIndex;
 use super::super::traits::ScanWriter;
@@ -20,10 +20,12 @@ use super::super::utils::MD5HashingStream;
 use mzpeaks::{CentroidPeak, DeconvolutedPeak};
 
 use crate::meta::{
-    ComponentType, DataProcessing, FileDescription, InstrumentConfiguration, MSDataFileMetadata,
-    Software, MassSpectrometryRun,
+    CVEntry, ComponentType, DataProcessing, FileDescription, InstrumentConfiguration,
+    MSDataFileMetadata, MassSpectrometryRun, Sample, ScanSettings, Software,
+};
+use crate::params::{
+    ControlledVocabulary, Param, ParamCow, ParamDescribed, ParamGroup, ParamLike, Unit,
 };
-use crate::params::{ControlledVocabulary, Param, ParamCow, ParamDescribed, ParamLike, Unit};
 use crate::spectrum::bindata::{
     to_bytes, ArrayRetrievalError, ArrayType, BinaryArrayMap, BinaryCompressionType,
     BinaryDataArrayType, BuildArrayMapFrom, ByteArrayView, DataArray,
@@ -196,6 +198,9 @@ impl<W: Write> Write for ByteCountingStream<W> {
 
 struct InnerXMLWriter<W: io::Write> {
     pub handle: Writer<ByteCountingStream<W>>,
+    /// When present, parameters are collected here instead of being written
+    /// so that they may be replaced by `referenceableParamGroupRef`s
+    param_block: Option<Vec<Param>>,
 }
 
 impl<W: Write> Debug for InnerXMLWriter<W> {
@@ -216,6 +221,7 @@ impl<W: io::Write> InnerXMLWriter<W> {
         ));
         Self {
             handle: Writer::new_with_indent(handle, b' ', 2),
+            param_block: None,
         }
     }
 
@@ -228,7 +234,36 @@ impl<W: io::Write> InnerXMLWriter<W> {
         self.handle.get_mut().flush()
     }
 
+    /// Start collecting parameters instead of writing them. A controlled parameter
+    /// whose accession was already collected will be skipped.
+    pub fn start_param_block(&mut self) {
+        self.param_block = Some(Vec::new());
+    }
+
+    /// Stop collecting parameters, returning those collected since the last call to
+    /// [`InnerXMLWriter::start_param_block`]
+    pub fn take_param_block(&mut self) -> Vec<Param> {
+        self.param_block.take().unwrap_or_default()
+    }
+
     pub fn write_param<P: ParamLike>(&mut self, param: &P) -> WriterResult {
+        if let Some(block) = self.param_block.as_mut() {
+            let is_duplicate = param.is_controlled()
+                && block.iter().any(|p| {
+                    p.accession == param.accession()
+                        && p.controlled_vocabulary == param.controlled_vocabulary()
+                });
+            if !is_duplicate {
+                block.push(Param {
+                    name: param.name().to_string(),
+                    value: param.value().to_string(),
+                    accession: param.accession(),
+                    controlled_vocabulary: param.controlled_vocabulary(),
+                    unit: param.unit(),
+                });
+            }
+            return Ok(());
+        }
         let mut elt = if !param.is_controlled() {
             bstart!("userParam")
         } else {
@@ -422,6 +457,25 @@ pub struct MzMLWriterType<
     /// The different instrument configurations that were in use during the
     /// data acquisition.
     pub instrument_configurations: HashMap<u32, InstrumentConfiguration>,
+    /// The referenceable param groups to write in the `<referenceableParamGroupList>`.
+    /// Spectra and scans which name one of these groups in their `param_groups` will
+    /// reference it instead of repeating its parameters.
+    pub param_groups: Vec<ParamGroup>,
+    /// Whether to reference any of [`MzMLWriterType::param_groups`] whose parameters
+    /// all appear in a spectrum or scan, not just the groups it names
+    pub deduplicate_param_groups: bool,
+    /// Whether to also write the parameters in each spectrum's [`SpectrumDescription::params`],
+    /// as needed to reproduce spectra read with
+    /// [`MzMLReaderType::preserve_structure`](crate::io::mzml::MzMLReaderType::preserve_structure)
+    pub preserve_structure: bool,
+    /// The samples analyzed to produce the data
+    pub samples: Vec<Sample>,
+    /// The acquisition settings of the instrument, including any target lists
+    pub scan_settings: Vec<ScanSettings>,
+    /// Controlled vocabularies declared by the source of the data. The versions given for
+    /// the PSI-MS and UO vocabularies replace the writer's defaults, and any others are
+    /// written as-is.
+    pub cv_list: Vec<CVEntry>,
 
     pub state: MzMLWriterState,
     pub write_index: bool,
@@ -475,10 +529,54 @@ impl<W: Write, C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
         *self.instrument_configurations_mut() = source.instrument_configurations().clone();
         *self.file_description_mut() = source.file_description().clone();
         *self.softwares_mut() = source.softwares().clone();
+        if let Some(param_groups) = source.reference_param_groups() {
+            self.param_groups = param_groups.clone();
+        }
+        if let Some(samples) = source.samples() {
+            self.samples = samples.clone();
+        }
+        if let Some(scan_settings) = source.scan_settings() {
+            self.scan_settings = scan_settings.clone();
+        }
+        if let Some(cv_list) = source.cv_list() {
+            self.cv_list = cv_list.clone();
+        }
         if let Some(value) = source.spectrum_count_hint() {
             self.spectrum_count = value;
         }
     }
+
+    fn reference_param_groups(&self) -> Option<&Vec<ParamGroup>> {
+        Some(&self.param_groups)
+    }
+
+    fn reference_param_groups_mut(&mut self) -> Option<&mut Vec<ParamGroup>> {
+        Some(&mut self.param_groups)
+    }
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        Some(&self.samples)
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        Some(&mut self.samples)
+    }
+
+    fn scan_settings(&self) -> Option<&Vec<ScanSettings>> {
+        Some(&self.scan_settings)
+    }
+
+    fn scan_settings_mut(&mut self) -> Option<&mut Vec<ScanSettings>> {
+        Some(&mut self.scan_settings)
+    }
+
+    fn cv_list(&self) -> Option<&Vec<CVEntry>> {
+        Some(&self.cv_list)
+    }
+
+    fn cv_list_mut(&mut self) -> Option<&mut Vec<CVEntry>> {
+        Some(&mut self.cv_list)
+    }
 }
 
 impl<W: Write, C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
@@ -513,6 +611,12 @@ where
             instrument_configurations: HashMap::new(),
             softwares: Vec::new(),
             data_processings: Vec::new(),
+            param_groups: Vec::new(),
+            deduplicate_param_groups: false,
+            preserve_structure: false,
+            samples: Vec::new(),
+            scan_settings: Vec::new(),
+            cv_list: Vec::new(),
             offset: 0,
             spectrum_offset_index: OffsetIndex::new("spectrum".into()),
             chromatogram_offset_index: OffsetIndex::new("chromatogram".into()),
@@ -554,34 +658,71 @@ where
         Ok(self.handle.handle.get_mut().bytes_written())
     }
 
-    fn make_psi_ms_cv(&self) -> BytesStart<'static> {
-        let mut cv = BytesStart::from_content("cv", 2);
-        cv.push_attribute(("id", "MS"));
-        cv.push_attribute(("fullName", "PSI-MS"));
-        cv.push_attribute(("URI", "http://purl.obolibrary.org/obo/ms.obo"));
-        cv.push_attribute(("version", Self::PSIMS_VERSION));
-        cv
-    }
-
-    fn make_unit_cv(&self) -> BytesStart<'static> {
-        let mut cv = BytesStart::from_content("cv", 2);
-        cv.push_attribute(("id", "UO"));
-        cv.push_attribute(("fullName", "UNIT-ONTOLOGY"));
-        cv.push_attribute(("URI", "http://ontologies.berkeleybop.org/uo.obo"));
-        cv.push_attribute(("version", Self::UNIT_VERSION));
-        cv
+    fn make_psi_ms_cv(&self) -> CVEntry {
+        CVEntry::new(
+            "MS".into(),
+            "PSI-MS".into(),
+            "http://purl.obolibrary.org/obo/ms.obo".into(),
+            Some(Self::PSIMS_VERSION.into()),
+        )
+    }
+
+    fn make_unit_cv(&self) -> CVEntry {
+        CVEntry::new(
+            "UO".into(),
+            "UNIT-ONTOLOGY".into(),
+            "http://ontologies.berkeleybop.org/uo.obo".into(),
+            Some(Self::UNIT_VERSION.into()),
+        )
+    }
+
+    /// Merge [`MzMLWriterType::cv_list`] into the vocabularies the writer always declares.
+    /// Parameters are always written with the `MS` and `UO` CV IDs, so those entries keep
+    /// their IDs.
+    fn resolve_cv_list(&self) -> Vec<CVEntry> {
+        let mut cvs = vec![self.make_psi_ms_cv(), self.make_unit_cv()];
+        for declared in self.cv_list.iter() {
+            let known = match declared.id.parse::<ControlledVocabulary>() {
+                Ok(ControlledVocabulary::MS) => Some(0),
+                Ok(ControlledVocabulary::UO) => Some(1),
+                _ => None,
+            };
+            match known {
+                Some(i) => {
+                    if declared.version.is_some() {
+                        cvs[i].version = declared.version.clone();
+                    }
+                    if !declared.uri.is_empty() {
+                        cvs[i].uri = declared.uri.clone();
+                    }
+                }
+                None => {
+                    if !cvs.iter().any(|cv| cv.id == declared.id) {
+                        cvs.push(declared.clone())
+                    }
+                }
+            }
+        }
+        cvs
     }
 
     fn write_cv_list(&mut self) -> WriterResult {
+        let cvs = self.resolve_cv_list();
         let mut cv_list = BytesStart::from_content("cvList", 6);
-        cv_list.push_attribute(("count", "2"));
+        let count = cvs.len().to_string();
+        attrib!("count", count, cv_list);
         self.handle.write_event(Event::Start(cv_list))?;
 
-        let cv = self.make_psi_ms_cv();
-        self.handle.write_event(Event::Empty(cv))?;
-
-        let cv = self.make_unit_cv();
-        self.handle.write_event(Event::Empty(cv))?;
+        for entry in cvs.iter() {
+            let mut cv = bstart!("cv");
+            attrib!("id", entry.id, cv);
+            attrib!("fullName", entry.full_name, cv);
+            attrib!("URI", entry.uri, cv);
+            if let Some(version) = entry.version.as_ref() {
+                attrib!("version", version, cv);
+            }
+            self.handle.write_event(Event::Empty(cv))?;
+        }
 
         self.handle
             .write_event(Event::End(BytesEnd::new("cvList")))?;
@@ -625,7 +766,10 @@ where
         }
         self.write_cv_list()?;
         self.write_file_description()?;
+        self.write_referenceable_param_groups()?;
+        self.write_sample_list()?;
         self.write_software_list()?;
+        self.write_scan_settings_list()?;
         self.write_instrument_configuration()?;
         self.write_data_processing()?;
 
@@ -665,6 +809,27 @@ where
         Ok(())
     }
 
+    fn write_referenceable_param_groups(&mut self) -> WriterResult {
+        if self.param_groups.is_empty() {
+            return Ok(());
+        }
+        let mut outer = bstart!("referenceableParamGroupList");
+        let count = self.param_groups.len().to_string();
+        attrib!("count", count, outer);
+        self.handle.write_event(Event::Start(outer.borrow()))?;
+        for group in self.param_groups.iter() {
+            let mut tag = bstart!("referenceableParamGroup");
+            attrib!("id", group.id, tag);
+            self.handle.write_event(Event::Start(tag.borrow()))?;
+            for param in group.params() {
+                self.handle.write_param(param)?
+            }
+            self.handle.write_event(Event::End(tag.to_end()))?;
+        }
+        self.handle.write_event(Event::End(outer.to_end()))?;
+        Ok(())
+    }
+
     fn write_software_list(&mut self) -> WriterResult {
         let mut outer = bstart!("softwareList");
         let count = self.softwares.len().to_string();
@@ -684,6 +849,79 @@ where
         Ok(())
     }
 
+    fn write_sample_list(&mut self) -> WriterResult {
+        if self.samples.is_empty() {
+            return Ok(());
+        }
+        let mut outer = bstart!("sampleList");
+        let count = self.samples.len().to_string();
+        attrib!("count", count, outer);
+        self.handle.write_event(Event::Start(outer.borrow()))?;
+        for sample in self.samples.iter() {
+            let mut tag = bstart!("sample");
+            attrib!("id", sample.id, tag);
+            if let Some(name) = sample.name.as_ref() {
+                attrib!("name", name, tag);
+            }
+            self.handle.write_event(Event::Start(tag.borrow()))?;
+            for param in sample.params() {
+                self.handle.write_param(param)?
+            }
+            self.handle.write_event(Event::End(tag.to_end()))?;
+        }
+        self.handle.write_event(Event::End(outer.to_end()))?;
+        Ok(())
+    }
+
+    fn write_scan_settings_list(&mut self) -> WriterResult {
+        if self.scan_settings.is_empty() {
+            return Ok(());
+        }
+        let mut outer = bstart!("scanSettingsList");
+        let count = self.scan_settings.len().to_string();
+        attrib!("count", count, outer);
+        self.handle.write_event(Event::Start(outer.borrow()))?;
+        for settings in self.scan_settings.iter() {
+            let mut tag = bstart!("scanSettings");
+            attrib!("id", settings.id, tag);
+            self.handle.write_event(Event::Start(tag.borrow()))?;
+            for param in settings.params() {
+                self.handle.write_param(param)?
+            }
+            if !settings.source_file_refs.is_empty() {
+                let mut ref_list = bstart!("sourceFileRefList");
+                let count = settings.source_file_refs.len().to_string();
+                attrib!("count", count, ref_list);
+                self.handle.write_event(Event::Start(ref_list.borrow()))?;
+                for source_file_ref in settings.source_file_refs.iter() {
+                    let mut ref_tag = bstart!("sourceFileRef");
+                    attrib!("ref", source_file_ref, ref_tag);
+                    self.handle.write_event(Event::Empty(ref_tag))?;
+                }
+                self.handle.write_event(Event::End(ref_list.to_end()))?;
+            }
+            if !settings.targets.is_empty() {
+                let mut target_list = bstart!("targetList");
+                let count = settings.targets.len().to_string();
+                attrib!("count", count, target_list);
+                self.handle
+                    .write_event(Event::Start(target_list.borrow()))?;
+                for target in settings.targets.iter() {
+                    let target_tag = bstart!("target");
+                    self.handle.write_event(Event::Start(target_tag.borrow()))?;
+                    for param in target.params() {
+                        self.handle.write_param(param)?
+                    }
+                    self.handle.write_event(Event::End(target_tag.to_end()))?;
+                }
+                self.handle.write_event(Event::End(target_list.to_end()))?;
+            }
+            self.handle.write_event(Event::End(tag.to_end()))?;
+        }
+        self.handle.write_event(Event::End(outer.to_end()))?;
+        Ok(())
+    }
+
     fn write_instrument_configuration(&mut self) -> WriterResult {
         let mut outer = bstart!("instrumentConfigurationList");
         let count = self.instrument_configurations.len().to_string();
@@ -930,8 +1168,12 @@ where
             let mut scan_tag = bstart!("scan");
             let id = instrument_id(&scan.instrument_configuration_id);
             attrib!("instrumentConfigurationRef", id, scan_tag);
+            if let Some(source_file_id) = scan.source_file_id.as_ref() {
+                attrib!("sourceFileRef", source_file_id, scan_tag);
+            }
             self.handle.write_event(Event::Start(scan_tag.borrow()))?;
 
+            self.handle.start_param_block();
             self.handle.write_param(
                 &self
                     .ms_cv
@@ -946,9 +1188,19 @@ where
                     .with_unit("UO:0000028", "millisecond"),
             )?;
 
+            if let Some(cv) = scan.compensation_voltage {
+                self.handle.write_param(
+                    &self
+                        .ms_cv
+                        .param_val("MS:1001581", "FAIMS compensation voltage", cv)
+                        .with_unit("UO:0000218", "volt"),
+                )?;
+            }
+
             for param in scan.params() {
                 self.handle.write_param(param)?
             }
+            self.finish_param_block(&scan.param_groups)?;
 
             let mut scan_window_list_tag = bstart!("scanWindowList");
             let scan_window_list_count = scan.scan_windows.len().to_string();
@@ -983,6 +1235,7 @@ where
             }
             self.handle
                 .write_event(Event::End(scan_window_list_tag.to_end()))?;
+            self.write_unknown_elements(&scan.unknown_elements)?;
             self.handle.write_event(Event::End(scan_tag.to_end()))?;
         }
         end_event!(self, scan_list_tag);
@@ -1026,13 +1279,25 @@ where
     }
 
     pub fn write_selected_ions(&mut self, precursor: &Precursor) -> WriterResult {
+        // The selected ion list is optional, and may not be empty if present
+        let ions = precursor.ions();
+        if ions.is_empty() {
+            return Ok(());
+        }
         let mut outer = bstart!("selectedIonList");
-        attrib!("count", "1", outer);
+        let count = ions.len().to_string();
+        attrib!("count", count, outer);
         start_event!(self, outer);
+        for ion in ions {
+            self.write_selected_ion(ion)?;
+        }
+        end_event!(self, outer);
+        Ok(())
+    }
+
+    fn write_selected_ion(&mut self, ion: &SelectedIon) -> WriterResult {
         let tag = bstart!("selectedIon");
         start_event!(self, tag);
-
-        let ion = precursor.ion();
         self.handle.write_param(
             &self
                 .ms_cv
@@ -1051,7 +1316,6 @@ where
         }
         self.handle.write_param_list(ion.params().iter())?;
         end_event!(self, tag);
-        end_event!(self, outer);
         Ok(())
     }
 
@@ -1059,30 +1323,52 @@ where
         let act = precursor.activation();
         let tag = bstart!("activation");
         start_event!(self, tag);
-        if let Some(meth) = act.method() {
+        for meth in act.methods() {
             let meth_param: Param = meth.clone().into();
             self.handle.write_param(&meth_param)?;
         }
         self.handle.write_param_list(act.params().iter())?;
-        self.handle.write_param(
-            &self
-                .ms_cv
-                .param_val("MS:1000045", "collision energy", act.energy)
-                .with_unit("UO:0000266", "electronvolt"),
-        )?;
+        for energy in act.energies.iter() {
+            let param = if energy.supplemental {
+                self.ms_cv
+                    .param_val("MS:1002680", "supplemental collision energy", energy.value)
+            } else if energy.is_normalized() {
+                self.ms_cv
+                    .param_val("MS:1000138", "normalized collision energy", energy.value)
+            } else {
+                self.ms_cv
+                    .param_val("MS:1000045", "collision energy", energy.value)
+            };
+            self.handle.write_param(&param.with_unit_t(&energy.unit))?;
+        }
         end_event!(self, tag);
         Ok(())
     }
 
     pub fn write_precursor(&mut self, precursor: &Precursor) -> WriterResult {
+        self.write_precursor_list(std::slice::from_ref(precursor))
+    }
+
+    pub fn write_precursor_list(&mut self, precursors: &[Precursor]) -> WriterResult {
         let mut precursor_list_tag = bstart!("precursorList");
-        attrib!("count", "1", precursor_list_tag);
+        let count = precursors.len().to_string();
+        attrib!("count", count, precursor_list_tag);
         start_event!(self, precursor_list_tag);
+        for precursor in precursors {
+            self.write_precursor_element(precursor)?;
+        }
+        end_event!(self, precursor_list_tag);
+        Ok(())
+    }
 
+    fn write_precursor_element(&mut self, precursor: &Precursor) -> WriterResult {
         let mut precursor_tag = bstart!("precursor");
         if let Some(prec_id) = precursor.precursor_id() {
             attrib!("spectrumRef", prec_id, precursor_tag);
         }
+        if let Some(source_file_id) = precursor.source_file_id.as_ref() {
+            attrib!("sourceFileRef", source_file_id, precursor_tag);
+        }
         self.handle
             .write_event(Event::Start(precursor_tag.borrow()))?;
 
@@ -1091,7 +1377,6 @@ where
         self.write_selected_ions(precursor)?;
         self.write_activation(precursor)?;
         end_event!(self, precursor_tag);
-        end_event!(self, precursor_list_tag);
         Ok(())
     }
 
@@ -1110,6 +1395,9 @@ where
             let array_len = array_len.to_string();
             attrib!("arrayLength", array_len, outer);
         }
+        if let Some(data_processing_id) = array.data_processing_id.as_ref() {
+            attrib!("dataProcessingRef", data_processing_id, outer);
+        }
 
         start_event!(self, outer);
         match &array.dtype {
@@ -1213,6 +1501,9 @@ where
         let default_array_len = default_array_len_u.to_string();
 
         attrib!("defaultArrayLength", default_array_len, outer);
+        if let Some(data_processing_id) = spectrum.description().data_processing_id.as_ref() {
+            attrib!("dataProcessingRef", data_processing_id, outer);
+        }
 
         self.handle.write_event(Event::Start(outer.borrow()))?;
         self.spectrum_counter += 1;
@@ -1290,18 +1581,190 @@ where
         &mut self,
         spectrum: &S,
     ) -> WriterResult {
+        self.handle.start_param_block();
         self.write_ms_level(spectrum)?;
         self.write_polarity(spectrum)?;
         self.write_continuity(spectrum)?;
         self.write_signal_properties(spectrum)?;
+        if self.preserve_structure {
+            for param in spectrum.params() {
+                self.handle.write_param(param)?
+            }
+        }
+        self.finish_param_block(&spectrum.description().param_groups)?;
 
         self.write_scan_list(spectrum.acquisition())?;
-        if let Some(precursor) = spectrum.precursor() {
-            self.write_precursor(precursor)?;
+        let precursors = &spectrum.description().precursors;
+        if !precursors.is_empty() {
+            self.write_precursor_list(precursors)?;
         };
+        self.write_unknown_elements(&spectrum.description().unknown_elements)?;
+        Ok(())
+    }
+
+    /// Write the parameters collected since [`InnerXMLWriter::start_param_block`], replacing
+    /// those covered by a referenceable param group with a reference to it.
+    ///
+    /// `param_groups` are the groups the element referenced when it was read. If
+    /// [`MzMLWriterType::deduplicate_param_groups`] is set, every group in
+    /// [`MzMLWriterType::param_groups`] is considered as well.
+    fn finish_param_block(&mut self, param_groups: &[ParamGroup]) -> WriterResult {
+        let mut params = self.handle.take_param_block();
+        let defined: &[ParamGroup] = &self.param_groups;
+        let deduplicate = self.deduplicate_param_groups;
+        // Only groups which will be written in the header can be referenced
+        let candidates = param_groups
+            .iter()
+            .filter_map(|group| defined.iter().find(|g| g.id == group.id))
+            .chain(defined.iter().filter(|_| deduplicate));
+
+        let mut refs: Vec<&str> = Vec::new();
+        for group in candidates {
+            if group.params.is_empty() || refs.contains(&group.id.as_str()) {
+                continue;
+            }
+            if group.params.iter().all(|p| params.contains(p)) {
+                params.retain(|p| !group.params.contains(p));
+                refs.push(&group.id);
+            }
+        }
+
+        for group_id in refs {
+            let mut tag = bstart!("referenceableParamGroupRef");
+            attrib!("ref", group_id, tag);
+            self.handle.write_event(Event::Empty(tag))?;
+        }
+        for param in params.iter() {
+            self.handle.write_param(param)?
+        }
+        Ok(())
+    }
+
+    /// Write out elements which were not recognised when read, as stored in
+    /// [`SpectrumDescription::unknown_elements`] or [`ScanEvent::unknown_elements`]
+    pub fn write_unknown_elements(&mut self, elements: &[UnknownElement]) -> WriterResult {
+        for element in elements {
+            let mut reader = Reader::from_str(&element.xml);
+            reader.trim_text(true);
+            loop {
+                match reader.read_event()? {
+                    Event::Eof => break,
+                    event => self.handle.write_event(event)?,
+                }
+            }
+        }
         Ok(())
     }
 
+    fn spectrum_block_params(&self, description: &SpectrumDescription) -> Vec<Param> {
+        let mut block: Vec<Param> = Vec::new();
+        if description.ms_level == 1 {
+            block.push(MS1_SPECTRUM.into());
+        } else {
+            block.push(MSN_SPECTRUM.into());
+        }
+        block.push(self.ms_cv.param_val(
+            "MS:1000511",
+            "ms level",
+            description.ms_level.to_string(),
+        ));
+        match description.polarity {
+            ScanPolarity::Negative => block.push(NEGATIVE_SCAN.into()),
+            _ => block.push(POSITIVE_SCAN.into()),
+        }
+        match description.signal_continuity {
+            SignalContinuity::Profile => block.push(PROFILE_SPECTRUM.into()),
+            _ => block.push(CENTROID_SPECTRUM.into()),
+        }
+        if !self.preserve_structure {
+            return block;
+        }
+        for param in description.params() {
+            // These are recomputed from the peak data for each spectrum
+            let is_signal_property = param.is_ms()
+                && matches!(
+                    param.accession,
+                    Some(1000285 | 1000505 | 1000504 | 1000528 | 1000527)
+                );
+            let is_duplicate = param.is_controlled()
+                && block.iter().any(|p| {
+                    p.accession == param.accession
+                        && p.controlled_vocabulary == param.controlled_vocabulary
+                });
+            if !is_signal_property && !is_duplicate {
+                block.push(param.clone());
+            }
+        }
+        block
+    }
+
+    /**
+    Find blocks of parameters which are repeated on at least `min_count` of `descriptions`
+    or their scans, and add a new referenceable param group for each to
+    [`MzMLWriterType::param_groups`], enabling [`MzMLWriterType::deduplicate_param_groups`].
+
+    This must be called before the header is written. Returns the number of groups added.
+    */
+    pub fn add_repeated_param_groups<'b, I: IntoIterator<Item = &'b SpectrumDescription>>(
+        &mut self,
+        descriptions: I,
+        min_count: usize,
+    ) -> usize {
+        let mut blocks: Vec<(Vec<Param>, usize)> = Vec::new();
+        let mut count_block = |block: Vec<Param>| {
+            if block.is_empty() {
+                return;
+            }
+            match blocks.iter_mut().find(|(b, _)| *b == block) {
+                Some((_, count)) => *count += 1,
+                None => blocks.push((block, 1)),
+            }
+        };
+        // Parameters covered by a group the element already names are left to that group
+        let without_named_groups = |mut block: Vec<Param>, named: &[ParamGroup]| {
+            for group in named {
+                if let Some(group) = self.param_groups.iter().find(|g| g.id == group.id) {
+                    if group.params.iter().all(|p| block.contains(p)) {
+                        block.retain(|p| !group.params.contains(p));
+                    }
+                }
+            }
+            block
+        };
+        for description in descriptions {
+            count_block(without_named_groups(
+                self.spectrum_block_params(description),
+                &description.param_groups,
+            ));
+            for scan in description.acquisition.scans.iter() {
+                count_block(without_named_groups(
+                    scan.params().to_vec(),
+                    &scan.param_groups,
+                ));
+            }
+        }
+
+        let mut added = 0;
+        let mut next_id = self.param_groups.len() + 1;
+        for (block, count) in blocks {
+            if count < min_count || self.param_groups.iter().any(|g| g.params == block) {
+                continue;
+            }
+            let mut id = format!("ParamGroup{}", next_id);
+            while self.param_groups.iter().any(|g| g.id == id) {
+                next_id += 1;
+                id = format!("ParamGroup{}", next_id);
+            }
+            next_id += 1;
+            self.param_groups.push(ParamGroup::new(id, block));
+            added += 1;
+        }
+        if added > 0 {
+            self.deduplicate_param_groups = true;
+        }
+        added
+    }
+
     /**
     Write a spectrum  out to the mzML file, encoding the highest procressing degree peak data present.
 
@@ -1580,4 +2043,373 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn preserve_structure_test() -> WriterResult {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let group_list = concat!(
+            "    </fileDescription>\n",
+            "    <referenceableParamGroupList count=\"1\">\n",
+            "      <referenceableParamGroup id=\"ScanTypes\">\n",
+            "        <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000130\" name=\"positive scan\" value=\"\"/>\n",
+            "        <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000128\" name=\"profile spectrum\" value=\"\"/>\n",
+            "      </referenceableParamGroup>\n",
+            "    </referenceableParamGroupList>\n",
+        );
+        let content = content
+            .replacen("    </fileDescription>\n", group_list, 1)
+            .replace(
+                concat!(
+                    "          <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000130\" name=\"positive scan\" value=\"\"/>\n",
+                    "          <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000128\" name=\"profile spectrum\" value=\"\"/>\n",
+                ),
+                concat!(
+                    "          <referenceableParamGroupRef ref=\"ScanTypes\"/>\n",
+                    "          <vendorExtension kind=\"test\"><note>kept</note></vendorExtension>\n",
+                ),
+            );
+
+        let mut reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        reader.preserve_structure = true;
+        assert_eq!(reader.reference_param_groups().unwrap().len(), 1);
+        let spectra: Vec<_> = reader.by_ref().collect();
+        assert_eq!(spectra.len(), 3);
+        for spectrum in spectra.iter() {
+            let description = spectrum.description();
+            assert_eq!(description.polarity, ScanPolarity::Positive);
+            assert_eq!(description.signal_continuity, SignalContinuity::Profile);
+            assert_eq!(description.param_groups.len(), 1);
+            assert_eq!(description.param_groups[0].id, "ScanTypes");
+            assert_eq!(description.unknown_elements.len(), 1);
+            assert_eq!(description.unknown_elements[0].name, "vendorExtension");
+        }
+
+        let mut writer = MzMLWriterType::new(io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&reader);
+        writer.preserve_structure = true;
+        let added = writer.add_repeated_param_groups(spectra.iter().map(|s| s.description()), 2);
+        assert_eq!(added, 1);
+        *writer.spectrum_count_mut() = spectra.len() as u64;
+        for spectrum in spectra.iter() {
+            writer.write_spectrum(spectrum)?;
+        }
+        writer.close()?;
+        let buffer = writer.into_inner()?.into_inner();
+
+        let text = String::from_utf8_lossy(&buffer);
+        let count = |pattern: &str| text.matches(pattern).count();
+        assert_eq!(count("<referenceableParamGroupList count=\"2\">"), 1);
+        assert_eq!(count("<referenceableParamGroupRef ref=\"ScanTypes\"/>"), 3);
+        assert_eq!(
+            count("<referenceableParamGroupRef ref=\"ParamGroup2\"/>"),
+            2
+        );
+        assert_eq!(count("name=\"positive scan\""), 1);
+        assert_eq!(count("<note>kept</note>"), 3);
+
+        let mut reader2 = MzMLReader::new(io::Cursor::new(buffer));
+        reader2.preserve_structure = true;
+        assert_eq!(reader2.reference_param_groups().unwrap().len(), 2);
+        for (a, b) in spectra.iter().zip(reader2.by_ref()) {
+            assert_eq!(a.id(), b.id());
+            assert_eq!(a.ms_level(), b.ms_level());
+            assert_eq!(a.polarity(), b.polarity());
+            assert_eq!(
+                a.description().unknown_elements,
+                b.description().unknown_elements
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spectrum_params_test() -> WriterResult {
+        let mut reader = MzMLReader::open_path("./test/data/three_test_scans.mzML")?;
+        let mut spectrum = reader.next().unwrap();
+        spectrum
+            .description_mut()
+            .add_param(Param::new_key_value("test note".to_string(), "kept".to_string()));
+
+        let write = |preserve_structure: bool| -> Result<String, MzMLWriterError> {
+            let mut writer = MzMLWriterType::new(io::Cursor::new(Vec::new()));
+            writer.copy_metadata_from(&reader);
+            writer.preserve_structure = preserve_structure;
+            *writer.spectrum_count_mut() = 1;
+            writer.write_spectrum(&spectrum)?;
+            writer.close()?;
+            let buffer = writer.into_inner()?.into_inner();
+            Ok(String::from_utf8_lossy(&buffer).to_string())
+        };
+
+        // Spectrum level params are only written when preserving structure
+        assert!(!write(false)?.contains("name=\"test note\""));
+        assert!(write(true)?.contains("name=\"test note\" value=\"kept\""));
+        Ok(())
+    }
+
+    #[test]
+    fn sample_and_scan_settings_test() -> WriterResult {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let scan_settings = concat!(
+            "    </softwareList>\n",
+            "    <scanSettingsList count=\"1\">\n",
+            "      <scanSettings id=\"prm\">\n",
+            "        <sourceFileRefList count=\"1\">\n",
+            "          <sourceFileRef ref=\"RAW1\"/>\n",
+            "        </sourceFileRefList>\n",
+            "        <targetList count=\"2\">\n",
+            "          <target>\n",
+            "            <cvParam cvRef=\"MS\" accession=\"MS:1000744\" name=\"selected ion m/z\" value=\"562.74\"/>\n",
+            "          </target>\n",
+            "          <target>\n",
+            "            <cvParam cvRef=\"MS\" accession=\"MS:1000744\" name=\"selected ion m/z\" value=\"617.26\"/>\n",
+            "          </target>\n",
+            "        </targetList>\n",
+            "      </scanSettings>\n",
+            "    </scanSettingsList>\n",
+        );
+        let content = content.replacen("    </softwareList>\n", scan_settings, 1);
+
+        let mut reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        let samples = reader.samples().unwrap().clone();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].id, "sample_1");
+        assert_eq!(samples[0].name.as_deref(), Some("sample_1"));
+        assert_eq!(samples[0].params.len(), 3);
+
+        let settings = reader.scan_settings().unwrap().clone();
+        assert_eq!(settings.len(), 1);
+        assert_eq!(settings[0].source_file_refs, vec!["RAW1".to_string()]);
+        assert_eq!(settings[0].targets.len(), 2);
+        assert_eq!(settings[0].targets[1].params[0].value, "617.26");
+
+        let cv_list = reader.cv_list().unwrap();
+        assert_eq!(cv_list.len(), 2);
+        assert_eq!(cv_list[0].version.as_deref(), Some("4.1.131"));
+
+        let mut writer = MzMLWriterType::new(io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&reader);
+        let spectra: Vec<_> = reader.by_ref().collect();
+        *writer.spectrum_count_mut() = spectra.len() as u64;
+        for spectrum in spectra.iter() {
+            writer.write_spectrum(spectrum)?;
+        }
+        writer.close()?;
+        let buffer = writer.into_inner()?.into_inner();
+
+        let text = String::from_utf8_lossy(&buffer);
+        assert!(text.contains("id=\"MS\" fullName=\"PSI-MS\""));
+        assert!(text.contains("version=\"4.1.131\""));
+
+        let reader2 = MzMLReader::new(io::Cursor::new(buffer));
+        assert_eq!(reader2.samples().unwrap(), &samples);
+        assert_eq!(reader2.scan_settings().unwrap(), &settings);
+        assert_eq!(reader2.cv_list().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn data_processing_and_source_file_refs_test() -> WriterResult {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let content = content
+            .replacen(
+                "<spectrum index=\"1\" ",
+                "<spectrum index=\"1\" dataProcessingRef=\"data_processing_1\" ",
+                1,
+            )
+            .replace(
+                "<scan instrumentConfigurationRef=\"IC1\">",
+                "<scan instrumentConfigurationRef=\"IC1\" sourceFileRef=\"RAW1\">",
+            )
+            .replacen(
+                "<precursor spectrumRef=",
+                "<precursor sourceFileRef=\"RAW1\" spectrumRef=",
+                1,
+            )
+            .replacen(
+                "<binaryDataArray encodedLength=\"86740\">",
+                "<binaryDataArray encodedLength=\"86740\" dataProcessingRef=\"pwiz_Reader_Thermo_conversion\">",
+                1,
+            );
+
+        let mut reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        let spectra: Vec<_> = reader.by_ref().collect();
+        assert_eq!(spectra.len(), 3);
+        assert_eq!(spectra[0].description().data_processing_id, None);
+        let dp_id = spectra[1].description().data_processing_id.as_deref();
+        assert_eq!(dp_id, Some("data_processing_1"));
+        assert!(reader.data_processing_by_id(dp_id.unwrap()).is_some());
+
+        for spectrum in spectra.iter() {
+            let scan = spectrum.acquisition().first_scan().unwrap();
+            assert_eq!(scan.source_file_id.as_deref(), Some("RAW1"));
+        }
+        let precursor = spectra[1].precursor().unwrap();
+        let sf_id = precursor.source_file_id.as_deref().unwrap();
+        assert_eq!(sf_id, "RAW1");
+        assert!(reader.source_file_by_id(sf_id).is_some());
+
+        let arrays = spectra[0].arrays.as_ref().unwrap();
+        let array = arrays.get(&ArrayType::MZArray).unwrap();
+        assert_eq!(
+            array.data_processing_id.as_deref(),
+            Some("pwiz_Reader_Thermo_conversion")
+        );
+
+        let mut writer = MzMLWriterType::new(io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&reader);
+        *writer.spectrum_count_mut() = spectra.len() as u64;
+        for spectrum in spectra.iter() {
+            writer.write_spectrum(spectrum)?;
+        }
+        writer.close()?;
+        let buffer = writer.into_inner()?.into_inner();
+
+        let mut reader2 = MzMLReader::new(io::Cursor::new(buffer));
+        for (a, b) in spectra.iter().zip(reader2.by_ref()) {
+            assert_eq!(a.id(), b.id());
+            assert_eq!(
+                a.description().data_processing_id,
+                b.description().data_processing_id
+            );
+            assert_eq!(
+                a.acquisition().first_scan().unwrap().source_file_id,
+                b.acquisition().first_scan().unwrap().source_file_id
+            );
+            assert_eq!(
+                a.precursor().and_then(|p| p.source_file_id.clone()),
+                b.precursor().and_then(|p| p.source_file_id.clone())
+            );
+            let a_mzs = a.arrays.as_ref().unwrap().get(&ArrayType::MZArray).unwrap();
+            let b_mzs = b.arrays.as_ref().unwrap().get(&ArrayType::MZArray).unwrap();
+            assert_eq!(a_mzs.data_processing_id, b_mzs.data_processing_id);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_precursors_test() -> WriterResult {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let second_ion = concat!(
+            "                </selectedIon>\n",
+            "                <selectedIon>\n",
+            "                  <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000744\" name=\"selected ion m/z\" value=\"563.24\" unitCvRef=\"PSI-MS\" unitAccession=\"MS:1000040\" unitName=\"m/z\"/>\n",
+            "                  <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000041\" name=\"charge state\" value=\"3\"/>\n",
+            "                </selectedIon>\n",
+        );
+        let second_precursor = concat!(
+            "            </precursor>\n",
+            "            <precursor>\n",
+            "              <isolationWindow>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000827\" name=\"isolation window target m/z\" value=\"700.5\" unitCvRef=\"PSI-MS\" unitAccession=\"MS:1000040\" unitName=\"m/z\"/>\n",
+            "              </isolationWindow>\n",
+            "              <selectedIonList count=\"1\">\n",
+            "                <selectedIon>\n",
+            "                  <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000744\" name=\"selected ion m/z\" value=\"700.5\" unitCvRef=\"PSI-MS\" unitAccession=\"MS:1000040\" unitName=\"m/z\"/>\n",
+            "                </selectedIon>\n",
+            "              </selectedIonList>\n",
+            "              <activation>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000422\" name=\"beam-type collision-induced dissociation\" value=\"\"/>\n",
+            "              </activation>\n",
+            "            </precursor>\n",
+        );
+        let content = content
+            .replacen("                </selectedIon>\n", second_ion, 1)
+            .replacen("            </precursor>\n", second_precursor, 1)
+            .replacen(
+                "<precursorList count=\"1\">",
+                "<precursorList count=\"2\">",
+                1,
+            )
+            .replacen(
+                "<selectedIonList count=\"1\">",
+                "<selectedIonList count=\"2\">",
+                1,
+            );
+
+        let mut reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        let spectra: Vec<_> = reader.by_ref().collect();
+        let precursors = &spectra[1].description().precursors;
+        assert_eq!(precursors.len(), 2);
+        assert_eq!(precursors[0].ions.len(), 2);
+        assert_eq!(precursors[0].ions[1].mz, 563.24);
+        assert_eq!(precursors[0].ions[1].charge, Some(3));
+        assert_eq!(precursors[1].ions.len(), 1);
+        assert_eq!(precursors[1].ion().mz, 700.5);
+        assert_eq!(precursors[1].precursor_id, None);
+        assert_eq!(spectra[1].precursor_iter().count(), 2);
+        assert_eq!(spectra[1].precursor().unwrap().ion().charge, Some(2));
+
+        let mut writer = MzMLWriterType::new(io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&reader);
+        *writer.spectrum_count_mut() = spectra.len() as u64;
+        for spectrum in spectra.iter() {
+            writer.write_spectrum(spectrum)?;
+        }
+        writer.close()?;
+        let buffer = writer.into_inner()?.into_inner();
+
+        let mut reader2 = MzMLReader::new(io::Cursor::new(buffer));
+        for (a, b) in spectra.iter().zip(reader2.by_ref()) {
+            let a_precursors = &a.description().precursors;
+            let b_precursors = &b.description().precursors;
+            assert_eq!(a_precursors.len(), b_precursors.len());
+            for (pa, pb) in a_precursors.iter().zip(b_precursors.iter()) {
+                assert_eq!(pa.ions, pb.ions);
+                assert_eq!(pa.precursor_id, pb.precursor_id);
+                assert_eq!(pa.isolation_window.target, pb.isolation_window.target);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn combined_activation_test() -> WriterResult {
+        let content = fs::read_to_string("./test/data/three_test_scans.mzML")?;
+        let combined = concat!(
+            "              <activation>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000598\" name=\"electron transfer dissociation\" value=\"\"/>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1002679\" name=\"supplemental beam-type collision-induced dissociation\" value=\"\"/>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000138\" name=\"normalized collision energy\" value=\"25\" unitCvRef=\"UO\" unitAccession=\"UO:0000187\" unitName=\"percent\"/>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000138\" name=\"normalized collision energy\" value=\"35\" unitCvRef=\"UO\" unitAccession=\"UO:0000187\" unitName=\"percent\"/>\n",
+            "                <cvParam cvRef=\"PSI-MS\" accession=\"MS:1002680\" name=\"supplemental collision energy\" value=\"20\" unitCvRef=\"UO\" unitAccession=\"UO:0000266\" unitName=\"electronvolt\"/>\n",
+            "              </activation>\n",
+        );
+        let start = content.find("              <activation>").unwrap();
+        let end = content[start..].find("</activation>\n").unwrap() + start + 14;
+        let content = format!("{}{}{}", &content[..start], combined, &content[end..]);
+
+        let mut reader = MzMLReader::new(io::Cursor::new(content.into_bytes()));
+        let spectra: Vec<_> = reader.by_ref().collect();
+        let activation = &spectra[1].precursor().unwrap().activation;
+        assert!(activation.is_combined());
+        assert!(activation.has_supplemental_activation());
+        assert_eq!(activation.is_collisional(), Some(true));
+        assert_eq!(
+            activation.method(),
+            Some(&ActivationMethod::ElectronTransferDissociation)
+        );
+        assert_eq!(activation.energies.len(), 3);
+        assert!(activation.energies[0].is_normalized());
+        assert_eq!(activation.energy(), 25.0);
+        assert!(activation.energies[2].supplemental);
+        assert!(activation.params().is_empty());
+
+        let mut writer = MzMLWriterType::new(io::Cursor::new(Vec::new()));
+        writer.copy_metadata_from(&reader);
+        *writer.spectrum_count_mut() = spectra.len() as u64;
+        for spectrum in spectra.iter() {
+            writer.write_spectrum(spectrum)?;
+        }
+        writer.close()?;
+        let buffer = writer.into_inner()?.into_inner();
+
+        let mut reader2 = MzMLReader::new(io::Cursor::new(buffer));
+        for (a, b) in spectra.iter().zip(reader2.by_ref()) {
+            for (pa, pb) in a.precursor_iter().zip(b.precursor_iter()) {
+                assert_eq!(pa.activation, pb.activation);
+            }
+        }
+        Ok(())
+    }
 }
@@ -21,5 +21,5 @@ mod reader;
 mod common;
 mod writer;
 
-pub use reader::{MzMLbReader, MzMLbError, MzMLbReaderType, MzMLbSpectrumBuilder};
+pub use reader::{MzMLbReader, MzMLbError, MzMLbReadError, MzMLbReaderType, MzMLbSpectrumBuilder};
 pub use writer::{MzMLbWriterType, MzMLbWriterError, MzMLbWriterBuilder, MzMLbWriter};
\ No newline at end of file
@@ -17,13 +17,17 @@ use crate::io::mzml::{
     CVParamParse, IncrementingIdMap, MzMLParserError, MzMLParserState, MzMLReaderType, MzMLSAX,
     MzMLSpectrumBuilder, ParserResult, SpectrumBuilding,
 };
-use crate::io::traits::MZFileReader;
+use crate::io::traits::{MZFileReader, SpectrumReadError, TryScanSource};
 use crate::io::utils::DetailLevel;
+use crate::io::SpectrumFilter;
 use crate::io::{OffsetIndex, RandomAccessSpectrumIterator, SpectrumAccessError, ScanSource};
 use crate::prelude::{MSDataFileMetadata, ParamLike};
 
-use crate::meta::{DataProcessing, FileDescription, InstrumentConfiguration, Software};
-use crate::params::{ControlledVocabulary, Param};
+use crate::meta::{
+    CVEntry, DataProcessing, FileDescription, InstrumentConfiguration, Sample, ScanSettings,
+    Software,
+};
+use crate::params::{ControlledVocabulary, Param, ParamGroup};
 use crate::spectrum::bindata::{
     as_bytes, delta_decoding, linear_prediction_decoding, ArrayRetrievalError,
     BinaryCompressionType, BinaryDataArrayType, ByteArrayView, ByteArrayViewMut, DataArray, BuildFromArrayMap,
@@ -464,6 +468,18 @@ impl<'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdaptin
     pub fn with_detail_level(detail_level: DetailLevel) -> Self {
         Self { inner: MzMLSpectrumBuilder::with_detail_level(detail_level), ..Default::default() }
     }
+
+    /// Set the [`SpectrumFilter`] to evaluate once the spectrum's metadata has been read
+    pub fn with_filter(mut self, filter: Option<SpectrumFilter>) -> Self {
+        self.inner = self.inner.with_filter(filter);
+        self
+    }
+
+    /// Record referenceable param groups and unrecognised elements on the spectrum
+    pub fn with_preserve_structure(mut self, preserve_structure: bool) -> Self {
+        self.inner = self.inner.with_preserve_structure(preserve_structure);
+        self
+    }
 }
 
 impl<'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdapting + BuildFromArrayMap> MzMLSAX
@@ -542,7 +558,7 @@ impl<'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdaptin
                     _ => return self.inner.empty_element(event, state, reader_position),
                 }
             }
-            _ => {}
+            _ => return self.inner.empty_element(event, state, reader_position),
         }
         Ok(state)
     }
@@ -562,9 +578,10 @@ impl<'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdaptin
                     ));
                 }
                 let detail_level = self.inner.detail_level;
+                let rejected = self.inner.is_rejected();
                 let data_request = mem::take(&mut self.current_data_range_query);
                 let array = self.inner.current_array_mut();
-                if !matches!(detail_level, DetailLevel::MetadataOnly) {
+                if !matches!(detail_level, DetailLevel::MetadataOnly) && !rejected {
                     self.data_registry
                         .as_mut()
                         .expect("Did not provide data registry")
@@ -642,6 +659,10 @@ impl<'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdaptin
         self.inner.fill_spectrum(param)
     }
 
+    fn is_rejected(&self) -> bool {
+        self.inner.is_rejected()
+    }
+
     fn fill_binary_data_array<P: ParamLike + Into<Param>>(&mut self, param: P) {
         self.inner.fill_binary_data_array(param)
     }
@@ -655,6 +676,11 @@ impl<'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdaptin
             .borrow_instrument_configuration(instrument_configurations);
         self
     }
+
+    fn borrow_param_groups(mut self, param_groups: &'a [ParamGroup]) -> Self {
+        self.inner = self.inner.borrow_param_groups(param_groups);
+        self
+    }
 }
 
 pub struct MzMLbReaderType<
@@ -680,6 +706,12 @@ pub struct MzMLbReaderType<
     pub schema_version: String,
 
     pub detail_level: DetailLevel,
+    /// A filter on spectrum metadata. Spectra which are rejected are skipped during
+    /// iteration without fetching their data arrays, see [`SpectrumFilter`].
+    pub filter: Option<SpectrumFilter>,
+    /// Whether to keep referenceable param groups and unrecognised elements on each
+    /// spectrum, see [`MzMLReaderType::preserve_structure`]
+    pub preserve_structure: bool,
 
     mzml_parser: MzMLReaderType<ByteReader, C, D>,
     data_buffers: ExternalDataRegistry,
@@ -724,6 +756,8 @@ impl<'a, 'b: 'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPea
             softwares: mzml_parser.softwares.clone(),
             data_processings: mzml_parser.data_processings.clone(),
             detail_level,
+            filter: None,
+            preserve_structure: false,
             mzml_parser,
             schema_version,
             data_buffers,
@@ -830,17 +864,29 @@ impl<'a, 'b: 'a, C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPea
     /// Populate a new [`MultiLayerSpectrum`] in-place on the next available spectrum data.
     /// This allocates memory to build the spectrum's attributes but then moves it
     /// into `spectrum` rather than copying it.
+    ///
+    /// If [`MzMLbReaderType::filter`] is set, spectra it rejects are skipped and the
+    /// next accepted spectrum is read instead.
     pub fn read_into(
         &mut self,
         spectrum: &mut MultiLayerSpectrum<C, D>,
     ) -> Result<usize, MzMLbError> {
-        let accumulator = MzMLbSpectrumBuilder::<C, D>::with_detail_level(self.detail_level);
-        match self._parse_into(accumulator) {
-            Ok((accumulator, sz)) => {
-                accumulator.into_spectrum(spectrum);
-                Ok(sz)
+        let mut skipped = 0;
+        loop {
+            let accumulator = MzMLbSpectrumBuilder::<C, D>::with_detail_level(self.detail_level)
+                .with_filter(self.filter.clone())
+                .with_preserve_structure(self.preserve_structure);
+            match self._parse_into(accumulator) {
+                Ok((accumulator, sz)) => {
+                    if accumulator.is_rejected() {
+                        skipped += sz;
+                        continue;
+                    }
+                    accumulator.into_spectrum(spectrum);
+                    return Ok(sz + skipped);
+                }
+                Err(err) => return Err(err),
             }
-            Err(err) => Err(err),
         }
     }
 
@@ -899,7 +945,9 @@ impl<C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdapting +
             "The next XML tag was not `spectrum`"
         );
         self.mzml_parser.state = MzMLParserState::Resume;
+        let filter = self.filter.take();
         let result = self.read_next();
+        self.filter = filter;
         self.mzml_parser
             .seek(SeekFrom::Start(start))
             .expect("Failed to restore offset");
@@ -922,7 +970,9 @@ impl<C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdapting +
             "The next XML tag was not `spectrum`"
         );
         self.mzml_parser.state = MzMLParserState::Resume;
+        let filter = self.filter.take();
         let result = self.read_next();
+        self.filter = filter;
         self.mzml_parser
             .seek(SeekFrom::Start(start))
             .expect("Failed to restore offset");
@@ -985,6 +1035,43 @@ impl<C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdapting +
     }
 }
 
+/// As with [`MzMLReaderType`], the offsets reported by [`TryScanSource::try_next`] are
+/// positions in the `mzML` dataset.
+impl<C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdapting + BuildFromArrayMap>
+    TryScanSource<C, D, MultiLayerSpectrum<C, D>> for MzMLbReaderType<C, D>
+{
+    type Error = MzMLbReadError;
+
+    fn try_next(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, Self::Error> {
+        if self.mzml_parser.state == MzMLParserState::EOF {
+            return Ok(None);
+        }
+        let start = self.mzml_parser.stream_position().map_err(|err| {
+            let state = self.mzml_parser.state;
+            MzMLbReadError::new(MzMLParserError::IOError(state, err).into(), state, 0)
+        })?;
+        let mut spectrum = MultiLayerSpectrum::<C, D>::default();
+        match self.read_into(&mut spectrum) {
+            Ok(_) => Ok(Some(spectrum)),
+            Err(MzMLbError::MzMLError(MzMLParserError::SectionOver(_))) => Ok(None),
+            Err(MzMLbError::MzMLError(MzMLParserError::IncompleteSpectrum))
+                if self.mzml_parser.state == MzMLParserState::EOF =>
+            {
+                Ok(None)
+            }
+            Err(err) => {
+                let state = match &err {
+                    MzMLbError::MzMLError(err) => err.state(),
+                    _ => None,
+                }
+                .unwrap_or(self.mzml_parser.state);
+                let offset = self.mzml_parser.stream_position().unwrap_or(start);
+                Err(MzMLbReadError::new(err, state, offset).with_spectrum_from(&self.index, start))
+            }
+        }
+    }
+}
+
 impl<C: CentroidPeakAdapting + BuildFromArrayMap, D: DeconvolutedPeakAdapting + BuildFromArrayMap>
     MZFileReader<C, D, MultiLayerSpectrum<C, D>> for MzMLbReaderType<C, D>
 {
@@ -1006,10 +1093,45 @@ impl<C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> MSDataFileMetadata
     fn spectrum_count_hint(&self) -> Option<u64> {
         Some(self.index.len() as u64)
     }
+
+    fn reference_param_groups(&self) -> Option<&Vec<ParamGroup>> {
+        self.mzml_parser.reference_param_groups()
+    }
+
+    fn reference_param_groups_mut(&mut self) -> Option<&mut Vec<ParamGroup>> {
+        self.mzml_parser.reference_param_groups_mut()
+    }
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        self.mzml_parser.samples()
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        self.mzml_parser.samples_mut()
+    }
+
+    fn scan_settings(&self) -> Option<&Vec<ScanSettings>> {
+        self.mzml_parser.scan_settings()
+    }
+
+    fn scan_settings_mut(&mut self) -> Option<&mut Vec<ScanSettings>> {
+        self.mzml_parser.scan_settings_mut()
+    }
+
+    fn cv_list(&self) -> Option<&Vec<CVEntry>> {
+        self.mzml_parser.cv_list()
+    }
+
+    fn cv_list_mut(&mut self) -> Option<&mut Vec<CVEntry>> {
+        self.mzml_parser.cv_list_mut()
+    }
 }
 
 pub type MzMLbReader = MzMLbReaderType<CentroidPeak, DeconvolutedPeak>;
 
+/// An error raised while reading a spectrum from an mzMLb file with [`TryScanSource::try_next`]
+pub type MzMLbReadError = SpectrumReadError<MzMLbError, MzMLParserState>;
+
 #[cfg(test)]
 mod test {
     use crate::{MzMLReader, SpectrumLike};
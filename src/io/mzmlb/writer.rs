@@ -26,8 +26,11 @@ use thiserror::Error;
 
 use crate::prelude::MSDataFileMetadata;
 use crate::io::traits::ScanWriter;
-use crate::meta::{DataProcessing, FileDescription, InstrumentConfiguration, Software};
-use crate::params::ControlledVocabulary;
+use crate::meta::{
+    CVEntry, DataProcessing, FileDescription, InstrumentConfiguration, Sample, ScanSettings,
+    Software,
+};
+use crate::params::{ControlledVocabulary, ParamGroup};
 use crate::spectrum::bindata::{
     ArrayRetrievalError, BinaryDataArrayType, BuildArrayMapFrom, ByteArrayView, DataArray,
 };
@@ -129,6 +132,38 @@ where
     fn copy_metadata_from<T: MSDataFileMetadata>(&mut self, source: &T) {
         self.mzml_writer.copy_metadata_from(source)
     }
+
+    fn reference_param_groups(&self) -> Option<&Vec<ParamGroup>> {
+        self.mzml_writer.reference_param_groups()
+    }
+
+    fn reference_param_groups_mut(&mut self) -> Option<&mut Vec<ParamGroup>> {
+        self.mzml_writer.reference_param_groups_mut()
+    }
+
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        self.mzml_writer.samples()
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        self.mzml_writer.samples_mut()
+    }
+
+    fn scan_settings(&self) -> Option<&Vec<ScanSettings>> {
+        self.mzml_writer.scan_settings()
+    }
+
+    fn scan_settings_mut(&mut self) -> Option<&mut Vec<ScanSettings>> {
+        self.mzml_writer.scan_settings_mut()
+    }
+
+    fn cv_list(&self) -> Option<&Vec<CVEntry>> {
+        self.mzml_writer.cv_list()
+    }
+
+    fn cv_list_mut(&mut self) -> Option<&mut Vec<CVEntry>> {
+        self.mzml_writer.cv_list_mut()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -439,6 +474,9 @@ where
         if size != default_array_size {
             attrib!("arrayLength", size_str, outer);
         }
+        if let Some(data_processing_id) = array.data_processing_id.as_ref() {
+            attrib!("dataProcessingRef", data_processing_id, outer);
+        }
 
         start_event!(self, outer);
         match &array.dtype {
@@ -82,6 +82,16 @@ impl OffsetIndex {
         self.offsets.contains_key(key)
     }
 
+    /// Find the first entry whose offset is at or after `offset`, returning its position,
+    /// key and offset. Entries are assumed to be in the order they occur in the file.
+    pub fn first_at_or_after(&self, offset: u64) -> Option<(usize, &String, u64)> {
+        self.offsets
+            .iter()
+            .enumerate()
+            .find(|(_, (_, o))| **o >= offset)
+            .map(|(i, (key, o))| (i, key, *o))
+    }
+
     pub fn to_writer<W: Write>(&self, writer: W) -> serde_json::Result<()> {
         serde_json::to_writer(writer, self)
     }
@@ -0,0 +1,38 @@
+#![cfg(feature = "parquet")]
+/*!
+Reads and writes spectra as a pair of Apache Parquet tables, for columnar analytics tools
+like DuckDB, Polars or Arrow-based data frames, or as an intermediate store.
+
+A run is written as two tables side by side:
+
+1. The spectrum table, with one row of metadata per spectrum: `index`, `id`, `ms_level`,
+   `time` (minutes), `polarity`, `signal_continuity`, the first selected ion's `precursor_mz`,
+   `precursor_charge` and `precursor_intensity`, `precursor_id`, the isolation window
+   (`isolation_window_target`, `isolation_window_lower`, `isolation_window_upper`), the
+   `activation` methods separated by `;`, `collision_energy` or
+   `normalized_collision_energy`, `injection_time` (milliseconds), `compensation_voltage`
+   and `ion_mobility_type`.
+2. The peak table, in long format with one row per peak or data point: `spectrum_index`,
+   `mz`, `intensity`, `charge` and `ion_mobility`. Deconvoluted peaks are written at
+   their m/z with their charge.
+
+[`ParquetWriterType`] implements [`ScanWriter`](crate::io::ScanWriter) and
+[`ParquetReaderType`] reads the tables back into [`MultiLayerSpectrum`](crate::spectrum::MultiLayerSpectrum)
+with their peaks in data arrays, the same way the mzML reader does. Only the fields listed above
+are preserved, so other parameters and the file-level metadata are not round-tripped. Only the
+first selected ion of the first precursor is written, and the writer logs a warning the first
+time it meets a spectrum with more.
+
+This module requires the `parquet` feature.
+*/
+
+mod common;
+mod reader;
+mod writer;
+
+pub use common::{
+    peak_schema, spectrum_schema, table_paths, ParquetTableError, PEAK_TABLE_SUFFIX,
+    SPECTRUM_TABLE_SUFFIX,
+};
+pub use reader::{ParquetReader, ParquetReaderType};
+pub use writer::{ParquetWriter, ParquetWriterType};
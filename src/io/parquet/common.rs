@@ -0,0 +1,92 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use parquet::errors::ParquetError;
+use thiserror::Error;
+
+use crate::spectrum::bindata::{ArrayRetrievalError, ArrayType};
+
+/// The file name suffix of the spectrum metadata table
+pub const SPECTRUM_TABLE_SUFFIX: &str = "spectra.parquet";
+/// The file name suffix of the peak table
+pub const PEAK_TABLE_SUFFIX: &str = "peaks.parquet";
+
+/// The array types written to the `ion_mobility` column, in order of preference
+pub(crate) const ION_MOBILITY_ARRAYS: [ArrayType; 4] = [
+    ArrayType::IonMobilityArray,
+    ArrayType::MeanIonMobilityArray,
+    ArrayType::RawIonMobilityArray,
+    ArrayType::DeconvolutedIonMobilityArray,
+];
+
+#[derive(Debug, Error)]
+pub enum ParquetTableError {
+    #[error("An Arrow-related error occurred: {0}")]
+    ArrowError(#[from] ArrowError),
+    #[error("A Parquet-related error occurred: {0}")]
+    ParquetError(#[from] ParquetError),
+    #[error("An error occurred while decoding binary data: {0}")]
+    ArrayRetrievalError(#[from] ArrayRetrievalError),
+    #[error("The column {0} is missing or has the wrong type")]
+    MissingColumn(String),
+    #[error("An IO error occurred: {0}")]
+    IOError(#[from] io::Error),
+}
+
+impl From<ParquetTableError> for io::Error {
+    fn from(value: ParquetTableError) -> Self {
+        match value {
+            ParquetTableError::IOError(err) => err,
+            _ => Self::other(value),
+        }
+    }
+}
+
+/// The paths of the spectrum and peak tables sharing `prefix`, e.g. `run.spectra.parquet`
+/// and `run.peaks.parquet` for `run`
+pub fn table_paths<P: AsRef<Path>>(prefix: P) -> (PathBuf, PathBuf) {
+    let prefix = prefix.as_ref().to_string_lossy();
+    (
+        PathBuf::from(format!("{}.{}", prefix, SPECTRUM_TABLE_SUFFIX)),
+        PathBuf::from(format!("{}.{}", prefix, PEAK_TABLE_SUFFIX)),
+    )
+}
+
+/// The schema of the spectrum metadata table
+pub fn spectrum_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("index", DataType::UInt64, false),
+        Field::new("id", DataType::Utf8, false),
+        Field::new("ms_level", DataType::UInt8, false),
+        Field::new("time", DataType::Float64, false),
+        Field::new("polarity", DataType::Int8, false),
+        Field::new("signal_continuity", DataType::Utf8, false),
+        Field::new("precursor_mz", DataType::Float64, true),
+        Field::new("precursor_charge", DataType::Int32, true),
+        Field::new("precursor_intensity", DataType::Float32, true),
+        Field::new("precursor_id", DataType::Utf8, true),
+        Field::new("isolation_window_target", DataType::Float32, true),
+        Field::new("isolation_window_lower", DataType::Float32, true),
+        Field::new("isolation_window_upper", DataType::Float32, true),
+        Field::new("activation", DataType::Utf8, true),
+        Field::new("collision_energy", DataType::Float32, true),
+        Field::new("normalized_collision_energy", DataType::Float32, true),
+        Field::new("injection_time", DataType::Float32, true),
+        Field::new("compensation_voltage", DataType::Float32, true),
+        Field::new("ion_mobility_type", DataType::Utf8, true),
+    ]))
+}
+
+/// The schema of the long-format peak table
+pub fn peak_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("spectrum_index", DataType::UInt64, false),
+        Field::new("mz", DataType::Float64, false),
+        Field::new("intensity", DataType::Float32, false),
+        Field::new("charge", DataType::Int32, true),
+        Field::new("ion_mobility", DataType::Float64, true),
+    ]))
+}
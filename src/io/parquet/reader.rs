@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use arrow::array::{
+    Array, ArrowPrimitiveType, Float32Array, Float64Array, Int32Array, Int8Array, PrimitiveArray,
+    StringArray, UInt64Array, UInt8Array,
+};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::file::reader::ChunkReader;
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use super::common::{table_paths, ParquetTableError, ION_MOBILITY_ARRAYS};
+use crate::io::traits::TryScanSource;
+use crate::params::Unit;
+use crate::spectrum::bindata::{
+    to_bytes, ArrayType, BinaryArrayMap, BinaryDataArrayType, DataArray,
+};
+use crate::spectrum::{
+    ActivationEnergy, ActivationMethod, IsolationWindow, IsolationWindowState, MultiLayerSpectrum,
+    Precursor, ScanEvent, ScanPolarity, SelectedIon, SignalContinuity, SpectrumDescription,
+};
+
+fn column<'a, T: Array + 'static>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a T, ParquetTableError> {
+    batch
+        .column_by_name(name)
+        .and_then(|array| array.as_any().downcast_ref::<T>())
+        .ok_or_else(|| ParquetTableError::MissingColumn(name.to_string()))
+}
+
+fn value_of<T: ArrowPrimitiveType>(array: &PrimitiveArray<T>, i: usize) -> Option<T::Native> {
+    if array.is_null(i) {
+        None
+    } else {
+        Some(array.value(i))
+    }
+}
+
+fn str_of(array: &StringArray, i: usize) -> Option<&str> {
+    if array.is_null(i) {
+        None
+    } else {
+        Some(array.value(i))
+    }
+}
+
+/// Decode every row of a spectrum table batch into a description and the type of its
+/// ion mobility array
+fn decode_spectrum_batch(
+    batch: &RecordBatch,
+) -> Result<Vec<(SpectrumDescription, Option<ArrayType>)>, ParquetTableError> {
+    let indices = column::<UInt64Array>(batch, "index")?;
+    let ids = column::<StringArray>(batch, "id")?;
+    let ms_levels = column::<UInt8Array>(batch, "ms_level")?;
+    let times = column::<Float64Array>(batch, "time")?;
+    let polarities = column::<Int8Array>(batch, "polarity")?;
+    let continuities = column::<StringArray>(batch, "signal_continuity")?;
+    let precursor_mzs = column::<Float64Array>(batch, "precursor_mz")?;
+    let precursor_charges = column::<Int32Array>(batch, "precursor_charge")?;
+    let precursor_intensities = column::<Float32Array>(batch, "precursor_intensity")?;
+    let precursor_ids = column::<StringArray>(batch, "precursor_id")?;
+    let targets = column::<Float32Array>(batch, "isolation_window_target")?;
+    let lower_bounds = column::<Float32Array>(batch, "isolation_window_lower")?;
+    let upper_bounds = column::<Float32Array>(batch, "isolation_window_upper")?;
+    let activations = column::<StringArray>(batch, "activation")?;
+    let collision_energies = column::<Float32Array>(batch, "collision_energy")?;
+    let normalized_collision_energies =
+        column::<Float32Array>(batch, "normalized_collision_energy")?;
+    let injection_times = column::<Float32Array>(batch, "injection_time")?;
+    let compensation_voltages = column::<Float32Array>(batch, "compensation_voltage")?;
+    let ion_mobility_types = column::<StringArray>(batch, "ion_mobility_type")?;
+
+    let mut rows = Vec::with_capacity(batch.num_rows());
+    for i in 0..batch.num_rows() {
+        let mut description = SpectrumDescription {
+            id: ids.value(i).to_string(),
+            index: indices.value(i) as usize,
+            ms_level: ms_levels.value(i),
+            polarity: match polarities.value(i) {
+                1 => ScanPolarity::Positive,
+                -1 => ScanPolarity::Negative,
+                _ => ScanPolarity::Unknown,
+            },
+            signal_continuity: match continuities.value(i) {
+                "Centroid" => SignalContinuity::Centroid,
+                "Profile" => SignalContinuity::Profile,
+                _ => SignalContinuity::Unknown,
+            },
+            ..Default::default()
+        };
+        description.acquisition.scans.push(ScanEvent {
+            start_time: times.value(i),
+            injection_time: value_of(injection_times, i).unwrap_or_default(),
+            compensation_voltage: value_of(compensation_voltages, i),
+            ..Default::default()
+        });
+
+        let precursor_mz = value_of(precursor_mzs, i);
+        let precursor_id = str_of(precursor_ids, i);
+        let target = value_of(targets, i);
+        let activation = str_of(activations, i);
+        if precursor_mz.is_some()
+            || precursor_id.is_some()
+            || target.is_some()
+            || activation.is_some()
+        {
+            let mut precursor = Precursor::default();
+            if let Some(mz) = precursor_mz {
+                precursor.ions = vec![SelectedIon {
+                    mz,
+                    intensity: value_of(precursor_intensities, i).unwrap_or_default(),
+                    charge: value_of(precursor_charges, i),
+                    params: None,
+                }];
+            }
+            precursor.precursor_id = precursor_id.map(String::from);
+            if let Some(target) = target {
+                precursor.isolation_window = IsolationWindow {
+                    target,
+                    lower_bound: value_of(lower_bounds, i).unwrap_or(target),
+                    upper_bound: value_of(upper_bounds, i).unwrap_or(target),
+                    flags: IsolationWindowState::Complete,
+                };
+            }
+            if let Some(names) = activation {
+                for name in names.split(';') {
+                    precursor
+                        .activation
+                        .add_method(ActivationMethod::from_name(name));
+                }
+            }
+            if let Some(energy) = value_of(collision_energies, i) {
+                precursor.activation.add_energy(ActivationEnergy::new(
+                    energy,
+                    Unit::Electronvolt,
+                    false,
+                ));
+            }
+            if let Some(energy) = value_of(normalized_collision_energies, i) {
+                precursor.activation.add_energy(ActivationEnergy::new(
+                    energy,
+                    Unit::PercentElectronVolt,
+                    false,
+                ));
+            }
+            description.precursors.push(precursor);
+        }
+
+        let ion_mobility_type = str_of(ion_mobility_types, i).map(|name| {
+            ION_MOBILITY_ARRAYS
+                .iter()
+                .find(|t| t.to_string() == name)
+                .cloned()
+                .unwrap_or(ArrayType::IonMobilityArray)
+        });
+        rows.push((description, ion_mobility_type));
+    }
+    Ok(rows)
+}
+
+/**
+Reads the spectrum and peak tables written by [`ParquetWriterType`](super::ParquetWriterType)
+back into [`MultiLayerSpectrum`], with the peaks stored in its data arrays.
+
+Both tables are streamed in batches, relying on the peaks of each spectrum being written
+contiguously and in the same order as the spectrum table, as the writer does.
+*/
+pub struct ParquetReaderType<
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+> {
+    spectrum_batches: ParquetRecordBatchReader,
+    peak_batches: ParquetRecordBatchReader,
+    descriptions: VecDeque<(SpectrumDescription, Option<ArrayType>)>,
+    peak_batch: Option<RecordBatch>,
+    peak_offset: usize,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default> ParquetReaderType<C, D> {
+    /// Read the spectrum table from `spectra` and the peak table from `peaks`
+    pub fn new<R: ChunkReader + 'static>(spectra: R, peaks: R) -> Result<Self, ParquetTableError> {
+        let spectrum_batches = ParquetRecordBatchReaderBuilder::try_new(spectra)?.build()?;
+        let peak_batches = ParquetRecordBatchReaderBuilder::try_new(peaks)?.build()?;
+        Ok(Self {
+            spectrum_batches,
+            peak_batches,
+            descriptions: VecDeque::new(),
+            peak_batch: None,
+            peak_offset: 0,
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        })
+    }
+
+    /// Open the spectrum and peak tables at the paths given by
+    /// [`table_paths`](super::table_paths) for `prefix`
+    pub fn open_path<P: AsRef<Path>>(prefix: P) -> Result<Self, ParquetTableError> {
+        let (spectrum_path, peak_path) = table_paths(prefix);
+        Self::new(fs::File::open(spectrum_path)?, fs::File::open(peak_path)?)
+    }
+
+    /// Read the next spectrum, or `None` once the spectrum table is exhausted
+    pub fn read_next(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, ParquetTableError> {
+        while self.descriptions.is_empty() {
+            match self.spectrum_batches.next() {
+                Some(batch) => self.descriptions.extend(decode_spectrum_batch(&batch?)?),
+                None => return Ok(None),
+            }
+        }
+        let (description, ion_mobility_type) = self.descriptions.pop_front().unwrap();
+        let arrays = self.read_peaks(description.index as u64, ion_mobility_type)?;
+        Ok(Some(MultiLayerSpectrum {
+            description,
+            arrays: Some(arrays),
+            ..Default::default()
+        }))
+    }
+
+    /// Collect the peak rows of the spectrum with `index` into data arrays
+    fn read_peaks(
+        &mut self,
+        index: u64,
+        ion_mobility_type: Option<ArrayType>,
+    ) -> Result<BinaryArrayMap, ParquetTableError> {
+        let mut mzs = Vec::new();
+        let mut intensities = Vec::new();
+        let mut charges = Vec::new();
+        let mut ion_mobilities = Vec::new();
+        let mut has_charge = false;
+        let mut has_ion_mobility = false;
+        loop {
+            let exhausted = match self.peak_batch.as_ref() {
+                Some(batch) => self.peak_offset >= batch.num_rows(),
+                None => true,
+            };
+            if exhausted {
+                match self.peak_batches.next() {
+                    Some(batch) => {
+                        self.peak_batch = Some(batch?);
+                        self.peak_offset = 0;
+                        continue;
+                    }
+                    None => {
+                        self.peak_batch = None;
+                        break;
+                    }
+                }
+            }
+            let batch = self.peak_batch.as_ref().unwrap();
+            let spectrum_indices = column::<UInt64Array>(batch, "spectrum_index")?;
+            let mz_array = column::<Float64Array>(batch, "mz")?;
+            let intensity_array = column::<Float32Array>(batch, "intensity")?;
+            let charge_array = column::<Int32Array>(batch, "charge")?;
+            let ion_mobility_array = column::<Float64Array>(batch, "ion_mobility")?;
+            while self.peak_offset < batch.num_rows()
+                && spectrum_indices.value(self.peak_offset) == index
+            {
+                let j = self.peak_offset;
+                mzs.push(mz_array.value(j));
+                intensities.push(intensity_array.value(j));
+                let charge = value_of(charge_array, j);
+                has_charge |= charge.is_some();
+                charges.push(charge.unwrap_or_default());
+                let ion_mobility = value_of(ion_mobility_array, j);
+                has_ion_mobility |= ion_mobility.is_some();
+                ion_mobilities.push(ion_mobility.unwrap_or_default());
+                self.peak_offset += 1;
+            }
+            if self.peak_offset < batch.num_rows() {
+                break;
+            }
+        }
+
+        let mut arrays = BinaryArrayMap::new();
+        arrays.add(DataArray::wrap(
+            &ArrayType::MZArray,
+            BinaryDataArrayType::Float64,
+            to_bytes(&mzs),
+        ));
+        arrays.add(DataArray::wrap(
+            &ArrayType::IntensityArray,
+            BinaryDataArrayType::Float32,
+            to_bytes(&intensities),
+        ));
+        if has_charge {
+            arrays.add(DataArray::wrap(
+                &ArrayType::ChargeArray,
+                BinaryDataArrayType::Int32,
+                to_bytes(&charges),
+            ));
+        }
+        if has_ion_mobility {
+            arrays.add(DataArray::wrap(
+                &ion_mobility_type.unwrap_or(ArrayType::IonMobilityArray),
+                BinaryDataArrayType::Float64,
+                to_bytes(&ion_mobilities),
+            ));
+        }
+        Ok(arrays)
+    }
+}
+
+impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default> Iterator
+    for ParquetReaderType<C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_next() {
+            Ok(spectrum) => spectrum,
+            Err(err) => {
+                log::error!("Failed to read spectrum from Parquet tables: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// Parquet tables are read by row rather than from a byte stream, so there is no offset to report
+impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
+    TryScanSource<C, D, MultiLayerSpectrum<C, D>> for ParquetReaderType<C, D>
+{
+    type Error = ParquetTableError;
+
+    fn try_next(&mut self) -> Result<Option<MultiLayerSpectrum<C, D>>, Self::Error> {
+        self.read_next()
+    }
+}
+
+pub type ParquetReader = ParquetReaderType<CentroidPeak, DeconvolutedPeak>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::parquet::ParquetWriter;
+    use crate::io::MzMLReader;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), ParquetTableError> {
+        let reader = MzMLReader::new(fs::File::open("./test/data/three_test_scans.mzML")?);
+        let spectra: Vec<_> = reader.collect();
+
+        let tmpdir = tempfile::tempdir()?;
+        let prefix = tmpdir.path().join("three_test_scans");
+        let mut writer = ParquetWriter::create_path(&prefix)?;
+        for spectrum in spectra.iter() {
+            writer.write_spectrum(spectrum)?;
+            writer.flush()?;
+        }
+        writer.finish()?;
+
+        let reader = ParquetReader::open_path(&prefix)?;
+        let restored: Vec<_> = reader.collect();
+        assert_eq!(restored.len(), spectra.len());
+        for (spectrum, copy) in spectra.iter().zip(restored.iter()) {
+            assert_eq!(spectrum.id(), copy.id());
+            assert_eq!(spectrum.ms_level(), copy.ms_level());
+            assert_eq!(spectrum.start_time(), copy.start_time());
+            assert_eq!(spectrum.polarity(), copy.polarity());
+            assert_eq!(spectrum.peaks().len(), copy.peaks().len());
+            assert_eq!(
+                spectrum.precursor().map(|p| p.ion().mz),
+                copy.precursor().map(|p| p.ion().mz)
+            );
+            assert_eq!(
+                spectrum
+                    .precursor()
+                    .map(|p| p.activation.methods().to_vec()),
+                copy.precursor().map(|p| p.activation.methods().to_vec())
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,397 @@
+use std::fs;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, Float32Builder, Float64Builder, Int32Builder, Int8Builder, StringBuilder,
+    UInt64Builder, UInt8Builder,
+};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use super::common::{
+    peak_schema, spectrum_schema, table_paths, ParquetTableError, ION_MOBILITY_ARRAYS,
+};
+use crate::io::traits::ScanWriter;
+use crate::spectrum::bindata::{ArrayType, ByteArrayView};
+use crate::spectrum::{PeakDataLevel, SpectrumDescription, SpectrumLike};
+use crate::utils::mass_charge_ratio;
+
+/// The default number of rows buffered in memory before they are written out
+const DEFAULT_BATCH_SIZE: usize = 65536;
+
+/// Buffers the rows of the spectrum metadata table
+#[derive(Default)]
+struct SpectrumRows {
+    index: UInt64Builder,
+    id: StringBuilder,
+    ms_level: UInt8Builder,
+    time: Float64Builder,
+    polarity: Int8Builder,
+    signal_continuity: StringBuilder,
+    precursor_mz: Float64Builder,
+    precursor_charge: Int32Builder,
+    precursor_intensity: Float32Builder,
+    precursor_id: StringBuilder,
+    isolation_window_target: Float32Builder,
+    isolation_window_lower: Float32Builder,
+    isolation_window_upper: Float32Builder,
+    activation: StringBuilder,
+    collision_energy: Float32Builder,
+    normalized_collision_energy: Float32Builder,
+    injection_time: Float32Builder,
+    compensation_voltage: Float32Builder,
+    ion_mobility_type: StringBuilder,
+    len: usize,
+}
+
+impl SpectrumRows {
+    fn append(&mut self, description: &SpectrumDescription, ion_mobility_type: Option<ArrayType>) {
+        self.index.append_value(description.index as u64);
+        self.id.append_value(&description.id);
+        self.ms_level.append_value(description.ms_level);
+        self.time.append_value(description.acquisition.start_time());
+        self.polarity.append_value(description.polarity as i8);
+        self.signal_continuity
+            .append_value(description.signal_continuity.to_string());
+
+        let precursor = description.precursors.first();
+        let ion = precursor.and_then(|p| p.ions.first());
+        self.precursor_mz.append_option(ion.map(|ion| ion.mz));
+        self.precursor_charge
+            .append_option(ion.and_then(|ion| ion.charge));
+        self.precursor_intensity
+            .append_option(ion.map(|ion| ion.intensity));
+        self.precursor_id
+            .append_option(precursor.and_then(|p| p.precursor_id.as_ref()));
+
+        let window = precursor.map(|p| &p.isolation_window);
+        self.isolation_window_target
+            .append_option(window.map(|w| w.target));
+        self.isolation_window_lower
+            .append_option(window.map(|w| w.lower_bound));
+        self.isolation_window_upper
+            .append_option(window.map(|w| w.upper_bound));
+
+        let activation = precursor.map(|p| &p.activation);
+        self.activation
+            .append_option(activation.filter(|a| !a.methods().is_empty()).map(|a| {
+                a.methods()
+                    .iter()
+                    .map(|method| method.name())
+                    .collect::<Vec<_>>()
+                    .join(";")
+            }));
+        let energy = activation.and_then(|a| a.energies.iter().find(|e| !e.supplemental));
+        self.collision_energy
+            .append_option(energy.filter(|e| !e.is_normalized()).map(|e| e.value));
+        self.normalized_collision_energy
+            .append_option(energy.filter(|e| e.is_normalized()).map(|e| e.value));
+
+        let scan = description.acquisition.first_scan();
+        self.injection_time
+            .append_option(scan.map(|s| s.injection_time));
+        self.compensation_voltage
+            .append_option(scan.and_then(|s| s.compensation_voltage));
+        self.ion_mobility_type
+            .append_option(ion_mobility_type.map(|t| t.to_string()));
+        self.len += 1;
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.index.finish()),
+            Arc::new(self.id.finish()),
+            Arc::new(self.ms_level.finish()),
+            Arc::new(self.time.finish()),
+            Arc::new(self.polarity.finish()),
+            Arc::new(self.signal_continuity.finish()),
+            Arc::new(self.precursor_mz.finish()),
+            Arc::new(self.precursor_charge.finish()),
+            Arc::new(self.precursor_intensity.finish()),
+            Arc::new(self.precursor_id.finish()),
+            Arc::new(self.isolation_window_target.finish()),
+            Arc::new(self.isolation_window_lower.finish()),
+            Arc::new(self.isolation_window_upper.finish()),
+            Arc::new(self.activation.finish()),
+            Arc::new(self.collision_energy.finish()),
+            Arc::new(self.normalized_collision_energy.finish()),
+            Arc::new(self.injection_time.finish()),
+            Arc::new(self.compensation_voltage.finish()),
+            Arc::new(self.ion_mobility_type.finish()),
+        ];
+        self.len = 0;
+        RecordBatch::try_new(spectrum_schema(), columns)
+    }
+}
+
+/// Buffers the rows of the long-format peak table
+#[derive(Default)]
+struct PeakRows {
+    spectrum_index: UInt64Builder,
+    mz: Float64Builder,
+    intensity: Float32Builder,
+    charge: Int32Builder,
+    ion_mobility: Float64Builder,
+    len: usize,
+}
+
+impl PeakRows {
+    fn append(
+        &mut self,
+        index: u64,
+        mz: f64,
+        intensity: f32,
+        charge: Option<i32>,
+        ion_mobility: Option<f64>,
+    ) {
+        self.spectrum_index.append_value(index);
+        self.mz.append_value(mz);
+        self.intensity.append_value(intensity);
+        self.charge.append_option(charge);
+        self.ion_mobility.append_option(ion_mobility);
+        self.len += 1;
+    }
+
+    /// Append the peaks of `spectrum`, returning the number of rows added and the type of
+    /// the ion mobility array they were read from, if any
+    fn append_spectrum<C: CentroidLike, D: DeconvolutedCentroidLike, S: SpectrumLike<C, D>>(
+        &mut self,
+        spectrum: &S,
+    ) -> Result<(usize, Option<ArrayType>), ParquetTableError> {
+        let index = spectrum.index() as u64;
+        let start = self.len;
+        let mut ion_mobility_type = None;
+        match spectrum.peaks() {
+            PeakDataLevel::Missing => {}
+            PeakDataLevel::RawData(arrays) => {
+                let mzs = arrays.mzs()?;
+                let intensities = arrays.intensities()?;
+                let charges = if arrays.has_array(&ArrayType::ChargeArray) {
+                    Some(arrays.charges()?)
+                } else {
+                    None
+                };
+                let ion_mobility = match ION_MOBILITY_ARRAYS
+                    .iter()
+                    .find_map(|t| arrays.get(t).map(|array| (t, array)))
+                {
+                    Some((t, array)) => {
+                        ion_mobility_type = Some(t.clone());
+                        Some(array.to_f64()?)
+                    }
+                    None => None,
+                };
+                for (i, (mz, intensity)) in mzs.iter().zip(intensities.iter()).enumerate() {
+                    self.append(
+                        index,
+                        *mz,
+                        *intensity,
+                        charges.as_ref().and_then(|c| c.get(i).copied()),
+                        ion_mobility.as_ref().and_then(|im| im.get(i).copied()),
+                    );
+                }
+            }
+            PeakDataLevel::Centroid(peaks) => {
+                for peak in peaks.iter() {
+                    self.append(index, peak.mz(), peak.intensity(), None, None);
+                }
+            }
+            PeakDataLevel::Deconvoluted(peaks) => {
+                for peak in peaks.iter() {
+                    self.append(
+                        index,
+                        mass_charge_ratio(peak.neutral_mass(), peak.charge()),
+                        peak.intensity(),
+                        Some(peak.charge()),
+                        None,
+                    );
+                }
+            }
+        }
+        Ok((self.len - start, ion_mobility_type))
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.spectrum_index.finish()),
+            Arc::new(self.mz.finish()),
+            Arc::new(self.intensity.finish()),
+            Arc::new(self.charge.finish()),
+            Arc::new(self.ion_mobility.finish()),
+        ];
+        self.len = 0;
+        RecordBatch::try_new(peak_schema(), columns)
+    }
+}
+
+/**
+Writes spectra into a spectrum metadata table and a peak table, each its own Parquet file.
+
+Rows are buffered in memory and written out in batches of [`ParquetWriterType::batch_size`].
+The Parquet footers are written by [`ParquetWriterType::finish`], or when the writer is dropped.
+*/
+pub struct ParquetWriterType<
+    W: Write + Send,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+> {
+    spectrum_writer: Option<ArrowWriter<W>>,
+    peak_writer: Option<ArrowWriter<W>>,
+    spectrum_rows: SpectrumRows,
+    peak_rows: PeakRows,
+    /// The number of rows of either table to buffer before writing them out
+    pub batch_size: usize,
+    warned_multiple_ions: bool,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<W: Write + Send, C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
+    ParquetWriterType<W, C, D>
+{
+    /// Create a writer that writes the spectrum table to `spectrum_sink` and the peak table
+    /// to `peak_sink`
+    pub fn new(spectrum_sink: W, peak_sink: W) -> Result<Self, ParquetTableError> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let spectrum_writer =
+            ArrowWriter::try_new(spectrum_sink, spectrum_schema(), Some(properties.clone()))?;
+        let peak_writer = ArrowWriter::try_new(peak_sink, peak_schema(), Some(properties))?;
+        Ok(Self {
+            spectrum_writer: Some(spectrum_writer),
+            peak_writer: Some(peak_writer),
+            spectrum_rows: SpectrumRows::default(),
+            peak_rows: PeakRows::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            warned_multiple_ions: false,
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        })
+    }
+
+    /// Write `spectrum`, returning the number of peak rows it produced.
+    ///
+    /// The spectrum table only holds the first selected ion of the first precursor, so
+    /// any others are not written.
+    pub fn write_spectrum<S: SpectrumLike<C, D>>(
+        &mut self,
+        spectrum: &S,
+    ) -> Result<usize, ParquetTableError> {
+        let description = spectrum.description();
+        let n_ions: usize = description.precursors.iter().map(|p| p.ions.len()).sum();
+        if n_ions > 1 && !self.warned_multiple_ions {
+            log::warn!(
+                "Spectrum {} has {} selected ions but only the first is written to the Parquet spectrum table, further spectra like it will not be reported",
+                description.id,
+                n_ions
+            );
+            self.warned_multiple_ions = true;
+        }
+        let (n_peaks, ion_mobility_type) = self.peak_rows.append_spectrum(spectrum)?;
+        self.spectrum_rows.append(description, ion_mobility_type);
+        self.write_full_batches()?;
+        Ok(n_peaks)
+    }
+
+    /// Write out the buffered rows if either table has reached [`ParquetWriterType::batch_size`]
+    fn write_full_batches(&mut self) -> Result<(), ParquetTableError> {
+        if self.spectrum_rows.len >= self.batch_size || self.peak_rows.len >= self.batch_size {
+            self.write_batches()?;
+        }
+        Ok(())
+    }
+
+    /// Write out any buffered rows
+    pub fn write_batches(&mut self) -> Result<(), ParquetTableError> {
+        if self.spectrum_rows.len > 0 {
+            let batch = self.spectrum_rows.finish()?;
+            self.spectrum_writer.as_mut().unwrap().write(&batch)?;
+        }
+        if self.peak_rows.len > 0 {
+            let batch = self.peak_rows.finish()?;
+            self.peak_writer.as_mut().unwrap().write(&batch)?;
+        }
+        Ok(())
+    }
+
+    /// Write out any buffered rows and the Parquet footers, returning the spectrum and
+    /// peak table sinks
+    pub fn finish(mut self) -> Result<(W, W), ParquetTableError> {
+        self.write_batches()?;
+        let spectrum_sink = self.spectrum_writer.take().unwrap().into_inner()?;
+        let peak_sink = self.peak_writer.take().unwrap().into_inner()?;
+        Ok((spectrum_sink, peak_sink))
+    }
+
+    fn try_finish(&mut self) -> Result<(), ParquetTableError> {
+        self.write_batches()?;
+        if let Some(writer) = self.spectrum_writer.take() {
+            writer.close()?;
+        }
+        if let Some(writer) = self.peak_writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
+    ParquetWriterType<fs::File, C, D>
+{
+    /// Create the spectrum and peak tables at the paths given by [`table_paths`] for `prefix`
+    pub fn create_path<P: AsRef<Path>>(prefix: P) -> Result<Self, ParquetTableError> {
+        let (spectrum_path, peak_path) = table_paths(prefix);
+        Self::new(
+            fs::File::create(spectrum_path)?,
+            fs::File::create(peak_path)?,
+        )
+    }
+}
+
+impl<W: Write + Send, C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default> Drop
+    for ParquetWriterType<W, C, D>
+{
+    fn drop(&mut self) {
+        if self.spectrum_writer.is_some() || self.peak_writer.is_some() {
+            if let Err(e) = self.try_finish() {
+                log::error!("Failed to finish writing the Parquet tables: {}", e);
+            }
+        }
+    }
+}
+
+impl<'a, W: Write + Send, C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
+    ScanWriter<'a, C, D> for ParquetWriterType<W, C, D>
+{
+    fn write<S: SpectrumLike<C, D> + 'static>(&mut self, spectrum: &'a S) -> io::Result<usize> {
+        Ok(self.write_spectrum(spectrum)?)
+    }
+
+    /// Write out any buffered rows and close the current row group of each table.
+    ///
+    /// Every call ends a row group however few rows it holds, so flushing after each
+    /// spectrum produces many small row groups. Only flush when the rows written so far
+    /// must be readable from the sinks.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_batches()?;
+        if let Some(writer) = self.spectrum_writer.as_mut() {
+            writer.flush().map_err(ParquetTableError::from)?;
+        }
+        if let Some(writer) = self.peak_writer.as_mut() {
+            writer.flush().map_err(ParquetTableError::from)?;
+        }
+        Ok(())
+    }
+}
+
+pub type ParquetWriter<W> = ParquetWriterType<W, CentroidPeak, DeconvolutedPeak>;
@@ -1,7 +1,10 @@
 use log::warn;
 use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt;
 use std::fs;
 use std::io;
+use std::iter::FusedIterator;
 use std::marker::PhantomData;
 use std::ops::Index;
 use std::path::{self, PathBuf};
@@ -9,9 +12,11 @@ use thiserror::Error;
 
 use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
 
+use crate::spectrum::dia::DIACycleIterator;
 use crate::spectrum::group::SpectrumGroupingIterator;
 use crate::spectrum::spectrum::{MultiLayerSpectrum, SpectrumLike};
 
+use super::metadata_index::SpectrumMetadataIndex;
 use super::utils::FileSource;
 use super::OffsetIndex;
 
@@ -131,6 +136,23 @@ pub trait ScanSource<
     {
         SpectrumGroupingIterator::new(self)
     }
+
+    /// Create a new `SpectrumIterator` over `self` and use that state to drive a `DIACycleIterator`,
+    /// grouping spectra by data-independent acquisition cycle instead of by precursor
+    fn dia_cycles(&mut self) -> DIACycleIterator<SpectrumIterator<'_, C, D, S, Self>, C, D, S>
+    where
+        Self: Sized,
+    {
+        DIACycleIterator::new(self.iter())
+    }
+
+    /// Consume `self` to create a `DIACycleIterator`
+    fn into_dia_cycles(self) -> DIACycleIterator<Self, C, D, S>
+    where
+        Self: Sized,
+    {
+        DIACycleIterator::new(self)
+    }
 }
 
 /// A generic iterator over a [`ScanSource`] implementer that assumes the
@@ -324,6 +346,18 @@ pub trait MZFileReader<
         }
     }
 
+    /// Open the file at `path` as with [`MZFileReader::open_path`], and read its
+    /// [`SpectrumMetadataIndex`] from next to the offset index, or build and save it
+    /// if it does not exist yet.
+    fn open_path_with_metadata_index<P>(path: P) -> io::Result<(Self, SpectrumMetadataIndex)>
+    where
+        P: Into<path::PathBuf> + Clone,
+    {
+        let mut reader = Self::open_path(path.clone())?;
+        let index = SpectrumMetadataIndex::open_or_build(path, &mut reader)?;
+        Ok((reader, index))
+    }
+
     /// Given a regular file, construct a new instance without indexing.
     fn open_file(source: fs::File) -> Self;
 }
@@ -434,6 +468,203 @@ impl<
     }
 }
 
+/// An error raised by a [`TryScanSource`] while reading a spectrum, recording where in the
+/// source the reader was when it failed.
+///
+/// `E` is the reader's own error type and `P` is the parser state type it tracks, like
+/// [`MzMLParserState`](crate::io::mzml::MzMLParserState), or `()` for formats without one.
+#[derive(Debug)]
+pub struct SpectrumReadError<E, P> {
+    /// The error which stopped the spectrum from being read
+    pub source: E,
+    /// The state the parser was in when it failed
+    pub state: P,
+    /// The byte offset in the source stream at which parsing failed
+    pub offset: u64,
+    /// The index of the spectrum being read, if it could be determined
+    pub index: Option<usize>,
+    /// The native ID of the spectrum being read, if it could be determined
+    pub id: Option<String>,
+}
+
+impl<E, P> SpectrumReadError<E, P> {
+    pub fn new(source: E, state: P, offset: u64) -> Self {
+        Self {
+            source,
+            state,
+            offset,
+            index: None,
+            id: None,
+        }
+    }
+
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn with_id(mut self, id: String) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Identify the spectrum being read when the error occurred from an [`OffsetIndex`],
+    /// taking the first entry starting at or after `start` but before the error's offset.
+    /// Does nothing if `offsets` has not been initialized.
+    pub fn with_spectrum_from(mut self, offsets: &OffsetIndex, start: u64) -> Self {
+        if !offsets.init {
+            return self;
+        }
+        if let Some((index, id, offset)) = offsets.first_at_or_after(start) {
+            if offset <= self.offset {
+                self.index = Some(index);
+                self.id = Some(id.clone());
+            }
+        }
+        self
+    }
+}
+
+impl<E: fmt::Display, P> fmt::Display for SpectrumReadError<E, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+        match (&self.id, self.index) {
+            (Some(id), Some(index)) => write!(f, " while reading spectrum {index} ({id})")?,
+            (Some(id), None) => write!(f, " while reading spectrum {id}")?,
+            (None, Some(index)) => write!(f, " while reading spectrum {index}")?,
+            (None, None) => {}
+        }
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+impl<E: std::error::Error + 'static, P: fmt::Debug> std::error::Error for SpectrumReadError<E, P> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// A region of a source which a reader skipped over to recover from an error while reading
+/// a spectrum, by resuming at the next spectrum after it
+#[derive(Debug)]
+pub struct SkippedRegion<E> {
+    /// The byte offset the reader was at when it started reading the failed spectrum
+    pub start: u64,
+    /// The byte offset at which reading resumed, or the end of the stream if there were
+    /// no more spectra to read
+    pub end: u64,
+    /// The error which caused the region to be skipped
+    pub error: E,
+}
+
+impl<E: fmt::Display> fmt::Display for SkippedRegion<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Skipped bytes {}..{}: {}",
+            self.start, self.end, self.error
+        )
+    }
+}
+
+/// A source of spectra whose iteration can report why it stopped.
+///
+/// Iterating over a reader yields `None` both when the source is exhausted and when
+/// a spectrum could not be parsed. [`TryScanSource::try_next`] tells the two apart,
+/// returning `Ok(None)` only at the end of the source.
+pub trait TryScanSource<
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+>
+{
+    type Error: std::error::Error;
+
+    /// Read the next spectrum, returning `Ok(None)` when there are no more spectra to read
+    fn try_next(&mut self) -> Result<Option<S>, Self::Error>;
+
+    /// Iterate over `Result`s of reading each spectrum, stopping after the first error
+    fn try_iter(&mut self) -> TryIterator<'_, C, D, S, Self>
+    where
+        Self: Sized,
+    {
+        TryIterator::new(self)
+    }
+}
+
+/// An [`Iterator`] adapter over a [`TryScanSource`] that yields the result of each read.
+/// Iteration stops after the first error is yielded.
+pub struct TryIterator<
+    'lifespan,
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+    R: TryScanSource<C, D, S>,
+> {
+    source: &'lifespan mut R,
+    done: bool,
+    phantom: PhantomData<S>,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<
+        'lifespan,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        R: TryScanSource<C, D, S>,
+    > TryIterator<'lifespan, C, D, S, R>
+{
+    pub fn new(source: &'lifespan mut R) -> Self {
+        Self {
+            source,
+            done: false,
+            phantom: PhantomData,
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        }
+    }
+}
+
+impl<
+        'lifespan,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        R: TryScanSource<C, D, S>,
+    > Iterator for TryIterator<'lifespan, C, D, S, R>
+{
+    type Item = Result<S, R::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.source.try_next() {
+            Ok(Some(spectrum)) => Some(Ok(spectrum)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<
+        'lifespan,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        R: TryScanSource<C, D, S>,
+    > FusedIterator for TryIterator<'lifespan, C, D, S, R>
+{
+}
+
 /// An alternative implementation of [`ScanSource`] for non-rewindable underlying streams
 pub struct StreamingSpectrumIterator<
     C: CentroidLike + Default,
@@ -805,6 +1036,19 @@ impl<
     }
 }
 
+impl<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D> + Clone,
+    > TryScanSource<C, D, S> for MemoryScanSource<C, D, S>
+{
+    type Error = Infallible;
+
+    fn try_next(&mut self) -> Result<Option<S>, Self::Error> {
+        Ok(self.next())
+    }
+}
+
 /// Common interface for spectrum writing
 pub trait ScanWriter<
     'a,
@@ -61,7 +61,7 @@ impl<'lifespan, T: io::Read> FileSource<T> {
         }
     }
 
-    pub fn index_file_name(&self) -> Option<path::PathBuf> {
+    fn sidecar_file_name(&self, extension: &str) -> Option<path::PathBuf> {
         match &self.source {
             FileWrapper::Empty => None,
             FileWrapper::Stream(_stream) => None,
@@ -69,7 +69,7 @@ impl<'lifespan, T: io::Read> FileSource<T> {
                 if let Some(stem) = path.file_name() {
                     if let Some(parent) = path.parent() {
                         let base = parent.join(stem);
-                        let name = base.with_extension("index.json");
+                        let name = base.with_extension(extension);
                         return Some(name);
                     }
                 }
@@ -78,6 +78,20 @@ impl<'lifespan, T: io::Read> FileSource<T> {
         }
     }
 
+    pub fn index_file_name(&self) -> Option<path::PathBuf> {
+        self.sidecar_file_name("index.json")
+    }
+
+    /// The path of the extended spectrum metadata index, stored next to the offset index
+    pub fn metadata_index_file_name(&self) -> Option<path::PathBuf> {
+        self.sidecar_file_name("metadata_index.json")
+    }
+
+    /// The path of the block index for a compressed file, stored next to the offset index
+    pub fn block_index_file_name(&self) -> Option<path::PathBuf> {
+        self.sidecar_file_name("blockindex.json")
+    }
+
     pub fn has_index_file(&self) -> bool {
         match self.index_file_name() {
             Some(path) => path.exists(),
@@ -247,14 +261,15 @@ impl<R: io::Read> io::Read for PreBufferedStream<R> {
     }
 }
 
-const BUFFER_SIZE: usize = 2usize.pow(16);
+/// The number of bytes [`PreBufferedStream::new`] buffers
+pub(crate) const PREBUFFER_SIZE: usize = 2usize.pow(16);
 
 impl<R: io::Read> PreBufferedStream<R> {
     /// Create a new pre-buffered stream wrapping `stream` with a buffer size of 2<sup>16</sup> bytes.
     ///
     /// This method fails if attempting to fill the buffer fails.
     pub fn new(stream: R) -> io::Result<Self> {
-        Self::new_with_buffer_size(stream, BUFFER_SIZE)
+        Self::new_with_buffer_size(stream, PREBUFFER_SIZE)
     }
 
     /// Create a new pre-buffered stream wrapping `stream` with a buffer size of `buffer_size` bytes.
@@ -282,6 +297,45 @@ impl<R: io::Read> PreBufferedStream<R> {
     }
 }
 
+/// Scan `handle` from byte offset `from` for the first occurrence of any of `patterns`,
+/// returning its byte offset and which pattern matched. The stream position is left
+/// somewhere after the match, or at the end of the stream if nothing matched.
+pub(crate) fn find_next_of<R: io::Read + io::Seek>(
+    handle: &mut R,
+    from: u64,
+    patterns: &[&[u8]],
+) -> io::Result<Option<(u64, usize)>> {
+    let overlap = patterns
+        .iter()
+        .map(|p| p.len())
+        .max()
+        .unwrap_or_default()
+        .saturating_sub(1);
+    handle.seek(io::SeekFrom::Start(from))?;
+    let mut chunk = vec![0u8; PREBUFFER_SIZE];
+    let mut window: Vec<u8> = Vec::with_capacity(PREBUFFER_SIZE + overlap);
+    let mut window_start = from;
+    loop {
+        let n = handle.read(&mut chunk)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        window.extend_from_slice(&chunk[..n]);
+        let hit = (0..window.len()).find_map(|i| {
+            patterns
+                .iter()
+                .position(|p| window[i..].starts_with(p))
+                .map(|k| (i, k))
+        });
+        if let Some((i, k)) = hit {
+            return Ok(Some((window_start + i as u64, k)));
+        }
+        let drop = window.len() - overlap.min(window.len());
+        window.drain(..drop);
+        window_start += drop as u64;
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -326,4 +380,20 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_next_of() -> io::Result<()> {
+        let mut data = vec![b'x'; PREBUFFER_SIZE - 3];
+        data.extend(b"BEGIN IONS\nEND IONS\n");
+        let mut stream = io::Cursor::new(data);
+        let patterns: &[&[u8]] = &[b"END IONS", b"BEGIN IONS"];
+
+        let hit = find_next_of(&mut stream, 0, patterns)?;
+        assert_eq!(hit, Some(((PREBUFFER_SIZE - 3) as u64, 1)));
+        let hit = find_next_of(&mut stream, PREBUFFER_SIZE as u64, patterns)?;
+        assert_eq!(hit, Some(((PREBUFFER_SIZE + 8) as u64, 0)));
+        let hit = find_next_of(&mut stream, PREBUFFER_SIZE as u64 + 9, patterns)?;
+        assert_eq!(hit, None);
+        Ok(())
+    }
 }
@@ -11,7 +11,8 @@
 //!   2. mzML & indexedmzML files using [`MzMLWriter`] in [`mzdata::io::mzml`](crate::io::mzml)
 //!   3. mzMLb files using [`MzMLbWriter`] in [`mzdata::io::mzmlb`](crate::io::mzmlb)
 //!
-//! It also includes a set of representation layers for spectra in [`mzdata::spectrum`](crate::spectrum)
+//! It also includes a set of representation layers for spectra in [`mzdata::spectrum`](crate::spectrum),
+//! and run-level quality control metrics in [`mzdata::qc`](crate::qc)
 //!
 //! # Example
 //! ```rust
@@ -47,7 +48,10 @@ pub mod meta;
 pub mod params;
 pub mod spectrum;
 pub mod prelude;
+pub mod qc;
 mod utils;
+#[cfg(feature = "capi")]
+pub mod capi;
 
 pub use crate::io::mgf::{MGFReader, MGFWriter, MGFError};
 pub use crate::io::mzml::{MzMLReader, MzMLWriter, MzMLParserError as MzMLError, MzMLWriterError};
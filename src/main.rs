@@ -13,13 +13,16 @@ use std::sync::mpsc::sync_channel;
 
 use mzdata::io::MassSpectrometryFormat;
 use mzdata::io::PreBufferedStream;
-use mzdata::io::infer_from_stream;
+use mzdata::io::{infer_format, infer_format_compression, infer_from_path, infer_from_stream_compression};
+use mzdata::io::{CompressionType, SeekableDecompressor};
 use mzdata::prelude::*;
 use mzdata::io::{mgf, mzml};
+use mzdata::meta::SourceFile;
+use mzdata::qc::RunQualityMetrics;
 #[cfg(feature = "mzmlb")]
 use mzdata::io::mzmlb;
 use mzdata::spectrum::{DeconvolutedSpectrum, MultiLayerSpectrum, PeakDataLevel, SpectrumLike, SignalContinuity};
-use mzpeaks::PeakCollection;
+use mzpeaks::{CentroidPeak, DeconvolutedPeak, PeakCollection};
 
 fn load_file<P: Into<path::PathBuf> + Clone>(path: P) -> io::Result<mzml::MzMLReader<fs::File>> {
     let reader = mzml::MzMLReader::open_path(path)?;
@@ -54,14 +57,20 @@ struct MSDataFileSummary {
     pub charge_table: HashMap<i32, usize>,
     pub peak_charge_table: HashMap<u8, HashMap<i32, usize>>,
     pub peak_mode_table: HashMap<SignalContinuity, usize>,
+    pub qc_metrics: RunQualityMetrics,
 }
 
 impl MSDataFileSummary {
     pub fn handle_scan(&mut self, scan: MultiLayerSpectrum) {
+        self.qc_metrics.observe(&scan);
         let level = scan.ms_level();
         *self.level_table.entry(level).or_default() += 1;
         if level > 1 {
-            if let Some(charge) = scan.precursor().unwrap().ion.charge {
+            if let Some(charge) = scan
+                .precursor()
+                .and_then(|p| p.first_ion())
+                .and_then(|ion| ion.charge)
+            {
                 *self.charge_table.entry(charge).or_default() += 1;
             } else {
                 *self.charge_table.entry(0).or_default() += 1;
@@ -193,25 +202,190 @@ impl MSDataFileSummary {
     }
 }
 
+fn write_mzqc(summarizer: &MSDataFileSummary, path: &path::Path, dest: &path::Path) -> io::Result<()> {
+    let source = if path.as_os_str() == "-" {
+        SourceFile {
+            name: "stdin".to_string(),
+            id: "stdin".to_string(),
+            ..Default::default()
+        }
+    } else {
+        SourceFile::from_path(path)?
+    };
+    let doc = summarizer.qc_metrics.to_mzqc(&source);
+    doc.write_to(io::BufWriter::new(fs::File::create(dest)?))
+}
+
+fn validate_file(path: &path::Path) -> io::Result<()> {
+    let report = if path.as_os_str() == "-" {
+        mzml::validate::validate_reader(io::stdin())?
+    } else {
+        mzml::validate::validate_path(path)?
+    };
+    for issue in report.iter() {
+        println!("{}", issue);
+    }
+    println!(
+        "{} spectra, {} chromatograms: {} errors, {} warnings",
+        report.spectrum_count,
+        report.chromatogram_count,
+        report.errors().count(),
+        report.warnings().count()
+    );
+    if !report.is_valid() {
+        process::exit(1)
+    }
+    Ok(())
+}
+
+fn read_error<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Count the spectra `reader` can read. Their data arrays are decoded just as they are when
+/// copying, since a spectrum with a corrupted array cannot be read either.
+fn count_readable<R>(reader: &mut R) -> io::Result<u64>
+where
+    R: TryScanSource<CentroidPeak, DeconvolutedPeak, MultiLayerSpectrum>,
+{
+    let mut count = 0;
+    while reader.try_next().map_err(read_error)?.is_some() {
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Copy every spectrum `reader` can read to `dest`, writing MGF or mzML depending upon
+/// the extension of `dest`, and return the number of spectra read. An mzML `dest` declares
+/// `spectrum_count` spectra, which should be the number `reader` will read.
+fn write_salvaged<R>(reader: &mut R, spectrum_count: u64, dest: &path::Path) -> io::Result<usize>
+where
+    R: TryScanSource<CentroidPeak, DeconvolutedPeak, MultiLayerSpectrum> + MSDataFileMetadata,
+{
+    let handle = io::BufWriter::new(fs::File::create(dest)?);
+    let mut count = 0;
+    if infer_from_path(dest).0 == MassSpectrometryFormat::MGF {
+        let mut writer = mgf::MGFWriter::new(handle);
+        writer.copy_metadata_from(reader);
+        while let Some(spectrum) = reader.try_next().map_err(read_error)? {
+            writer.write(&spectrum)?;
+            count += 1;
+        }
+        writer.flush()?;
+    } else {
+        let mut writer = mzml::MzMLWriter::new(handle);
+        writer.copy_metadata_from(reader);
+        writer.set_spectrum_count(spectrum_count);
+        while let Some(spectrum) = reader.try_next().map_err(read_error)? {
+            writer.write(&spectrum)?;
+            count += 1;
+        }
+        writer.close()?;
+    }
+    Ok(count)
+}
+
+/// Copy the spectra that can be read from a damaged mzML or MGF file at `path` to `dest`,
+/// skipping past those which cannot be parsed and reporting each region skipped.
+///
+/// The file is read twice, first to count the spectra that can be read so that an mzML
+/// `dest` has the right `spectrumList` count.
+fn salvage_file(path: &path::Path, dest: &path::Path) -> io::Result<()> {
+    let (count, skipped) = match infer_format_compression(path)? {
+        (MassSpectrometryFormat::MGF, CompressionType::None) => {
+            let mut counter = load_mgf_file(path)?;
+            counter.recover = true;
+            let spectrum_count = count_readable(&mut counter)?;
+            let mut reader = load_mgf_file(path)?;
+            reader.recover = true;
+            let count = write_salvaged(&mut reader, spectrum_count, dest)?;
+            let skipped: Vec<_> = reader
+                .skipped_regions
+                .iter()
+                .map(|r| r.to_string())
+                .collect();
+            (count, skipped)
+        }
+        (MassSpectrometryFormat::MzML, CompressionType::None) => {
+            let mut counter = load_file(path)?;
+            counter.recover = true;
+            let spectrum_count = count_readable(&mut counter)?;
+            let mut reader = load_file(path)?;
+            reader.recover = true;
+            let count = write_salvaged(&mut reader, spectrum_count, dest)?;
+            let skipped: Vec<_> = reader
+                .skipped_regions
+                .iter()
+                .map(|r| r.to_string())
+                .collect();
+            (count, skipped)
+        }
+        (format, compression) => {
+            eprintln!("Cannot salvage {format:?} files with {compression} compression, only uncompressed mzML and MGF");
+            process::exit(1)
+        }
+    };
+    for region in skipped.iter() {
+        println!("{}", region);
+    }
+    println!(
+        "{count} spectra salvaged, {} regions skipped",
+        skipped.len()
+    );
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
-    let path = path::PathBuf::from(
-        env::args()
-            .nth(1)
-            .unwrap_or_else(|| {
-                eprintln!("Please provide a path to an MS data file");
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(|arg| arg == "salvage").unwrap_or_default() {
+        args.next();
+        let (path, dest) = match (args.next(), args.next()) {
+            (Some(path), Some(dest)) => (path, dest),
+            _ => {
+                eprintln!("Please provide the path to a damaged MS data file and an output path");
                 process::exit(1)
-            }),
-    );
+            }
+        };
+        return salvage_file(path::Path::new(&path), path::Path::new(&dest));
+    }
+    if args.peek().map(|arg| arg == "validate").unwrap_or_default() {
+        args.next();
+        let path = args.next().unwrap_or_else(|| {
+            eprintln!("Please provide a path to an mzML file to validate");
+            process::exit(1)
+        });
+        return validate_file(path::Path::new(&path));
+    }
+    let mut path = None;
+    let mut mzqc_path = None;
+    while let Some(arg) = args.next() {
+        if arg == "--mzqc" {
+            mzqc_path = Some(path::PathBuf::from(args.next().unwrap_or_else(|| {
+                eprintln!("--mzqc requires an output path");
+                process::exit(1)
+            })));
+        } else if path.is_none() {
+            path = Some(path::PathBuf::from(arg));
+        } else {
+            eprintln!("Unexpected argument {}", arg);
+            process::exit(1)
+        }
+    }
+    let path = path.unwrap_or_else(|| {
+        eprintln!("Please provide a path to an MS data file");
+        process::exit(1)
+    });
+    let source_path = path.clone();
     let mut summarizer = MSDataFileSummary::default();
 
     if path.as_os_str() == "-" {
         let mut stream = PreBufferedStream::new(io::stdin())?;
-        match infer_from_stream(&mut stream)? {
-            (MassSpectrometryFormat::MGF, false) => {
+        match infer_from_stream_compression(&mut stream)? {
+            (MassSpectrometryFormat::MGF, CompressionType::None) => {
                 let reader = mgf::MGFReader::new(io::BufReader::new(stream));
                 summarizer.scan_file(reader)
             },
-            (MassSpectrometryFormat::MzML, false) => {
+            (MassSpectrometryFormat::MzML, CompressionType::None) => {
                 let reader = mzml::MzMLReader::new(stream);
                 summarizer.scan_file(reader)
             },
@@ -220,12 +394,34 @@ fn main() -> io::Result<()> {
                 eprintln!("Cannot read mzMLb files from STDIN");
                 process::exit(1);
             },
+            (_, compression) if compression.is_compressed() => {
+                eprintln!("Cannot read {compression} compressed data from STDIN");
+                process::exit(1);
+            },
             (_, _) => {
                 eprintln!("Could not infer format from STDIN");
                 process::exit(1);
             },
         }
     }
+    else if infer_from_path(&path).1 {
+        let (format, _) = infer_format(&path)?;
+        let handle = SeekableDecompressor::open_path(path)?;
+        match format {
+            MassSpectrometryFormat::MGF => {
+                let reader = mgf::MGFReader::new_indexed(handle);
+                summarizer.scan_file(reader)
+            },
+            MassSpectrometryFormat::MzML => {
+                let reader = mzml::MzMLReader::new_indexed(handle);
+                summarizer.scan_file(reader)
+            },
+            _ => {
+                eprintln!("Could not infer the format of the compressed file");
+                process::exit(1);
+            },
+        }
+    }
     else if let Some(ext) = path.extension() {
         if ext.to_string_lossy().to_lowercase() == "mzmlb" {
             #[cfg(feature = "mzmlb")]
@@ -250,5 +446,48 @@ fn main() -> io::Result<()> {
     };
 
     summarizer.write_out();
+    if let Some(dest) = mzqc_path {
+        write_mzqc(&summarizer, &source_path, &dest)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_salvage_file() -> io::Result<()> {
+        let path = path::Path::new("./test/data/three_test_scans.mzML");
+        let index = load_file(path)?.get_index().clone();
+        let (_, offset) = index.get_index(1).unwrap();
+
+        // Corrupt the first data array of the second spectrum
+        let mut content = fs::read(path)?;
+        let binary = offset as usize
+            + content[offset as usize..]
+                .windows(8)
+                .position(|w| w == b"<binary>")
+                .unwrap()
+            + 8;
+        content[binary..binary + 8].copy_from_slice(b"!!!!!!!!");
+
+        let tmpdir = tempfile::tempdir()?;
+        let damaged = tmpdir.path().join("damaged.mzML");
+        fs::write(&damaged, content)?;
+        let dest = tmpdir.path().join("salvaged.mzML");
+        salvage_file(&damaged, &dest)?;
+
+        let reader = load_file(&dest)?;
+        assert_eq!(reader.spectrum_count_hint(), Some(2));
+        let ids: Vec<_> = reader.map(|s| s.id().to_string()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                index.get_index(0).unwrap().0.to_string(),
+                index.get_index(2).unwrap().0.to_string()
+            ]
+        );
+        Ok(())
+    }
+}
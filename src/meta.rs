@@ -6,12 +6,21 @@ mod data_processing;
 mod instrument;
 mod software;
 mod run;
+mod sample;
+mod scan_settings;
+mod cv_list;
 #[macro_use]
 mod traits;
 
 pub use crate::meta::data_processing::{DataProcessing, ProcessingMethod};
 pub use crate::meta::file_description::{FileDescription, SourceFile};
-pub use crate::meta::instrument::{Component, ComponentType, InstrumentConfiguration};
+pub use crate::meta::instrument::{
+    Component, ComponentType, DetectorType, InstrumentConfiguration, InstrumentVendor,
+    IonizationType, MassAnalyzer,
+};
 pub use crate::meta::software::Software;
 pub use crate::meta::traits::MSDataFileMetadata;
 pub use run::MassSpectrometryRun;
+pub use sample::Sample;
+pub use scan_settings::{ScanSettings, ScanTarget};
+pub use cv_list::CVEntry;
@@ -0,0 +1,21 @@
+/// A controlled vocabulary declared in a file's `<cvList>`, which the file's
+/// parameters refer to by `id`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CVEntry {
+    pub id: String,
+    pub full_name: String,
+    pub uri: String,
+    pub version: Option<String>,
+}
+
+impl CVEntry {
+    pub fn new(id: String, full_name: String, uri: String, version: Option<String>) -> Self {
+        Self {
+            id,
+            full_name,
+            uri,
+            version,
+        }
+    }
+}
@@ -5,6 +5,7 @@ use crate::impl_param_described;
 use crate::params::{ParamList, Param, ParamCow, ControlledVocabulary};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProcessingMethod {
     pub order: i8,
     pub software_reference: String,
@@ -12,6 +13,7 @@ pub struct ProcessingMethod {
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataProcessing {
     pub id: String,
     pub methods: Vec<ProcessingMethod>,
@@ -39,6 +41,7 @@ impl DataProcessing {
 
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DataTransformationAction {
     FormatConversion(FormatConversion),
     DataProcessingAction(DataProcessingAction),
@@ -52,6 +55,7 @@ impl Display for DataTransformationAction {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DataProcessingAction {
     Deisotoping,
     ChargeDeconvolution,
@@ -113,6 +117,7 @@ impl DataProcessingAction {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FormatConversion {
     ConversionToMzML,
     ConversionToMzMLb,
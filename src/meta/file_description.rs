@@ -2,6 +2,7 @@ use crate::impl_param_described;
 use crate::params::{Param, ParamDescribed, ParamList};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SourceFile {
     pub name: String,
     pub location: String,
@@ -12,6 +13,7 @@ pub struct SourceFile {
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileDescription {
     pub contents: ParamList,
     pub source_files: Vec<SourceFile>,
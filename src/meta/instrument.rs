@@ -1,10 +1,195 @@
 use std::fmt::Display;
 
 use crate::impl_param_described;
-use crate::params::ParamList;
+use crate::params::{ControlledVocabulary, Param, ParamDescribed, ParamLike, ParamList, Unit};
+
+/// Define an enum whose variants each correspond to a term of the PSI-MS controlled
+/// vocabulary, along with the conversions to and from those terms
+macro_rules! cv_term_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident => ($accession:literal, $term:literal)
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)+
+        }
+
+        impl $name {
+            pub fn from_accession(
+                controlled_vocabulary: ControlledVocabulary,
+                accession: u32,
+            ) -> Option<Self> {
+                match controlled_vocabulary {
+                    ControlledVocabulary::MS => match accession {
+                        $($accession => Some(Self::$variant),)+
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+
+            /// Find the term `param` refers to, if it is one of these
+            pub fn from_param<P: ParamLike>(param: &P) -> Option<Self> {
+                Self::from_accession(param.controlled_vocabulary()?, param.accession()?)
+            }
+
+            pub fn name(&self) -> &str {
+                match self {
+                    $(Self::$variant => $term,)+
+                }
+            }
+
+            pub fn accession(&self) -> u32 {
+                match self {
+                    $(Self::$variant => $accession,)+
+                }
+            }
+
+            pub fn to_param(&self) -> Param {
+                Param {
+                    name: self.name().to_string(),
+                    value: String::default(),
+                    accession: Some(self.accession()),
+                    controlled_vocabulary: Some(ControlledVocabulary::MS),
+                    unit: Unit::Unknown,
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.name())
+            }
+        }
+    };
+}
+
+cv_term_enum!(
+    /// The kinds of mass analyzer, children of MS:1000443 "mass analyzer type"
+    MassAnalyzer {
+        Orbitrap => (1000484, "orbitrap"),
+        TimeOfFlight => (1000084, "time-of-flight"),
+        Quadrupole => (1000081, "quadrupole"),
+        IonTrap => (1000264, "ion trap"),
+        QuadrupoleIonTrap => (1000082, "quadrupole ion trap"),
+        LinearIonTrap => (1000291, "linear ion trap"),
+        RadialEjectionLinearIonTrap => (1000083, "radial ejection linear ion trap"),
+        AxialEjectionLinearIonTrap => (1000078, "axial ejection linear ion trap"),
+        FourierTransformIonCyclotronResonance =>
+            (1000079, "fourier transform ion cyclotron resonance mass spectrometer"),
+        MagneticSector => (1000080, "magnetic sector"),
+        ElectrostaticEnergyAnalyzer => (1000254, "electrostatic energy analyzer"),
+    }
+);
+
+impl MassAnalyzer {
+    /// Whether this is any kind of ion trap
+    pub fn is_ion_trap(&self) -> bool {
+        matches!(
+            self,
+            Self::IonTrap
+                | Self::QuadrupoleIonTrap
+                | Self::LinearIonTrap
+                | Self::RadialEjectionLinearIonTrap
+                | Self::AxialEjectionLinearIonTrap
+        )
+    }
+
+    /// Whether this analyzer is typically operated at high resolution
+    pub fn is_high_resolution(&self) -> bool {
+        matches!(
+            self,
+            Self::Orbitrap | Self::TimeOfFlight | Self::FourierTransformIonCyclotronResonance
+        )
+    }
+}
+
+cv_term_enum!(
+    /// The kinds of ionization, children of MS:1000008 "ionization type"
+    IonizationType {
+        ElectrosprayIonization => (1000073, "electrospray ionization"),
+        Nanoelectrospray => (1000398, "nanoelectrospray"),
+        MatrixAssistedLaserDesorptionIonization =>
+            (1000075, "matrix-assisted laser desorption ionization"),
+        AtmosphericPressureChemicalIonization =>
+            (1000070, "atmospheric pressure chemical ionization"),
+        AtmosphericPressurePhotoionization => (1000382, "atmospheric pressure photoionization"),
+        ElectronIonization => (1000389, "electron ionization"),
+        ChemicalIonization => (1000071, "chemical ionization"),
+    }
+);
+
+cv_term_enum!(
+    /// The kinds of detector, children of MS:1000026 "detector type"
+    DetectorType {
+        InductiveDetector => (1000624, "inductive detector"),
+        ElectronMultiplier => (1000253, "electron multiplier"),
+        ConversionDynodeElectronMultiplier => (1000108, "conversion dynode electron multiplier"),
+        MicrochannelPlateDetector => (1000114, "microchannel plate detector"),
+        Photomultiplier => (1000116, "photomultiplier"),
+        FaradayCup => (1000112, "faraday cup"),
+    }
+);
+
+/// The manufacturer of an instrument
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InstrumentVendor {
+    ThermoFisher,
+    Bruker,
+    Sciex,
+    Waters,
+    Agilent,
+    Shimadzu,
+}
+
+impl Display for InstrumentVendor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl InstrumentVendor {
+    /// Identify the vendor of an "instrument model" term. This recognizes the vendor-level
+    /// model terms and a selection of common specific models.
+    pub fn from_model_accession(
+        controlled_vocabulary: ControlledVocabulary,
+        accession: u32,
+    ) -> Option<Self> {
+        if controlled_vocabulary != ControlledVocabulary::MS {
+            return None;
+        }
+        match accession {
+            // Vendor-level terms for Thermo Fisher Scientific, Thermo Electron,
+            // Thermo Scientific and Thermo Finnigan
+            1000483 | 1000492 | 1000494 | 1000125 => Some(Self::ThermoFisher),
+            // LTQ, LTQ FT, LTQ Orbitrap, LTQ Orbitrap XL, LTQ Orbitrap Velos
+            1000447 | 1000448 | 1000449 | 1000556 | 1001742 => Some(Self::ThermoFisher),
+            // Q Exactive, Q Exactive Plus, Q Exactive HF
+            1001911 | 1002634 | 1002523 => Some(Self::ThermoFisher),
+            // Orbitrap Fusion, Orbitrap Fusion Lumos, Orbitrap Exploris 480
+            1002416 | 1002732 | 1003028 => Some(Self::ThermoFisher),
+            1000122 => Some(Self::Bruker),
+            1000121 => Some(Self::Sciex),
+            1000126 => Some(Self::Waters),
+            1000490 => Some(Self::Agilent),
+            1000124 => Some(Self::Shimadzu),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComponentType {
     Analyzer,
     IonSource,
@@ -20,13 +205,32 @@ impl Display for ComponentType {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Component {
     pub component_type: ComponentType,
     pub order: u8,
     pub params: ParamList,
 }
 
+impl Component {
+    /// The kind of mass analyzer this component is, if it is a recognized analyzer
+    pub fn mass_analyzer(&self) -> Option<MassAnalyzer> {
+        self.params.iter().find_map(MassAnalyzer::from_param)
+    }
+
+    /// The kind of ionization this component performs, if it is a recognized source
+    pub fn ionization_type(&self) -> Option<IonizationType> {
+        self.params.iter().find_map(IonizationType::from_param)
+    }
+
+    /// The kind of detector this component is, if it is a recognized detector
+    pub fn detector_type(&self) -> Option<DetectorType> {
+        self.params.iter().find_map(DetectorType::from_param)
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InstrumentConfiguration {
     pub components: Vec<Component>,
     pub params: ParamList,
@@ -34,4 +238,56 @@ pub struct InstrumentConfiguration {
     pub id: u32,
 }
 
+/// The "instrument serial number" term, which sits alongside the model term
+const INSTRUMENT_SERIAL_NUMBER: u32 = 1000529;
+
+impl InstrumentConfiguration {
+    /// The components of a particular type, in order
+    pub fn components_of(
+        &self,
+        component_type: ComponentType,
+    ) -> impl Iterator<Item = &Component> + '_ {
+        self.components
+            .iter()
+            .filter(move |c| c.component_type == component_type)
+    }
+
+    /// The recognized mass analyzers of this configuration, in order
+    pub fn analyzers(&self) -> impl Iterator<Item = MassAnalyzer> + '_ {
+        self.components_of(ComponentType::Analyzer)
+            .filter_map(|c| c.mass_analyzer())
+    }
+
+    /// The recognized ionization types of this configuration's sources
+    pub fn ionization_types(&self) -> impl Iterator<Item = IonizationType> + '_ {
+        self.components_of(ComponentType::IonSource)
+            .filter_map(|c| c.ionization_type())
+    }
+
+    /// The recognized detector types of this configuration
+    pub fn detectors(&self) -> impl Iterator<Item = DetectorType> + '_ {
+        self.components_of(ComponentType::Detector)
+            .filter_map(|c| c.detector_type())
+    }
+
+    /// Whether any analyzer of this configuration is of the given kind
+    pub fn has_analyzer(&self, analyzer: MassAnalyzer) -> bool {
+        self.analyzers().any(|a| a == analyzer)
+    }
+
+    /// The "instrument model" term of this configuration. The model is taken to be the first
+    /// PSI-MS term that is not the instrument serial number, as writers like msconvert place it.
+    pub fn model(&self) -> Option<&Param> {
+        self.params()
+            .iter()
+            .find(|p| p.is_ms() && p.accession != Some(INSTRUMENT_SERIAL_NUMBER))
+    }
+
+    /// The manufacturer of the instrument, if its model is recognized
+    pub fn vendor(&self) -> Option<InstrumentVendor> {
+        let model = self.model()?;
+        InstrumentVendor::from_model_accession(model.controlled_vocabulary?, model.accession?)
+    }
+}
+
 impl_param_described!(InstrumentConfiguration, Component);
@@ -1,4 +1,5 @@
 #[derive(Debug, Default, PartialEq, Hash, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MassSpectrometryRun {
     pub id: Option<String>,
     pub default_data_processing_id: Option<String>,
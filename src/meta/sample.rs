@@ -0,0 +1,19 @@
+use crate::impl_param_described;
+use crate::params::ParamList;
+
+/// A sample that was analyzed to produce the data in a file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    pub id: String,
+    pub name: Option<String>,
+    pub params: ParamList,
+}
+
+impl Sample {
+    pub fn new(id: String, name: Option<String>, params: ParamList) -> Self {
+        Self { id, name, params }
+    }
+}
+
+impl_param_described!(Sample);
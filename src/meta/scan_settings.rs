@@ -0,0 +1,38 @@
+use crate::impl_param_described;
+use crate::params::ParamList;
+
+/// An ion the instrument was directed to acquire, such as an entry of an
+/// inclusion list for parallel reaction monitoring
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanTarget {
+    pub params: ParamList,
+}
+
+impl ScanTarget {
+    pub fn new(params: ParamList) -> Self {
+        Self { params }
+    }
+}
+
+/// The acquisition settings an instrument was configured with prior to a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanSettings {
+    pub id: String,
+    /// The IDs of the [`SourceFile`](crate::meta::SourceFile)s these settings were read from
+    pub source_file_refs: Vec<String>,
+    pub targets: Vec<ScanTarget>,
+    pub params: ParamList,
+}
+
+impl ScanSettings {
+    pub fn new(id: String) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+}
+
+impl_param_described!(ScanTarget, ScanSettings);
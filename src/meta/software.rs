@@ -2,6 +2,7 @@ use crate::impl_param_described;
 use crate::params::ParamList;
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Software {
     pub id: String,
     pub version: String,
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 
-use super::{DataProcessing, FileDescription, InstrumentConfiguration, Software};
+use super::{
+    CVEntry, DataProcessing, FileDescription, InstrumentConfiguration, Sample, ScanSettings,
+    Software, SourceFile,
+};
+use crate::params::ParamGroup;
 
 pub trait MSDataFileMetadata {
     fn data_processings(&self) -> &Vec<DataProcessing>;
@@ -20,11 +24,83 @@ pub trait MSDataFileMetadata {
         *self.instrument_configurations_mut() = source.instrument_configurations().clone();
         *self.file_description_mut() = source.file_description().clone();
         *self.softwares_mut() = source.softwares().clone();
+        if let (Some(groups), Some(dest)) = (
+            source.reference_param_groups(),
+            self.reference_param_groups_mut(),
+        ) {
+            *dest = groups.clone();
+        }
+        if let (Some(samples), Some(dest)) = (source.samples(), self.samples_mut()) {
+            *dest = samples.clone();
+        }
+        if let (Some(scan_settings), Some(dest)) =
+            (source.scan_settings(), self.scan_settings_mut())
+        {
+            *dest = scan_settings.clone();
+        }
+        if let (Some(cv_list), Some(dest)) = (source.cv_list(), self.cv_list_mut()) {
+            *dest = cv_list.clone();
+        }
     }
 
     fn spectrum_count_hint(&self) -> Option<u64> {
         None
     }
+
+    /// Find the [`DataProcessing`] with the ID `id`, such as the one named by
+    /// [`SpectrumDescription::data_processing_id`](crate::spectrum::SpectrumDescription::data_processing_id)
+    /// or [`DataArray::data_processing_id`](crate::spectrum::DataArray::data_processing_id)
+    fn data_processing_by_id(&self, id: &str) -> Option<&DataProcessing> {
+        self.data_processings().iter().find(|dp| dp.id == id)
+    }
+
+    /// Find the [`SourceFile`] with the ID `id`, such as the one named by
+    /// [`ScanEvent::source_file_id`](crate::spectrum::ScanEvent::source_file_id)
+    /// or [`Precursor::source_file_id`](crate::spectrum::Precursor::source_file_id)
+    fn source_file_by_id(&self, id: &str) -> Option<&SourceFile> {
+        self.file_description()
+            .source_files
+            .iter()
+            .find(|sf| sf.id == id)
+    }
+
+    /// The named param groups shared between elements of the file, for formats
+    /// which support them
+    fn reference_param_groups(&self) -> Option<&Vec<ParamGroup>> {
+        None
+    }
+
+    fn reference_param_groups_mut(&mut self) -> Option<&mut Vec<ParamGroup>> {
+        None
+    }
+
+    /// The samples the file's data were acquired from, for formats which describe them
+    fn samples(&self) -> Option<&Vec<Sample>> {
+        None
+    }
+
+    fn samples_mut(&mut self) -> Option<&mut Vec<Sample>> {
+        None
+    }
+
+    /// The instrument's acquisition settings, including any target lists, for formats
+    /// which describe them
+    fn scan_settings(&self) -> Option<&Vec<ScanSettings>> {
+        None
+    }
+
+    fn scan_settings_mut(&mut self) -> Option<&mut Vec<ScanSettings>> {
+        None
+    }
+
+    /// The controlled vocabularies, and their versions, the file declares
+    fn cv_list(&self) -> Option<&Vec<CVEntry>> {
+        None
+    }
+
+    fn cv_list_mut(&mut self) -> Option<&mut Vec<CVEntry>> {
+        None
+    }
 }
 
 #[macro_export]
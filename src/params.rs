@@ -73,6 +73,7 @@ pub trait ParamLike {
 
 /// A statically allocate-able or non-owned data version of [`Param`]
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParamCow<'a> {
     pub name: Cow<'a, str>,
     pub value: Cow<'a, str>,
@@ -161,6 +162,7 @@ impl<'a> From<ParamCow<'a>> for Param {
 
 /// A controlled vocabulary or user parameter
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Param {
     pub name: String,
     pub value: String,
@@ -261,6 +263,7 @@ impl ParamLike for Param {
 
 /// Controlled vocabularies used in mass spectrometry data files
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ControlledVocabulary {
     MS,
     UO,
@@ -422,6 +425,23 @@ macro_rules! impl_param_described {
 #[doc(hidden)]
 pub const _EMPTY_PARAM: &[Param] = &[];
 
+/// A named, reusable collection of parameters, corresponding to mzML's
+/// `<referenceableParamGroup>`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamGroup {
+    pub id: String,
+    pub params: ParamList,
+}
+
+impl ParamGroup {
+    pub fn new(id: String, params: ParamList) -> Self {
+        Self { id, params }
+    }
+}
+
+impl_param_described!(ParamGroup);
+
 #[macro_export]
 macro_rules! impl_param_described_deferred {
     ($($t:ty), +) => {$(
@@ -450,6 +470,7 @@ macro_rules! impl_param_described_deferred {
 
 /// Units that a term's value might have
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Unit {
     // Mass
     MZ,
@@ -496,6 +517,7 @@ impl Unit {
 
             Self::Electronvolt => ("UO:0000266", "electronvolt"),
             Self::PercentElectronVolt => ("UO:0000187", "percent"),
+            Self::Volt => ("UO:0000218", "volt"),
 
             _ => ("", ""),
         }
@@ -518,6 +540,7 @@ impl Unit {
 
             "electronvolt" => Self::Electronvolt,
             "percent" => Self::PercentElectronVolt,
+            "volt" => Self::Volt,
             _ => Unit::Unknown,
         }
     }
@@ -539,6 +562,7 @@ impl Unit {
 
             "UO:0000266" => Self::Electronvolt,
             "UO:0000187" => Self::PercentElectronVolt,
+            "UO:0000218" => Self::Volt,
             _ => Unit::Unknown,
         }
     }
@@ -1,7 +1,7 @@
 //! A set of foundational traits used throughout the library.
 pub use crate::io::traits::{
     MZFileReader, RandomAccessSpectrumIterator, SpectrumAccessError, ScanSource, ScanWriter, SeekRead,
-    SpectrumGrouping, SpectrumIterator,
+    SpectrumGrouping, SpectrumIterator, TryScanSource,
 };
 
 pub use crate::meta::MSDataFileMetadata;
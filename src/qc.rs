@@ -0,0 +1,740 @@
+/*!
+Compute run-level quality control metrics over a stream of spectra and report them as
+a [HUPO-PSI mzQC](https://hupo-psi.github.io/mzQC/) document.
+
+[`RunQualityMetrics`] accumulates per-spectrum observations incrementally, so it can be fed
+from any [`ScanSource`](crate::io::ScanSource) without holding spectra in memory. Once all
+spectra have been seen, [`RunQualityMetrics::to_mzqc`] produces an [`MzQC`] document tied to
+the [`SourceFile`] the metrics were computed from, which can be written out with
+[`MzQC::write_to`].
+
+# Example
+```no_run
+use std::fs;
+use mzdata::prelude::*;
+use mzdata::io::MzMLReader;
+use mzdata::meta::SourceFile;
+use mzdata::qc::RunQualityMetrics;
+
+let reader = MzMLReader::open_path("./test/data/small.mzML").unwrap();
+let mut metrics = RunQualityMetrics::default();
+for spectrum in reader {
+    metrics.observe(&spectrum);
+}
+println!("{} MS1 spectra", metrics.ms1_count());
+let source = SourceFile::from_path("./test/data/small.mzML").unwrap();
+let doc = metrics.to_mzqc(&source);
+doc.write_to(fs::File::create("small.mzQC.json").unwrap()).unwrap();
+```
+*/
+use std::collections::HashMap;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::meta::SourceFile;
+use crate::params::{ControlledVocabulary, Param, ParamDescribed, ParamLike};
+use crate::spectrum::{PrecursorSelection, SpectrumLike};
+
+/// The version of the mzQC schema written by this module
+pub const MZQC_VERSION: &str = "1.0.0";
+
+const PSIMS_VERSION: &str = "4.1.135";
+
+/// A PSI-MS QC metric term as `(accession, name)`
+type QCTerm = (&'static str, &'static str);
+
+const MS1_COUNT_TERM: QCTerm = ("MS:4000059", "number of MS1 spectra");
+const MS2_COUNT_TERM: QCTerm = ("MS:4000060", "number of MS2 spectra");
+const CHROMATOGRAPHY_DURATION_TERM: QCTerm = ("MS:4000053", "chromatography duration");
+const RT_RANGE_TERM: QCTerm = ("MS:4000070", "retention time acquisition range");
+const MZ_RANGE_TERM: QCTerm = ("MS:4000069", "m/z acquisition range");
+const AREA_UNDER_TIC_TERM: QCTerm = ("MS:4000029", "area under TIC");
+const AREA_UNDER_TIC_QUANTILES_TERM: QCTerm = ("MS:4000030", "area under TIC RT quantiles");
+const PRECURSOR_CHARGE_FRACTIONS_TERM: QCTerm =
+    ("MS:4000063", "MS2 known precursor charges fractions");
+const FASTEST_MS1_FREQUENCY_TERM: QCTerm =
+    ("MS:4000065", "fastest frequency for MS level 1 collection");
+const FASTEST_MS2_FREQUENCY_TERM: QCTerm =
+    ("MS:4000066", "fastest frequency for MS level 2 collection");
+const SLOWEST_MS1_FREQUENCY_TERM: QCTerm =
+    ("MS:4000067", "slowest frequency for MS level 1 collection");
+const SLOWEST_MS2_FREQUENCY_TERM: QCTerm =
+    ("MS:4000068", "slowest frequency for MS level 2 collection");
+const MS1_INJECTION_TIME_QUANTILES_TERM: QCTerm =
+    ("MS:4000117", "MS1 ion injection time quantiles");
+const MS2_INJECTION_TIME_QUANTILES_TERM: QCTerm =
+    ("MS:4000118", "MS2 ion injection time quantiles");
+
+const SECOND_UNIT: QCTerm = ("UO:0000010", "second");
+const MILLISECOND_UNIT: QCTerm = ("UO:0000028", "millisecond");
+const HERTZ_UNIT: QCTerm = ("UO:0000106", "hertz");
+const MZ_UNIT: QCTerm = ("MS:1000040", "m/z");
+
+/// Order statistics describing a distribution of values
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct SummaryStatistics {
+    pub count: usize,
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl SummaryStatistics {
+    /// Compute the summary statistics of `values`, returning `None` if there are no
+    /// finite values to summarize.
+    pub fn from_values<I: IntoIterator<Item = f64>>(values: I) -> Option<Self> {
+        let mut values: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let count = values.len();
+        let mean = values.iter().sum::<f64>() / count as f64;
+        Some(Self {
+            count,
+            min: values[0],
+            q1: quantile_of_sorted(&values, 0.25),
+            median: quantile_of_sorted(&values, 0.5),
+            q3: quantile_of_sorted(&values, 0.75),
+            max: values[count - 1],
+            mean,
+        })
+    }
+
+    /// The quartiles of the distribution, in ascending order
+    pub fn quartiles(&self) -> [f64; 3] {
+        [self.q1, self.median, self.q3]
+    }
+}
+
+/// Linearly interpolated quantile of an already sorted, non-empty slice
+fn quantile_of_sorted(values: &[f64], q: f64) -> f64 {
+    let pos = (values.len() - 1) as f64 * q;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    if lo == hi {
+        values[lo]
+    } else {
+        let frac = pos - lo as f64;
+        values[lo] + (values[hi] - values[lo]) * frac
+    }
+}
+
+/// Accumulates run-level quality control metrics over a sequence of spectra.
+///
+/// All times are recorded as they are stored on [`SpectrumLike`], in minutes, and
+/// are converted to seconds when reported, as mzQC requires.
+#[derive(Debug, Clone, Default)]
+pub struct RunQualityMetrics {
+    /// The number of spectra observed at each MS level
+    pub level_counts: HashMap<u8, usize>,
+    /// The number of MSn spectra whose precursor reported each charge state. Precursors
+    /// without a reported charge state are not counted.
+    pub precursor_charges: HashMap<i32, usize>,
+    ms1_tic: Vec<(f64, f64)>,
+    acquisition_times: HashMap<u8, Vec<f64>>,
+    injection_times: HashMap<u8, Vec<f64>>,
+    first_time: Option<f64>,
+    last_time: Option<f64>,
+    mz_range: Option<(f64, f64)>,
+}
+
+impl RunQualityMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the properties of a single spectrum. Spectra are expected to be observed
+    /// in acquisition order.
+    pub fn observe<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    >(
+        &mut self,
+        spectrum: &S,
+    ) {
+        // Formats like MGF may not record an MS level, but a spectrum with a precursor is MSn
+        let level = match spectrum.ms_level() {
+            0 if spectrum.precursor().is_some() => 2,
+            level => level,
+        };
+        let time = spectrum.start_time();
+        *self.level_counts.entry(level).or_default() += 1;
+
+        self.first_time = Some(self.first_time.map_or(time, |t| t.min(time)));
+        self.last_time = Some(self.last_time.map_or(time, |t| t.max(time)));
+        self.acquisition_times.entry(level).or_default().push(time);
+
+        if level == 1 {
+            self.ms1_tic.push((time, spectrum.peaks().tic() as f64));
+        } else if let Some(precursor) = spectrum.precursor() {
+            if let Some(charge) = precursor.first_ion().and_then(|ion| ion.charge) {
+                *self.precursor_charges.entry(charge).or_default() += 1;
+            }
+        }
+
+        for scan in spectrum.acquisition().scans.iter() {
+            if scan.injection_time > 0.0 {
+                self.injection_times
+                    .entry(level)
+                    .or_default()
+                    .push(scan.injection_time as f64);
+            }
+            for window in scan.scan_windows.iter() {
+                let (lo, hi) = (window.lower_bound as f64, window.upper_bound as f64);
+                if lo == 0.0 && hi == 0.0 {
+                    continue;
+                }
+                self.mz_range = Some(match self.mz_range {
+                    Some((a, b)) => (a.min(lo), b.max(hi)),
+                    None => (lo, hi),
+                });
+            }
+        }
+    }
+
+    /// Record the properties of every spectrum produced by `iter`
+    pub fn observe_all<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        I: Iterator<Item = S>,
+    >(
+        &mut self,
+        iter: I,
+    ) {
+        for spectrum in iter {
+            self.observe(&spectrum);
+        }
+    }
+
+    /// The total number of spectra observed
+    pub fn spectrum_count(&self) -> usize {
+        self.level_counts.values().sum()
+    }
+
+    /// The number of spectra observed at `level`
+    pub fn count_at_level(&self, level: u8) -> usize {
+        self.level_counts.get(&level).copied().unwrap_or_default()
+    }
+
+    pub fn ms1_count(&self) -> usize {
+        self.count_at_level(1)
+    }
+
+    pub fn ms2_count(&self) -> usize {
+        self.count_at_level(2)
+    }
+
+    /// The first and last acquisition times observed, in minutes
+    pub fn retention_time_range(&self) -> Option<(f64, f64)> {
+        Some((self.first_time?, self.last_time?))
+    }
+
+    /// The duration spanned by the observed spectra, in minutes
+    pub fn retention_time_span(&self) -> Option<f64> {
+        self.retention_time_range().map(|(a, b)| b - a)
+    }
+
+    /// The union of all scan windows observed
+    pub fn mz_acquisition_range(&self) -> Option<(f64, f64)> {
+        self.mz_range
+    }
+
+    /// The distribution of the total ion current of MS1 spectra
+    pub fn tic_statistics(&self) -> Option<SummaryStatistics> {
+        SummaryStatistics::from_values(self.ms1_tic.iter().map(|(_, tic)| *tic))
+    }
+
+    /// The area under the MS1 total ion current chromatogram, integrated with the
+    /// trapezoid rule over time in seconds.
+    pub fn area_under_tic(&self) -> f64 {
+        self.cumulative_tic_area().last().copied().unwrap_or_default()
+    }
+
+    fn cumulative_tic_area(&self) -> Vec<f64> {
+        let mut acc = 0.0;
+        let mut areas = Vec::with_capacity(self.ms1_tic.len());
+        areas.push(0.0);
+        for pair in self.ms1_tic.windows(2) {
+            let (t0, y0) = pair[0];
+            let (t1, y1) = pair[1];
+            acc += (t1 - t0) * 60.0 * (y0 + y1) / 2.0;
+            areas.push(acc);
+        }
+        areas
+    }
+
+    /// The area under the MS1 TIC up to `time`, interpolating linearly within the
+    /// segment `time` falls in
+    fn tic_area_until(&self, areas: &[f64], time: f64) -> f64 {
+        let idx = self.ms1_tic.partition_point(|(t, _)| *t <= time);
+        if idx == 0 {
+            return 0.0;
+        }
+        if idx == self.ms1_tic.len() {
+            return areas[idx - 1];
+        }
+        let (t0, y0) = self.ms1_tic[idx - 1];
+        let (t1, y1) = self.ms1_tic[idx];
+        let y = y0 + (y1 - y0) * (time - t0) / (t1 - t0);
+        areas[idx - 1] + (time - t0) * 60.0 * (y0 + y) / 2.0
+    }
+
+    /// The area under the MS1 TIC within each quarter of the MS1 TIC's retention time range
+    pub fn area_under_tic_quantiles(&self) -> Option<[f64; 4]> {
+        let start = self.ms1_tic.first()?.0;
+        let end = self.ms1_tic.last()?.0;
+        if end <= start {
+            return None;
+        }
+        let areas = self.cumulative_tic_area();
+        let mut quantiles = [0.0; 4];
+        let mut previous = 0.0;
+        for (i, q) in quantiles.iter_mut().enumerate() {
+            let cutoff = start + (end - start) * (i + 1) as f64 / 4.0;
+            let area = self.tic_area_until(&areas, cutoff);
+            *q = area - previous;
+            previous = area;
+        }
+        Some(quantiles)
+    }
+
+    /// The distribution of time elapsed between consecutive spectra of `level`, in seconds.
+    ///
+    /// For MS1 spectra this is the instrument's duty cycle time.
+    pub fn cycle_time_statistics(&self, level: u8) -> Option<SummaryStatistics> {
+        let times = self.acquisition_times.get(&level)?;
+        SummaryStatistics::from_values(times.windows(2).map(|w| (w[1] - w[0]) * 60.0))
+    }
+
+    /// The distribution of ion injection times for spectra of `level`, in milliseconds.
+    /// Spectra which do not report an injection time are skipped.
+    pub fn injection_time_statistics(&self, level: u8) -> Option<SummaryStatistics> {
+        let times = self.injection_times.get(&level)?;
+        SummaryStatistics::from_values(times.iter().copied())
+    }
+
+    /// The fraction of MSn spectra whose precursor has each charge state, sorted by
+    /// charge. Precursors without a known charge state are left out.
+    pub fn precursor_charge_fractions(&self) -> Vec<(i32, f64)> {
+        let total: usize = self.precursor_charges.values().sum();
+        let mut fractions: Vec<(i32, f64)> = self
+            .precursor_charges
+            .iter()
+            .map(|(z, c)| (*z, *c as f64 / total as f64))
+            .collect();
+        fractions.sort_by_key(|(z, _)| *z);
+        fractions
+    }
+
+    /// Build the list of mzQC quality metrics describing this run
+    pub fn quality_metrics(&self) -> Vec<QualityMetric> {
+        let mut metrics = vec![
+            QualityMetric::new(MS1_COUNT_TERM, json!(self.ms1_count())),
+            QualityMetric::new(MS2_COUNT_TERM, json!(self.ms2_count())),
+        ];
+
+        if let Some((start, end)) = self.retention_time_range() {
+            metrics.push(
+                QualityMetric::new(CHROMATOGRAPHY_DURATION_TERM, json!((end - start) * 60.0))
+                    .with_unit(SECOND_UNIT),
+            );
+            metrics.push(
+                QualityMetric::new(RT_RANGE_TERM, json!([start * 60.0, end * 60.0]))
+                    .with_unit(SECOND_UNIT),
+            );
+        }
+        if let Some((lo, hi)) = self.mz_acquisition_range() {
+            metrics.push(QualityMetric::new(MZ_RANGE_TERM, json!([lo, hi])).with_unit(MZ_UNIT));
+        }
+        if !self.ms1_tic.is_empty() {
+            metrics.push(QualityMetric::new(
+                AREA_UNDER_TIC_TERM,
+                json!(self.area_under_tic()),
+            ));
+        }
+        if let Some(quantiles) = self.area_under_tic_quantiles() {
+            metrics.push(QualityMetric::new(
+                AREA_UNDER_TIC_QUANTILES_TERM,
+                json!(quantiles),
+            ));
+        }
+
+        let charges = self.precursor_charge_fractions();
+        if !charges.is_empty() {
+            let (z, f): (Vec<i32>, Vec<f64>) = charges.into_iter().unzip();
+            metrics.push(QualityMetric::new(
+                PRECURSOR_CHARGE_FRACTIONS_TERM,
+                json!({
+                    "MS:1000041": z,
+                    "UO:0000191": f,
+                }),
+            ));
+        }
+
+        for (level, fastest, slowest) in [
+            (1, FASTEST_MS1_FREQUENCY_TERM, SLOWEST_MS1_FREQUENCY_TERM),
+            (2, FASTEST_MS2_FREQUENCY_TERM, SLOWEST_MS2_FREQUENCY_TERM),
+        ]
+        .iter()
+        .copied()
+        {
+            if let Some(stats) = self.cycle_time_statistics(level) {
+                if stats.min > 0.0 {
+                    metrics.push(
+                        QualityMetric::new(fastest, json!(1.0 / stats.min)).with_unit(HERTZ_UNIT),
+                    );
+                }
+                if stats.max > 0.0 {
+                    metrics.push(
+                        QualityMetric::new(slowest, json!(1.0 / stats.max)).with_unit(HERTZ_UNIT),
+                    );
+                }
+            }
+        }
+
+        for (level, term) in [
+            (1, MS1_INJECTION_TIME_QUANTILES_TERM),
+            (2, MS2_INJECTION_TIME_QUANTILES_TERM),
+        ]
+        .iter()
+        .copied()
+        {
+            if let Some(stats) = self.injection_time_statistics(level) {
+                metrics.push(
+                    QualityMetric::new(term, json!(stats.quartiles())).with_unit(MILLISECOND_UNIT),
+                );
+            }
+        }
+        metrics
+    }
+
+    /// Build an mzQC document reporting the metrics of this run, computed from `source`
+    pub fn to_mzqc(&self, source: &SourceFile) -> MzQC {
+        let run = RunQuality {
+            metadata: MzQCMetadata {
+                label: if source.id.is_empty() {
+                    source.name.clone()
+                } else {
+                    source.id.clone()
+                },
+                input_files: vec![InputFile::from(source)],
+                analysis_software: vec![AnalysisSoftware::default()],
+            },
+            quality_metrics: self.quality_metrics(),
+        };
+        MzQC::new(vec![run])
+    }
+}
+
+/// A controlled vocabulary term as represented in mzQC
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CvTerm {
+    pub accession: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+}
+
+impl CvTerm {
+    fn from_term(term: QCTerm) -> Self {
+        Self {
+            accession: term.0.to_string(),
+            name: term.1.to_string(),
+            value: None,
+        }
+    }
+}
+
+impl From<&Param> for CvTerm {
+    fn from(param: &Param) -> Self {
+        let value = if param.value().is_empty() {
+            None
+        } else {
+            Some(Value::String(param.value().to_string()))
+        };
+        Self {
+            accession: param.curie().unwrap_or_default(),
+            name: param.name().to_string(),
+            value,
+        }
+    }
+}
+
+/// A single quality metric, named by a controlled vocabulary term
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityMetric {
+    pub accession: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub value: Value,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub unit: Vec<CvTerm>,
+}
+
+impl QualityMetric {
+    fn new(term: QCTerm, value: Value) -> Self {
+        Self {
+            accession: term.0.to_string(),
+            name: term.1.to_string(),
+            description: None,
+            value,
+            unit: Vec::new(),
+        }
+    }
+
+    fn with_unit(mut self, unit: QCTerm) -> Self {
+        self.unit.push(CvTerm::from_term(unit));
+        self
+    }
+}
+
+/// A file the quality metrics were computed from
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputFile {
+    pub location: String,
+    pub name: String,
+    pub file_format: CvTerm,
+    #[serde(default)]
+    pub file_properties: Vec<CvTerm>,
+}
+
+impl From<&SourceFile> for InputFile {
+    fn from(source: &SourceFile) -> Self {
+        let location = if source.location.is_empty() {
+            source.name.clone()
+        } else {
+            format!("{}/{}", source.location.trim_end_matches('/'), source.name)
+        };
+        let file_format = source
+            .file_format
+            .as_ref()
+            .map(CvTerm::from)
+            .unwrap_or_else(|| CvTerm::from_term(("MS:1000560", "mass spectrometer file format")));
+        let file_properties = source
+            .id_format
+            .iter()
+            .chain(source.params().iter())
+            .filter(|p| p.is_controlled())
+            .map(CvTerm::from)
+            .collect();
+        Self {
+            location,
+            name: source.name.clone(),
+            file_format,
+            file_properties,
+        }
+    }
+}
+
+/// The software used to compute the quality metrics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisSoftware {
+    pub accession: String,
+    pub name: String,
+    pub version: String,
+    pub uri: String,
+}
+
+impl Default for AnalysisSoftware {
+    fn default() -> Self {
+        let term = ControlledVocabulary::MS.const_param_ident("custom unreleased software tool", 1000799);
+        Self {
+            accession: term.curie().unwrap(),
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uri: env!("CARGO_PKG_REPOSITORY").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MzQCMetadata {
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub label: String,
+    pub input_files: Vec<InputFile>,
+    pub analysis_software: Vec<AnalysisSoftware>,
+}
+
+/// The quality metrics computed for a single run
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunQuality {
+    pub metadata: MzQCMetadata,
+    pub quality_metrics: Vec<QualityMetric>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlledVocabularyReference {
+    pub name: String,
+    pub uri: String,
+    pub version: String,
+}
+
+/// The body of an mzQC document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MzQCContent {
+    pub version: String,
+    pub creation_date: String,
+    pub run_qualities: Vec<RunQuality>,
+    pub controlled_vocabularies: Vec<ControlledVocabularyReference>,
+}
+
+/// A HUPO-PSI mzQC document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MzQC {
+    #[serde(rename = "mzQC")]
+    pub mz_qc: MzQCContent,
+}
+
+impl MzQC {
+    pub fn new(run_qualities: Vec<RunQuality>) -> Self {
+        let controlled_vocabularies = vec![
+            ControlledVocabularyReference {
+                name: "Proteomics Standards Initiative Mass Spectrometry Ontology".to_string(),
+                uri: format!(
+                    "https://github.com/HUPO-PSI/psi-ms-CV/releases/download/v{PSIMS_VERSION}/psi-ms.obo"
+                ),
+                version: PSIMS_VERSION.to_string(),
+            },
+            ControlledVocabularyReference {
+                name: "Unit Ontology".to_string(),
+                uri: "http://purl.obolibrary.org/obo/uo.obo".to_string(),
+                version: "releases/2020-03-10".to_string(),
+            },
+        ];
+        Self {
+            mz_qc: MzQCContent {
+                version: MZQC_VERSION.to_string(),
+                creation_date: format_timestamp(SystemTime::now()),
+                run_qualities,
+                controlled_vocabularies,
+            },
+        }
+    }
+
+    pub fn run_qualities(&self) -> &[RunQuality] {
+        &self.mz_qc.run_qualities
+    }
+
+    /// Serialize this document as JSON to `stream`
+    pub fn write_to<W: io::Write>(&self, stream: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(stream, self)?;
+        Ok(())
+    }
+
+    /// Deserialize an mzQC document from `stream`
+    pub fn read_from<R: io::Read>(stream: R) -> io::Result<Self> {
+        Ok(serde_json::from_reader(stream)?)
+    }
+}
+
+/// Format a timestamp as an ISO 8601 UTC date-time string without pulling in a date library
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default() as i64;
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    let (hour, minute, second) = (rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mgf::MGFReader;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_summary_statistics() {
+        let stats = SummaryStatistics::from_values(vec![4.0, 1.0, 3.0, 2.0, 5.0]).unwrap();
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 5.0);
+        assert_eq!(stats.median, 3.0);
+        assert_eq!(stats.q1, 2.0);
+        assert_eq!(stats.q3, 4.0);
+        assert_eq!(stats.mean, 3.0);
+        assert!(SummaryStatistics::from_values(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let t = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(format_timestamp(t), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn test_area_under_tic_quantiles() {
+        let mut metrics = RunQualityMetrics::new();
+        // A flat TIC of 1 from 0 to 4 minutes, with an MS2 time beyond the MS1 range
+        metrics.ms1_tic = vec![(0.0, 1.0), (1.5, 1.0), (4.0, 1.0)];
+        metrics.first_time = Some(0.0);
+        metrics.last_time = Some(10.0);
+        assert_eq!(metrics.area_under_tic(), 240.0);
+        let quantiles = metrics.area_under_tic_quantiles().unwrap();
+        for q in quantiles {
+            assert!((q - 60.0).abs() < 1e-9, "{:?}", quantiles);
+        }
+    }
+
+    #[test]
+    fn test_mgf_metrics() -> io::Result<()> {
+        let reader = MGFReader::new(fs::File::open("./test/data/small.mgf")?);
+        let mut metrics = RunQualityMetrics::new();
+        metrics.observe_all(reader);
+        assert_eq!(metrics.ms1_count(), 0);
+        assert_eq!(metrics.ms2_count(), 34);
+        // None of the spectra in this file report a precursor charge
+        assert!(metrics.precursor_charge_fractions().is_empty());
+
+        let source = SourceFile {
+            name: "small.mgf".to_string(),
+            location: "file:///test/data".to_string(),
+            id: "small".to_string(),
+            ..Default::default()
+        };
+        let doc = metrics.to_mzqc(&source);
+        let mut buffer = Vec::new();
+        doc.write_to(&mut buffer)?;
+        let dup = MzQC::read_from(io::Cursor::new(buffer))?;
+        assert_eq!(doc, dup);
+        let run = &dup.run_qualities()[0];
+        assert_eq!(
+            run.metadata.input_files[0].location,
+            "file:///test/data/small.mgf"
+        );
+        assert!(run
+            .quality_metrics
+            .iter()
+            .any(|m| m.accession == MS2_COUNT_TERM.0 && m.value == json!(34)));
+        Ok(())
+    }
+}
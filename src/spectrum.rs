@@ -54,9 +54,15 @@ for spectrum in reader {
 
 pub(crate) mod scan_properties;
 pub mod bindata;
+pub mod binning;
 pub(crate) mod group;
+pub(crate) mod dia;
+pub(crate) mod faims;
+pub(crate) mod tree;
 pub(crate) mod spectrum;
 pub(crate) mod chromatogram;
+pub(crate) mod filter_string;
+pub mod similarity;
 pub mod utils;
 
 pub use crate::spectrum::scan_properties::*;
@@ -67,8 +73,67 @@ pub use crate::spectrum::spectrum::{
     CentroidSpectrum, RawSpectrum, Spectrum, DeconvolutedSpectrum,
     MultiLayerSpectrum, CentroidSpectrumType, DeconvolutedSpectrumType};
 pub use crate::spectrum::chromatogram::{Chromatogram, ChromatogramLike};
+pub use crate::spectrum::filter_string::{
+    FilterActivation, FilterAnalyzer, FilterPrecursor, FilterScanMode, FilterString,
+    FilterStringError, FILTER_STRING_ACCESSION,
+};
 
 pub use group::{SpectrumGroup, SpectrumGroupIter, SpectrumGroupingIterator};
+pub use dia::{DIACycleIterator, IsolationWindowScheme, DEFAULT_WINDOW_TOLERANCE};
+pub use faims::{CompensationVoltageSplitter, CompensationVoltageStream};
+pub use tree::{SpectrumTree, SpectrumTreeIter, SpectrumTreeIterator, SpectrumTreeNode};
 
 #[cfg(feature = "mzsignal")]
-pub use group::{average_spectra, SpectrumAveragingIterator, DeferredSpectrumAveragingIterator};
\ No newline at end of file
+pub use group::{average_spectra, SpectrumAveragingIterator, DeferredSpectrumAveragingIterator};
+
+/// Spectrum builders shared by the tests of this module's submodules
+#[cfg(test)]
+pub(crate) mod test_fixtures {
+    use mzpeaks::CentroidPeak;
+
+    use super::{MultiLayerSpectrum, ScanEvent, SignalContinuity, SpectrumDescription};
+
+    /// Build an empty spectrum at `ms_level` whose native ID is derived from `index`
+    pub(crate) fn make_spectrum(index: usize, ms_level: u8) -> MultiLayerSpectrum {
+        let description = SpectrumDescription {
+            id: format!("scan={}", index + 1),
+            index,
+            ms_level,
+            ..Default::default()
+        };
+        MultiLayerSpectrum::from_description(description)
+    }
+
+    /// Build an empty spectrum as [`make_spectrum`] does, with a single scan acquired at
+    /// the FAIMS compensation voltage `cv`
+    pub(crate) fn make_spectrum_with_cv(
+        index: usize,
+        ms_level: u8,
+        cv: Option<f32>,
+    ) -> MultiLayerSpectrum {
+        let mut spectrum = make_spectrum(index, ms_level);
+        spectrum.description.acquisition.scans.push(ScanEvent {
+            compensation_voltage: cv,
+            ..Default::default()
+        });
+        spectrum
+    }
+
+    /// Build a centroided spectrum as [`make_spectrum`] does, holding `(m/z, intensity)` `peaks`
+    pub(crate) fn make_centroid_spectrum(
+        index: usize,
+        ms_level: u8,
+        peaks: &[(f64, f32)],
+    ) -> MultiLayerSpectrum {
+        let mut spectrum = make_spectrum(index, ms_level);
+        spectrum.description.signal_continuity = SignalContinuity::Centroid;
+        spectrum.peaks = Some(
+            peaks
+                .iter()
+                .enumerate()
+                .map(|(i, (mz, intensity))| CentroidPeak::new(*mz, *intensity, i as u32))
+                .collect(),
+        );
+        spectrum
+    }
+}
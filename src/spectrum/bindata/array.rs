@@ -18,15 +18,77 @@ use super::traits::{ByteArrayView, ByteArrayViewMut};
 #[allow(unused)]
 use super::vec_as_bytes;
 
+/// Serializes a byte buffer as a base64 string for human-readable formats like JSON and
+/// as raw bytes otherwise, rather than as a list of numbers
+#[cfg(feature = "serde")]
+mod bytes_serde {
+    use std::fmt::{self, Formatter};
+
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Bytes;
+
+    pub fn serialize<S: Serializer>(value: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64_simd::STANDARD.encode_type::<String>(value))
+        } else {
+            serializer.serialize_bytes(value)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Bytes;
+
+        fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+            formatter.write_str("a byte buffer")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut buffer = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(byte) = seq.next_element()? {
+                buffer.push(byte);
+            }
+            Ok(buffer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            base64_simd::STANDARD
+                .decode_type::<Bytes>(encoded.as_bytes())
+                .map_err(de::Error::custom)
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+}
+
 /// Represents a data array
 #[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataArray {
+    #[cfg_attr(feature = "serde", serde(with = "bytes_serde"))]
     pub data: Bytes,
     pub dtype: BinaryDataArrayType,
     pub compression: BinaryCompressionType,
     pub name: ArrayType,
     pub params: Option<Box<ParamList>>,
     pub unit: Unit,
+    /// The ID of the [`DataProcessing`](crate::meta::DataProcessing) applied to this array,
+    /// if it differs from its spectrum's
+    pub data_processing_id: Option<String>,
 }
 
 impl core::fmt::Debug for DataArray {
@@ -210,19 +272,19 @@ impl<'transient, 'lifespan: 'transient> DataArray {
             BinaryCompressionType::Decoded => Ok(Cow::Borrowed(self.data.as_slice())),
             BinaryCompressionType::NoCompression => {
                 let bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                    .expect("Failed to decode base64 array");
+                    .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                 Ok(Cow::Owned(bytestring))
             }
             BinaryCompressionType::Zlib => {
                 let bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                    .expect("Failed to decode base64 array");
+                    .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                 Ok(Cow::Owned(Self::decompres_zlib(&bytestring)))
             }
             #[cfg(feature = "numpress")]
             BinaryCompressionType::NumpressLinear => match self.dtype {
                 BinaryDataArrayType::Float64 => {
                     let mut bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                        .expect("Failed to decode base64 array");
+                        .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                     let decoded = Self::decompres_numpress_linear(&mut bytestring)?;
                     let view = vec_as_bytes(decoded);
                     Ok(Cow::Owned(view))
@@ -252,12 +314,12 @@ impl<'transient, 'lifespan: 'transient> DataArray {
             BinaryCompressionType::Decoded => Ok(Cow::Borrowed(&self.data.as_slice()[start..end])),
             BinaryCompressionType::NoCompression => {
                 let bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                    .expect("Failed to decode base64 array");
+                    .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                 Ok(Cow::Owned(bytestring[start..end].to_vec()))
             }
             BinaryCompressionType::Zlib => {
                 let bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                    .expect("Failed to decode base64 array");
+                    .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                 Ok(Cow::Owned(
                     Self::decompres_zlib(&bytestring)[start..end].to_vec(),
                 ))
@@ -274,14 +336,14 @@ impl<'transient, 'lifespan: 'transient> DataArray {
             BinaryCompressionType::Decoded => Ok(&mut self.data),
             BinaryCompressionType::NoCompression => {
                 let bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                    .expect("Failed to decode base64 array");
+                    .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                 self.data = bytestring;
                 self.compression = BinaryCompressionType::Decoded;
                 Ok(&mut self.data)
             }
             BinaryCompressionType::Zlib => {
                 let bytestring = base64_simd::STANDARD.decode_type::<Bytes>(&self.data)
-                    .expect("Failed to decode base64 array");
+                    .map_err(|e| ArrayRetrievalError::DecompressionError(e.to_string()))?;
                 self.data = bytestring;
                 self.compression = BinaryCompressionType::Decoded;
                 Ok(&mut self.data)
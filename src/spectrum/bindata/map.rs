@@ -12,9 +12,38 @@ use super::array::DataArray;
 use super::encodings::{ArrayType, ArrayRetrievalError, BinaryCompressionType};
 use super::traits::{ByteArrayView, ByteArrayViewMut};
 
+/// Serializes the arrays as a sequence, as [`ArrayType`] keys are not all valid map keys in
+/// formats like JSON, and each [`DataArray`] already carries its name
+#[cfg(feature = "serde")]
+mod array_map_serde {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{ArrayType, DataArray};
+
+    pub fn serialize<S: Serializer>(
+        value: &HashMap<ArrayType, DataArray>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.values())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<ArrayType, DataArray>, D::Error> {
+        let arrays = Vec::<DataArray>::deserialize(deserializer)?;
+        Ok(arrays
+            .into_iter()
+            .map(|array| (array.name.clone(), array))
+            .collect())
+    }
+}
 
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryArrayMap {
+    #[cfg_attr(feature = "serde", serde(with = "array_map_serde"))]
     pub byte_buffer_map: HashMap<ArrayType, DataArray>,
 }
 
@@ -0,0 +1,470 @@
+/*!
+Convert spectra into fixed-width intensity bin vectors, e.g. as features for machine learning.
+
+A [`SpectrumBinner`] describes the bin grid and how binned intensities are transformed and
+normalized. It produces a [`SparseBinVector`] per spectrum, and can batch a whole
+[`ScanSource`](crate::io::ScanSource) into a [`BinnedMatrix`] in compressed sparse row (CSR)
+form. When the `ndarray` dependency is enabled, both can be converted into dense `ndarray` arrays.
+
+```rust
+use std::fs::File;
+use mzdata::io::MGFReader;
+use mzdata::spectrum::binning::{BinNormalization, IntensityTransform, SpectrumBinner};
+
+let reader = MGFReader::new(File::open("./test/data/small.mgf").unwrap());
+let binner = SpectrumBinner::new(1.0005, 100.0, 2000.0)
+    .unwrap()
+    .with_offset(0.4)
+    .with_transform(IntensityTransform::Sqrt)
+    .with_normalization(BinNormalization::Max);
+let matrix = binner.bin_source(reader).unwrap();
+println!("{} spectra x {} bins, {} non-zero", matrix.n_rows(), matrix.n_columns, matrix.nnz());
+```
+*/
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike};
+
+#[cfg(feature = "ndarray")]
+use ndarray::{Array1, Array2};
+use thiserror::Error;
+
+use crate::spectrum::bindata::{ArrayRetrievalError, BinaryArrayMap};
+use crate::spectrum::{PeakDataLevel, SpectrumLike};
+
+/// The errors that can occur when configuring a [`SpectrumBinner`]
+#[derive(Debug, Clone, Copy, Error, PartialEq)]
+pub enum BinningError {
+    #[error("The bin width must be a positive number, got {0}")]
+    InvalidBinWidth(f64),
+}
+
+/// A transformation applied to each bin's summed intensity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IntensityTransform {
+    #[default]
+    Identity,
+    Sqrt,
+    /// `ln(1 + x)`
+    Log1p,
+}
+
+impl IntensityTransform {
+    pub fn apply(&self, intensity: f32) -> f32 {
+        match self {
+            Self::Identity => intensity,
+            Self::Sqrt => intensity.sqrt(),
+            Self::Log1p => intensity.ln_1p(),
+        }
+    }
+}
+
+/// How the transformed bin intensities of each spectrum are scaled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BinNormalization {
+    #[default]
+    None,
+    /// Divide by the largest bin, so the base peak's bin is 1
+    Max,
+    /// Divide by the sum of all bins
+    Sum,
+    /// Divide by the Euclidean norm, so the vector has unit length
+    Unit,
+}
+
+/// The bins of a single spectrum with a non-zero intensity
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparseBinVector {
+    /// The bin indices, in ascending order
+    pub indices: Vec<usize>,
+    pub values: Vec<f32>,
+    /// The total number of bins, including empty ones
+    pub size: usize,
+}
+
+impl SparseBinVector {
+    /// The number of non-zero bins
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, f32)> + '_ {
+        self.indices
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+
+    pub fn to_dense(&self) -> Vec<f32> {
+        let mut dense = vec![0.0; self.size];
+        for (i, v) in self.iter() {
+            dense[i] = v;
+        }
+        dense
+    }
+
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> Array1<f32> {
+        Array1::from(self.to_dense())
+    }
+}
+
+/**
+A batch of binned spectra in compressed sparse row (CSR) form, one row per spectrum.
+
+The bins of row `i` are `indices[indptr[i]..indptr[i + 1]]` with the intensities at the same
+positions in `values`, matching the layout `scipy.sparse.csr_matrix((values, indices, indptr))`
+expects.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinnedMatrix {
+    pub indptr: Vec<usize>,
+    pub indices: Vec<usize>,
+    pub values: Vec<f32>,
+    pub n_columns: usize,
+    /// The native ID of the spectrum each row was built from
+    pub row_ids: Vec<String>,
+}
+
+impl BinnedMatrix {
+    pub fn new(n_columns: usize) -> Self {
+        Self {
+            indptr: vec![0],
+            indices: Vec::new(),
+            values: Vec::new(),
+            n_columns,
+            row_ids: Vec::new(),
+        }
+    }
+
+    pub fn n_rows(&self) -> usize {
+        self.indptr.len() - 1
+    }
+
+    /// The number of non-zero entries across all rows
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn push_row(&mut self, id: String, row: SparseBinVector) {
+        self.indices.extend(row.indices);
+        self.values.extend(row.values);
+        self.indptr.push(self.indices.len());
+        self.row_ids.push(id);
+    }
+
+    /// The bin indices and intensities of row `i`
+    pub fn row(&self, i: usize) -> Option<(&[usize], &[f32])> {
+        if i >= self.n_rows() {
+            return None;
+        }
+        let (start, end) = (self.indptr[i], self.indptr[i + 1]);
+        Some((&self.indices[start..end], &self.values[start..end]))
+    }
+
+    /// Convert the matrix into dense rows of length `n_columns`
+    pub fn to_dense(&self) -> Vec<Vec<f32>> {
+        (0..self.n_rows())
+            .map(|i| {
+                let (indices, values) = self.row(i).unwrap();
+                let mut dense = vec![0.0; self.n_columns];
+                for (j, v) in indices.iter().zip(values.iter()) {
+                    dense[*j] = *v;
+                }
+                dense
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "ndarray")]
+    pub fn to_ndarray(&self) -> Array2<f32> {
+        let mut dense = Array2::zeros((self.n_rows(), self.n_columns));
+        for i in 0..self.n_rows() {
+            let (indices, values) = self.row(i).unwrap();
+            for (j, v) in indices.iter().zip(values.iter()) {
+                dense[[i, *j]] = *v;
+            }
+        }
+        dense
+    }
+}
+
+/**
+Bin peaks onto a fixed-width grid starting at `mz_start` and ending before `mz_end`.
+
+Peaks that fall into the same bin have their intensities summed, then the
+[`IntensityTransform`] is applied to each bin, and finally the bins are scaled according
+to the [`BinNormalization`]. Peaks outside the grid are dropped.
+
+With an `offset`, the first bin is narrower as its lower edge is clipped to `mz_start`, and
+an extra bin is added at the top so that the grid still reaches `mz_end`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumBinner {
+    pub bin_width: f64,
+    pub mz_start: f64,
+    pub mz_end: f64,
+    /// Shift the bin edges down by this fraction of `bin_width`, e.g. so that bin edges fall
+    /// between the clusters of peptide fragment masses rather than through them
+    pub offset: f64,
+    pub transform: IntensityTransform,
+    pub normalization: BinNormalization,
+}
+
+impl SpectrumBinner {
+    pub fn new(bin_width: f64, mz_start: f64, mz_end: f64) -> Result<Self, BinningError> {
+        if !(bin_width > 0.0 && bin_width.is_finite()) {
+            return Err(BinningError::InvalidBinWidth(bin_width));
+        }
+        Ok(Self {
+            bin_width,
+            mz_start,
+            mz_end,
+            offset: 0.0,
+            transform: IntensityTransform::default(),
+            normalization: BinNormalization::default(),
+        })
+    }
+
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: IntensityTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: BinNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// The number of bins in the grid, including the bin the offset adds at the top
+    pub fn num_bins(&self) -> usize {
+        ((self.mz_end - self.mz_start) / self.bin_width + self.offset)
+            .ceil()
+            .max(0.0) as usize
+    }
+
+    /// The bin `mz` falls into, if it is on the grid
+    pub fn bin_index(&self, mz: f64) -> Option<usize> {
+        if mz < self.mz_start || mz >= self.mz_end {
+            return None;
+        }
+        let index = ((mz - self.mz_start) / self.bin_width + self.offset).floor();
+        if index < 0.0 || index as usize >= self.num_bins() {
+            None
+        } else {
+            Some(index as usize)
+        }
+    }
+
+    /// The lower edge of bin `index`, the smallest m/z [`SpectrumBinner::bin_index`] puts in it
+    pub fn bin_start(&self, index: usize) -> f64 {
+        (self.mz_start + (index as f64 - self.offset) * self.bin_width).max(self.mz_start)
+    }
+
+    /// Bin `(m/z, intensity)` pairs, which need not be sorted
+    pub fn bin_peaks<I: IntoIterator<Item = (f64, f32)>>(&self, peaks: I) -> SparseBinVector {
+        let mut bins: Vec<(usize, f32)> = peaks
+            .into_iter()
+            .filter_map(|(mz, intensity)| self.bin_index(mz).map(|i| (i, intensity)))
+            .collect();
+        bins.sort_by_key(|(i, _)| *i);
+
+        let mut vector = SparseBinVector {
+            size: self.num_bins(),
+            ..Default::default()
+        };
+        for (i, intensity) in bins {
+            if vector.indices.last() == Some(&i) {
+                *vector.values.last_mut().unwrap() += intensity;
+            } else {
+                vector.indices.push(i);
+                vector.values.push(intensity);
+            }
+        }
+        vector
+            .values
+            .iter_mut()
+            .for_each(|v| *v = self.transform.apply(*v));
+        self.normalize(&mut vector.values);
+        vector
+    }
+
+    /// Bin the m/z and intensity arrays of `arrays`
+    pub fn bin_arrays(
+        &self,
+        arrays: &BinaryArrayMap,
+    ) -> Result<SparseBinVector, ArrayRetrievalError> {
+        let mzs = arrays.mzs()?;
+        let intensities = arrays.intensities()?;
+        Ok(self.bin_peaks(mzs.iter().copied().zip(intensities.iter().copied())))
+    }
+
+    /// Bin the most processed peak data of `spectrum`, as given by [`SpectrumLike::peaks`].
+    ///
+    /// Deconvoluted peaks are binned by their neutral mass, and a spectrum without any peak
+    /// data produces an empty vector.
+    pub fn bin_spectrum<C: CentroidLike, D: DeconvolutedCentroidLike, S: SpectrumLike<C, D>>(
+        &self,
+        spectrum: &S,
+    ) -> Result<SparseBinVector, ArrayRetrievalError> {
+        match spectrum.peaks() {
+            PeakDataLevel::Missing => Ok(self.bin_peaks(std::iter::empty())),
+            PeakDataLevel::RawData(arrays) => self.bin_arrays(arrays),
+            PeakDataLevel::Centroid(peaks) => {
+                Ok(self.bin_peaks(peaks.iter().map(|p| (p.mz(), p.intensity()))))
+            }
+            PeakDataLevel::Deconvoluted(peaks) => {
+                Ok(self.bin_peaks(peaks.iter().map(|p| (p.neutral_mass(), p.intensity()))))
+            }
+        }
+    }
+
+    /// Bin `spectrum` into a dense vector of length [`SpectrumBinner::num_bins`]
+    pub fn bin_spectrum_dense<
+        C: CentroidLike,
+        D: DeconvolutedCentroidLike,
+        S: SpectrumLike<C, D>,
+    >(
+        &self,
+        spectrum: &S,
+    ) -> Result<Vec<f32>, ArrayRetrievalError> {
+        self.bin_spectrum(spectrum).map(|v| v.to_dense())
+    }
+
+    /// Bin every spectrum from `source`, such as a [`ScanSource`](crate::io::ScanSource),
+    /// into the rows of a [`BinnedMatrix`]
+    pub fn bin_source<
+        C: CentroidLike,
+        D: DeconvolutedCentroidLike,
+        S: SpectrumLike<C, D>,
+        I: IntoIterator<Item = S>,
+    >(
+        &self,
+        source: I,
+    ) -> Result<BinnedMatrix, ArrayRetrievalError> {
+        let mut matrix = BinnedMatrix::new(self.num_bins());
+        for spectrum in source {
+            let row = self.bin_spectrum(&spectrum)?;
+            matrix.push_row(spectrum.id().to_string(), row);
+        }
+        Ok(matrix)
+    }
+
+    fn normalize(&self, values: &mut [f32]) {
+        let scale = match self.normalization {
+            BinNormalization::None => return,
+            BinNormalization::Max => values.iter().copied().fold(0.0, f32::max),
+            BinNormalization::Sum => values.iter().sum(),
+            BinNormalization::Unit => values.iter().map(|v| v * v).sum::<f32>().sqrt(),
+        };
+        if scale > 0.0 {
+            values.iter_mut().for_each(|v| *v /= scale);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{MZFileReader, MemoryScanSource, MzMLReader};
+    use crate::spectrum::test_fixtures::make_centroid_spectrum;
+
+    #[test]
+    fn test_bin_peaks() {
+        let binner = SpectrumBinner::new(1.0, 100.0, 110.0).unwrap();
+        assert_eq!(binner.num_bins(), 10);
+        assert_eq!(binner.bin_index(99.9), None);
+        assert_eq!(binner.bin_index(100.0), Some(0));
+        assert_eq!(binner.bin_index(109.99), Some(9));
+        assert_eq!(binner.bin_index(110.0), None);
+
+        let vector = binner.bin_peaks(vec![(105.2, 9.0), (101.5, 4.0), (105.7, 7.0), (200.0, 1.0)]);
+        assert_eq!(vector.indices, vec![1, 5]);
+        assert_eq!(vector.values, vec![4.0, 16.0]);
+        assert_eq!(vector.to_dense()[5], 16.0);
+
+        let binner = binner
+            .with_offset(0.5)
+            .with_transform(IntensityTransform::Sqrt)
+            .with_normalization(BinNormalization::Max);
+        let vector = binner.bin_peaks(vec![(105.2, 9.0), (101.5, 4.0), (105.7, 7.0)]);
+        assert_eq!(vector.indices, vec![2, 5, 6]);
+        assert_eq!(vector.values, vec![2.0 / 3.0, 1.0, 7f32.sqrt() / 3.0]);
+
+        // The offset bin at the top keeps the whole range on the grid
+        assert_eq!(binner.num_bins(), 11);
+        assert_eq!(binner.bin_index(100.0), Some(0));
+        assert_eq!(binner.bin_index(100.5), Some(1));
+        assert_eq!(binner.bin_index(109.99), Some(10));
+        assert_eq!(binner.bin_start(0), 100.0);
+        assert_eq!(binner.bin_start(1), 100.5);
+        for mz in [100.0, 100.49, 103.5, 105.2, 109.99] {
+            let index = binner.bin_index(mz).unwrap();
+            assert!(binner.bin_start(index) <= mz && mz < binner.bin_start(index + 1));
+        }
+
+        assert_eq!(
+            SpectrumBinner::new(0.0, 100.0, 110.0),
+            Err(BinningError::InvalidBinWidth(0.0))
+        );
+        assert!(SpectrumBinner::new(f64::NAN, 100.0, 110.0).is_err());
+    }
+
+    #[test]
+    fn test_bin_source() {
+        let spectra = vec![
+            make_centroid_spectrum(0, 2, &[(100.5, 10.0), (150.5, 30.0)]),
+            make_centroid_spectrum(1, 2, &[]),
+            make_centroid_spectrum(2, 2, &[(120.0, 5.0)]),
+        ];
+        let binner = SpectrumBinner::new(10.0, 100.0, 200.0)
+            .unwrap()
+            .with_normalization(BinNormalization::Sum);
+        let matrix = binner.bin_source(MemoryScanSource::new(spectra)).unwrap();
+        assert_eq!(matrix.n_rows(), 3);
+        assert_eq!(matrix.n_columns, 10);
+        assert_eq!(matrix.indptr, vec![0, 2, 2, 3]);
+        assert_eq!(matrix.row(0), Some((&[0, 5][..], &[0.25, 0.75][..])));
+        assert_eq!(matrix.row(2), Some((&[2][..], &[1.0][..])));
+        assert_eq!(matrix.row_ids[2], "scan=3");
+        let dense = matrix.to_dense();
+        assert_eq!(dense[1], vec![0.0; 10]);
+    }
+
+    #[test]
+    fn test_bin_file() -> std::io::Result<()> {
+        let binner = SpectrumBinner::new(1.0, 200.0, 2000.0).unwrap();
+        let reader = MzMLReader::open_path("./test/data/read_index_of.mzML")?;
+        let spectra: Vec<_> = reader.collect();
+        let matrix = binner.bin_source(spectra.iter().cloned()).unwrap();
+        assert_eq!(matrix.n_rows(), 48);
+        assert_eq!(matrix.row_ids[0], spectra[0].id());
+
+        // Binning without normalization keeps the total intensity within the grid
+        for (i, spectrum) in spectra.iter().enumerate() {
+            let arrays = spectrum.raw_arrays().unwrap();
+            let expected: f32 = arrays
+                .mzs()
+                .unwrap()
+                .iter()
+                .zip(arrays.intensities().unwrap().iter())
+                .filter(|(mz, _)| (200.0..2000.0).contains(*mz))
+                .map(|(_, intensity)| *intensity)
+                .sum();
+            let (_, values) = matrix.row(i).unwrap();
+            let total: f32 = values.iter().sum();
+            assert!(
+                (total - expected).abs() <= expected * 1e-4,
+                "{}: {} != {}",
+                spectrum.id(),
+                total,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
@@ -9,6 +9,7 @@ use super::bindata::{ArrayType, BinaryArrayMap, ArrayRetrievalError, ByteArrayVi
 
 
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chromatogram {
     description: ChromatogramDescription,
     pub arrays: BinaryArrayMap
@@ -0,0 +1,454 @@
+/*!
+Group data-independent acquisition (DIA) spectra by acquisition cycle.
+
+In DIA (SWATH, MSE-like window schemes) runs, MSn spectra do not reference a selected precursor
+spectrum, so grouping by `precursor_id` as [`SpectrumGroupingIterator`](super::SpectrumGroupingIterator)
+does degenerates. Instead, the instrument repeatedly steps through a fixed list of isolation windows,
+the [`IsolationWindowScheme`], and each pass over that list, optionally preceded by an MS1 spectrum,
+forms one cycle.
+*/
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use crate::io::traits::SpectrumGrouping;
+use crate::io::{RandomAccessSpectrumIterator, ScanSource, SpectrumAccessError};
+use crate::spectrum::scan_properties::IsolationWindow;
+use crate::spectrum::{MultiLayerSpectrum, SpectrumGroup, SpectrumLike};
+
+/// The default m/z tolerance used when deciding whether two isolation windows are the same
+pub const DEFAULT_WINDOW_TOLERANCE: f32 = 0.01;
+
+/**
+The set of isolation windows an instrument steps through during each DIA cycle, ordered
+by their lower bound.
+
+A scheme may be detected from the spectra of a run with [`IsolationWindowScheme::from_spectra`],
+or accumulated incrementally as [`DIACycleIterator`] reads spectra, and then used to assign any
+MSn spectrum to its window with [`IsolationWindowScheme::window_index_of`].
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsolationWindowScheme {
+    windows: Vec<IsolationWindow>,
+    tolerance: f32,
+}
+
+impl Default for IsolationWindowScheme {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW_TOLERANCE)
+    }
+}
+
+impl IsolationWindowScheme {
+    pub fn new(tolerance: f32) -> Self {
+        Self {
+            windows: Vec::new(),
+            tolerance,
+        }
+    }
+
+    /// Build a scheme from an explicit list of windows
+    pub fn from_windows<I: IntoIterator<Item = IsolationWindow>>(windows: I, tolerance: f32) -> Self {
+        let mut scheme = Self::new(tolerance);
+        for window in windows {
+            scheme.add(window);
+        }
+        scheme
+    }
+
+    /// Detect the window scheme from the isolation windows of every MSn spectrum in `spectra`
+    pub fn from_spectra<
+        'a,
+        C: CentroidLike + Default + 'a,
+        D: DeconvolutedCentroidLike + Default + 'a,
+        S: SpectrumLike<C, D> + 'a,
+        I: IntoIterator<Item = &'a S>,
+    >(
+        spectra: I,
+        tolerance: f32,
+    ) -> Self {
+        let mut scheme = Self::new(tolerance);
+        for spectrum in spectra {
+            if let Some(window) = isolation_window_of(spectrum) {
+                scheme.add(window.clone());
+            }
+        }
+        scheme
+    }
+
+    fn same_window(&self, a: &IsolationWindow, b: &IsolationWindow) -> bool {
+        (a.lower_bound - b.lower_bound).abs() <= self.tolerance
+            && (a.upper_bound - b.upper_bound).abs() <= self.tolerance
+    }
+
+    /// Add `window` to the scheme if it is not already present, returning its index
+    pub fn add(&mut self, window: IsolationWindow) -> usize {
+        if let Some(i) = self.index_of(&window) {
+            return i;
+        }
+        let i = self
+            .windows
+            .partition_point(|w| w.lower_bound < window.lower_bound);
+        self.windows.insert(i, window);
+        i
+    }
+
+    /// Find the index of the window in this scheme matching `window`
+    pub fn index_of(&self, window: &IsolationWindow) -> Option<usize> {
+        self.windows.iter().position(|w| self.same_window(w, window))
+    }
+
+    /// Find the index of the window in this scheme that `spectrum` was isolated with
+    pub fn window_index_of<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    >(
+        &self,
+        spectrum: &S,
+    ) -> Option<usize> {
+        isolation_window_of(spectrum).and_then(|w| self.index_of(w))
+    }
+
+    /// Find the indices of all windows whose bounds contain `mz`. Overlapping schemes
+    /// may return more than one window.
+    pub fn windows_containing(&self, mz: f64) -> Vec<usize> {
+        let mz = mz as f32;
+        self.windows
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.lower_bound <= mz && mz < w.upper_bound)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Partition `spectra` by the window they were acquired in. Spectra that do not
+    /// belong to any window of this scheme are omitted.
+    pub fn group_by_window<
+        'a,
+        C: CentroidLike + Default + 'a,
+        D: DeconvolutedCentroidLike + Default + 'a,
+        S: SpectrumLike<C, D> + 'a,
+        I: IntoIterator<Item = &'a S>,
+    >(
+        &self,
+        spectra: I,
+    ) -> Vec<Vec<&'a S>> {
+        let mut bins: Vec<Vec<&'a S>> = (0..self.len()).map(|_| Vec::new()).collect();
+        for spectrum in spectra {
+            if let Some(i) = self.window_index_of(spectrum) {
+                bins[i].push(spectrum);
+            }
+        }
+        bins
+    }
+
+    /// Whether any two adjacent windows overlap
+    pub fn is_overlapping(&self) -> bool {
+        self.windows
+            .windows(2)
+            .any(|pair| pair[1].lower_bound < pair[0].upper_bound - self.tolerance)
+    }
+
+    /// The m/z range covered by the scheme
+    pub fn mz_range(&self) -> Option<(f32, f32)> {
+        let lo = self.windows.first()?.lower_bound;
+        let hi = self
+            .windows
+            .iter()
+            .map(|w| w.upper_bound)
+            .fold(f32::NEG_INFINITY, f32::max);
+        Some((lo, hi))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&IsolationWindow> {
+        self.windows.get(index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, IsolationWindow> {
+        self.windows.iter()
+    }
+
+    pub fn windows(&self) -> &[IsolationWindow] {
+        &self.windows
+    }
+
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+}
+
+fn isolation_window_of<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default, S: SpectrumLike<C, D>>(
+    spectrum: &S,
+) -> Option<&IsolationWindow> {
+    if spectrum.ms_level() < 2 {
+        return None;
+    }
+    let window = &spectrum.precursor()?.isolation_window;
+    if window.lower_bound == 0.0 && window.upper_bound == 0.0 {
+        None
+    } else {
+        Some(window)
+    }
+}
+
+/**
+A wrapper for [`Iterator`]-implementors that batches DIA spectra into acquisition cycles,
+producing one [`SpectrumGroup`] per cycle holding the cycle's MS1 spectrum, if any, and every
+window's MSn spectrum in acquisition order.
+
+A cycle ends when the next MS1 spectrum is read, or, for runs without MS1 spectra, when an
+isolation window already visited in the current cycle is seen again. The window scheme is
+accumulated as spectra are read and is available from [`DIACycleIterator::scheme`].
+*/
+pub struct DIACycleIterator<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+    G: SpectrumGrouping<C, D, S> = SpectrumGroup<C, D, S>,
+> {
+    pub source: R,
+    scheme: IsolationWindowScheme,
+    pending: VecDeque<S>,
+    current: Option<G>,
+    visited: Vec<IsolationWindow>,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        G: SpectrumGrouping<C, D, S>,
+    > DIACycleIterator<R, C, D, S, G>
+{
+    /// Construct a new [`DIACycleIterator`] which detects the window scheme as it reads
+    pub fn new(source: R) -> Self {
+        Self::with_scheme(source, IsolationWindowScheme::default())
+    }
+
+    /// Construct a new [`DIACycleIterator`] starting from a known window scheme. Windows
+    /// not present in `scheme` will still be added to it as they are encountered.
+    pub fn with_scheme(source: R, scheme: IsolationWindowScheme) -> Self {
+        Self {
+            source,
+            scheme,
+            pending: VecDeque::new(),
+            current: None,
+            visited: Vec::new(),
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        }
+    }
+
+    /// The isolation window scheme observed so far
+    pub fn scheme(&self) -> &IsolationWindowScheme {
+        &self.scheme
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.current = None;
+        self.visited.clear();
+    }
+
+    fn next_spectrum(&mut self) -> Option<S> {
+        self.pending.pop_front().or_else(|| self.source.next())
+    }
+
+    fn emit(&mut self) -> Option<G> {
+        self.visited.clear();
+        self.current.take()
+    }
+
+    /// Retrieve the next complete acquisition cycle
+    pub fn next_group(&mut self) -> Option<G> {
+        while let Some(spectrum) = self.next_spectrum() {
+            if spectrum.ms_level() == 1 {
+                if self.current.is_some() {
+                    self.pending.push_front(spectrum);
+                    return self.emit();
+                }
+                let mut group = G::default();
+                group.set_precursor(spectrum);
+                self.current = Some(group);
+                continue;
+            }
+
+            if let Some(window) = isolation_window_of(&spectrum) {
+                let seen = self
+                    .visited
+                    .iter()
+                    .any(|w| self.scheme.same_window(w, window));
+                let has_precursor = self
+                    .current
+                    .as_ref()
+                    .map(|g| g.precursor().is_some())
+                    .unwrap_or_default();
+                if seen && !has_precursor {
+                    self.pending.push_front(spectrum);
+                    return self.emit();
+                }
+                self.scheme.add(window.clone());
+                self.visited.push(window.clone());
+            }
+            self.current
+                .get_or_insert_with(G::default)
+                .products_mut()
+                .push(spectrum);
+        }
+        self.emit()
+    }
+}
+
+impl<
+        R: ScanSource<C, D, S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        G: SpectrumGrouping<C, D, S> + Default,
+    > Iterator for DIACycleIterator<R, C, D, S, G>
+{
+    type Item = G;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_group()
+    }
+}
+
+impl<
+        R: RandomAccessSpectrumIterator<C, D, S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        G: SpectrumGrouping<C, D, S> + Default,
+    > DIACycleIterator<R, C, D, S, G>
+{
+    pub fn start_from_id(&mut self, id: &str) -> Result<&Self, SpectrumAccessError> {
+        match self.source.start_from_id(id) {
+            Ok(_) => {
+                self.clear();
+                Ok(self)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn start_from_index(&mut self, index: usize) -> Result<&Self, SpectrumAccessError> {
+        match self.source.start_from_index(index) {
+            Ok(_) => {
+                self.clear();
+                Ok(self)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn start_from_time(&mut self, time: f64) -> Result<&Self, SpectrumAccessError> {
+        match self.source.start_from_time(time) {
+            Ok(_) => {
+                self.clear();
+                Ok(self)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{MZFileReader, MzMLReader};
+    use crate::spectrum::test_fixtures::make_spectrum;
+    use crate::spectrum::Precursor;
+
+    fn make_run(n_cycles: usize, with_ms1: bool) -> Vec<MultiLayerSpectrum> {
+        let windows = [(400.0, 425.0), (425.0, 450.0), (450.0, 475.0)];
+        let mut spectra = Vec::new();
+        for _ in 0..n_cycles {
+            if with_ms1 {
+                spectra.push(make_spectrum(spectra.len(), 1));
+            }
+            for (lo, hi) in windows.iter().copied() {
+                let mut spectrum = make_spectrum(spectra.len(), 2);
+                spectrum.description.precursors.push(Precursor {
+                    isolation_window: IsolationWindow {
+                        target: (lo + hi) / 2.0,
+                        lower_bound: lo,
+                        upper_bound: hi,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+                spectra.push(spectrum);
+            }
+        }
+        spectra
+    }
+
+    #[test]
+    fn test_cycles_with_ms1() {
+        let spectra = make_run(4, true);
+        let mut iter: DIACycleIterator<_> = DIACycleIterator::new(spectra.into_iter());
+        let mut n = 0;
+        while let Some(cycle) = iter.next_group() {
+            assert!(cycle.precursor().is_some());
+            assert_eq!(cycle.products().len(), 3);
+            n += 1;
+        }
+        assert_eq!(n, 4);
+        assert_eq!(iter.scheme().len(), 3);
+        assert!(!iter.scheme().is_overlapping());
+        assert_eq!(iter.scheme().windows_containing(430.0), vec![1]);
+    }
+
+    #[test]
+    fn test_cycles_without_ms1() {
+        let spectra = make_run(3, false);
+        let scheme = IsolationWindowScheme::from_spectra(spectra.iter(), DEFAULT_WINDOW_TOLERANCE);
+        assert_eq!(scheme.len(), 3);
+        let by_window = scheme.group_by_window(spectra.iter());
+        assert!(by_window.iter().all(|b| b.len() == 3));
+
+        let mut iter: DIACycleIterator<_> = DIACycleIterator::new(spectra.into_iter());
+        let mut n = 0;
+        while let Some(cycle) = iter.next_group() {
+            assert!(cycle.precursor().is_none());
+            assert_eq!(cycle.products().len(), 3);
+            n += 1;
+        }
+        assert_eq!(n, 3);
+    }
+
+    #[test]
+    fn test_cycles_from_file() -> std::io::Result<()> {
+        let reader = MzMLReader::open_path("./test/data/read_index_of.mzML")?;
+        let mut iter: DIACycleIterator<_> = DIACycleIterator::new(reader);
+        let mut n_cycles = 0;
+        let mut n_products = 0;
+        while let Some(cycle) = iter.next_group() {
+            assert_eq!(cycle.precursor().unwrap().ms_level(), 1);
+            assert!(cycle.products().iter().all(|s| s.ms_level() == 2));
+            n_cycles += 1;
+            n_products += cycle.products().len();
+        }
+        assert_eq!(n_cycles, 14);
+        assert_eq!(n_products, 34);
+        // Each data dependent scan isolates its own window around the first precursor
+        assert!(!iter.scheme().is_empty());
+        assert!(!iter.scheme().windows_containing(810.789).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+/*!
+Separate the interleaved compensation voltages of a FAIMS run.
+
+A FAIMS device cycles through several compensation voltages (CVs) during a run, so
+neighboring spectra may have sampled entirely different ion populations. The
+[`CompensationVoltageSplitter`] partitions the spectra of a source by CV, so that each
+stream can be grouped, averaged or traced on its own.
+*/
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use crate::io::MemoryScanSource;
+use crate::spectrum::{MultiLayerSpectrum, SpectrumLike};
+
+/**
+Partition the spectra of an [`Iterator`] by their FAIMS compensation voltage.
+
+Spectra are read from the source on demand and buffered under their compensation voltage
+until requested, so reading one stream to completion buffers every other stream. Spectra
+without a compensation voltage form their own stream, keyed by `None`.
+*/
+pub struct CompensationVoltageSplitter<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    pub source: R,
+    partitions: Vec<(Option<f32>, VecDeque<S>)>,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > CompensationVoltageSplitter<R, C, D, S>
+{
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            partitions: Vec::new(),
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// The compensation voltages encountered so far, in the order they were first seen
+    pub fn compensation_voltages(&self) -> Vec<Option<f32>> {
+        self.partitions.iter().map(|(cv, _)| *cv).collect()
+    }
+
+    /// Find the buffer for `compensation_voltage`, creating it if this is the first time
+    /// it has been seen
+    fn partition_mut(
+        partitions: &mut Vec<(Option<f32>, VecDeque<S>)>,
+        compensation_voltage: Option<f32>,
+    ) -> &mut VecDeque<S> {
+        let i = match partitions
+            .iter()
+            .position(|(cv, _)| *cv == compensation_voltage)
+        {
+            Some(i) => i,
+            None => {
+                partitions.push((compensation_voltage, VecDeque::new()));
+                partitions.len() - 1
+            }
+        };
+        &mut partitions[i].1
+    }
+
+    /// Read the next spectrum acquired at `compensation_voltage`, buffering any spectra
+    /// at other compensation voltages read along the way
+    pub fn next_for(&mut self, compensation_voltage: Option<f32>) -> Option<S> {
+        let buffered = self
+            .partitions
+            .iter_mut()
+            .find(|(cv, _)| *cv == compensation_voltage)
+            .and_then(|(_, spectra)| spectra.pop_front());
+        if buffered.is_some() {
+            return buffered;
+        }
+        for spectrum in self.source.by_ref() {
+            let cv = spectrum.compensation_voltage();
+            let partition = Self::partition_mut(&mut self.partitions, cv);
+            if cv == compensation_voltage {
+                return Some(spectrum);
+            }
+            partition.push_back(spectrum);
+        }
+        None
+    }
+
+    /// Iterate over the spectra acquired at `compensation_voltage`
+    pub fn stream(
+        &mut self,
+        compensation_voltage: Option<f32>,
+    ) -> CompensationVoltageStream<'_, R, C, D, S> {
+        CompensationVoltageStream {
+            splitter: self,
+            compensation_voltage,
+        }
+    }
+
+    /// Read the rest of the source, returning every partition as its own
+    /// [`ScanSource`](crate::io::ScanSource) in the order its compensation voltage was
+    /// first seen
+    pub fn into_partitions(mut self) -> Vec<(Option<f32>, MemoryScanSource<C, D, S>)>
+    where
+        S: Clone,
+    {
+        for spectrum in self.source.by_ref() {
+            Self::partition_mut(&mut self.partitions, spectrum.compensation_voltage())
+                .push_back(spectrum);
+        }
+        self.partitions
+            .into_iter()
+            .map(|(cv, spectra)| (cv, MemoryScanSource::new(spectra.into())))
+            .collect()
+    }
+}
+
+/// The spectra of a [`CompensationVoltageSplitter`] acquired at a single compensation voltage
+pub struct CompensationVoltageStream<
+    'a,
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    splitter: &'a mut CompensationVoltageSplitter<R, C, D, S>,
+    compensation_voltage: Option<f32>,
+}
+
+impl<
+        'a,
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > Iterator for CompensationVoltageStream<'a, R, C, D, S>
+{
+    type Item = S;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.splitter.next_for(self.compensation_voltage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{MZFileReader, MzMLReader, ScanSource};
+    use crate::spectrum::test_fixtures::make_spectrum_with_cv;
+
+    fn make_run() -> Vec<MultiLayerSpectrum> {
+        let cvs = [Some(-45.0), Some(-60.0), None];
+        (0..9).map(|i| make_spectrum_with_cv(i, 1, cvs[i % 3])).collect()
+    }
+
+    #[test]
+    fn test_streams() {
+        let mut splitter: CompensationVoltageSplitter<_> =
+            CompensationVoltageSplitter::new(make_run().into_iter());
+        let ids: Vec<_> = splitter
+            .stream(Some(-60.0))
+            .map(|s| s.id().to_string())
+            .collect();
+        assert_eq!(ids, vec!["scan=2", "scan=5", "scan=8"]);
+        assert_eq!(
+            splitter.compensation_voltages(),
+            vec![Some(-45.0), Some(-60.0), None]
+        );
+        assert_eq!(splitter.stream(Some(-45.0)).count(), 3);
+        assert_eq!(splitter.stream(None).count(), 3);
+        assert!(splitter.next_for(Some(-30.0)).is_none());
+    }
+
+    #[test]
+    fn test_into_partitions() {
+        let splitter: CompensationVoltageSplitter<_> =
+            CompensationVoltageSplitter::new(make_run().into_iter());
+        let partitions = splitter.into_partitions();
+        assert_eq!(partitions.len(), 3);
+        for (cv, source) in partitions {
+            assert_eq!(source.len(), 3);
+            for spectrum in source {
+                assert_eq!(spectrum.compensation_voltage(), cv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_file() -> std::io::Result<()> {
+        // The file has no FAIMS data, so everything is in the `None` stream
+        let reader = MzMLReader::open_path("./test/data/read_index_of.mzML")?;
+        let partitions = CompensationVoltageSplitter::new(reader).into_partitions();
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].0, None);
+        assert_eq!(partitions[0].1.len(), 48);
+
+        // Alternate the compensation voltage between acquisition cycles, as a FAIMS
+        // device would
+        let mut cycle = 0;
+        let reader = MzMLReader::open_path("./test/data/read_index_of.mzML")?;
+        let tagged = reader.map(|mut spectrum| {
+            if spectrum.ms_level() == 1 {
+                cycle += 1;
+            }
+            let cv = if cycle % 2 == 0 { -60.0 } else { -45.0 };
+            spectrum.description_mut().acquisition.scans[0].compensation_voltage = Some(cv);
+            spectrum
+        });
+        let mut splitter = CompensationVoltageSplitter::new(tagged);
+        let first: Vec<_> = splitter.stream(Some(-45.0)).collect();
+        assert_eq!(
+            splitter.compensation_voltages(),
+            vec![Some(-45.0), Some(-60.0)]
+        );
+        let second: Vec<_> = splitter.stream(Some(-60.0)).collect();
+        assert_eq!(first.iter().filter(|s| s.ms_level() == 1).count(), 7);
+        assert_eq!(second.iter().filter(|s| s.ms_level() == 1).count(), 7);
+        assert_eq!(first.len() + second.len(), 48);
+        assert!(first
+            .iter()
+            .all(|s| s.compensation_voltage() == Some(-45.0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,472 @@
+/*!
+Parse Thermo scan filter strings.
+
+Spectra converted from Thermo RAW files carry a `filter string` (MS:1000512) on each scan
+event, such as `FTMS + p NSI d Full ms2 445.12@hcd30.00 [100.00-1000.00]`, which summarizes
+how the scan was acquired. Older converters often wrote little else, so [`FilterString`] can
+recover the polarity, activation, isolation target and scan range of a spectrum when the
+explicit controlled vocabulary terms are missing.
+*/
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::params::{ControlledVocabulary, Param, ParamDescribed, Unit};
+use crate::spectrum::scan_properties::{
+    ActivationEnergy, ActivationMethod, Precursor, ScanEvent, ScanPolarity, ScanWindow,
+    SelectedIon, SignalContinuity, SpectrumDescription,
+};
+
+/// The accession of the `filter string` term
+pub const FILTER_STRING_ACCESSION: &str = "MS:1000512";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FilterStringError {
+    #[error("The filter string is empty")]
+    Empty,
+    #[error("Failed to parse {0:?} as a number")]
+    InvalidNumber(String),
+    #[error("Malformed scan range {0:?}")]
+    MalformedScanRange(String),
+}
+
+/// The mass analyzer named at the start of a filter string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterAnalyzer {
+    /// `FTMS`, an Orbitrap or ICR cell
+    FourierTransform,
+    /// `ITMS`
+    IonTrap,
+    /// `TQMS`
+    TripleQuadrupole,
+    /// `SQMS`
+    SingleQuadrupole,
+    /// `TOFMS`
+    TimeOfFlight,
+    /// `SECTOR`
+    Sector,
+    /// `ASTMS`
+    Astral,
+}
+
+impl FilterAnalyzer {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "FTMS" => Some(Self::FourierTransform),
+            "ITMS" => Some(Self::IonTrap),
+            "TQMS" => Some(Self::TripleQuadrupole),
+            "SQMS" => Some(Self::SingleQuadrupole),
+            "TOFMS" => Some(Self::TimeOfFlight),
+            "SECTOR" => Some(Self::Sector),
+            "ASTMS" => Some(Self::Astral),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of scan described by a filter string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FilterScanMode {
+    #[default]
+    Full,
+    /// `SIM`
+    SelectedIonMonitoring,
+    /// `SRM`
+    SelectedReactionMonitoring,
+    /// `CRM`
+    ConsecutiveReactionMonitoring,
+    /// `Q1MS`
+    Q1,
+    /// `Q3MS`
+    Q3,
+    /// `Z`
+    Zoom,
+}
+
+impl FilterScanMode {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "Full" => Some(Self::Full),
+            "SIM" => Some(Self::SelectedIonMonitoring),
+            "SRM" => Some(Self::SelectedReactionMonitoring),
+            "CRM" => Some(Self::ConsecutiveReactionMonitoring),
+            "Q1MS" => Some(Self::Q1),
+            "Q3MS" => Some(Self::Q3),
+            "Z" => Some(Self::Zoom),
+            _ => None,
+        }
+    }
+}
+
+/// One activation step applied to a precursor, like `hcd30.00`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterActivation {
+    pub method: ActivationMethod,
+    /// The number following the activation code. For collisional methods this is a
+    /// normalized collision energy, for electron-based methods a reaction time.
+    pub value: f32,
+}
+
+/// A precursor listed in a filter string, like `445.12@hcd30.00`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FilterPrecursor {
+    pub mz: f64,
+    pub activations: Vec<FilterActivation>,
+}
+
+impl FilterPrecursor {
+    fn parse(token: &str, supplemental: bool) -> Result<Self, FilterStringError> {
+        let mut parts = token.split('@');
+        let mz = parse_number(parts.next().unwrap_or_default())?;
+        let mut activations: Vec<FilterActivation> = Vec::new();
+        for part in parts {
+            let split_at = part
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(part.len());
+            let (code, value) = part.split_at(split_at);
+            let value = if value.is_empty() {
+                0.0
+            } else {
+                parse_number(value)?
+            };
+            let method = activation_from_code(code, supplemental && !activations.is_empty());
+            activations.push(FilterActivation { method, value });
+        }
+        Ok(Self { mz, activations })
+    }
+
+    /// Write the activation steps of this precursor into `precursor`, unless it already
+    /// has activation methods or energies of its own
+    pub fn fill_precursor(&self, precursor: &mut Precursor) {
+        let activation = &mut precursor.activation;
+        if activation.methods().is_empty() {
+            for step in self.activations.iter() {
+                activation.add_method(step.method.clone());
+            }
+        }
+        if activation.energies.is_empty() {
+            for step in self.activations.iter() {
+                if step.method.is_collisional() == Some(true) {
+                    activation.add_energy(ActivationEnergy::new(
+                        step.value,
+                        Unit::PercentElectronVolt,
+                        step.method.is_supplemental(),
+                    ));
+                }
+            }
+        }
+        let window = &mut precursor.isolation_window;
+        if window.target == 0.0 && window.lower_bound == 0.0 && window.upper_bound == 0.0 {
+            window.target = self.mz as f32;
+        }
+    }
+}
+
+/**
+A parsed Thermo scan filter string.
+
+Tokens this parser does not recognize are kept in [`FilterString::other`] so that nothing
+is silently lost.
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FilterString {
+    pub analyzer: Option<FilterAnalyzer>,
+    pub polarity: ScanPolarity,
+    pub signal_continuity: SignalContinuity,
+    /// The ionization source, like `NSI` or `ESI`
+    pub ionization: Option<String>,
+    /// Whether this was a data-dependent scan, the `d` flag
+    pub dependent: bool,
+    /// Whether supplemental activation was used, the `sa` flag
+    pub supplemental_activation: bool,
+    /// The FAIMS compensation voltage, from a `cv=` token
+    pub compensation_voltage: Option<f32>,
+    pub scan_mode: FilterScanMode,
+    /// The MS level, or 0 if the filter string does not say
+    pub ms_level: u8,
+    /// Whether this is a multiplexed scan, the `msx` flag
+    pub multiplexed: bool,
+    pub precursors: Vec<FilterPrecursor>,
+    pub scan_ranges: Vec<ScanWindow>,
+    pub other: Vec<String>,
+}
+
+const IONIZATION_TOKENS: [&str; 12] = [
+    "NSI", "ESI", "APCI", "APPI", "EI", "CI", "MALDI", "FAB", "TSP", "FD", "GD", "PSI",
+];
+
+fn parse_number<T: FromStr>(token: &str) -> Result<T, FilterStringError> {
+    token
+        .trim()
+        .parse()
+        .map_err(|_| FilterStringError::InvalidNumber(token.to_string()))
+}
+
+/// Map an activation code to its method. Codes without a controlled vocabulary term,
+/// like `ptr` or `sid`, are kept as a user param named after the code.
+fn activation_from_code(code: &str, supplemental: bool) -> ActivationMethod {
+    match code {
+        "cid" if supplemental => ActivationMethod::SupplementalCollisionInducedDissociation,
+        "cid" => ActivationMethod::CollisionInducedDissociation,
+        "hcd" if supplemental => ActivationMethod::SupplementalBeamTypeCollisionInducedDissociation,
+        "hcd" => ActivationMethod::BeamTypeCollisionInducedDissociation,
+        "etd" => ActivationMethod::ElectronTransferDissociation,
+        "ecd" => ActivationMethod::ElectronCaptureDissociation,
+        "netd" => ActivationMethod::NegativeElectronTransferDissociation,
+        "uvpd" => ActivationMethod::UltravioletPhotodissociation,
+        "mpd" => ControlledVocabulary::MS
+            .param("MS:1000262", "infrared multiphoton dissociation")
+            .into(),
+        "pqd" => ControlledVocabulary::MS
+            .param("MS:1000599", "pulsed q dissociation")
+            .into(),
+        _ => ActivationMethod::Other(Box::new(Param::new_key_value(
+            code.to_string(),
+            String::new(),
+        ))),
+    }
+}
+
+fn parse_scan_ranges(text: &str) -> Result<Vec<ScanWindow>, FilterStringError> {
+    text.split(',')
+        .filter(|range| !range.trim().is_empty())
+        .map(|range| {
+            let (lower, upper) = range
+                .trim()
+                .split_once('-')
+                .ok_or_else(|| FilterStringError::MalformedScanRange(range.to_string()))?;
+            Ok(ScanWindow {
+                lower_bound: parse_number(lower)?,
+                upper_bound: parse_number(upper)?,
+            })
+        })
+        .collect()
+}
+
+impl FromStr for FilterString {
+    type Err = FilterStringError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(FilterStringError::Empty);
+        }
+        let mut filter = FilterString::default();
+        let (head, ranges) = match s.split_once('[') {
+            Some((head, rest)) => {
+                let ranges = rest.trim_end().trim_end_matches(']');
+                (head, Some(ranges))
+            }
+            None => (s, None),
+        };
+        if let Some(ranges) = ranges {
+            filter.scan_ranges = parse_scan_ranges(ranges)?;
+        }
+
+        for token in head.split_ascii_whitespace() {
+            if let Some(analyzer) = FilterAnalyzer::from_token(token) {
+                filter.analyzer = Some(analyzer);
+            } else if let Some(scan_mode) = FilterScanMode::from_token(token) {
+                filter.scan_mode = scan_mode;
+            } else if IONIZATION_TOKENS.contains(&token) {
+                filter.ionization = Some(token.to_string());
+            } else if let Some(cv) = token.strip_prefix("cv=") {
+                filter.compensation_voltage = Some(parse_number(cv)?);
+            } else if token == "msx" {
+                filter.multiplexed = true;
+            } else if let Some(level) = token.strip_prefix("ms").and_then(|level| {
+                if level.is_empty() {
+                    Some(1)
+                } else {
+                    level.parse().ok()
+                }
+            }) {
+                filter.ms_level = level;
+            } else if filter.ms_level > 0 && token.starts_with(|c: char| c.is_ascii_digit()) {
+                filter.precursors.push(FilterPrecursor::parse(
+                    token,
+                    filter.supplemental_activation,
+                )?);
+            } else {
+                match token {
+                    "+" => filter.polarity = ScanPolarity::Positive,
+                    "-" => filter.polarity = ScanPolarity::Negative,
+                    "p" => filter.signal_continuity = SignalContinuity::Profile,
+                    "c" => filter.signal_continuity = SignalContinuity::Centroid,
+                    "d" => filter.dependent = true,
+                    "sa" => filter.supplemental_activation = true,
+                    _ => filter.other.push(token.to_string()),
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl FilterString {
+    /// Find and parse the filter string of `event`, if it has one
+    pub fn from_scan_event(event: &ScanEvent) -> Option<Result<Self, FilterStringError>> {
+        event
+            .get_param_by_accession(FILTER_STRING_ACCESSION)
+            .map(|param| param.value.parse())
+    }
+
+    /// Set `polarity` if it is not already known
+    pub fn fill_polarity(&self, polarity: &mut ScanPolarity) {
+        if *polarity == ScanPolarity::Unknown {
+            *polarity = self.polarity;
+        }
+    }
+
+    /// Set the scan windows and compensation voltage of `event` if it has none
+    pub fn fill_scan_event(&self, event: &mut ScanEvent) {
+        if event.scan_windows.is_empty() {
+            event.scan_windows = self.scan_ranges.clone();
+        }
+        if event.compensation_voltage.is_none() {
+            event.compensation_voltage = self.compensation_voltage;
+        }
+    }
+
+    /// Fill in the activation and isolation target of `precursors` from the filter string's
+    /// precursors. They are matched from the last, as the filter string of an MSn scan lists
+    /// every precursor from MS2 onwards while a spectrum usually only describes the last one.
+    /// If there are no precursors but the filter string names some, one is created.
+    pub fn fill_precursors(&self, precursors: &mut Vec<Precursor>) {
+        if precursors.is_empty() {
+            if let Some(last) = self.precursors.last() {
+                precursors.push(Precursor {
+                    ions: vec![SelectedIon {
+                        mz: last.mz,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                });
+            }
+        }
+        for (precursor, source) in precursors
+            .iter_mut()
+            .rev()
+            .zip(self.precursors.iter().rev())
+        {
+            source.fill_precursor(precursor);
+        }
+    }
+
+    /// Fill in whatever `description` is missing from this filter string
+    pub fn fill_description(&self, description: &mut SpectrumDescription) {
+        self.fill_polarity(&mut description.polarity);
+        if description.ms_level == 0 {
+            description.ms_level = self.ms_level;
+        }
+        if description.signal_continuity == SignalContinuity::Unknown {
+            description.signal_continuity = self.signal_continuity;
+        }
+        if let Some(event) = description.acquisition.first_scan_mut() {
+            self.fill_scan_event(event);
+        }
+        if self.ms_level > 1 {
+            self.fill_precursors(&mut description.precursors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_parse_ms2() {
+        let filter: FilterString = "FTMS + p NSI d Full ms2 445.12@hcd30.00 [100.00-1000.00]"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.analyzer, Some(FilterAnalyzer::FourierTransform));
+        assert_eq!(filter.polarity, ScanPolarity::Positive);
+        assert_eq!(filter.signal_continuity, SignalContinuity::Profile);
+        assert_eq!(filter.ionization.as_deref(), Some("NSI"));
+        assert!(filter.dependent);
+        assert_eq!(filter.scan_mode, FilterScanMode::Full);
+        assert_eq!(filter.ms_level, 2);
+        assert_eq!(filter.precursors.len(), 1);
+        assert_eq!(filter.precursors[0].mz, 445.12);
+        assert_eq!(
+            filter.precursors[0].activations[0].method,
+            ActivationMethod::BeamTypeCollisionInducedDissociation
+        );
+        assert_eq!(filter.precursors[0].activations[0].value, 30.0);
+        assert_eq!(
+            filter.scan_ranges,
+            vec![ScanWindow {
+                lower_bound: 100.0,
+                upper_bound: 1000.0
+            }]
+        );
+        assert!(filter.other.is_empty());
+    }
+
+    #[test]
+    fn test_parse_variants() {
+        let filter: FilterString =
+            "ITMS - c ESI cv=-45.00 sa Full ms3 800.00@cid35.00 500.00@etd25.00@cid15.00 [135.00-1000.00]"
+                .parse()
+                .unwrap();
+        assert_eq!(filter.polarity, ScanPolarity::Negative);
+        assert_eq!(filter.compensation_voltage, Some(-45.0));
+        assert_eq!(filter.ms_level, 3);
+        assert_eq!(filter.precursors.len(), 2);
+        let methods: Vec<_> = filter.precursors[1]
+            .activations
+            .iter()
+            .map(|a| a.method.clone())
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                ActivationMethod::ElectronTransferDissociation,
+                ActivationMethod::SupplementalCollisionInducedDissociation
+            ]
+        );
+
+        let filter: FilterString = "FTMS + p NSI SIM ms [400.00-500.00, 600.00-700.00]"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.ms_level, 1);
+        assert_eq!(filter.scan_mode, FilterScanMode::SelectedIonMonitoring);
+        assert_eq!(filter.scan_ranges.len(), 2);
+
+        assert_eq!("".parse::<FilterString>(), Err(FilterStringError::Empty));
+
+        let filter: FilterString =
+            "ITMS + c NSI Full ms3 445.12@cid35.00 300.10@ptr25.00 [100.00-1000.00]"
+                .parse()
+                .unwrap();
+        assert_eq!(filter.precursors.len(), 2);
+        let step = &filter.precursors[1].activations[0];
+        assert_eq!(step.method.name(), "ptr");
+        assert_eq!(step.value, 25.0);
+        assert_eq!(step.method.is_collisional(), None);
+    }
+
+    #[test]
+    fn test_fill_description() {
+        let filter: FilterString =
+            "FTMS + p NSI sa Full ms2 445.12@etd25.00@hcd20.00 [100.00-1000.00]"
+                .parse()
+                .unwrap();
+        let mut description = SpectrumDescription::default();
+        filter.fill_description(&mut description);
+
+        assert_eq!(description.polarity, ScanPolarity::Positive);
+        assert_eq!(description.ms_level, 2);
+        let scan = description.acquisition.first_scan().unwrap();
+        assert_eq!(scan.scan_windows.len(), 1);
+        let precursor = description.precursors.first().unwrap();
+        assert_eq!(precursor.ion().mz, 445.12);
+        assert_eq!(precursor.isolation_window.target, 445.12);
+        assert!(precursor.activation.is_combined());
+        assert!(precursor.activation.has_supplemental_activation());
+        assert_eq!(precursor.activation.energies.len(), 1);
+        assert!(precursor.activation.energies[0].supplemental);
+    }
+}
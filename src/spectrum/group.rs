@@ -16,6 +16,7 @@ use super::MultiLayerSpectrum;
 A pairing of an optional MS1 spectrum with all its associated MSn spectra.
 */
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpectrumGroup<C = CentroidPeak, D = DeconvolutedPeak, S = MultiLayerSpectrum<C, D>>
 where
     C: CentroidLike + Default,
@@ -338,13 +339,18 @@ impl<
                     }
                 }
             }
-        } else if !self.queue.is_empty() {
-            // Consider replacing with normal get_mut to avoid re-copying
-            let last = self.queue.back().unwrap();
+        } else if let Some(last) = self
+            .queue
+            .iter()
+            .rev()
+            .find(|prec| prec.compensation_voltage() == scan.compensation_voltage())
+        {
+            // Only associate with a precursor acquired at the same FAIMS compensation voltage
+            let last_id = last.id().to_owned();
             self.generation_tracker
-                .add(last.id().to_owned(), self.generation);
+                .add(last_id.clone(), self.generation);
             self.product_scan_mapping
-                .entry(last.id().to_owned())
+                .entry(last_id)
                 .or_default()
                 .push(scan);
         } else {
@@ -369,13 +375,36 @@ impl<
         }
     }
 
+    /// Remove the products without a known precursor that were acquired at the
+    /// same FAIMS compensation voltage as `precursor`
+    fn pop_unassociated(&mut self, precursor: &S) -> Vec<S> {
+        let compensation_voltage = precursor.compensation_voltage();
+        let buffer = match self.product_scan_mapping.get_mut(MISSING_SCAN_ID) {
+            Some(buffer) => buffer,
+            None => return Vec::new(),
+        };
+        let (matched, held): (Vec<S>, Vec<S>) = buffer
+            .drain(..)
+            .partition(|prod| prod.compensation_voltage() == compensation_voltage);
+        if held.is_empty() {
+            self.product_scan_mapping.remove(MISSING_SCAN_ID);
+        } else {
+            *buffer = held;
+        }
+        matched
+    }
+
     fn flush_first_ms1(&mut self, group: &mut G) {
-        let current_ms1_time = group.precursor().unwrap().start_time();
+        let current_ms1 = group.precursor().unwrap();
+        let current_ms1_time = current_ms1.start_time();
+        let current_ms1_cv = current_ms1.compensation_voltage();
         let mut ids_to_remove = Vec::new();
         for (prec_id, prods) in self.product_scan_mapping.iter_mut() {
             let mut hold = vec![];
             for prod in prods.drain(..) {
-                if prod.start_time() <= current_ms1_time {
+                if prod.start_time() <= current_ms1_time
+                    && prod.compensation_voltage() == current_ms1_cv
+                {
                     group.products_mut().push(prod);
                 } else {
                     hold.push(prod);
@@ -440,9 +469,7 @@ impl<
         if let Some(precursor) = self.queue.pop_front() {
             group.set_precursor(precursor);
             let mut products = self.pop_precursor(group.precursor().unwrap().id());
-            if self.product_scan_mapping.contains_key(MISSING_SCAN_ID) {
-                products.extend(self.pop_precursor(MISSING_SCAN_ID));
-            }
+            products.extend(self.pop_unassociated(group.precursor().unwrap()));
             group.products_mut().extend(products);
 
             self.flush_generations(&mut group);
@@ -556,6 +583,7 @@ impl<
 
 #[cfg(feature = "mzsignal")]
 mod mzsignal_impl {
+    use std::mem;
     use std::sync::Arc;
 
     use crate::spectrum::bindata::{to_bytes, BuildArrayMapFrom, BuildFromArrayMap};
@@ -640,6 +668,8 @@ mod mzsignal_impl {
         pub mz_array: Arc<Vec<f64>>,
         pub intensity_array: Arc<Vec<f32>>,
         pub is_profile: bool,
+        /// The FAIMS compensation voltage of the spectrum these arrays came from
+        pub compensation_voltage: Option<f32>,
     }
 
     impl ArcArrays {
@@ -652,9 +682,15 @@ mod mzsignal_impl {
                 mz_array,
                 intensity_array,
                 is_profile,
+                compensation_voltage: None,
             }
         }
 
+        pub fn with_compensation_voltage(mut self, compensation_voltage: Option<f32>) -> Self {
+            self.compensation_voltage = compensation_voltage;
+            self
+        }
+
         pub fn reprofile_on(
             &self,
             reprofiler: &PeakSetReprofiler,
@@ -870,7 +906,7 @@ mod mzsignal_impl {
             &self,
             group: &G,
         ) -> Option<ArcArrays> {
-            group.precursor().and_then(|scan| {
+            let arrays = group.precursor().and_then(|scan| {
                 if scan.signal_continuity() == SignalContinuity::Profile {
                     if let Some(array_map) = scan.raw_arrays() {
                         let mz = array_map.mzs().unwrap().to_vec();
@@ -904,7 +940,11 @@ mod mzsignal_impl {
                         None
                     }
                 }
-            })
+            });
+            let compensation_voltage = group
+                .precursor()
+                .and_then(|scan| scan.compensation_voltage());
+            arrays.map(|arrays| arrays.with_compensation_voltage(compensation_voltage))
         }
 
         fn initial_feed(&mut self) {
@@ -956,8 +996,19 @@ mod mzsignal_impl {
             result
         }
 
+        /// Collect the MS1 context for the next group, skipping spectra acquired at a different
+        /// FAIMS compensation voltage
         fn process_block(&self) -> Vec<ArcArrays> {
-            self.context_buffer.iter().cloned().collect()
+            let compensation_voltage = self
+                .buffer
+                .front()
+                .and_then(|group| group.precursor())
+                .and_then(|scan| scan.compensation_voltage());
+            self.context_buffer
+                .iter()
+                .filter(|arrays| arrays.compensation_voltage == compensation_voltage)
+                .cloned()
+                .collect()
         }
 
         fn next_group(&mut self) -> Option<SpectrumAveragingContext<C, D, G>> {
@@ -987,6 +1038,8 @@ mod mzsignal_impl {
         buffer: VecDeque<SpectrumGroup<C, D, MultiLayerSpectrum<C, D>>>,
         output_buffer: VecDeque<SpectrumGroup<C, D, MultiLayerSpectrum<C, D>>>,
         averager: SignalAverager<'lifespan>,
+        /// The FAIMS compensation voltage of each entry in `averager`, in the same order
+        compensation_voltages: VecDeque<Option<f32>>,
     }
 
     impl<
@@ -1015,6 +1068,7 @@ mod mzsignal_impl {
                 buffer: VecDeque::with_capacity(averaging_width_index * 2 + 1),
                 output_buffer: VecDeque::with_capacity(averaging_width_index * 2 + 1),
                 averager: SignalAverager::new(mz_start, mz_end, dx),
+                compensation_voltages: VecDeque::with_capacity(averaging_width_index * 2 + 1),
             };
             inst.initial_feed();
             inst
@@ -1080,20 +1134,25 @@ mod mzsignal_impl {
             let mut arrays = VecDeque::with_capacity(self.capacity());
             self.buffer.iter().for_each(|group| {
                 if let Some(pair) = self.copy_out_arrays(group) {
-                    arrays.push_back(pair)
+                    let compensation_voltage = group
+                        .precursor()
+                        .and_then(|scan| scan.compensation_voltage());
+                    arrays.push_back((pair, compensation_voltage))
                 }
             });
 
             for _ in 0..self.half_capacity() {
-                if let Some(pair) = arrays.pop_front() {
+                if let Some((pair, compensation_voltage)) = arrays.pop_front() {
                     self.averager.push(pair);
+                    self.compensation_voltages.push_back(compensation_voltage);
                 }
             }
             let next_group = self._next_group_no_pop();
             self.output_buffer.extend(next_group.into_iter());
             for _ in 0..self.averaging_width_index {
-                if let Some(pair) = arrays.pop_front() {
+                if let Some((pair, compensation_voltage)) = arrays.pop_front() {
                     self.averager.push(pair);
+                    self.compensation_voltages.push_back(compensation_voltage);
                     let next_group = self._next_group_no_pop();
                     self.output_buffer.extend(next_group.into_iter());
                 }
@@ -1117,16 +1176,56 @@ mod mzsignal_impl {
             let result = self._next_group_no_pop();
             if let Some(group) = self.source.next_group() {
                 self.averager.pop();
+                self.compensation_voltages.pop_front();
                 if let Some(pair) = self.copy_out_arrays(&group) {
                     self.averager.push(pair);
+                    self.compensation_voltages.push_back(
+                        group
+                            .precursor()
+                            .and_then(|scan| scan.compensation_voltage()),
+                    );
                 }
                 self.buffer.push_back(group);
             }
             result
         }
 
-        fn average_spectra(&self) -> BinaryArrayMap {
-            let first_intensity = self.averager.interpolate();
+        /// Average the spectra in the window acquired at the same FAIMS compensation voltage as
+        /// the next group's precursor
+        fn average_spectra(&mut self) -> BinaryArrayMap {
+            let compensation_voltage = self
+                .buffer
+                .front()
+                .and_then(|group| group.precursor())
+                .and_then(|scan| scan.compensation_voltage());
+
+            let first_intensity = if self
+                .compensation_voltages
+                .iter()
+                .all(|cv| *cv == compensation_voltage)
+            {
+                self.averager.interpolate()
+            } else {
+                // Temporarily swap out the window for only the matching spectra, borrowing
+                // their arrays from the saved window until it is restored below.
+                let saved = mem::take(&mut self.averager.array_pairs);
+                for (arrays, cv) in saved.iter().zip(self.compensation_voltages.iter()) {
+                    if *cv != compensation_voltage {
+                        continue;
+                    }
+                    let mp = arrays.mz_array.as_ptr();
+                    let ip = arrays.intensity_array.as_ptr();
+                    let unsafe_mzs =
+                        unsafe { std::slice::from_raw_parts(mp, arrays.mz_array.len()) };
+                    let unsafe_intens =
+                        unsafe { std::slice::from_raw_parts(ip, arrays.intensity_array.len()) };
+                    self.averager
+                        .push(ArrayPair::from((unsafe_mzs, unsafe_intens)));
+                }
+                let intensity = self.averager.interpolate();
+                self.averager.array_pairs = saved;
+                intensity
+            };
             let first_mz = self.averager.mz_grid.as_slice();
 
             let mz_array = DataArray::wrap(
@@ -1272,6 +1371,8 @@ pub use mzsignal_impl::{average_spectra, SpectrumAveragingIterator, DeferredSpec
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::spectrum::test_fixtures::make_spectrum_with_cv;
+    use crate::spectrum::Precursor;
 
     #[test]
     fn test_group_iter() {
@@ -1287,4 +1388,36 @@ mod test {
         let entries: Vec<_> = group.iter().collect();
         assert_eq!(entries.len(), 0);
     }
+
+    #[test]
+    fn test_group_by_compensation_voltage() {
+        let spectra: Vec<_> = [(1, -45.0), (1, -60.0), (2, -45.0), (2, -60.0)]
+            .iter()
+            .cycle()
+            .take(8)
+            .enumerate()
+            .map(|(i, (ms_level, cv))| {
+                let mut spectrum = make_spectrum_with_cv(i, *ms_level, Some(*cv));
+                if *ms_level > 1 {
+                    spectrum.description.precursors.push(Precursor::default());
+                }
+                spectrum
+            })
+            .collect();
+        let mut iter: SpectrumGroupingIterator<_> =
+            SpectrumGroupingIterator::new(spectra.into_iter());
+        let mut groups = Vec::new();
+        while let Some(group) = iter.next_group() {
+            groups.push(group);
+        }
+        assert_eq!(groups.len(), 4);
+        for group in groups.iter() {
+            let precursor = group.precursor().unwrap();
+            assert_eq!(group.products().len(), 1);
+            assert_eq!(
+                group.products()[0].compensation_voltage(),
+                precursor.compensation_voltage()
+            );
+        }
+    }
 }
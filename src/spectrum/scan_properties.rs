@@ -2,13 +2,14 @@ use std::fmt::Display;
 
 use super::spectrum::{CentroidPeakAdapting, DeconvolutedPeakAdapting, SpectrumLike};
 use crate::io::traits::ScanSource;
-use crate::params::{ControlledVocabulary, Param, ParamLike, Unit};
+use crate::params::{ControlledVocabulary, Param, ParamGroup, ParamLike, Unit};
 use crate::{impl_param_described, ParamList};
 
 /**
 Describe the initialization stage of an isolation window
 */
 #[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i8)]
 pub enum IsolationWindowState {
     #[default]
@@ -26,6 +27,7 @@ impl Display for IsolationWindowState {
 
 
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// The interval around the precursor ion that was isolated in the precursor scan.
 /// Although an isolation window may be specified either with explicit bounds or
 /// offsets from the target, this data structure always uses explicit bounds.
@@ -48,6 +50,7 @@ impl PartialEq for IsolationWindow {
 
 /// The m/z range which was scanned
 #[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ScanWindow {
     /// The minimum m/z scanned
     pub lower_bound: f32,
@@ -57,7 +60,25 @@ pub struct ScanWindow {
 
 type ScanWindowList = Vec<ScanWindow>;
 
+/// An element of the source document that the reader did not recognise, kept
+/// verbatim so that it can be written back out
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnknownElement {
+    /// The element's tag name
+    pub name: String,
+    /// The serialized XML of the element, including its children
+    pub xml: String,
+}
+
+impl UnknownElement {
+    pub fn new(name: String, xml: String) -> Self {
+        Self { name, xml }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Describes a single scan event. Unless additional post-processing is done,
 /// there is usually only one event per spectrum.
 pub struct ScanEvent {
@@ -65,7 +86,17 @@ pub struct ScanEvent {
     pub injection_time: f32,
     pub scan_windows: ScanWindowList,
     pub instrument_configuration_id: u32,
+    /// The FAIMS compensation voltage the scan was acquired at, in volts
+    pub compensation_voltage: Option<f32>,
+    /// The ID of the [`SourceFile`](crate::meta::SourceFile) this scan was read from, if it
+    /// is not the run's default source file
+    pub source_file_id: Option<String>,
     pub params: Option<Box<ParamList>>,
+    /// The referenceable param groups this scan referred to in the source document. Their
+    /// params are also present in [`ScanEvent::params`] or the typed fields they map to.
+    pub param_groups: Vec<ParamGroup>,
+    /// Elements of the scan the reader did not recognise
+    pub unknown_elements: Vec<UnknownElement>,
 }
 
 type ScanEventList = Vec<ScanEvent>;
@@ -73,6 +104,7 @@ type ScanEventList = Vec<ScanEvent>;
 /// Represents means by which a spectrum is generated using
 /// one or more instrument analyzers
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScanCombination {
     // MS:1000795
     #[default]
@@ -133,6 +165,7 @@ impl ScanCombination {
 }
 
 #[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Describe the series of acquisition events that constructed the spectrum
 /// being described.
 pub struct Acquisition {
@@ -191,6 +224,7 @@ pub trait IonProperties {
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Describes a single selected ion from a precursor isolation
 pub struct SelectedIon {
     /// The selected ion's m/z as reported, may not be the monoisotopic peak.
@@ -220,6 +254,7 @@ impl IonProperties for SelectedIon {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ActivationMethod {
     CollisionInducedDissociation,
     HighEnergyCollisionInducedDissociation,
@@ -242,6 +277,50 @@ pub enum ActivationMethod {
 }
 
 impl ActivationMethod {
+    /// The methods with a dedicated variant, used to look them up by name
+    const KNOWN: [ActivationMethod; 14] = [
+        ActivationMethod::CollisionInducedDissociation,
+        ActivationMethod::HighEnergyCollisionInducedDissociation,
+        ActivationMethod::LowEnergyCollisionInducedDissociation,
+        ActivationMethod::InSourceCollisionInducedDissociation,
+        ActivationMethod::BeamTypeCollisionInducedDissociation,
+        ActivationMethod::TrapTypeCollisionInducedDissociation,
+        ActivationMethod::SupplementalCollisionInducedDissociation,
+        ActivationMethod::SupplementalBeamTypeCollisionInducedDissociation,
+        ActivationMethod::ElectronTransferDissociation,
+        ActivationMethod::ElectronCaptureDissociation,
+        ActivationMethod::ElectronActivationDissociation,
+        ActivationMethod::NegativeElectronTransferDissociation,
+        ActivationMethod::Photodissociation,
+        ActivationMethod::UltravioletPhotodissociation,
+    ];
+
+    /// Look up a method by its controlled vocabulary name, falling back to
+    /// [`ActivationMethod::Other`] for names without a dedicated variant.
+    ///
+    /// The fallback only knows the name, so it has no accession even if the
+    /// method it was written from had one.
+    pub fn from_name(name: &str) -> Self {
+        Self::KNOWN
+            .iter()
+            .find(|method| method.name() == name)
+            .cloned()
+            .unwrap_or_else(|| {
+                Self::Other(Box::new(Param::new_key_value(
+                    name.to_string(),
+                    String::new(),
+                )))
+            })
+    }
+
+    /// The controlled vocabulary name of the method
+    pub fn name(&self) -> String {
+        match self {
+            ActivationMethod::Other(param) => param.name.clone(),
+            _ => Param::from(self.clone()).name,
+        }
+    }
+
     pub fn accession(&self) -> Option<u32> {
         match self {
             ActivationMethod::CollisionInducedDissociation => Some(1000133),
@@ -276,6 +355,15 @@ impl ActivationMethod {
         }
     }
 
+    /// Whether this method supplements another activation method, as in EThcD or ETciD
+    pub fn is_supplemental(&self) -> bool {
+        matches!(
+            self,
+            Self::SupplementalCollisionInducedDissociation
+                | Self::SupplementalBeamTypeCollisionInducedDissociation
+        )
+    }
+
     pub fn controlled_vocabulary(&self) -> Option<ControlledVocabulary> {
         match self {
             ActivationMethod::Other(param) => param.controlled_vocabulary(),
@@ -394,25 +482,132 @@ impl<P: ParamLike + Into<Param>> From<P> for ActivationMethod {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// An energy applied to activate the precursor ion
+pub struct ActivationEnergy {
+    pub value: f32,
+    /// [`Unit::Electronvolt`] for an absolute energy or [`Unit::PercentElectronVolt`]
+    /// for a normalized collision energy
+    pub unit: Unit,
+    /// Whether the energy was applied by a supplemental activation method
+    pub supplemental: bool,
+}
+
+impl ActivationEnergy {
+    pub fn new(value: f32, unit: Unit, supplemental: bool) -> Self {
+        Self {
+            value,
+            unit,
+            supplemental,
+        }
+    }
+
+    /// Whether this is a normalized collision energy rather than an absolute one
+    pub fn is_normalized(&self) -> bool {
+        self.unit == Unit::PercentElectronVolt
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
-/// Describes the activation method used to dissociate the precursor ion
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Describes the activation methods used to dissociate the precursor ion. Combined
+/// activation like EThcD lists every method in the order they were reported.
 pub struct Activation {
-    _method: Option<ActivationMethod>,
-    pub energy: f32,
+    #[cfg_attr(feature = "serde", serde(rename = "methods"))]
+    _methods: Vec<ActivationMethod>,
+    /// The energies applied, of which stepped collision energy experiments have several
+    pub energies: Vec<ActivationEnergy>,
     pub params: ParamList,
 }
 
 impl Activation {
+    /// The first activation method, if any
     pub fn method(&self) -> Option<&ActivationMethod> {
-        if self._method.is_some() {
-            self._method.as_ref()
-        } else {
+        self._methods.first()
+    }
+
+    pub fn method_mut(&mut self) -> Option<&mut ActivationMethod> {
+        self._methods.first_mut()
+    }
+
+    /// All of the activation methods that were combined
+    pub fn methods(&self) -> &[ActivationMethod] {
+        &self._methods
+    }
+
+    pub fn methods_mut(&mut self) -> &mut Vec<ActivationMethod> {
+        &mut self._methods
+    }
+
+    pub fn add_method(&mut self, method: ActivationMethod) {
+        self._methods.push(method)
+    }
+
+    /// Whether more than one activation method was used
+    pub fn is_combined(&self) -> bool {
+        self._methods.len() > 1
+    }
+
+    /// Whether any of the activation methods is supplemental
+    pub fn has_supplemental_activation(&self) -> bool {
+        self._methods.iter().any(|method| method.is_supplemental())
+    }
+
+    /// Whether any of the activation methods is collisional. This is `None` if
+    /// there are no methods, or if none are known to be collisional but some
+    /// are of an unknown kind.
+    pub fn is_collisional(&self) -> Option<bool> {
+        let mut saw_unknown = false;
+        for method in self._methods.iter() {
+            match method.is_collisional() {
+                Some(true) => return Some(true),
+                Some(false) => {}
+                None => saw_unknown = true,
+            }
+        }
+        if saw_unknown || self._methods.is_empty() {
             None
+        } else {
+            Some(false)
         }
     }
 
-    pub fn method_mut(&mut self) -> &mut Option<ActivationMethod> {
-        &mut self._method
+    /// The first energy applied by the primary activation method, or 0 if none was given
+    pub fn energy(&self) -> f32 {
+        self.energies
+            .iter()
+            .find(|energy| !energy.supplemental)
+            .map(|energy| energy.value)
+            .unwrap_or_default()
+    }
+
+    pub fn add_energy(&mut self, energy: ActivationEnergy) {
+        self.energies.push(energy)
+    }
+
+    /// Store `param` as an activation method, an energy or, failing either, as a
+    /// plain parameter
+    pub fn fill_param(&mut self, param: Param) {
+        if Self::is_param_activation(&param) {
+            self.add_method(param.into());
+            return;
+        }
+        let (default_unit, supplemental) = match param.name.as_ref() {
+            "collision energy" | "activation energy" => (Unit::Electronvolt, false),
+            "supplemental collision energy" => (Unit::Electronvolt, true),
+            "normalized collision energy" => (Unit::PercentElectronVolt, false),
+            _ => {
+                self.params.push(param);
+                return;
+            }
+        };
+        let unit = match param.unit {
+            Unit::Unknown => default_unit,
+            unit => unit,
+        };
+        let value = param.parse().expect("Failed to parse activation energy");
+        self.add_energy(ActivationEnergy::new(value, unit, supplemental));
     }
 
     pub fn is_param_activation<P: ParamLike>(p: &P) -> bool {
@@ -470,27 +665,50 @@ impl Activation {
             .map(|(i, _)| i)
             .next();
         if let Some(hit) = found {
-            self._method = Some(self.params.remove(hit).into());
+            self._methods.push(self.params.remove(hit).into());
         }
     }
 }
 
+/// The [`SelectedIon`] reported for a [`Precursor`] without any selected ions
+static EMPTY_SELECTED_ION: SelectedIon = SelectedIon {
+    mz: 0.0,
+    intensity: 0.0,
+    charge: None,
+    params: None,
+};
+
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Describes the precursor ion of the owning spectrum.
 pub struct Precursor {
-    /// Describes the selected ion's properties
-    pub ion: SelectedIon,
+    /// Describes the selected ions' properties. There is usually only one, but
+    /// multiplexed or co-isolated precursors may report several.
+    pub ions: Vec<SelectedIon>,
     /// Describes the isolation window around the selected ion
     pub isolation_window: IsolationWindow,
     /// The precursor scan ID, if given
     pub precursor_id: Option<String>,
     /// The product scan ID, if given
     pub product_id: Option<String>,
+    /// The ID of the [`SourceFile`](crate::meta::SourceFile) containing the precursor scan,
+    /// if it is not the same file as the product scan
+    pub source_file_id: Option<String>,
     /// The activation process applied to the precursor ion
     pub activation: Activation,
 }
 
 impl Precursor {
+    /// Add another selected ion to this precursor
+    pub fn add_ion(&mut self, ion: SelectedIon) {
+        self.ions.push(ion)
+    }
+
+    /// Iterate over the selected ions of this precursor
+    pub fn iter(&self) -> std::slice::Iter<'_, SelectedIon> {
+        self.ions.iter()
+    }
+
     /// Given a ScanSource object, look up the precursor scan in it.
     /// This is useful when examining the area *around* where the precursor
     /// ion was or to obtain a snapshot of the retention time when the spectrum
@@ -529,8 +747,16 @@ impl Precursor {
 A trait for abstracting over how a precursor ion is described, immutably.
 */
 pub trait PrecursorSelection {
-    /// Describes the selected ion's properties
+    /// Describes the first selected ion's properties, if there are any selected ions
+    fn first_ion(&self) -> Option<&SelectedIon>;
+    /// Describes the first selected ion's properties.
+    ///
+    /// This is kept for compatibility with code written when a precursor had exactly one
+    /// selected ion. If there are no selected ions, a placeholder with an m/z of 0 and no
+    /// charge is returned, so prefer [`PrecursorSelection::first_ion`].
     fn ion(&self) -> &SelectedIon;
+    /// Describes all of the selected ions' properties
+    fn ions(&self) -> &[SelectedIon];
     /// Describes the isolation window around the selected ion
     fn isolation_window(&self) -> &IsolationWindow;
     /// The precursor scan ID, if given
@@ -540,14 +766,27 @@ pub trait PrecursorSelection {
     /// The activation process applied to the precursor ion
     fn activation(&self) -> &Activation;
 
+    /// Describes the first selected ion's properties, mutably.
+    ///
+    /// If there are no selected ions, a default [`SelectedIon`] is added first so that
+    /// there is one to return. Use [`PrecursorSelection::ions_mut`] to avoid this.
     fn ion_mut(&mut self) -> &mut SelectedIon;
+    fn ions_mut(&mut self) -> &mut Vec<SelectedIon>;
     fn activation_mut(&mut self) -> &mut Activation;
     fn isolation_window_mut(&mut self) -> &mut IsolationWindow;
 }
 
 impl PrecursorSelection for Precursor {
+    fn first_ion(&self) -> Option<&SelectedIon> {
+        self.ions.first()
+    }
+
     fn ion(&self) -> &SelectedIon {
-        &self.ion
+        self.ions.first().unwrap_or(&EMPTY_SELECTED_ION)
+    }
+
+    fn ions(&self) -> &[SelectedIon] {
+        &self.ions
     }
 
     fn isolation_window(&self) -> &IsolationWindow {
@@ -567,7 +806,14 @@ impl PrecursorSelection for Precursor {
     }
 
     fn ion_mut(&mut self) -> &mut SelectedIon {
-        &mut self.ion
+        if self.ions.is_empty() {
+            self.ions.push(SelectedIon::default());
+        }
+        &mut self.ions[0]
+    }
+
+    fn ions_mut(&mut self) -> &mut Vec<SelectedIon> {
+        &mut self.ions
     }
 
     fn activation_mut(&mut self) -> &mut Activation {
@@ -606,6 +852,7 @@ or `Unknown` (0). The `Unknown` state is the default.
 */
 #[repr(i8)]
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScanPolarity {
     #[default]
     Unknown = 0,
@@ -627,6 +874,7 @@ or an assumed level, the `Unknown` option is retained for partial initialization
 */
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SignalContinuity {
     #[default]
     Unknown = 0,
@@ -646,6 +894,7 @@ within a particular run. This forms the basis for a large portion of the [`Spect
 trait.
 */
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpectrumDescription {
     pub id: String,
     pub index: usize,
@@ -656,7 +905,30 @@ pub struct SpectrumDescription {
 
     pub params: ParamList,
     pub acquisition: Acquisition,
-    pub precursor: Option<Precursor>,
+    /// The precursors this spectrum was derived from. Most MSn spectra have exactly one,
+    /// but multiplexed spectra may have several.
+    pub precursors: Vec<Precursor>,
+    /// The ID of the [`DataProcessing`](crate::meta::DataProcessing) applied to this spectrum,
+    /// if it differs from the spectrum list's default
+    pub data_processing_id: Option<String>,
+
+    /// The referenceable param groups this spectrum referred to in the source document. Their
+    /// params are also present in [`SpectrumDescription::params`] or the typed fields they
+    /// map to.
+    pub param_groups: Vec<ParamGroup>,
+    /// Elements of the spectrum the reader did not recognise
+    pub unknown_elements: Vec<UnknownElement>,
+}
+
+impl SpectrumDescription {
+    /// The first of this spectrum's [`SpectrumDescription::precursors`], if there are any
+    pub fn precursor(&self) -> Option<&Precursor> {
+        self.precursors.first()
+    }
+
+    pub fn precursor_mut(&mut self) -> Option<&mut Precursor> {
+        self.precursors.first_mut()
+    }
 }
 
 impl_param_described!(Activation, SpectrumDescription);
@@ -665,6 +937,7 @@ impl_param_described_deferred!(SelectedIon, Acquisition, ScanEvent);
 
 /// Types of chromatograms enumerated in the PSI-MS controlled vocabulary
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChromatogramType {
     #[default]
     Unknown,
@@ -678,6 +951,7 @@ pub enum ChromatogramType {
 /// The set of descriptive metadata that give context for how a chromatogram was
 /// recorded.
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChromatogramDescription {
     pub id: String,
     pub index: usize,
@@ -689,4 +963,58 @@ pub struct ChromatogramDescription {
     pub precursor: Option<Precursor>,
 }
 
-impl_param_described!(ChromatogramDescription);
\ No newline at end of file
+impl_param_described!(ChromatogramDescription);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_precursor_without_ions() {
+        let mut precursor = Precursor {
+            ions: Vec::new(),
+            ..Default::default()
+        };
+        assert!(precursor.first_ion().is_none());
+        assert_eq!(precursor.ion().mz, 0.0);
+        assert!(precursor.ions().is_empty());
+
+        precursor.ion_mut().mz = 500.0;
+        assert_eq!(precursor.ions().len(), 1);
+        assert_eq!(precursor.first_ion().map(|ion| ion.mz), Some(500.0));
+    }
+
+    #[test]
+    fn test_activation_method_from_name() {
+        let method = ActivationMethod::from_name("electron transfer dissociation");
+        assert_eq!(method, ActivationMethod::ElectronTransferDissociation);
+        assert_eq!(method.accession(), Some(1000598));
+
+        let method = ActivationMethod::from_name("custom activation");
+        assert!(matches!(method, ActivationMethod::Other(_)));
+        assert_eq!(method.name(), "custom activation");
+        assert_eq!(method.accession(), None);
+    }
+
+    #[test]
+    fn test_is_collisional_with_unknown_method() {
+        // An unknown method makes collisionality unknown regardless of order
+        let unknown = ActivationMethod::Other(Box::new(Param::new_key_value(
+            "custom activation".to_string(),
+            String::new(),
+        )));
+        let mut activation = Activation::default();
+        activation.add_method(unknown.clone());
+        activation.add_method(ActivationMethod::ElectronTransferDissociation);
+        assert_eq!(activation.is_collisional(), None);
+
+        let mut activation = Activation::default();
+        activation.add_method(ActivationMethod::ElectronTransferDissociation);
+        activation.add_method(unknown);
+        assert_eq!(activation.is_collisional(), None);
+
+        let mut activation = Activation::default();
+        activation.add_method(ActivationMethod::ElectronTransferDissociation);
+        activation.add_method(ActivationMethod::SupplementalBeamTypeCollisionInducedDissociation);
+        assert_eq!(activation.is_collisional(), Some(true));
+    }
+}
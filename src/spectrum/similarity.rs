@@ -0,0 +1,488 @@
+/*!
+Score the similarity of two spectra's peak lists.
+
+Peaks from the two spectra are first paired up within an error [`Tolerance`] in a
+[`PeakAlignment`], from which each of the scores in [`SimilarityMethod`] can be computed
+without matching the peaks again. Centroided spectra are compared by m/z, while deconvoluted
+spectra are compared by neutral mass, so a centroided spectrum cannot be compared with a
+deconvoluted one.
+
+```rust
+use std::fs::File;
+use mzpeaks::Tolerance;
+use mzdata::io::MGFReader;
+use mzdata::spectrum::similarity::{PeakAlignment, SimilarityMethod};
+
+let mut reader = MGFReader::new(File::open("./test/data/small.mgf").unwrap());
+let first = reader.next().unwrap();
+let second = reader.next().unwrap();
+let alignment = PeakAlignment::from_spectra(&first, &second, Tolerance::PPM(20.0)).unwrap();
+println!(
+    "{} shared peaks, cosine {:0.3}",
+    alignment.shared_peaks(),
+    alignment.score(SimilarityMethod::Cosine)
+);
+```
+*/
+use std::f64::consts::PI;
+
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike, MZPeakSetType, MassPeakSetType, Tolerance};
+use thiserror::Error;
+
+use crate::spectrum::bindata::ArrayRetrievalError;
+use crate::spectrum::{PeakDataLevel, SignalContinuity, SpectrumLike};
+
+/// The ways two spectra may be scored against each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimilarityMethod {
+    /// The normalized dot product of the two intensity vectors, between 0 and 1
+    Cosine,
+    /// The spectral contrast angle, `1 - 2 * acos(cosine) / pi`, between 0 and 1
+    SpectralContrastAngle,
+    /// The unweighted spectral entropy similarity of Li et al. (2021), between 0 and 1
+    Entropy,
+    /// The spectral entropy similarity after entropy-based intensity weighting, between 0 and 1
+    WeightedEntropy,
+    /// The number of peaks matched between the two spectra
+    SharedPeaks,
+}
+
+/// The errors that can occur when collecting the peaks of a spectrum to compare
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum SimilarityError {
+    #[error("Spectrum {0} has no peak data")]
+    MissingPeaks(String),
+    #[error("Spectrum {0} is in profile mode and must be centroided before comparing")]
+    ProfileData(String),
+    #[error("Cannot compare m/z peaks with neutral mass peaks")]
+    MismatchedPeakTypes,
+    #[error("Failed to read the peak arrays: {0}")]
+    ArrayRetrievalError(#[from] ArrayRetrievalError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PeakCoordinate {
+    MassToCharge,
+    NeutralMass,
+}
+
+/// A pair of peaks, one from each spectrum, matched within the error tolerance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeakMatch {
+    pub query_index: usize,
+    pub reference_index: usize,
+}
+
+/**
+A one-to-one matching between the peaks of a query and a reference spectrum.
+
+When a peak falls within the error tolerance of several peaks in the other spectrum, the pairs
+with the largest intensity products are matched first.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeakAlignment {
+    query: Vec<(f64, f32)>,
+    reference: Vec<(f64, f32)>,
+    matches: Vec<PeakMatch>,
+}
+
+impl PeakAlignment {
+    /// Match two lists of `(coordinate, intensity)` pairs, which need not be sorted
+    pub fn new(
+        mut query: Vec<(f64, f32)>,
+        mut reference: Vec<(f64, f32)>,
+        error_tolerance: Tolerance,
+    ) -> Self {
+        query.sort_by(|a, b| a.0.total_cmp(&b.0));
+        reference.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let matches = match_peaks(&query, &reference, error_tolerance);
+        Self {
+            query,
+            reference,
+            matches,
+        }
+    }
+
+    /// Match two centroid peak sets by m/z
+    pub fn from_centroids<C: CentroidLike>(
+        query: &MZPeakSetType<C>,
+        reference: &MZPeakSetType<C>,
+        error_tolerance: Tolerance,
+    ) -> Self {
+        Self::new(
+            query.iter().map(|p| (p.mz(), p.intensity())).collect(),
+            reference.iter().map(|p| (p.mz(), p.intensity())).collect(),
+            error_tolerance,
+        )
+    }
+
+    /// Match two deconvoluted peak sets by neutral mass
+    pub fn from_deconvoluted<D: DeconvolutedCentroidLike>(
+        query: &MassPeakSetType<D>,
+        reference: &MassPeakSetType<D>,
+        error_tolerance: Tolerance,
+    ) -> Self {
+        Self::new(
+            query
+                .iter()
+                .map(|p| (p.neutral_mass(), p.intensity()))
+                .collect(),
+            reference
+                .iter()
+                .map(|p| (p.neutral_mass(), p.intensity()))
+                .collect(),
+            error_tolerance,
+        )
+    }
+
+    /// Match the most processed peak data of two spectra, as given by [`SpectrumLike::peaks`].
+    ///
+    /// Raw data arrays are used as-is unless the spectrum is in profile mode.
+    pub fn from_spectra<
+        C1: CentroidLike,
+        D1: DeconvolutedCentroidLike,
+        C2: CentroidLike,
+        D2: DeconvolutedCentroidLike,
+        S1: SpectrumLike<C1, D1>,
+        S2: SpectrumLike<C2, D2>,
+    >(
+        query: &S1,
+        reference: &S2,
+        error_tolerance: Tolerance,
+    ) -> Result<Self, SimilarityError> {
+        let (query_kind, query_peaks) = peak_list(query)?;
+        let (reference_kind, reference_peaks) = peak_list(reference)?;
+        if query_kind != reference_kind {
+            return Err(SimilarityError::MismatchedPeakTypes);
+        }
+        Ok(Self::new(query_peaks, reference_peaks, error_tolerance))
+    }
+
+    /// The matched peak pairs, ordered by query peak
+    pub fn matches(&self) -> &[PeakMatch] {
+        &self.matches
+    }
+
+    pub fn shared_peaks(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// The normalized dot product of the query and reference intensities
+    pub fn cosine(&self) -> f64 {
+        let query_norm: f64 = self.query.iter().map(|(_, i)| (*i as f64).powi(2)).sum();
+        let reference_norm: f64 = self
+            .reference
+            .iter()
+            .map(|(_, i)| (*i as f64).powi(2))
+            .sum();
+        if query_norm == 0.0 || reference_norm == 0.0 {
+            return 0.0;
+        }
+        let dot: f64 = self
+            .matches
+            .iter()
+            .map(|m| {
+                self.query[m.query_index].1 as f64 * self.reference[m.reference_index].1 as f64
+            })
+            .sum();
+        (dot / (query_norm.sqrt() * reference_norm.sqrt())).min(1.0)
+    }
+
+    pub fn spectral_contrast_angle(&self) -> f64 {
+        1.0 - 2.0 * self.cosine().acos() / PI
+    }
+
+    pub fn entropy_similarity(&self) -> f64 {
+        self.entropy_similarity_of(
+            normalize(self.query.iter().map(|(_, i)| *i as f64).collect()),
+            normalize(self.reference.iter().map(|(_, i)| *i as f64).collect()),
+        )
+    }
+
+    pub fn weighted_entropy_similarity(&self) -> f64 {
+        self.entropy_similarity_of(
+            entropy_weight(normalize(
+                self.query.iter().map(|(_, i)| *i as f64).collect(),
+            )),
+            entropy_weight(normalize(
+                self.reference.iter().map(|(_, i)| *i as f64).collect(),
+            )),
+        )
+    }
+
+    pub fn score(&self, method: SimilarityMethod) -> f64 {
+        match method {
+            SimilarityMethod::Cosine => self.cosine(),
+            SimilarityMethod::SpectralContrastAngle => self.spectral_contrast_angle(),
+            SimilarityMethod::Entropy => self.entropy_similarity(),
+            SimilarityMethod::WeightedEntropy => self.weighted_entropy_similarity(),
+            SimilarityMethod::SharedPeaks => self.shared_peaks() as f64,
+        }
+    }
+
+    fn entropy_similarity_of(&self, query: Vec<f64>, reference: Vec<f64>) -> f64 {
+        if query.is_empty() || reference.is_empty() {
+            return 0.0;
+        }
+        let mut query_matched = vec![false; query.len()];
+        let mut reference_matched = vec![false; reference.len()];
+        let mut merged = Vec::with_capacity(query.len() + reference.len());
+        for m in self.matches.iter() {
+            query_matched[m.query_index] = true;
+            reference_matched[m.reference_index] = true;
+            merged.push((query[m.query_index] + reference[m.reference_index]) / 2.0);
+        }
+        merged.extend(
+            query
+                .iter()
+                .zip(query_matched)
+                .filter(|(_, matched)| !matched)
+                .map(|(i, _)| i / 2.0),
+        );
+        merged.extend(
+            reference
+                .iter()
+                .zip(reference_matched)
+                .filter(|(_, matched)| !matched)
+                .map(|(i, _)| i / 2.0),
+        );
+        let distance = 2.0 * entropy(&merged) - entropy(&query) - entropy(&reference);
+        (1.0 - distance / 4f64.ln()).clamp(0.0, 1.0)
+    }
+}
+
+/// Score `query` against `reference` with `method`, matching peaks within `error_tolerance`
+pub fn spectrum_similarity<
+    C1: CentroidLike,
+    D1: DeconvolutedCentroidLike,
+    C2: CentroidLike,
+    D2: DeconvolutedCentroidLike,
+    S1: SpectrumLike<C1, D1>,
+    S2: SpectrumLike<C2, D2>,
+>(
+    query: &S1,
+    reference: &S2,
+    method: SimilarityMethod,
+    error_tolerance: Tolerance,
+) -> Result<f64, SimilarityError> {
+    PeakAlignment::from_spectra(query, reference, error_tolerance)
+        .map(|alignment| alignment.score(method))
+}
+
+fn peak_list<C: CentroidLike, D: DeconvolutedCentroidLike, S: SpectrumLike<C, D>>(
+    spectrum: &S,
+) -> Result<(PeakCoordinate, Vec<(f64, f32)>), SimilarityError> {
+    match spectrum.peaks() {
+        PeakDataLevel::Missing => Err(SimilarityError::MissingPeaks(spectrum.id().to_string())),
+        PeakDataLevel::RawData(arrays) => {
+            if spectrum.signal_continuity() == SignalContinuity::Profile {
+                return Err(SimilarityError::ProfileData(spectrum.id().to_string()));
+            }
+            let mzs = arrays.mzs()?;
+            let intensities = arrays.intensities()?;
+            let peaks = mzs
+                .iter()
+                .copied()
+                .zip(intensities.iter().copied())
+                .collect();
+            Ok((PeakCoordinate::MassToCharge, peaks))
+        }
+        PeakDataLevel::Centroid(peaks) => Ok((
+            PeakCoordinate::MassToCharge,
+            peaks.iter().map(|p| (p.mz(), p.intensity())).collect(),
+        )),
+        PeakDataLevel::Deconvoluted(peaks) => Ok((
+            PeakCoordinate::NeutralMass,
+            peaks
+                .iter()
+                .map(|p| (p.neutral_mass(), p.intensity()))
+                .collect(),
+        )),
+    }
+}
+
+/// Pair up the peaks of two coordinate-sorted peak lists, taking the candidate pairs with the
+/// largest intensity products first
+fn match_peaks(
+    query: &[(f64, f32)],
+    reference: &[(f64, f32)],
+    error_tolerance: Tolerance,
+) -> Vec<PeakMatch> {
+    let mut candidates = Vec::new();
+    let mut start = 0;
+    for (query_index, (coordinate, intensity)) in query.iter().enumerate() {
+        let (lower, upper) = error_tolerance.bounds(*coordinate);
+        while start < reference.len() && reference[start].0 < lower {
+            start += 1;
+        }
+        for (reference_index, (reference_coordinate, reference_intensity)) in
+            reference.iter().enumerate().skip(start)
+        {
+            if *reference_coordinate > upper {
+                break;
+            }
+            candidates.push((
+                query_index,
+                reference_index,
+                intensity * reference_intensity,
+            ));
+        }
+    }
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let mut query_used = vec![false; query.len()];
+    let mut reference_used = vec![false; reference.len()];
+    let mut matches = Vec::new();
+    for (query_index, reference_index, _) in candidates {
+        if query_used[query_index] || reference_used[reference_index] {
+            continue;
+        }
+        query_used[query_index] = true;
+        reference_used[reference_index] = true;
+        matches.push(PeakMatch {
+            query_index,
+            reference_index,
+        });
+    }
+    matches.sort_by_key(|m| m.query_index);
+    matches
+}
+
+fn normalize(mut intensities: Vec<f64>) -> Vec<f64> {
+    let total: f64 = intensities.iter().sum();
+    if total > 0.0 {
+        intensities.iter_mut().for_each(|i| *i /= total);
+    }
+    intensities
+}
+
+fn entropy(intensities: &[f64]) -> f64 {
+    -intensities
+        .iter()
+        .filter(|i| **i > 0.0)
+        .map(|i| i * i.ln())
+        .sum::<f64>()
+}
+
+/// Flatten low-entropy spectra so that a few intense peaks do not dominate the score
+fn entropy_weight(intensities: Vec<f64>) -> Vec<f64> {
+    let spectral_entropy = entropy(&intensities);
+    if spectral_entropy >= 3.0 {
+        return intensities;
+    }
+    let weight = 0.25 + 0.25 * spectral_entropy;
+    normalize(intensities.into_iter().map(|i| i.powf(weight)).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::{CentroidSpectrum, DeconvolutedSpectrum};
+    use mzpeaks::{CentroidPeak, DeconvolutedPeak};
+
+    fn make_centroids(peaks: &[(f64, f32)]) -> CentroidSpectrum {
+        let mut spectrum = CentroidSpectrum::default();
+        spectrum.description.signal_continuity = SignalContinuity::Centroid;
+        spectrum.peaks = peaks
+            .iter()
+            .enumerate()
+            .map(|(i, (mz, intensity))| CentroidPeak::new(*mz, *intensity, i as u32))
+            .collect();
+        spectrum
+    }
+
+    #[test]
+    fn test_identical() {
+        let spectrum = make_centroids(&[(200.1, 50.0), (350.2, 1000.0), (512.3, 250.0)]);
+        let alignment =
+            PeakAlignment::from_spectra(&spectrum, &spectrum, Tolerance::PPM(10.0)).unwrap();
+        assert_eq!(alignment.shared_peaks(), 3);
+        assert!((alignment.cosine() - 1.0).abs() < 1e-6);
+        assert!((alignment.spectral_contrast_angle() - 1.0).abs() < 1e-3);
+        assert!((alignment.entropy_similarity() - 1.0).abs() < 1e-6);
+        assert!((alignment.weighted_entropy_similarity() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_partial_and_disjoint() {
+        let query = make_centroids(&[(200.1, 50.0), (350.2, 1000.0), (512.3, 250.0)]);
+        let reference = make_centroids(&[(350.201, 900.0), (350.25, 800.0), (600.0, 100.0)]);
+        let alignment =
+            PeakAlignment::from_spectra(&query, &reference, Tolerance::PPM(10.0)).unwrap();
+        assert_eq!(
+            alignment.matches(),
+            &[PeakMatch {
+                query_index: 1,
+                reference_index: 0
+            }]
+        );
+        let cosine = alignment.cosine();
+        assert!(cosine > 0.0 && cosine < 1.0);
+        let entropy = alignment.entropy_similarity();
+        assert!(entropy > 0.0 && entropy < 1.0);
+
+        let disjoint = make_centroids(&[(100.0, 10.0), (700.0, 20.0)]);
+        for method in [
+            SimilarityMethod::Cosine,
+            SimilarityMethod::SpectralContrastAngle,
+            SimilarityMethod::Entropy,
+            SimilarityMethod::WeightedEntropy,
+            SimilarityMethod::SharedPeaks,
+        ] {
+            let score =
+                spectrum_similarity(&query, &disjoint, method, Tolerance::Da(0.02)).unwrap();
+            assert!(score.abs() < 1e-6, "{:?} = {}", method, score);
+        }
+    }
+
+    #[test]
+    fn test_deconvoluted() {
+        let query = DeconvolutedSpectrum {
+            deconvoluted_peaks: vec![
+                DeconvolutedPeak {
+                    neutral_mass: 1000.5,
+                    intensity: 100.0,
+                    charge: 2,
+                    index: 0,
+                },
+                DeconvolutedPeak {
+                    neutral_mass: 1500.7,
+                    intensity: 300.0,
+                    charge: 3,
+                    index: 1,
+                },
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let mut reference = query.clone();
+        reference.deconvoluted_peaks = vec![
+            DeconvolutedPeak {
+                neutral_mass: 1000.501,
+                intensity: 120.0,
+                charge: 1,
+                index: 0,
+            },
+            DeconvolutedPeak {
+                neutral_mass: 1500.7,
+                intensity: 280.0,
+                charge: 2,
+                index: 1,
+            },
+        ]
+        .into_iter()
+        .collect();
+        let alignment =
+            PeakAlignment::from_spectra(&query, &reference, Tolerance::PPM(5.0)).unwrap();
+        assert_eq!(alignment.shared_peaks(), 2);
+        assert!(alignment.cosine() > 0.99);
+
+        let centroids = make_centroids(&[(1000.5, 100.0)]);
+        assert_eq!(
+            PeakAlignment::from_spectra(&query, &centroids, Tolerance::PPM(5.0)),
+            Err(SimilarityError::MismatchedPeakTypes)
+        );
+    }
+}
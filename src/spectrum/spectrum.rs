@@ -185,24 +185,25 @@ pub trait SpectrumLike<
         &self.description().acquisition
     }
 
-    /// Access the precursor information, if it exists.
+    /// Access the first precursor's information, if it exists.
     #[inline]
     fn precursor(&self) -> Option<&Precursor> {
-        let desc = self.description();
-        if let Some(precursor) = &desc.precursor {
-            Some(precursor)
-        } else {
-            None
-        }
+        self.description().precursors.first()
     }
 
     fn precursor_mut(&mut self) -> Option<&mut Precursor> {
-        let desc = self.description_mut();
-        if let Some(precursor) = desc.precursor.as_mut() {
-            Some(precursor)
-        } else {
-            None
-        }
+        self.description_mut().precursors.first_mut()
+    }
+
+    /// Iterate over all of the precursors of this spectrum, of which
+    /// multiplexed spectra may have more than one.
+    #[inline]
+    fn precursor_iter(&self) -> std::slice::Iter<'_, Precursor> {
+        self.description().precursors.iter()
+    }
+
+    fn precursor_iter_mut(&mut self) -> std::slice::IterMut<'_, Precursor> {
+        self.description_mut().precursors.iter_mut()
     }
 
     /// A shortcut method to retrieve the scan start time
@@ -216,6 +217,15 @@ pub trait SpectrumLike<
         }
     }
 
+    /// A shortcut method to retrieve the FAIMS compensation voltage
+    /// of the first scan of a spectrum, if one was used.
+    #[inline]
+    fn compensation_voltage(&self) -> Option<f32> {
+        self.acquisition()
+            .first_scan()
+            .and_then(|evt| evt.compensation_voltage)
+    }
+
     /// Access the MS exponentiation level
     #[inline]
     fn ms_level(&self) -> u8 {
@@ -259,6 +269,7 @@ pub trait SpectrumLike<
 }
 
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents a spectrum that hasn't been processed yet, with only
 /// data arrays, potentially no discrete peaks.
 pub struct RawSpectrum {
@@ -439,6 +450,7 @@ impl SpectrumLike for RawSpectrum {
 }
 
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents a spectrum that has been centroided
 pub struct CentroidSpectrumType<C: CentroidLike + Default> {
     /// The spectrum metadata describing acquisition conditions and details.
@@ -485,6 +497,7 @@ pub type CentroidSpectrum = CentroidSpectrumType<CentroidPeak>;
 
 /// Represents a spectrum that has been centroided, deisotoped, and charge state deconvolved
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeconvolutedSpectrumType<D: DeconvolutedCentroidLike + Default> {
     /// The spectrum metadata describing acquisition conditions and details.
     pub description: SpectrumDescription,
@@ -516,6 +529,7 @@ impl<D: DeconvolutedCentroidLike + Default> SpectrumLike<CentroidPeak, D>
 pub type DeconvolutedSpectrum = DeconvolutedSpectrumType<DeconvolutedPeak>;
 
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represent a spectrum with multiple layers of representation of the
 /// peak data.
 pub struct MultiLayerSpectrum<
@@ -907,4 +921,32 @@ mod test {
             assert!((p.mz() - 563.739).abs() < 1e-3)
         }
     }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use super::*;
+        use crate::io::mzml::MzMLReader;
+        use crate::prelude::*;
+
+        let reader = MzMLReader::open_path("./test/data/three_test_scans.mzML")
+            .expect("Failed to open test file");
+        for scan in reader {
+            let encoded = serde_json::to_string(&scan).expect("Failed to serialize spectrum");
+            let decoded: MultiLayerSpectrum =
+                serde_json::from_str(&encoded).expect("Failed to deserialize spectrum");
+            assert_eq!(scan.description, decoded.description);
+
+            let arrays = scan.arrays.as_ref().unwrap();
+            let decoded_arrays = decoded.arrays.as_ref().unwrap();
+            assert_eq!(arrays.len(), decoded_arrays.len());
+            for (array_type, array) in arrays.iter() {
+                let decoded_array = decoded_arrays.get(array_type).unwrap();
+                assert_eq!(array.data, decoded_array.data);
+                assert_eq!(array.dtype, decoded_array.dtype);
+                assert_eq!(array.compression, decoded_array.compression);
+                assert_eq!(array.unit, decoded_array.unit);
+                assert_eq!(array.params, decoded_array.params);
+            }
+        }
+    }
 }
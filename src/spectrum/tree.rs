@@ -0,0 +1,516 @@
+/*!
+Represent MSn acquisitions as trees of spectra.
+
+[`SpectrumGroup`] stores all MSn spectra associated with an MS1 spectrum in a single flat list.
+For multi-stage acquisitions like SPS-MS3 or MSn fragmentation trees, [`SpectrumTree`] instead
+arranges each spectrum beneath the spectrum it selected its precursor from, as named by
+[`Precursor::precursor_id`](crate::spectrum::Precursor::precursor_id).
+*/
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use crate::io::{RandomAccessSpectrumIterator, ScanSource, SpectrumAccessError};
+use crate::spectrum::{MultiLayerSpectrum, SpectrumGroup, SpectrumGroupingIterator, SpectrumLike};
+
+/// A spectrum and all the spectra whose precursors were selected from it
+#[derive(Debug, Clone)]
+pub struct SpectrumTreeNode<C = CentroidPeak, D = DeconvolutedPeak, S = MultiLayerSpectrum<C, D>>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    pub spectrum: S,
+    /// The spectra derived from this node's spectrum, in acquisition order
+    pub children: Vec<SpectrumTreeNode<C, D, S>>,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<C, D, S> SpectrumTreeNode<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    pub fn new(spectrum: S, children: Vec<SpectrumTreeNode<C, D, S>>) -> Self {
+        Self {
+            spectrum,
+            children,
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        self.spectrum.id()
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// The number of spectra in this subtree, including this node
+    pub fn subtree_size(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|c| c.subtree_size())
+            .sum::<usize>()
+    }
+
+    /// The number of levels in this subtree, including this node
+    pub fn depth(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|c| c.depth())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Find the node holding the spectrum with the native ID `id` in this subtree
+    pub fn find(&self, id: &str) -> Option<&SpectrumTreeNode<C, D, S>> {
+        if self.id() == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(id))
+    }
+
+    fn path_to<'a>(&'a self, id: &str, path: &mut Vec<&'a S>) -> bool {
+        path.push(&self.spectrum);
+        if self.id() == id || self.children.iter().any(|c| c.path_to(id, path)) {
+            true
+        } else {
+            path.pop();
+            false
+        }
+    }
+
+    fn flatten_into(self, products: &mut Vec<S>) {
+        products.push(self.spectrum);
+        for child in self.children {
+            child.flatten_into(products);
+        }
+    }
+}
+
+/**
+A tree of spectra rooted at an optional MS1 spectrum, where each MSn spectrum is placed beneath
+the spectrum its precursor was selected from.
+
+MSn spectra whose precursor spectrum is the MS1 spectrum, is not present in the tree, or is not
+given are placed at the top level, in [`SpectrumTree::children`].
+*/
+#[derive(Debug, Clone)]
+pub struct SpectrumTree<C = CentroidPeak, D = DeconvolutedPeak, S = MultiLayerSpectrum<C, D>>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    /// The MS1 spectrum of the tree. This may be absent when the source does not contain any MS1 spectra
+    pub precursor: Option<S>,
+    /// The MSn spectra selected directly from the MS1 spectrum
+    pub children: Vec<SpectrumTreeNode<C, D, S>>,
+}
+
+impl<C, D, S> Default for SpectrumTree<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    fn default() -> Self {
+        Self {
+            precursor: None,
+            children: Vec::new(),
+        }
+    }
+}
+
+impl<C, D, S> SpectrumTree<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    /// Arrange `products` into a tree beneath `precursor` using each product's precursor spectrum ID
+    pub fn from_parts(precursor: Option<S>, mut products: Vec<S>) -> Self {
+        products.sort_by_key(|s| s.index());
+        let n = products.len();
+        let positions: HashMap<&str, usize> = products
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.id(), i))
+            .collect();
+        let mut parents: Vec<Option<usize>> = products
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                s.precursor()
+                    .and_then(|p| p.precursor_id.as_deref())
+                    .and_then(|pid| positions.get(pid).copied())
+                    .filter(|j| *j != i)
+            })
+            .collect();
+        drop(positions);
+
+        // Break any reference cycles so every chain of parents terminates
+        for (i, product) in products.iter().enumerate() {
+            let mut seen = HashSet::new();
+            let mut cur = i;
+            seen.insert(cur);
+            while let Some(parent) = parents[cur] {
+                if !seen.insert(parent) {
+                    log::warn!("Precursor reference cycle detected for {}", product.id());
+                    parents[cur] = None;
+                    break;
+                }
+                cur = parent;
+            }
+        }
+
+        let mut children_of: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut top_level = Vec::new();
+        for (i, parent) in parents.iter().enumerate() {
+            match parent {
+                Some(j) => children_of[*j].push(i),
+                None => top_level.push(i),
+            }
+        }
+
+        let mut slots: Vec<Option<S>> = products.into_iter().map(Some).collect();
+        let children = top_level
+            .into_iter()
+            .map(|i| Self::build_node(i, &mut slots, &children_of))
+            .collect();
+        Self {
+            precursor,
+            children,
+        }
+    }
+
+    fn build_node(
+        i: usize,
+        slots: &mut Vec<Option<S>>,
+        children_of: &[Vec<usize>],
+    ) -> SpectrumTreeNode<C, D, S> {
+        let spectrum = slots[i].take().unwrap();
+        let children = children_of[i]
+            .iter()
+            .map(|j| Self::build_node(*j, slots, children_of))
+            .collect();
+        SpectrumTreeNode::new(spectrum, children)
+    }
+
+    /// Flatten the tree back into a [`SpectrumGroup`], with products in depth-first order
+    pub fn into_group(self) -> SpectrumGroup<C, D, S> {
+        let mut group = SpectrumGroup::default();
+        group.precursor = self.precursor;
+        for child in self.children {
+            child.flatten_into(&mut group.products);
+        }
+        group
+    }
+
+    /// The total number of spectra in the tree, including the MS1 spectrum
+    pub fn len(&self) -> usize {
+        self.precursor.iter().count()
+            + self
+                .children
+                .iter()
+                .map(|c| c.subtree_size())
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of levels of MSn spectra beneath the MS1 spectrum
+    pub fn depth(&self) -> usize {
+        self.children
+            .iter()
+            .map(|c| c.depth())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Find the node holding the MSn spectrum with the native ID `id`
+    pub fn find(&self, id: &str) -> Option<&SpectrumTreeNode<C, D, S>> {
+        self.children.iter().find_map(|c| c.find(id))
+    }
+
+    /// Retrieve the spectrum with the native ID `id`, including the MS1 spectrum
+    pub fn get_spectrum_by_id(&self, id: &str) -> Option<&S> {
+        match self.precursor.as_ref() {
+            Some(p) if p.id() == id => Some(p),
+            _ => self.find(id).map(|n| &n.spectrum),
+        }
+    }
+
+    /// The sequence of spectra from the root of the tree down to the spectrum with the native ID `id`,
+    /// inclusive. Returns an empty list if `id` is not in the tree.
+    pub fn path_to(&self, id: &str) -> Vec<&S> {
+        let mut path = Vec::new();
+        if let Some(p) = self.precursor.as_ref() {
+            if p.id() == id {
+                path.push(p);
+                return path;
+            }
+        }
+        for child in self.children.iter() {
+            if child.path_to(id, &mut path) {
+                if let Some(p) = self.precursor.as_ref() {
+                    path.insert(0, p);
+                }
+                return path;
+            }
+        }
+        Vec::new()
+    }
+
+    /// The spectrum the spectrum with the native ID `id` selected its precursor from
+    pub fn parent_of(&self, id: &str) -> Option<&S> {
+        let path = self.path_to(id);
+        if path.len() > 1 {
+            Some(path[path.len() - 2])
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over all spectra in the tree in depth-first order, yielding each spectrum
+    /// with its depth. The MS1 spectrum has depth `0`.
+    pub fn iter(&self) -> SpectrumTreeIter<'_, C, D, S> {
+        SpectrumTreeIter::new(self)
+    }
+
+    /// Iterate over all spectra with MS level `ms_level`
+    pub fn at_level(&self, ms_level: u8) -> impl Iterator<Item = &S> + '_ {
+        self.iter()
+            .map(|(_, s)| s)
+            .filter(move |s| s.ms_level() == ms_level)
+    }
+
+    /// Iterate over the spectra which have no derived spectra
+    pub fn leaves(&self) -> impl Iterator<Item = &S> + '_ {
+        let mut stack: Vec<&SpectrumTreeNode<C, D, S>> = self.children.iter().rev().collect();
+        std::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                if node.is_leaf() {
+                    return Some(&node.spectrum);
+                }
+                stack.extend(node.children.iter().rev());
+            }
+            None
+        })
+    }
+}
+
+impl<C, D, S> From<SpectrumGroup<C, D, S>> for SpectrumTree<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    fn from(value: SpectrumGroup<C, D, S>) -> Self {
+        Self::from_parts(value.precursor, value.products)
+    }
+}
+
+/// Iterate over the spectra of a [`SpectrumTree`] in depth-first order
+pub struct SpectrumTreeIter<
+    'a,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    precursor: Option<&'a S>,
+    stack: Vec<(usize, &'a SpectrumTreeNode<C, D, S>)>,
+}
+
+impl<'a, C, D, S> SpectrumTreeIter<'a, C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    pub fn new(tree: &'a SpectrumTree<C, D, S>) -> Self {
+        Self {
+            precursor: tree.precursor.as_ref(),
+            stack: tree.children.iter().rev().map(|c| (1, c)).collect(),
+        }
+    }
+}
+
+impl<'a, C, D, S> Iterator for SpectrumTreeIter<'a, C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    type Item = (usize, &'a S);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(prec) = self.precursor.take() {
+            return Some((0, prec));
+        }
+        let (depth, node) = self.stack.pop()?;
+        self.stack
+            .extend(node.children.iter().rev().map(|c| (depth + 1, c)));
+        Some((depth, &node.spectrum))
+    }
+}
+
+/**
+A wrapper around [`SpectrumGroupingIterator`] which yields each group as a [`SpectrumTree`].
+
+Because a group is only emitted once all of its MSn spectra have been read, including MS3 and
+higher spectra, each tree is complete.
+*/
+pub struct SpectrumTreeIterator<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    pub source: SpectrumGroupingIterator<R, C, D, S, SpectrumGroup<C, D, S>>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > SpectrumTreeIterator<R, C, D, S>
+{
+    pub fn new(source: SpectrumGroupingIterator<R, C, D, S, SpectrumGroup<C, D, S>>) -> Self {
+        Self { source }
+    }
+
+    /// Retrieve the next complete tree of spectra
+    pub fn next_tree(&mut self) -> Option<SpectrumTree<C, D, S>> {
+        self.source.next_group().map(SpectrumTree::from)
+    }
+}
+
+impl<
+        R: ScanSource<C, D, S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > Iterator for SpectrumTreeIterator<R, C, D, S>
+{
+    type Item = SpectrumTree<C, D, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_tree()
+    }
+}
+
+impl<
+        R: RandomAccessSpectrumIterator<C, D, S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > SpectrumTreeIterator<R, C, D, S>
+{
+    pub fn start_from_id(&mut self, id: &str) -> Result<&Self, SpectrumAccessError> {
+        self.source.start_from_id(id)?;
+        Ok(self)
+    }
+
+    pub fn start_from_index(&mut self, index: usize) -> Result<&Self, SpectrumAccessError> {
+        self.source.start_from_index(index)?;
+        Ok(self)
+    }
+
+    pub fn start_from_time(&mut self, time: f64) -> Result<&Self, SpectrumAccessError> {
+        self.source.start_from_time(time)?;
+        Ok(self)
+    }
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > SpectrumGroupingIterator<R, C, D, S, SpectrumGroup<C, D, S>>
+{
+    /// Convert this iterator into one which yields [`SpectrumTree`]s instead of flat groups
+    pub fn into_trees(self) -> SpectrumTreeIterator<R, C, D, S> {
+        SpectrumTreeIterator::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{MZFileReader, MzMLReader};
+    use crate::spectrum::test_fixtures::make_spectrum;
+    use crate::spectrum::Precursor;
+
+    fn make_product(index: usize, ms_level: u8, parent: usize) -> MultiLayerSpectrum {
+        let mut spectrum = make_spectrum(index, ms_level);
+        spectrum.description.precursors.push(Precursor {
+            precursor_id: Some(format!("scan={}", parent + 1)),
+            ..Default::default()
+        });
+        spectrum
+    }
+
+    #[test]
+    fn test_sps_ms3_tree() {
+        // MS1, then two MS2 each followed by their SPS-MS3
+        let precursor = make_spectrum(0, 1);
+        let products = vec![
+            make_product(1, 2, 0),
+            make_product(2, 3, 1),
+            make_product(3, 2, 0),
+            make_product(4, 3, 3),
+            make_product(5, 4, 4),
+        ];
+        let tree: SpectrumTree = SpectrumTree::from_parts(Some(precursor), products);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.at_level(3).count(), 2);
+
+        let path: Vec<_> = tree.path_to("scan=6").iter().map(|s| s.id()).collect();
+        assert_eq!(path, vec!["scan=1", "scan=4", "scan=5", "scan=6"]);
+        assert_eq!(tree.parent_of("scan=3").unwrap().id(), "scan=2");
+        assert!(tree.parent_of("scan=1").is_none());
+
+        let leaves: Vec<_> = tree.leaves().map(|s| s.id()).collect();
+        assert_eq!(leaves, vec!["scan=3", "scan=6"]);
+
+        let order: Vec<_> = tree.iter().map(|(d, s)| (d, s.index())).collect();
+        assert_eq!(order, vec![(0, 0), (1, 1), (2, 2), (1, 3), (2, 4), (3, 5)]);
+
+        let group = tree.into_group();
+        assert_eq!(group.products.len(), 5);
+    }
+
+    #[test]
+    fn test_trees_from_file() -> std::io::Result<()> {
+        let mut reader = MzMLReader::open_path("./test/data/read_index_of.mzML")?;
+        let trees: Vec<_> = reader.groups().into_trees().collect();
+        assert_eq!(trees.iter().map(|t| t.len()).sum::<usize>(), 48);
+        assert!(trees.iter().any(|t| t.depth() == 1));
+        for tree in trees.iter() {
+            let precursor = tree.precursor.as_ref().unwrap();
+            assert!(tree.depth() <= 1);
+            for child in tree.children.iter() {
+                assert!(child.is_leaf());
+                assert_eq!(child.subtree_size(), 1);
+                assert_eq!(tree.parent_of(child.id()).unwrap().id(), precursor.id());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+/*
+ * Exercises the C interface against test/data/small.mzML. Build and run it with
+ * `just test-capi`.
+ */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "mzdata.h"
+
+#define CHECK(call)                                                            \
+    do {                                                                       \
+        MzDataStatus status_ = (call);                                         \
+        if (status_ != MZ_DATA_STATUS_OK) {                                    \
+            const char *message_ = mzdata_last_error_message();                \
+            fprintf(stderr, "%s:%d: %s failed with %d: %s\n", __FILE__,        \
+                    __LINE__, #call, (int)status_,                             \
+                    message_ ? message_ : "(no message)");                     \
+            return 1;                                                          \
+        }                                                                      \
+    } while (0)
+
+static int test_random_access(MzDataReader *reader) {
+    MzDataSpectrum *spectrum = NULL;
+    const double *mzs = NULL;
+    const float *intensities = NULL;
+    size_t n_mzs = 0, n_intensities = 0;
+
+    CHECK(mzdata_reader_get_by_index(reader, 10, &spectrum));
+    assert(strcmp(mzdata_spectrum_id(spectrum),
+                  "controllerType=0 controllerNumber=1 scan=11") == 0);
+    assert(mzdata_spectrum_index(spectrum) == 10);
+
+    CHECK(mzdata_spectrum_mzs(spectrum, &mzs, &n_mzs));
+    CHECK(mzdata_spectrum_intensities(spectrum, &intensities, &n_intensities));
+    assert(n_mzs == 941);
+    assert(n_mzs == n_intensities);
+    for (size_t i = 1; i < n_mzs; i++) {
+        assert(mzs[i - 1] <= mzs[i]);
+    }
+    mzdata_spectrum_free(spectrum);
+
+    spectrum = NULL;
+    CHECK(mzdata_reader_get_by_id(
+        reader, "controllerType=0 controllerNumber=1 scan=11", &spectrum));
+    assert(mzdata_spectrum_index(spectrum) == 10);
+    mzdata_spectrum_free(spectrum);
+
+    assert(mzdata_reader_get_by_id(reader, "not a spectrum", &spectrum) ==
+           MZ_DATA_STATUS_NOT_FOUND);
+    assert(spectrum == NULL);
+    assert(mzdata_last_error_message() != NULL);
+    return 0;
+}
+
+static int test_iteration(MzDataReader *reader, size_t expected) {
+    MzDataSpectrum *spectrum = NULL;
+    MzDataStatus status;
+    size_t count = 0, n_msn = 0;
+    double last_time = -1.0;
+
+    CHECK(mzdata_reader_reset(reader));
+    while ((status = mzdata_reader_next(reader, &spectrum)) ==
+           MZ_DATA_STATUS_OK) {
+        MzDataPrecursor precursor;
+        double time = mzdata_spectrum_time(spectrum);
+        assert(time >= last_time);
+        last_time = time;
+        assert(mzdata_spectrum_index(spectrum) == count);
+        if (mzdata_spectrum_ms_level(spectrum) > 1) {
+            CHECK(mzdata_spectrum_precursor(spectrum, &precursor));
+            assert(precursor.mz > 0.0);
+            n_msn++;
+        } else {
+            assert(mzdata_spectrum_precursor(spectrum, &precursor) ==
+                   MZ_DATA_STATUS_NO_PRECURSOR);
+        }
+        mzdata_spectrum_free(spectrum);
+        count++;
+    }
+    assert(status == MZ_DATA_STATUS_END_OF_STREAM);
+    assert(spectrum == NULL);
+    assert(count == expected);
+    assert(n_msn > 0);
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    const char *path = argc > 1 ? argv[1] : "test/data/small.mzML";
+    MzDataReader *reader = NULL;
+    size_t n = 0;
+
+    assert(mzdata_reader_open("no/such/file.mzML", &reader) ==
+           MZ_DATA_STATUS_IO_ERROR);
+    assert(reader == NULL);
+
+    CHECK(mzdata_reader_open(path, &reader));
+    CHECK(mzdata_reader_len(reader, &n));
+    assert(n == 48);
+
+    if (test_random_access(reader) != 0 || test_iteration(reader, n) != 0) {
+        mzdata_reader_free(reader);
+        return 1;
+    }
+    mzdata_reader_free(reader);
+    mzdata_reader_free(NULL);
+    printf("All C interface tests passed\n");
+    return 0;
+}